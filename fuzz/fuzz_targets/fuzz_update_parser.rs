@@ -1,9 +1,21 @@
 #![no_main]
 use libfuzzer_sys::fuzz_target;
+use nexuslite::document::{Document, DocumentType};
 
 fuzz_target!(|data: &[u8]| {
     if data.len() > 8192 { return; }
     if let Ok(s) = std::str::from_utf8(data) {
-        let _ = nexuslite::query::parse_update_json(s);
+        if let Ok(upd) = nexuslite::query::parse_update_json(s) {
+            // Applying any parsed update must not panic (type errors are fine), whatever the document shape
+            let samples = [
+                bson::doc! {},
+                bson::doc! {"a": 1, "s": [3, 1, 2], "o": {"a": [{"b": 1}]}},
+                bson::doc! {"a": "x", "s": "str", "o": [[1], 2.5]},
+            ];
+            for d in samples {
+                let mut doc = Document::new(d, DocumentType::Persistent);
+                let _ = nexuslite::query::apply_update(&mut doc, &upd);
+            }
+        }
     }
 });
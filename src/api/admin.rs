@@ -20,6 +20,21 @@ pub struct CollectionInfo {
     pub cache_hits: u64,
     pub cache_misses: u64,
     pub indexes: Vec<crate::index::IndexDescriptor>,
+    /// Runtime state of every index, including ones still building or that failed to build.
+    #[serde(default)]
+    pub index_status: Vec<IndexStatus>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IndexStatus {
+    pub field: String,
+    pub kind: crate::index::IndexKind,
+    pub state: crate::index::IndexState,
+    /// Fraction of a background build completed (1.0 once ready).
+    pub progress: f64,
+    pub keys: usize,
+    pub entries: usize,
+    pub multikey: bool,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -49,7 +64,27 @@ pub fn info(engine: &Engine) -> InfoReport {
                 }
             }
             let m = col.cache_metrics();
-            let idx = col.indexes.read().descriptors();
+            let (idx, mut index_status) = {
+                let mgr = col.indexes.read();
+                let status: Vec<IndexStatus> = mgr
+                    .indexes
+                    .iter()
+                    .map(|(field, i)| {
+                        let st = i.stats();
+                        IndexStatus {
+                            field: field.clone(),
+                            kind: i.kind(),
+                            state: st.state.clone(),
+                            progress: st.progress(),
+                            keys: st.keys,
+                            entries: st.entries,
+                            multikey: st.multikey,
+                        }
+                    })
+                    .collect();
+                (mgr.descriptors(), status)
+            };
+            index_status.sort_by(|a, b| a.field.cmp(&b.field));
             if name == "_tempDocuments" {
                 total_e += e + p;
             } else {
@@ -63,6 +98,7 @@ pub fn info(engine: &Engine) -> InfoReport {
                 cache_hits: m.hits,
                 cache_misses: m.misses,
                 indexes: idx,
+                index_status,
             });
         }
     }
@@ -5,7 +5,7 @@ use crate::engine::Engine;
 use crate::errors::DbError;
 use crate::export::{ExportOptions, export_file};
 use crate::import::{ImportOptions, import_file};
-use crate::query::{self, Filter, FindOptions, UpdateDoc};
+use crate::query::{self, Filter, FindOptions, UpdateDoc, UpdateOptions};
 use std::path::Path;
 
 pub fn find(
@@ -42,7 +42,7 @@ pub fn update_many(
     let col = engine
         .get_collection(collection)
         .ok_or_else(|| DbError::NoSuchCollection(collection.to_string()))?;
-    Ok(query::update_many(&col, filter, update))
+    query::update_many(&col, filter, update)
 }
 
 pub fn update_one(
@@ -54,7 +54,33 @@ pub fn update_one(
     let col = engine
         .get_collection(collection)
         .ok_or_else(|| DbError::NoSuchCollection(collection.to_string()))?;
-    Ok(query::update_one(&col, filter, update))
+    query::update_one(&col, filter, update)
+}
+
+pub fn update_many_with(
+    engine: &Engine,
+    collection: &str,
+    filter: &Filter,
+    update: &UpdateDoc,
+    opts: &UpdateOptions,
+) -> Result<crate::query::UpdateReport, DbError> {
+    let col = engine
+        .get_collection(collection)
+        .ok_or_else(|| DbError::NoSuchCollection(collection.to_string()))?;
+    query::update_many_with(&col, filter, update, opts)
+}
+
+pub fn update_one_with(
+    engine: &Engine,
+    collection: &str,
+    filter: &Filter,
+    update: &UpdateDoc,
+    opts: &UpdateOptions,
+) -> Result<crate::query::UpdateReport, DbError> {
+    let col = engine
+        .get_collection(collection)
+        .ok_or_else(|| DbError::NoSuchCollection(collection.to_string()))?;
+    query::update_one_with(&col, filter, update, opts)
 }
 
 pub fn delete_many(
@@ -134,5 +160,5 @@ pub fn create_document(
     if ephemeral && let Some(s) = ttl_secs {
         doc.set_ttl(std::time::Duration::from_secs(s));
     }
-    Ok(col.insert_document(doc))
+    col.try_insert_document(doc)
 }
@@ -7,14 +7,15 @@ pub mod feature;
 
 // Re-export the public API surface from submodules for a stable facade
 pub use admin::{
-    CollectionInfo, InfoReport, info, log_configure, log_configure_from_env, log_init_from_file,
-    log_init_from_file_path, telemetry_configure_rate_limit, telemetry_remove_rate_limit,
-    telemetry_set_audit_enabled, telemetry_set_db_name, telemetry_set_default_rate_limit,
-    telemetry_set_max_results_for, telemetry_set_max_results_global, telemetry_set_query_log,
+    CollectionInfo, IndexStatus, InfoReport, info, log_configure, log_configure_from_env,
+    log_init_from_file, log_init_from_file_path, telemetry_configure_rate_limit,
+    telemetry_remove_rate_limit, telemetry_set_audit_enabled, telemetry_set_db_name,
+    telemetry_set_default_rate_limit, telemetry_set_max_results_for,
+    telemetry_set_max_results_global, telemetry_set_query_log,
 };
 pub use collections::{
     count, create_document, delete_many, delete_one, export, find, import, parse_filter_json,
-    parse_update_json, update_many, update_one,
+    parse_update_json, update_many, update_many_with, update_one, update_one_with,
 };
 pub use crypto::{
     checkpoint_encrypted, crypto_decrypt_file, crypto_encrypt_file, crypto_generate_p256,
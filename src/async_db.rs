@@ -0,0 +1,540 @@
+//! Async (tokio) facade over [`Database`].
+//!
+//! Every call runs the blocking database work on tokio's blocking pool, so async services can
+//! call it directly instead of wrapping it in `spawn_blocking`. File I/O (import, export,
+//! checkpoints, opening files) runs on a small dedicated pool of its own, so a slow disk never
+//! holds up queries.
+//!
+//! Dropping a call's future cancels it: work not yet started is skipped, and imports and
+//! cursors stop part-way. A write or export already in progress is finished rather than torn.
+
+use crate::Database;
+use crate::document::Document;
+use crate::errors::DbError;
+use crate::export::{ExportOptions, ExportReport};
+use crate::import::{ImportFormat, ImportOptions, ImportReport};
+use crate::query::{
+    Cursor, DeleteReport, Filter, FindOptions, Stage, UpdateDoc, UpdateOptions, UpdateReport,
+};
+use crate::types::DocumentId;
+use futures_core::Stream;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{mpsc as tokio_mpsc, oneshot};
+
+/// Threads in the file I/O pool unless set with [`AsyncDatabase::with_io_threads`].
+pub const DEFAULT_IO_THREADS: usize = 2;
+
+/// Documents a cursor reads ahead of its consumer.
+const CURSOR_BUFFER: usize = 64;
+
+/// Bytes `import_from_reader` reads from the async source at a time.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// A [`Database`] whose calls are `async`.
+///
+/// Clones share the database and the I/O pool.
+#[derive(Clone)]
+pub struct AsyncDatabase {
+    db: Arc<Database>,
+    io: Arc<IoPool>,
+}
+
+impl AsyncDatabase {
+    /// Wrap an open database, with a file I/O pool of [`DEFAULT_IO_THREADS`] threads.
+    #[must_use]
+    pub fn new(db: Database) -> Self {
+        Self::with_io_threads(db, DEFAULT_IO_THREADS)
+    }
+
+    /// Wrap an open database, with a file I/O pool of `threads` threads (at least one).
+    #[must_use]
+    pub fn with_io_threads(db: Database, threads: usize) -> Self {
+        Self { db: Arc::new(db), io: Arc::new(IoPool::new(threads)) }
+    }
+
+    /// [`Database::new`] on the file I/O pool.
+    ///
+    /// # Errors
+    /// As for [`Database::new`].
+    pub async fn create(name_or_path: Option<&str>) -> Result<Self, DbError> {
+        let io = Arc::new(IoPool::new(DEFAULT_IO_THREADS));
+        let path = name_or_path.map(str::to_string);
+        let db = io.run(move |_| Database::new(path.as_deref())).await?;
+        Ok(Self { db: Arc::new(db), io })
+    }
+
+    /// [`Database::open`] on the file I/O pool.
+    ///
+    /// # Errors
+    /// As for [`Database::open`].
+    pub async fn open(name_or_path: &str) -> Result<Self, DbError> {
+        let io = Arc::new(IoPool::new(DEFAULT_IO_THREADS));
+        let path = name_or_path.to_string();
+        let db = io.run(move |_| Database::open(&path)).await?;
+        Ok(Self { db: Arc::new(db), io })
+    }
+
+    /// The wrapped database, for calls that are cheap enough to make directly.
+    #[must_use]
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Run `f` on tokio's blocking pool. It is skipped if the returned future is dropped
+    /// before it starts, and is handed a flag set once the future is dropped.
+    async fn run<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database, &AtomicBool) -> Result<T, DbError> + Send + 'static,
+    {
+        let db = self.db.clone();
+        let cancel = CancelOnDrop::new();
+        let flag = cancel.flag();
+        let task = tokio::task::spawn_blocking(move || {
+            if flag.load(Ordering::Relaxed) {
+                return Err(cancelled());
+            }
+            f(&db, &flag)
+        });
+        task.await.map_err(|e| DbError::Io(format!("database task failed: {e}")))?
+    }
+
+    /// Run `f` on the file I/O pool, as [`AsyncDatabase::run`] does on the blocking pool.
+    async fn run_io<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database, &AtomicBool) -> Result<T, DbError> + Send + 'static,
+    {
+        let db = self.db.clone();
+        self.io.run(move |flag| f(&db, flag)).await
+    }
+
+    /// Create a collection, rebuilding any indexes recorded for it.
+    ///
+    /// # Errors
+    /// Returns an error if the blocking task fails.
+    pub async fn create_collection(&self, name: &str) -> Result<(), DbError> {
+        let name = name.to_string();
+        self.run(move |db, _| {
+            let _ = db.create_collection(&name);
+            Ok(())
+        })
+        .await
+    }
+
+    /// # Errors
+    /// As for [`Database::insert_document`].
+    pub async fn insert_document(
+        &self,
+        collection: &str,
+        document: Document,
+    ) -> Result<DocumentId, DbError> {
+        let collection = collection.to_string();
+        self.run(move |db, _| db.insert_document(&collection, document)).await
+    }
+
+    /// # Errors
+    /// As for [`Database::update_document`].
+    pub async fn update_document(
+        &self,
+        collection: &str,
+        id: &DocumentId,
+        document: Document,
+    ) -> Result<bool, DbError> {
+        let (collection, id) = (collection.to_string(), id.clone());
+        self.run(move |db, _| db.update_document(&collection, &id, document)).await
+    }
+
+    /// # Errors
+    /// As for [`Database::delete_document`].
+    pub async fn delete_document(
+        &self,
+        collection: &str,
+        id: &DocumentId,
+    ) -> Result<bool, DbError> {
+        let (collection, id) = (collection.to_string(), id.clone());
+        self.run(move |db, _| db.delete_document(&collection, &id)).await
+    }
+
+    /// Find documents, streamed as the consumer reads them.
+    ///
+    /// # Errors
+    /// As for [`Database::find`].
+    pub async fn find(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        opts: &FindOptions,
+    ) -> Result<AsyncCursor, DbError> {
+        let (collection, filter, opts) = (collection.to_string(), filter.clone(), opts.clone());
+        let cursor = self.run(move |db, _| db.find(&collection, &filter, &opts)).await?;
+        Ok(AsyncCursor::spawn(cursor))
+    }
+
+    /// # Errors
+    /// As for [`Database::count`].
+    pub async fn count(&self, collection: &str, filter: &Filter) -> Result<usize, DbError> {
+        let (collection, filter) = (collection.to_string(), filter.clone());
+        self.run(move |db, _| db.count(&collection, &filter)).await
+    }
+
+    /// # Errors
+    /// As for [`Database::aggregate`].
+    pub async fn aggregate(
+        &self,
+        collection: &str,
+        pipeline: &[Stage],
+    ) -> Result<Vec<bson::Document>, DbError> {
+        let (collection, pipeline) = (collection.to_string(), pipeline.to_vec());
+        self.run(move |db, _| db.aggregate(&collection, &pipeline)).await
+    }
+
+    /// # Errors
+    /// As for [`Database::update_many_with`].
+    pub async fn update_many(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &UpdateDoc,
+        opts: &UpdateOptions,
+    ) -> Result<UpdateReport, DbError> {
+        let (collection, filter) = (collection.to_string(), filter.clone());
+        let (update, opts) = (update.clone(), *opts);
+        self.run(move |db, _| db.update_many_with(&collection, &filter, &update, &opts)).await
+    }
+
+    /// # Errors
+    /// As for [`Database::update_one_with`].
+    pub async fn update_one(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &UpdateDoc,
+        opts: &UpdateOptions,
+    ) -> Result<UpdateReport, DbError> {
+        let (collection, filter) = (collection.to_string(), filter.clone());
+        let (update, opts) = (update.clone(), *opts);
+        self.run(move |db, _| db.update_one_with(&collection, &filter, &update, &opts)).await
+    }
+
+    /// # Errors
+    /// As for [`Database::delete_many`].
+    pub async fn delete_many(
+        &self,
+        collection: &str,
+        filter: &Filter,
+    ) -> Result<DeleteReport, DbError> {
+        let (collection, filter) = (collection.to_string(), filter.clone());
+        self.run(move |db, _| db.delete_many(&collection, &filter)).await
+    }
+
+    /// # Errors
+    /// As for [`Database::delete_one`].
+    pub async fn delete_one(
+        &self,
+        collection: &str,
+        filter: &Filter,
+    ) -> Result<DeleteReport, DbError> {
+        let (collection, filter) = (collection.to_string(), filter.clone());
+        self.run(move |db, _| db.delete_one(&collection, &filter)).await
+    }
+
+    /// [`Database::checkpoint`] on the file I/O pool.
+    ///
+    /// # Errors
+    /// As for [`Database::checkpoint`].
+    pub async fn checkpoint(&self, path: impl AsRef<Path>) -> Result<(), DbError> {
+        let path = path.as_ref().to_path_buf();
+        self.run_io(move |db, _| db.checkpoint(&path)).await
+    }
+
+    /// Import a file into `opts.collection` on the file I/O pool; see
+    /// [`import_file`](crate::import::import_file).
+    ///
+    /// # Errors
+    /// Returns `DbError::Io` if the file cannot be read or parsed, or the import is cancelled.
+    pub async fn import(
+        &self,
+        path: impl AsRef<Path>,
+        opts: &ImportOptions,
+    ) -> Result<ImportReport, DbError> {
+        let (path, opts) = (path.as_ref().to_path_buf(), opts.clone());
+        self.run_io(move |db, flag| {
+            let file = std::fs::File::open(&path).map_err(|e| DbError::Io(e.to_string()))?;
+            let mut reader = io::BufReader::new(Cancellable { inner: file, flag });
+            let format = match opts.format {
+                ImportFormat::Auto => crate::import::detect_format(&mut reader, &path)
+                    .map_err(|e| DbError::Io(e.to_string()))?,
+                other => other,
+            };
+            crate::import::import_from_reader(&db.engine, reader, format, &opts)
+                .map_err(|e| DbError::Io(e.to_string()))
+        })
+        .await
+    }
+
+    /// Import documents of a known `format` from an async source into `opts.collection`.
+    ///
+    /// The source is read on the calling task and parsed on the blocking pool as it arrives.
+    /// Reading stops when the import does, even if the source has not ended.
+    ///
+    /// # Errors
+    /// Returns `DbError::Io` if `format` is `Auto`, the source fails, the data cannot be
+    /// parsed, or the import is cancelled.
+    pub async fn import_from_reader<R>(
+        &self,
+        mut reader: R,
+        format: ImportFormat,
+        opts: &ImportOptions,
+    ) -> Result<ImportReport, DbError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        if matches!(format, ImportFormat::Auto) {
+            return Err(DbError::Io("import_from_reader needs an explicit format".into()));
+        }
+        let (tx, rx) = tokio_mpsc::channel::<io::Result<Vec<u8>>>(4);
+        let opts = opts.clone();
+        let import = self.run(move |db, flag| {
+            let reader = ChannelReader { rx, buf: Vec::new(), pos: 0, done: false };
+            let reader = Cancellable { inner: reader, flag };
+            crate::import::import_from_reader(&db.engine, reader, format, &opts)
+                .map_err(|e| DbError::Io(e.to_string()))
+        });
+        let pump = async move {
+            let mut buf = vec![0u8; READ_CHUNK];
+            loop {
+                let chunk = match reader.read(&mut buf).await {
+                    Ok(n) => Ok(buf[..n].to_vec()),
+                    Err(e) => Err(e),
+                };
+                // An empty chunk marks the end; a send fails once the import has stopped
+                let last = !matches!(chunk, Ok(ref c) if !c.is_empty());
+                if tx.send(chunk).await.is_err() || last {
+                    return;
+                }
+            }
+        };
+        // The import can finish before the source does (e.g. on a parse error), so stop reading
+        // then rather than wait for a source that may never end
+        tokio::pin!(import);
+        tokio::select! {
+            report = &mut import => report,
+            () = pump => import.await,
+        }
+    }
+
+    /// Export a collection to a file on the file I/O pool; see
+    /// [`export_file`](crate::export::export_file).
+    ///
+    /// # Errors
+    /// Returns `DbError::Io` if the collection does not exist, the file cannot be written, or
+    /// the export is cancelled before it starts.
+    pub async fn export(
+        &self,
+        collection: &str,
+        path: impl AsRef<Path>,
+        opts: &ExportOptions,
+    ) -> Result<ExportReport, DbError> {
+        let (collection, path): (String, PathBuf) =
+            (collection.to_string(), path.as_ref().to_path_buf());
+        let opts = opts.clone();
+        self.run_io(move |db, _| {
+            crate::export::export_file(&db.engine, &collection, &path, &opts)
+                .map_err(|e| DbError::Io(e.to_string()))
+        })
+        .await
+    }
+}
+
+/// Documents of a find, read ahead on the blocking pool. Dropping it stops the read.
+pub struct AsyncCursor {
+    rx: tokio_mpsc::Receiver<Document>,
+}
+
+impl AsyncCursor {
+    fn spawn(mut cursor: Cursor) -> Self {
+        let (tx, rx) = tokio_mpsc::channel(CURSOR_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            while let Some(doc) = cursor.advance() {
+                // The consumer dropped the cursor
+                if tx.blocking_send(doc).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { rx }
+    }
+
+    /// The next document, or `None` once every result has been read.
+    pub async fn next(&mut self) -> Option<Document> {
+        self.rx.recv().await
+    }
+
+    /// Read the remaining documents.
+    pub async fn to_vec(mut self) -> Vec<Document> {
+        let mut out = Vec::new();
+        while let Some(d) = self.next().await {
+            out.push(d);
+        }
+        out
+    }
+}
+
+impl Stream for AsyncCursor {
+    type Item = Document;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Document>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+fn cancelled() -> DbError {
+    DbError::Io("operation cancelled".into())
+}
+
+/// Sets its flag when dropped, i.e. when the future holding it completes or is dropped.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl CancelOnDrop {
+    fn new() -> Self {
+        Self(Arc::new(AtomicBool::new(false)))
+    }
+
+    fn flag(&self) -> CancelFlag {
+        CancelFlag(self.0.clone())
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+struct CancelFlag(Arc<AtomicBool>);
+
+impl std::ops::Deref for CancelFlag {
+    type Target = AtomicBool;
+    fn deref(&self) -> &AtomicBool {
+        &self.0
+    }
+}
+
+/// A reader that fails once its call has been cancelled.
+struct Cancellable<R, F> {
+    inner: R,
+    flag: F,
+}
+
+impl<R: Read, F: std::ops::Deref<Target = AtomicBool>> Read for Cancellable<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.flag.load(Ordering::Relaxed) {
+            return Err(io::Error::other("import cancelled"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+/// The blocking end of `import_from_reader`'s pipe. An empty chunk marks the end of the
+/// data; a pipe closed without one was cancelled.
+struct ChannelReader {
+    rx: tokio_mpsc::Receiver<io::Result<Vec<u8>>>,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.done {
+                return Ok(0);
+            }
+            match self.rx.blocking_recv() {
+                Some(Ok(chunk)) if chunk.is_empty() => self.done = true,
+                Some(Ok(chunk)) => (self.buf, self.pos) = (chunk, 0),
+                Some(Err(e)) => return Err(e),
+                None => return Err(io::Error::other("import cancelled")),
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads dedicated to blocking file I/O. They exit once the pool is dropped.
+struct IoPool {
+    jobs: Mutex<Option<mpsc::Sender<Job>>>,
+}
+
+impl IoPool {
+    fn new(threads: usize) -> Self {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        for i in 0..threads.max(1) {
+            let rx = rx.clone();
+            let spawned =
+                std::thread::Builder::new().name(format!("nexuslite-io-{i}")).spawn(move || {
+                    loop {
+                        let job = match rx.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(_) => return,
+                        };
+                        let Ok(job) = job else { return };
+                        // A panicking job fails its own call, not the pool
+                        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+                    }
+                });
+            if let Err(e) = spawned {
+                log::warn!("I/O pool thread not started: {e}");
+            }
+        }
+        Self { jobs: Mutex::new(Some(tx)) }
+    }
+
+    /// Run `f` on a pool thread, skipping it if the returned future is dropped first; `f` is
+    /// handed a flag set once the future is dropped.
+    async fn run<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&AtomicBool) -> Result<T, DbError> + Send + 'static,
+    {
+        let cancel = CancelOnDrop::new();
+        let flag = cancel.flag();
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            if flag.load(Ordering::Relaxed) {
+                return;
+            }
+            let _ = tx.send(f(&flag));
+        });
+        let sent = match self.jobs.lock() {
+            Ok(jobs) => jobs.as_ref().is_some_and(|jobs| jobs.send(job).is_ok()),
+            Err(_) => false,
+        };
+        if !sent {
+            return Err(DbError::Io("I/O pool is not running".into()));
+        }
+        rx.await.map_err(|_| DbError::Io("I/O task failed".into()))?
+    }
+}
+
+impl Drop for IoPool {
+    fn drop(&mut self) {
+        // Closing the queue lets each thread finish its job and exit
+        if let Ok(mut jobs) = self.jobs.lock() {
+            jobs.take();
+        }
+    }
+}
@@ -246,7 +246,7 @@ enum QueryCommands {
         collection: Option<String>,
         #[arg(long, help = "Filter expression in JSON string form", default_value = "{}")]
         filter: String,
-        #[arg(long, help = "Projection JSON, e.g. {\"_id\":0}")]
+        #[arg(long, help = "Projection JSON, e.g. {\"password\":0}, or comma-separated fields")]
         project: Option<String>,
         #[arg(long, help = "Sort JSON, e.g. {\"x\":1}")]
         sort: Option<String>,
@@ -256,6 +256,33 @@ enum QueryCommands {
         skip: Option<usize>,
         #[arg(long, help = "Fields to redact from output (repeatable)")]
         redact: Option<Vec<String>>,
+        #[arg(long, help = "Resume after a previous page: the next_token it printed to stderr")]
+        after: Option<String>,
+    },
+    #[command(name = "explain", about = "Show the query plan and execution statistics for a find")]
+    Explain {
+        #[arg(help = "Target collection name; falls back to default_collection if set in config")]
+        collection: Option<String>,
+        #[arg(long, help = "Filter expression in JSON string form", default_value = "{}")]
+        filter: String,
+        #[arg(long, help = "Projection JSON or comma-separated fields")]
+        project: Option<String>,
+        #[arg(long, help = "Sort fields, comma-separated; prefix with - for descending")]
+        sort: Option<String>,
+        #[arg(long, help = "Max results")]
+        limit: Option<usize>,
+        #[arg(long, help = "Skip first N results")]
+        skip: Option<usize>,
+    },
+    #[command(name = "aggregate", about = "Run an aggregation pipeline and print NDJSON results")]
+    Aggregate {
+        #[arg(help = "Target collection name; falls back to default_collection if set in config")]
+        collection: Option<String>,
+        #[arg(
+            long,
+            help = "Pipeline as a JSON array of stages ($match/$group/$project/$sort/$unwind/$limit/$count/$lookup)"
+        )]
+        pipeline: String,
     },
     #[command(name = "count", about = "Count documents matching a filter")]
     Count {
@@ -275,6 +302,8 @@ enum QueryCommands {
         filter: String,
         #[arg(long, help = "Update expression in JSON string form")]
         update: String,
+        #[arg(long, help = "Insert a document built from the filter and update if none matches")]
+        upsert: bool,
     },
     #[command(name = "delete", about = "Delete documents matching a filter")]
     Delete {
@@ -291,6 +320,8 @@ enum QueryCommands {
         filter: String,
         #[arg(long, help = "Update expression in JSON string form")]
         update: String,
+        #[arg(long, help = "Insert a document built from the filter and update if none matches")]
+        upsert: bool,
     },
     #[command(name = "delete-one", about = "Delete a single document matching a filter")]
     DeleteOne {
@@ -324,7 +355,10 @@ enum Commands {
         #[command(subcommand)]
         cmd: CollectionCommands,
     },
-    #[command(name = "query", about = "Query operations (find/count/update/delete)")]
+    #[command(
+        name = "query",
+        about = "Query operations (find/explain/aggregate/count/update/delete)"
+    )]
     Query {
         #[command(subcommand)]
         cmd: QueryCommands,
@@ -750,7 +784,16 @@ fn main() {
             }
         },
         Commands::Query { cmd } => match cmd {
-            QueryCommands::Find { collection, filter, project, sort, limit, skip, redact } => {
+            QueryCommands::Find {
+                collection,
+                filter,
+                project,
+                sort,
+                limit,
+                skip,
+                redact,
+                after,
+            } => {
                 let c = collection.or_else(|| def_col.clone()).unwrap_or_else(|| "default".into());
                 if let Some(fields) = redact {
                     prog_cli::run(
@@ -762,6 +805,7 @@ fn main() {
                             sort,
                             limit,
                             skip,
+                            after,
                             redact_fields: Some(fields),
                         },
                     )
@@ -775,10 +819,33 @@ fn main() {
                             sort,
                             limit,
                             skip,
+                            after,
                         },
                     )
                 }
             }
+            QueryCommands::Explain { collection, filter, project, sort, limit, skip } => {
+                let c = collection.or_else(|| def_col.clone()).unwrap_or_else(|| "default".into());
+                prog_cli::run_with_format(
+                    &engine,
+                    prog_cli::Command::QueryExplain {
+                        collection: c,
+                        filter_json: filter,
+                        project,
+                        sort,
+                        limit,
+                        skip,
+                    },
+                    mode,
+                )
+            }
+            QueryCommands::Aggregate { collection, pipeline } => {
+                let c = collection.or_else(|| def_col.clone()).unwrap_or_else(|| "default".into());
+                prog_cli::run(
+                    &engine,
+                    prog_cli::Command::QueryAggregate { collection: c, pipeline_json: pipeline },
+                )
+            }
             QueryCommands::Count { collection, filter } => {
                 let c = collection.or_else(|| def_col.clone()).unwrap_or_else(|| "default".into());
                 prog_cli::run_with_format(
@@ -787,7 +854,7 @@ fn main() {
                     mode,
                 )
             }
-            QueryCommands::Update { collection, filter, update } => {
+            QueryCommands::Update { collection, filter, update, upsert } => {
                 let c = collection.or_else(|| def_col.clone()).unwrap_or_else(|| "default".into());
                 prog_cli::run_with_format(
                     &engine,
@@ -795,6 +862,7 @@ fn main() {
                         collection: c,
                         filter_json: filter,
                         update_json: update,
+                        upsert,
                     },
                     mode,
                 )
@@ -807,7 +875,7 @@ fn main() {
                     mode,
                 )
             }
-            QueryCommands::UpdateOne { collection, filter, update } => {
+            QueryCommands::UpdateOne { collection, filter, update, upsert } => {
                 let c = collection.or_else(|| def_col.clone()).unwrap_or_else(|| "default".into());
                 prog_cli::run_with_format(
                     &engine,
@@ -815,6 +883,7 @@ fn main() {
                         collection: c,
                         filter_json: filter,
                         update_json: update,
+                        upsert,
                     },
                     mode,
                 )
@@ -1048,7 +1117,7 @@ fn main() {
                     }
                     if trimmed.eq_ignore_ascii_case("help") {
                         println!(
-                            "Commands:\n  info\n  config\n  list-collections\n  list-ephemeral\n  purge-ephemeral [all]\n  find <collection> <filter-json>\n  count <collection> <filter-json>\n  update <collection> <filter-json> <update-json>\n  delete <collection> <filter-json>\n  create-document <collection> <json> [ephemeral] [ttl=SECS]\n  exit"
+                            "Commands:\n  info\n  config\n  list-collections\n  list-ephemeral\n  purge-ephemeral [all]\n  find <collection> <filter-json>\n  count <collection> <filter-json>\n  aggregate <collection> <pipeline-json>\n  update <collection> <filter-json> <update-json>\n  delete <collection> <filter-json>\n  create-document <collection> <json> [ephemeral] [ttl=SECS]\n  exit"
                         );
                         continue;
                     }
@@ -1101,6 +1170,7 @@ fn main() {
                                     sort: None,
                                     limit: None,
                                     skip: None,
+                                    after: None,
                                 },
                             );
                             continue;
@@ -1118,6 +1188,20 @@ fn main() {
                         );
                         continue;
                     }
+                    if let Some(rest) = trimmed.strip_prefix("aggregate ")
+                        && let Some((col, pjson)) = rest.split_once(' ')
+                    {
+                        if let Err(e) = prog_cli::run(
+                            &engine,
+                            prog_cli::Command::QueryAggregate {
+                                collection: col.to_string(),
+                                pipeline_json: pjson.to_string(),
+                            },
+                        ) {
+                            eprintln!("error: {e}");
+                        }
+                        continue;
+                    }
                     if let Some(rest) = trimmed.strip_prefix("delete ")
                         && let Some((col, fjson)) = rest.split_once(' ')
                     {
@@ -1141,6 +1225,7 @@ fn main() {
                                     collection: col.to_string(),
                                     filter_json: fjson.to_string(),
                                     update_json: uj.to_string(),
+                                    upsert: false,
                                 },
                             );
                             continue;
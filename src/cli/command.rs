@@ -44,6 +44,8 @@ pub enum Command {
         sort: Option<String>,
         limit: Option<usize>,
         skip: Option<usize>,
+        /// Resume token printed by an earlier find with the same sort.
+        after: Option<String>,
     },
     QueryFindR {
         collection: String,
@@ -52,16 +54,30 @@ pub enum Command {
         sort: Option<String>,
         limit: Option<usize>,
         skip: Option<usize>,
+        after: Option<String>,
         redact_fields: Option<Vec<String>>,
     },
     QueryCount {
         collection: String,
         filter_json: String,
     },
+    QueryAggregate {
+        collection: String,
+        pipeline_json: String,
+    },
+    QueryExplain {
+        collection: String,
+        filter_json: String,
+        project: Option<String>,
+        sort: Option<String>,
+        limit: Option<usize>,
+        skip: Option<usize>,
+    },
     QueryUpdate {
         collection: String,
         filter_json: String,
         update_json: String,
+        upsert: bool,
     },
     QueryDelete {
         collection: String,
@@ -71,6 +87,7 @@ pub enum Command {
         collection: String,
         filter_json: String,
         update_json: String,
+        upsert: bool,
     },
     QueryDeleteOne {
         collection: String,
@@ -2,7 +2,7 @@ use crate::engine::Engine;
 use crate::errors::DbError;
 use crate::export::{ExportOptions, export_file};
 use crate::import::{ImportOptions, import_file};
-use crate::query::{self, FindOptions, Order, SortSpec};
+use crate::query::{self, FindOptions, Order, SortSpec, UpdateOptions, UpdateReport};
 use std::io::Write;
 
 use super::command::Command;
@@ -113,22 +113,30 @@ pub fn run_with_format(
             }
             Ok(())
         }
-        Command::QueryUpdate { collection, filter_json, update_json } => {
+        Command::QueryExplain { collection, filter_json, project, sort, limit, skip } => {
             let col = engine
                 .get_collection(&collection)
                 .ok_or_else(|| crate::errors::DbError::NoSuchCollection(collection.clone()))?;
             let filter = query::parse_filter_json(&filter_json)?;
-            let update = query::parse_update_json(&update_json)?;
-            let r = query::update_many(&col, &filter, &update);
+            let opts = find_options(project, sort, limit, skip)?;
+            let report = query::explain(&col, &filter, &opts);
             match mode {
-                OutputMode::Json => {
-                    let json = serde_json::json!({"matched": r.matched, "modified": r.modified});
-                    println!("{json}");
-                }
-                _ => println!("{{\"matched\":{},\"modified\":{}}}", r.matched, r.modified),
+                OutputMode::Json => println!("{}", serde_json::to_string(&report)?),
+                _ => println!("{}", serde_json::to_string_pretty(&report)?),
             }
             Ok(())
         }
+        Command::QueryUpdate { collection, filter_json, update_json, upsert } => {
+            let col = engine
+                .get_collection(&collection)
+                .ok_or_else(|| crate::errors::DbError::NoSuchCollection(collection.clone()))?;
+            let filter = query::parse_filter_json(&filter_json)?;
+            let update = query::parse_update_json(&update_json)?;
+            let r = query::update_many_with(&col, &filter, &update, &UpdateOptions { upsert })?;
+            // Same compact object in every mode
+            println!("{}", update_report_json(&r));
+            Ok(())
+        }
         Command::QueryDelete { collection, filter_json } => {
             let col = engine
                 .get_collection(&collection)
@@ -144,20 +152,15 @@ pub fn run_with_format(
             }
             Ok(())
         }
-        Command::QueryUpdateOne { collection, filter_json, update_json } => {
+        Command::QueryUpdateOne { collection, filter_json, update_json, upsert } => {
             let col = engine
                 .get_collection(&collection)
                 .ok_or_else(|| crate::errors::DbError::NoSuchCollection(collection.clone()))?;
             let filter = query::parse_filter_json(&filter_json)?;
             let update = query::parse_update_json(&update_json)?;
-            let r = query::update_one(&col, &filter, &update);
-            match mode {
-                OutputMode::Json => {
-                    let json = serde_json::json!({"matched": r.matched, "modified": r.modified});
-                    println!("{json}");
-                }
-                _ => println!("{{\"matched\":{},\"modified\":{}}}", r.matched, r.modified),
-            }
+            let r = query::update_one_with(&col, &filter, &update, &UpdateOptions { upsert })?;
+            // Same compact object in every mode
+            println!("{}", update_report_json(&r));
             Ok(())
         }
         Command::QueryDeleteOne { collection, filter_json } => {
@@ -198,7 +201,7 @@ pub fn run_with_format(
             if ephemeral && let Some(secs) = ttl_secs {
                 doc.set_ttl(std::time::Duration::from_secs(secs));
             }
-            let id = col.insert_document(doc);
+            let id = col.try_insert_document(doc)?;
             match mode {
                 OutputMode::Json => {
                     let id0 = id.0;
@@ -681,7 +684,7 @@ pub fn run(engine: &Engine, cmd: Command) -> Result<(), Box<dyn std::error::Erro
             let _report = export_file(engine, &collection, file, &opts)?;
             Ok(())
         }
-        Command::QueryFind { collection, filter_json, project, sort, limit, skip } => {
+        Command::QueryFind { collection, filter_json, project, sort, limit, skip, after } => {
             let col = engine
                 .get_collection(&collection)
                 .ok_or_else(|| crate::errors::DbError::NoSuchCollection(collection.clone()))?;
@@ -691,40 +694,15 @@ pub fn run(engine: &Engine, cmd: Command) -> Result<(), Box<dyn std::error::Erro
                 return Err(Box::new(DbError::RateLimitedWithRetry { retry_after_ms: ra }));
             }
             let filter = query::parse_filter_json(&filter_json)?;
-            let mut opts = FindOptions::default();
-            if let Some(p) = project {
-                opts.projection = Some(
-                    p.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
-                );
-            }
-            if let Some(s) = sort {
-                let mut specs = Vec::new();
-                for part in s.split(',') {
-                    let part = part.trim();
-                    if part.is_empty() {
-                        continue;
-                    }
-                    let (order, field) = if let Some(rest) = part.strip_prefix('-') {
-                        (Order::Desc, rest)
-                    } else if let Some(rest) = part.strip_prefix('+') {
-                        (Order::Asc, rest)
-                    } else {
-                        (Order::Asc, part)
-                    };
-                    specs.push(SortSpec { field: field.to_string(), order });
-                }
-                if !specs.is_empty() {
-                    opts.sort = Some(specs);
-                }
-            }
-            opts.limit = limit;
-            opts.skip = skip;
-            let cursor = query::find_docs(&col, &filter, &opts);
+            let mut opts = find_options(project, sort, limit, skip)?;
+            opts.after = after;
+            let mut cursor = query::find_docs_rate_limited(&col, &filter, &opts)?;
             // Stream as NDJSON to stdout
-            for doc in cursor.to_vec() {
+            while let Some(doc) = cursor.advance() {
                 let line = serde_json::to_string(&doc.data.0)?;
                 println!("{}", line);
             }
+            print_next_token(&cursor)?;
             Ok(())
         }
         Command::QueryFindR {
@@ -734,6 +712,7 @@ pub fn run(engine: &Engine, cmd: Command) -> Result<(), Box<dyn std::error::Erro
             sort,
             limit,
             skip,
+            after,
             redact_fields,
         } => {
             let col = engine
@@ -745,36 +724,10 @@ pub fn run(engine: &Engine, cmd: Command) -> Result<(), Box<dyn std::error::Erro
                 return Err(Box::new(DbError::RateLimitedWithRetry { retry_after_ms: ra }));
             }
             let filter = query::parse_filter_json(&filter_json)?;
-            let mut opts = FindOptions::default();
-            if let Some(p) = project {
-                opts.projection = Some(
-                    p.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
-                );
-            }
-            if let Some(s) = sort {
-                let mut specs = Vec::new();
-                for part in s.split(',') {
-                    let part = part.trim();
-                    if part.is_empty() {
-                        continue;
-                    }
-                    let (order, field) = if let Some(rest) = part.strip_prefix('-') {
-                        (Order::Desc, rest)
-                    } else if let Some(rest) = part.strip_prefix('+') {
-                        (Order::Asc, rest)
-                    } else {
-                        (Order::Asc, part)
-                    };
-                    specs.push(SortSpec { field: field.to_string(), order });
-                }
-                if !specs.is_empty() {
-                    opts.sort = Some(specs);
-                }
-            }
-            opts.limit = limit;
-            opts.skip = skip;
-            let cursor = query::find_docs(&col, &filter, &opts);
-            for mut doc in cursor.to_vec() {
+            let mut opts = find_options(project, sort, limit, skip)?;
+            opts.after = after;
+            let mut cursor = query::find_docs_rate_limited(&col, &filter, &opts)?;
+            while let Some(mut doc) = cursor.advance() {
                 if let Some(fields) = &redact_fields {
                     for f in fields {
                         if doc.data.0.contains_key(f) {
@@ -787,6 +740,24 @@ pub fn run(engine: &Engine, cmd: Command) -> Result<(), Box<dyn std::error::Erro
                 let line = serde_json::to_string(&doc.data.0)?;
                 println!("{}", line);
             }
+            print_next_token(&cursor)?;
+            Ok(())
+        }
+        cmd @ Command::QueryExplain { .. } => run_with_format(engine, cmd, OutputMode::Human),
+        Command::QueryAggregate { collection, pipeline_json } => {
+            let col = engine
+                .get_collection(&collection)
+                .ok_or_else(|| crate::errors::DbError::NoSuchCollection(collection.clone()))?;
+            if !crate::telemetry::try_consume_token(&col.name_str(), 1) {
+                crate::telemetry::log_rate_limited(&collection, "aggregate");
+                let ra = crate::telemetry::retry_after_ms(&col.name_str(), 1);
+                return Err(Box::new(DbError::RateLimitedWithRetry { retry_after_ms: ra }));
+            }
+            let pipeline = query::parse_pipeline_json(&pipeline_json)?;
+            // Stream as NDJSON to stdout
+            for doc in query::aggregate_with(&col, &pipeline, |name| engine.get_collection(name))? {
+                println!("{}", serde_json::to_string(&doc)?);
+            }
             Ok(())
         }
         Command::QueryCount { collection, filter_json } => {
@@ -803,14 +774,14 @@ pub fn run(engine: &Engine, cmd: Command) -> Result<(), Box<dyn std::error::Erro
             println!("{}", n);
             Ok(())
         }
-        Command::QueryUpdate { collection, filter_json, update_json } => {
+        Command::QueryUpdate { collection, filter_json, update_json, upsert } => {
             let col = engine
                 .get_collection(&collection)
                 .ok_or_else(|| crate::errors::DbError::NoSuchCollection(collection.clone()))?;
             let filter = query::parse_filter_json(&filter_json)?;
             let update = query::parse_update_json(&update_json)?;
-            let r = query::update_many(&col, &filter, &update);
-            println!("{{\"matched\":{},\"modified\":{}}}", r.matched, r.modified);
+            let r = query::update_many_with(&col, &filter, &update, &UpdateOptions { upsert })?;
+            println!("{}", update_report_json(&r));
             Ok(())
         }
         Command::QueryDelete { collection, filter_json } => {
@@ -822,14 +793,14 @@ pub fn run(engine: &Engine, cmd: Command) -> Result<(), Box<dyn std::error::Erro
             println!("{{\"deleted\":{}}}", r.deleted);
             Ok(())
         }
-        Command::QueryUpdateOne { collection, filter_json, update_json } => {
+        Command::QueryUpdateOne { collection, filter_json, update_json, upsert } => {
             let col = engine
                 .get_collection(&collection)
                 .ok_or_else(|| crate::errors::DbError::NoSuchCollection(collection.clone()))?;
             let filter = query::parse_filter_json(&filter_json)?;
             let update = query::parse_update_json(&update_json)?;
-            let r = query::update_one(&col, &filter, &update);
-            println!("{{\"matched\":{},\"modified\":{}}}", r.matched, r.modified);
+            let r = query::update_one_with(&col, &filter, &update, &UpdateOptions { upsert })?;
+            println!("{}", update_report_json(&r));
             Ok(())
         }
         Command::QueryDeleteOne { collection, filter_json } => {
@@ -864,7 +835,7 @@ pub fn run(engine: &Engine, cmd: Command) -> Result<(), Box<dyn std::error::Erro
             if ephemeral && let Some(secs) = ttl_secs {
                 doc.set_ttl(std::time::Duration::from_secs(secs));
             }
-            let id = col.insert_document(doc);
+            let id = col.try_insert_document(doc)?;
             println!("{}", id.0);
             Ok(())
         }
@@ -1155,3 +1126,62 @@ pub fn run(engine: &Engine, cmd: Command) -> Result<(), Box<dyn std::error::Erro
         }
     }
 }
+
+/// Print the resume token for the next page to stderr, keeping stdout pure NDJSON.
+fn print_next_token(cursor: &query::Cursor) -> Result<(), DbError> {
+    if let Some(token) = cursor.next_token()? {
+        eprintln!("next_token: {token}");
+    }
+    Ok(())
+}
+
+/// Build `FindOptions` from the CLI's projection (a JSON object, or comma-separated paths to
+/// include) and `[+-]field` sort list.
+fn find_options(
+    project: Option<String>,
+    sort: Option<String>,
+    limit: Option<usize>,
+    skip: Option<usize>,
+) -> Result<FindOptions, DbError> {
+    let mut opts = FindOptions::default();
+    if let Some(p) = project {
+        opts.projection = Some(if p.trim_start().starts_with('{') {
+            query::parse_projection_json(&p)?
+        } else {
+            let paths = p.split(',').map(str::trim).filter(|s| !s.is_empty());
+            query::Projection::include(paths)
+        });
+    }
+    if let Some(s) = sort {
+        let mut specs = Vec::new();
+        for part in s.split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            let (order, field) = if let Some(rest) = part.strip_prefix('-') {
+                (Order::Desc, rest)
+            } else if let Some(rest) = part.strip_prefix('+') {
+                (Order::Asc, rest)
+            } else {
+                (Order::Asc, part)
+            };
+            specs.push(SortSpec { field: field.to_string(), order });
+        }
+        if !specs.is_empty() {
+            opts.sort = Some(specs);
+        }
+    }
+    opts.limit = limit;
+    opts.skip = skip;
+    Ok(opts)
+}
+
+/// `{"matched":..,"modified":..}`, plus `upserted_id` when an upsert inserted.
+fn update_report_json(r: &UpdateReport) -> serde_json::Value {
+    let mut json = serde_json::json!({"matched": r.matched, "modified": r.modified});
+    if let Some(id) = &r.upserted_id {
+        json["upserted_id"] = serde_json::Value::String(id.0.to_string());
+    }
+    json
+}
@@ -0,0 +1,189 @@
+use super::core::Collection;
+use crate::document::Document;
+use crate::errors::DbError;
+use crate::index::{IndexImpl, IndexManager, index_insert_all, index_key_values, index_remove_all};
+use crate::query::{Geometry, geo_cell, geo_geometries};
+use crate::telemetry;
+use crate::types::{DocumentId, Operation};
+use crate::wasp::{DeltaKey, DeltaOp, IndexDelta};
+use bson::Document as BsonDocument;
+use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
+
+/// A group of writes applied under one hold of the collection's index lock.
+///
+/// Each write is checked against unique indexes and applied to the cache and indexes as
+/// soon as it is staged, so later writes in the batch observe earlier ones. The storage
+/// operations and index deltas are buffered and appended in a single batch by
+/// [`WriteBatch::commit`]; a batch dropped without committing appends them too, logging any
+/// failure.
+pub struct WriteBatch<'a> {
+    col: &'a Collection,
+    mgr: RwLockWriteGuard<'a, IndexManager>,
+    _build: RwLockReadGuard<'a, ()>,
+    ops: Vec<Operation>,
+    deltas: Vec<IndexDelta>,
+}
+
+impl Collection {
+    /// Start a write batch. Other writers on this collection block until it is dropped.
+    pub fn write_batch(&self) -> WriteBatch<'_> {
+        let build = self.build_lock.read();
+        let mgr = self.indexes.write();
+        WriteBatch { col: self, mgr, _build: build, ops: Vec::new(), deltas: Vec::new() }
+    }
+}
+
+impl WriteBatch<'_> {
+    pub fn collection(&self) -> &Collection {
+        self.col
+    }
+
+    /// The collection's indexes, as this batch has left them so far.
+    pub(crate) fn indexes(&self) -> &IndexManager {
+        &self.mgr
+    }
+
+    /// Insert a document, enforcing unique indexes.
+    ///
+    /// # Errors
+    /// Returns `DbError::DuplicateKey` if the document would violate a unique index.
+    pub fn insert(&mut self, document: Document) -> Result<DocumentId, DbError> {
+        let doc_id = document.id.clone();
+        self.mgr.check_unique(&document.data.0, &doc_id)?;
+        self.col.cache.insert(document.clone());
+        index_insert_all(&mut self.mgr, &document.data.0, &doc_id);
+        self.stage_deltas(&document.data.0, &doc_id, &DeltaOp::Add);
+        telemetry::log_audit("insert", &self.col.name_str(), &doc_id.0.to_string(), None);
+        self.ops.push(Operation::Insert { document });
+        Ok(doc_id)
+    }
+
+    /// Replace the document stored under `id`, optionally only if its current contents equal
+    /// `expected`. Returns `Ok(false)` if `id` is unknown or the contents differ.
+    ///
+    /// # Errors
+    /// Returns `DbError::DuplicateKey` if the new document would violate a unique index.
+    pub fn replace(
+        &mut self,
+        id: &DocumentId,
+        new_document: Document,
+        expected: Option<&BsonDocument>,
+    ) -> Result<bool, DbError> {
+        let Some(old) = self.col.cache.get(id) else {
+            return Ok(false);
+        };
+        if expected.is_some_and(|e| *e != old.data.0) {
+            return Ok(false);
+        }
+        let mut new_doc_same_id = new_document;
+        new_doc_same_id.id = id.clone();
+        self.mgr.check_unique(&new_doc_same_id.data.0, id)?;
+        index_remove_all(&mut self.mgr, &old.data.0, id);
+        self.col.cache.insert(new_doc_same_id.clone());
+        index_insert_all(&mut self.mgr, &new_doc_same_id.data.0, id);
+        self.stage_deltas(&old.data.0, id, &DeltaOp::Remove);
+        self.stage_deltas(&new_doc_same_id.data.0, id, &DeltaOp::Add);
+        telemetry::log_audit("update", &self.col.name_str(), &id.0.to_string(), None);
+        self.ops.push(Operation::Update { document_id: id.clone(), new_document: new_doc_same_id });
+        Ok(true)
+    }
+
+    /// Delete the document stored under `id`, optionally only if its current contents equal
+    /// `expected`. Returns whether a document was removed.
+    pub fn delete(&mut self, id: &DocumentId, expected: Option<&BsonDocument>) -> bool {
+        let Some(old) = self.col.cache.get(id) else {
+            return false;
+        };
+        if expected.is_some_and(|e| *e != old.data.0) {
+            return false;
+        }
+        let _ = self.col.cache.remove(id);
+        index_remove_all(&mut self.mgr, &old.data.0, id);
+        self.stage_deltas(&old.data.0, id, &DeltaOp::Remove);
+        telemetry::log_audit("delete", &self.col.name_str(), &id.0.to_string(), None);
+        self.ops.push(Operation::Delete { document_id: id.clone() });
+        true
+    }
+
+    /// Append the staged operations and index deltas to storage and release the locks.
+    ///
+    /// # Errors
+    /// Returns `DbError::Io` if storage rejects the append. The writes stay applied in memory
+    /// but are not durable.
+    pub fn commit(mut self) -> Result<(), DbError> {
+        self.append().map_err(|e| DbError::Io(e.to_string()))
+    }
+
+    /// Still under the index lock, so batches reach storage in the order they applied.
+    fn append(&mut self) -> std::io::Result<()> {
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+        let ops = std::mem::take(&mut self.ops);
+        let deltas = std::mem::take(&mut self.deltas);
+        self.col.storage.write().append_batch(&ops, deltas)
+    }
+
+    /// Stage one index delta per key `doc` holds in each ready index.
+    fn stage_deltas(&mut self, doc: &BsonDocument, id: &DocumentId, op: &DeltaOp) {
+        let collection = self.col.name_str();
+        for (field, idx) in &self.mgr.indexes {
+            if !idx.is_ready() || !idx.admits(doc) {
+                continue;
+            }
+            // Multikey documents emit one delta per element key, text indexes one per term and
+            // geo indexes one per point cell. Vector indexes are rebuilt from the documents and
+            // their saved graph instead.
+            let keys: Vec<DeltaKey> = match idx {
+                IndexImpl::Text(t) => t.terms(doc).into_iter().map(DeltaKey::Str).collect(),
+                IndexImpl::Vector(_) => Vec::new(),
+                IndexImpl::Geo(_) => geo_geometries(doc, field)
+                    .into_iter()
+                    .filter_map(|g| match g {
+                        Geometry::Point(p) => i64::try_from(geo_cell(p)).ok().map(DeltaKey::I64),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => index_key_values(doc, field).into_iter().filter_map(delta_key).collect(),
+            };
+            for k in keys {
+                self.deltas.push(IndexDelta {
+                    collection: collection.clone(),
+                    field: field.clone(),
+                    kind: idx.kind(),
+                    op: op.clone(),
+                    key: k,
+                    id: id.clone(),
+                });
+            }
+        }
+    }
+}
+
+impl Drop for WriteBatch<'_> {
+    /// Fallback for batches that were never committed, e.g. on an early return.
+    fn drop(&mut self) {
+        let n = self.ops.len();
+        if let Err(e) = self.append() {
+            log::error!("storage append({n} ops) failed: {e}");
+        }
+    }
+}
+
+fn delta_key(v: &bson::Bson) -> Option<DeltaKey> {
+    match v {
+        bson::Bson::String(s) => Some(DeltaKey::Str(s.clone())),
+        bson::Bson::Int32(i) => Some(DeltaKey::I64(i64::from(*i))),
+        bson::Bson::Int64(i) => Some(DeltaKey::I64(*i)),
+        bson::Bson::Double(f) => Some(DeltaKey::F64(*f)),
+        bson::Bson::Boolean(b) => Some(DeltaKey::Bool(*b)),
+        bson::Bson::Null => Some(DeltaKey::Null),
+        bson::Bson::DateTime(d) => Some(DeltaKey::DateTime(d.timestamp_millis())),
+        bson::Bson::ObjectId(o) => Some(DeltaKey::ObjectId(o.bytes())),
+        bson::Bson::Decimal128(d) => Some(DeltaKey::Decimal128(d.bytes())),
+        bson::Bson::Binary(b) => {
+            Some(DeltaKey::Binary { subtype: u8::from(b.subtype), bytes: b.bytes.clone() })
+        }
+        _ => None,
+    }
+}
@@ -1,7 +1,8 @@
 use crate::cache::Cache;
 use crate::index::IndexManager;
+use crate::query::{ParallelScan, TokenKey};
 use crate::wasp::StorageEngine;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use std::sync::Arc;
 
 pub struct Collection {
@@ -10,6 +11,12 @@ pub struct Collection {
     pub(crate) storage: Arc<RwLock<Box<dyn StorageEngine>>>,
     pub indexes: RwLock<IndexManager>,
     pub(crate) build_lock: RwLock<()>,
+    /// Serializes upserts so two of them cannot both miss and insert.
+    pub(crate) upsert_lock: Mutex<()>,
+    /// Signs `find` resume tokens; shared by every collection of a database.
+    pub(crate) token_key: Arc<TokenKey>,
+    /// How large scans are split across threads; shared by every collection of a database.
+    pub(crate) parallel: Arc<RwLock<ParallelScan>>,
 }
 
 impl Collection {
@@ -24,6 +31,9 @@ impl Collection {
             storage,
             indexes: RwLock::new(IndexManager::new()),
             build_lock: RwLock::new(()),
+            upsert_lock: Mutex::new(()),
+            token_key: Arc::new(TokenKey::ephemeral()),
+            parallel: Arc::new(RwLock::new(ParallelScan::default())),
         }
     }
 
@@ -38,9 +48,27 @@ impl Collection {
             storage,
             indexes: RwLock::new(IndexManager::new()),
             build_lock: RwLock::new(()),
+            upsert_lock: Mutex::new(()),
+            token_key: Arc::new(TokenKey::ephemeral()),
+            parallel: Arc::new(RwLock::new(ParallelScan::default())),
         }
     }
 
+    pub(crate) fn with_token_key(mut self, key: Arc<TokenKey>) -> Self {
+        self.token_key = key;
+        self
+    }
+
+    pub(crate) fn with_parallel_scan(mut self, parallel: Arc<RwLock<ParallelScan>>) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// How this collection's scans are split across threads.
+    pub fn parallel_scan(&self) -> ParallelScan {
+        *self.parallel.read()
+    }
+
     pub fn set_name(&self, new_name: String) {
         *self.name.write() = new_name;
     }
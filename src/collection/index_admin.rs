@@ -1,14 +1,55 @@
 use super::core::Collection;
 use crate::document::Document;
-use crate::index::{IndexImpl, IndexKind, index_insert_all};
+use crate::errors::DbError;
+use crate::index::{
+    IndexDescriptor, IndexImpl, IndexKind, IndexManager, IndexState, SideOp, UniqueConflict,
+};
+use crate::query::{HnswGraph, VectorStore};
 use crate::types::DocumentId;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// How many snapshot documents a background build scans between progress updates.
+const BUILD_PROGRESS_EVERY: usize = 1024;
 
 impl Collection {
     // --- Index admin helpers ---
     pub fn create_index(&self, field: &str, kind: IndexKind) {
+        // Non-unique builds cannot fail.
+        let _ = self.create_index_with(&IndexDescriptor::new(field, kind));
+    }
+
+    /// Build an index from a descriptor over the current documents and register it.
+    ///
+    /// # Errors
+    /// Returns `DbError::UniqueIndexConflicts` listing every duplicated key when a unique index
+    /// is requested over data that already violates it; the index is not registered. Returns
+    /// `DbError::QueryError` for a unique text index or a second text index, and for a unique
+    /// vector index or one with invalid options.
+    pub fn create_index_with(&self, desc: &IndexDescriptor) -> Result<(), DbError> {
+        self.build_index(desc, IndexImpl::from_descriptor(desc))
+    }
+
+    /// Rebuild a vector index from `desc`, starting from an HNSW graph persisted by an earlier
+    /// session when it was built with the same options. Documents whose vectors the graph
+    /// already holds are not linked again, and nodes left without a document are dropped.
+    pub(crate) fn restore_vector_index(
+        &self,
+        desc: &IndexDescriptor,
+        graph: HnswGraph,
+    ) -> Result<(), DbError> {
+        let mut idx = IndexImpl::from_descriptor(desc);
+        if let (IndexImpl::Vector(v), Some(opts)) = (&mut idx, &desc.vector)
+            && graph.fits(opts)
+        {
+            v.store = VectorStore::Hnsw(Box::new(graph.restored()));
+        }
+        self.build_index(desc, idx)
+    }
+
+    fn build_index(&self, desc: &IndexDescriptor, mut idx: IndexImpl) -> Result<(), DbError> {
         let _wguard = self.build_lock.write();
-        let mut mgr = self.indexes.write();
-        mgr.create_index(field, kind);
+        self.indexes.read().validate(desc)?;
         // offline build: rebuild from current cache
         let start = std::time::Instant::now();
         let ids_docs: Vec<(DocumentId, Document)> = {
@@ -16,17 +57,138 @@ impl Collection {
             let store = cache.store.read();
             store.iter().map(|(id, doc)| (id.clone(), doc.clone())).collect()
         };
+        let mut conflicts: BTreeMap<String, Vec<DocumentId>> = BTreeMap::new();
         for (id, doc) in ids_docs {
-            index_insert_all(&mut mgr, &doc.data.0, &id);
+            if desc.unique
+                && let Some((key, holder)) = idx.find_conflict(&doc.data.0, &id)
+            {
+                let ids = conflicts.entry(key).or_insert_with(|| vec![holder]);
+                ids.push(id);
+                continue;
+            }
+            idx.insert(&doc.data.0, &id);
+        }
+        if let IndexImpl::Vector(v) = &mut idx {
+            v.prune_unconfirmed();
+        }
+        if !conflicts.is_empty() {
+            return Err(DbError::UniqueIndexConflicts {
+                field: desc.field.clone(),
+                conflicts: conflicts
+                    .into_iter()
+                    .map(|(key, ids)| UniqueConflict { key, ids })
+                    .collect(),
+            });
         }
         // record build time on the created index only
-        if let Some(idx) = mgr.indexes.get_mut(field) {
-            let elapsed = start.elapsed().as_millis();
-            match idx {
-                IndexImpl::Hash(h) => h.stats.build_time_ms = elapsed,
-                IndexImpl::BTree(b) => b.stats.build_time_ms = elapsed,
+        idx.stats_mut().build_time_ms = start.elapsed().as_millis();
+        let mut mgr = self.indexes.write();
+        mgr.side_buffers.remove(&desc.field);
+        mgr.indexes.insert(desc.field.clone(), idx);
+        Ok(())
+    }
+
+    /// Build an index on a background thread without blocking writers.
+    ///
+    /// The index is registered immediately in `IndexState::Building` and ignored by the
+    /// planner. The builder scans a snapshot of the collection while concurrent writes are
+    /// captured in a side buffer, then replays the buffer and marks the index ready. Progress
+    /// is reported through the index's `IndexStats`.
+    ///
+    /// # Errors
+    /// The returned handle yields `DbError::UniqueIndexConflicts` when a unique index cannot be
+    /// built (the index is left in `IndexState::Failed`), or `DbError::QueryError` when the
+    /// descriptor is invalid (see [`IndexManager::validate`](crate::index::IndexManager::validate)),
+    /// the field already has a ready or building index (drop it first; a failed one is
+    /// replaced), or the index was dropped before the build finished.
+    pub fn create_index_background(
+        self: &Arc<Self>,
+        desc: &IndexDescriptor,
+    ) -> std::thread::JoinHandle<Result<(), DbError>> {
+        // Registering the placeholder and taking the snapshot under the index lock means every
+        // write is either in the snapshot or in the side buffer, never both.
+        let (build, snapshot): (u64, Vec<(DocumentId, Document)>) = {
+            let mut mgr = self.indexes.write();
+            if let Err(e) = mgr.validate(desc).and_then(|()| check_not_indexed(&mgr, &desc.field)) {
+                return std::thread::spawn(move || Err(e));
+            }
+            let cache = self.cache.clone();
+            let store = cache.store.read();
+            let docs: Vec<_> = store.iter().map(|(id, doc)| (id.clone(), doc.clone())).collect();
+            let build = mgr.begin_build(desc, docs.len());
+            (build, docs)
+        };
+        let col = Arc::clone(self);
+        let desc = desc.clone();
+        std::thread::spawn(move || {
+            let res = col.run_background_build(&desc, build, snapshot);
+            if let Err(e) = &res {
+                log::warn!("background index build on '{}' failed: {e}", desc.field);
+            }
+            res
+        })
+    }
+
+    fn run_background_build(
+        &self,
+        desc: &IndexDescriptor,
+        build: u64,
+        snapshot: Vec<(DocumentId, Document)>,
+    ) -> Result<(), DbError> {
+        let start = std::time::Instant::now();
+        let mut idx = IndexImpl::from_descriptor(desc);
+        for (n, (id, doc)) in snapshot.iter().enumerate() {
+            idx.insert(&doc.data.0, id);
+            if (n + 1) % BUILD_PROGRESS_EVERY == 0
+                && !self.update_build_progress(&desc.field, build, n + 1)
+            {
+                return Err(build_abandoned(&desc.field));
             }
         }
+        let mut mgr = self.indexes.write();
+        // A drop followed by a new build hands the field to another token: leave its buffer
+        if !mgr.owns_build(&desc.field, build) {
+            return Err(build_abandoned(&desc.field));
+        }
+        let Some(buffer) = mgr.side_buffers.remove(&desc.field) else {
+            return Err(build_abandoned(&desc.field));
+        };
+        for op in buffer.ops {
+            match op {
+                SideOp::Insert(doc, id) => idx.insert(&doc, &id),
+                SideOp::Remove(doc, id) => idx.remove(&doc, &id),
+            }
+        }
+        let Some(slot) = mgr.indexes.get_mut(&desc.field) else {
+            return Err(build_abandoned(&desc.field));
+        };
+        if desc.unique {
+            let conflicts = idx.duplicate_keys();
+            if !conflicts.is_empty() {
+                let err = DbError::UniqueIndexConflicts { field: desc.field.clone(), conflicts };
+                slot.stats_mut().state = IndexState::Failed(err.to_string());
+                return Err(err);
+            }
+        }
+        let stats = idx.stats_mut();
+        stats.build_time_ms = start.elapsed().as_millis();
+        stats.build_total = snapshot.len();
+        stats.build_scanned = snapshot.len();
+        *slot = idx;
+        Ok(())
+    }
+
+    /// Record scan progress; returns false if the build's placeholder is gone or belongs to
+    /// another build.
+    fn update_build_progress(&self, field: &str, build: u64, scanned: usize) -> bool {
+        let mut mgr = self.indexes.write();
+        if !mgr.owns_build(field, build) {
+            return false;
+        }
+        mgr.indexes.get_mut(field).is_some_and(|i| {
+            i.stats_mut().build_scanned = scanned;
+            true
+        })
     }
 
     pub fn drop_index(&self, field: &str) {
@@ -34,3 +196,21 @@ impl Collection {
         self.indexes.write().drop_index(field);
     }
 }
+
+/// A background build may only replace a failed index: a ready one stays in service and a
+/// building one keeps its side buffer.
+fn check_not_indexed(mgr: &IndexManager, field: &str) -> Result<(), DbError> {
+    match mgr.indexes.get(field).map(|i| &i.stats().state) {
+        Some(IndexState::Ready) => {
+            Err(DbError::QueryError(format!("an index on '{field}' already exists")))
+        }
+        Some(IndexState::Building) => {
+            Err(DbError::QueryError(format!("an index on '{field}' is already being built")))
+        }
+        Some(IndexState::Failed(_)) | None => Ok(()),
+    }
+}
+
+fn build_abandoned(field: &str) -> DbError {
+    DbError::QueryError(format!("index build on '{field}' was cancelled"))
+}
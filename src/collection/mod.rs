@@ -1,5 +1,7 @@
+mod batch;
 mod core;
 mod index_admin;
 mod ops;
 
+pub use batch::WriteBatch;
 pub use core::Collection;
@@ -1,194 +1,121 @@
 use super::core::Collection;
 use crate::document::Document;
-use crate::index::{IndexImpl, index_insert_all, index_remove_all};
-use crate::telemetry;
-use crate::types::{DocumentId, Operation};
-use crate::wasp::{DeltaKey, DeltaOp, IndexDelta};
+use crate::errors::DbError;
+use crate::types::DocumentId;
+use crate::wasp::IndexDelta;
+use bson::Document as BsonDocument;
 
 impl Collection {
-    pub fn insert_document(&self, document: Document) -> DocumentId {
-        let _guard = self.build_lock.read();
-        let doc_id = document.id.clone();
-        // First, persist operation
-        let operation = Operation::Insert { document: document.clone() };
-        let res = {
-            let mut st = self.storage.write();
-            st.append(&operation)
-        };
-        if let Err(e) = res {
-            log::error!("storage append(insert) failed: {e}");
-        }
-        // Then apply to cache and indexes
-        self.cache.insert(document.clone());
-        telemetry::log_audit("insert", &self.name_str(), &doc_id.0.to_string(), None);
-        index_insert_all(&mut self.indexes.write(), &document.data.0, &doc_id);
-        // Emit index deltas for WASP overlay
-        let mut st = self.storage.write();
-        for (field, idx) in &self.indexes.read().indexes {
-            if let Some(v) = document.data.0.get(field) {
-                let key = match v {
-                    bson::Bson::String(s) => Some(DeltaKey::Str(s.clone())),
-                    bson::Bson::Int32(i) => Some(DeltaKey::I64(i64::from(*i))),
-                    bson::Bson::Int64(i) => Some(DeltaKey::I64(*i)),
-                    bson::Bson::Double(f) => Some(DeltaKey::F64(*f)),
-                    bson::Bson::Boolean(b) => Some(DeltaKey::Bool(*b)),
-                    _ => None,
-                };
-                if let Some(k) = key {
-                    let kind = match idx {
-                        IndexImpl::Hash(_) => crate::index::IndexKind::Hash,
-                        IndexImpl::BTree(_) => crate::index::IndexKind::BTree,
-                    };
-                    let delta = IndexDelta {
-                        collection: self.name_str(),
-                        field: field.clone(),
-                        kind,
-                        op: DeltaOp::Add,
-                        key: k,
-                        id: doc_id.clone(),
-                    };
-                    let _ = st.append_index_delta(delta);
-                }
-            }
-        }
-        doc_id
+    /// Insert a document, logging (rather than returning) unique-index violations and storage
+    /// failures. Returns `None` when the document was rejected.
+    ///
+    /// Use [`Collection::try_insert_document`] to observe the error itself.
+    pub fn insert_document(&self, document: Document) -> Option<DocumentId> {
+        self.try_insert_document(document)
+            .inspect_err(|e| log::error!("insert rejected: {e}"))
+            .ok()
+    }
+
+    /// Insert a document, enforcing unique indexes.
+    ///
+    /// # Errors
+    /// Returns `DbError::DuplicateKey` if the document would violate a unique index, or
+    /// `DbError::Io` if storage rejects the write.
+    pub fn try_insert_document(&self, document: Document) -> Result<DocumentId, DbError> {
+        // The batch holds the index lock across the uniqueness check and the write so that
+        // two concurrent inserts cannot both claim the same unique key.
+        let mut batch = self.write_batch();
+        let id = batch.insert(document)?;
+        batch.commit()?;
+        Ok(id)
     }
 
     pub fn find_document(&self, id: &DocumentId) -> Option<Document> {
         self.cache.get(id)
     }
 
+    /// Replace a document, logging (rather than returning) unique-index violations.
     pub fn update_document(&self, id: &DocumentId, new_document: Document) -> bool {
-        let _guard = self.build_lock.read();
-        if let Some(old) = self.cache.get(id) {
-            let mut new_doc_same_id = new_document;
-            new_doc_same_id.id = id.clone();
-            // Persist update first
-            let operation = Operation::Update {
-                document_id: id.clone(),
-                new_document: new_doc_same_id.clone(),
-            };
-            let res = {
-                let mut st = self.storage.write();
-                st.append(&operation)
-            };
-            if let Err(e) = res {
-                log::error!("storage append(update) failed: {e}");
-            }
-            // Then mutate cache and indexes
-            index_remove_all(&mut self.indexes.write(), &old.data.0, id);
-            self.cache.insert(new_doc_same_id.clone());
-            index_insert_all(&mut self.indexes.write(), &new_doc_same_id.data.0, id);
-            telemetry::log_audit("update", &self.name_str(), &id.0.to_string(), None);
-            // Emit deltas
-            let mut st = self.storage.write();
-            for (field, idx) in &self.indexes.read().indexes {
-                if let Some(v) = old.data.0.get(field) {
-                    let key = match v {
-                        bson::Bson::String(s) => Some(DeltaKey::Str(s.clone())),
-                        bson::Bson::Int32(i) => Some(DeltaKey::I64(i64::from(*i))),
-                        bson::Bson::Int64(i) => Some(DeltaKey::I64(*i)),
-                        bson::Bson::Double(f) => Some(DeltaKey::F64(*f)),
-                        bson::Bson::Boolean(b) => Some(DeltaKey::Bool(*b)),
-                        _ => None,
-                    };
-                    if let Some(k) = key {
-                        let kind = match idx {
-                            IndexImpl::Hash(_) => crate::index::IndexKind::Hash,
-                            IndexImpl::BTree(_) => crate::index::IndexKind::BTree,
-                        };
-                        let delta = IndexDelta {
-                            collection: self.name_str(),
-                            field: field.clone(),
-                            kind,
-                            op: DeltaOp::Remove,
-                            key: k,
-                            id: id.clone(),
-                        };
-                        let _ = st.append_index_delta(delta);
-                    }
-                }
-                if let Some(v) = new_doc_same_id.data.0.get(field) {
-                    let key = match v {
-                        bson::Bson::String(s) => Some(DeltaKey::Str(s.clone())),
-                        bson::Bson::Int32(i) => Some(DeltaKey::I64(i64::from(*i))),
-                        bson::Bson::Int64(i) => Some(DeltaKey::I64(*i)),
-                        bson::Bson::Double(f) => Some(DeltaKey::F64(*f)),
-                        bson::Bson::Boolean(b) => Some(DeltaKey::Bool(*b)),
-                        _ => None,
-                    };
-                    if let Some(k) = key {
-                        let kind = match idx {
-                            IndexImpl::Hash(_) => crate::index::IndexKind::Hash,
-                            IndexImpl::BTree(_) => crate::index::IndexKind::BTree,
-                        };
-                        let delta = IndexDelta {
-                            collection: self.name_str(),
-                            field: field.clone(),
-                            kind,
-                            op: DeltaOp::Add,
-                            key: k,
-                            id: id.clone(),
-                        };
-                        let _ = st.append_index_delta(delta);
-                    }
-                }
-            }
-            true
-        } else {
+        self.try_update_document(id, new_document).unwrap_or_else(|e| {
+            log::error!("update rejected: {e}");
             false
-        }
+        })
+    }
+
+    /// Replace a document, enforcing unique indexes. Returns `Ok(false)` if `id` is unknown.
+    ///
+    /// # Errors
+    /// Returns `DbError::DuplicateKey` if the new document would violate a unique index, or
+    /// `DbError::Io` if storage rejects the write.
+    pub fn try_update_document(
+        &self,
+        id: &DocumentId,
+        new_document: Document,
+    ) -> Result<bool, DbError> {
+        self.replace_checked(id, new_document, None)
+    }
+
+    /// Like [`Collection::try_update_document`], but only if the document's current contents
+    /// equal `expected`; returns `Ok(false)` when another writer changed it first.
+    ///
+    /// # Errors
+    /// As for [`Collection::try_update_document`].
+    pub fn try_update_document_if(
+        &self,
+        id: &DocumentId,
+        expected: &BsonDocument,
+        new_document: Document,
+    ) -> Result<bool, DbError> {
+        self.replace_checked(id, new_document, Some(expected))
+    }
+
+    fn replace_checked(
+        &self,
+        id: &DocumentId,
+        new_document: Document,
+        expected: Option<&BsonDocument>,
+    ) -> Result<bool, DbError> {
+        // The index lock also orders the `expected` check against every other writer
+        let mut batch = self.write_batch();
+        let replaced = batch.replace(id, new_document, expected)?;
+        batch.commit()?;
+        Ok(replaced)
     }
 
+    /// Delete a document, logging (rather than returning) storage failures.
     pub fn delete_document(&self, id: &DocumentId) -> bool {
-        let _guard = self.build_lock.read();
-        if let Some(old) = self.cache.get(id) {
-            // Persist delete first
-            let operation = Operation::Delete { document_id: id.clone() };
-            let res = {
-                let mut st = self.storage.write();
-                st.append(&operation)
-            };
-            if let Err(e) = res {
-                log::error!("storage append(delete) failed: {e}");
-            }
-            // Then remove from cache and indexes
-            let _ = self.cache.remove(id);
-            index_remove_all(&mut self.indexes.write(), &old.data.0, id);
-            telemetry::log_audit("delete", &self.name_str(), &id.0.to_string(), None);
-            // Emit remove deltas
-            let mut st = self.storage.write();
-            for (field, idx) in &self.indexes.read().indexes {
-                if let Some(v) = old.data.0.get(field) {
-                    let key = match v {
-                        bson::Bson::String(s) => Some(DeltaKey::Str(s.clone())),
-                        bson::Bson::Int32(i) => Some(DeltaKey::I64(i64::from(*i))),
-                        bson::Bson::Int64(i) => Some(DeltaKey::I64(*i)),
-                        bson::Bson::Double(f) => Some(DeltaKey::F64(*f)),
-                        bson::Bson::Boolean(b) => Some(DeltaKey::Bool(*b)),
-                        _ => None,
-                    };
-                    if let Some(k) = key {
-                        let kind = match idx {
-                            IndexImpl::Hash(_) => crate::index::IndexKind::Hash,
-                            IndexImpl::BTree(_) => crate::index::IndexKind::BTree,
-                        };
-                        let delta = IndexDelta {
-                            collection: self.name_str(),
-                            field: field.clone(),
-                            kind,
-                            op: DeltaOp::Remove,
-                            key: k,
-                            id: id.clone(),
-                        };
-                        let _ = st.append_index_delta(delta);
-                    }
-                }
-            }
-            true
-        } else {
+        self.try_delete_document(id).unwrap_or_else(|e| {
+            log::error!("delete failed: {e}");
             false
-        }
+        })
+    }
+
+    /// Delete a document. Returns `Ok(false)` if `id` is unknown.
+    ///
+    /// # Errors
+    /// Returns `DbError::Io` if storage rejects the write.
+    pub fn try_delete_document(&self, id: &DocumentId) -> Result<bool, DbError> {
+        self.delete_checked(id, None)
+    }
+
+    /// Delete a document only if its current contents equal `expected`, logging storage
+    /// failures.
+    pub fn delete_document_if(&self, id: &DocumentId, expected: &BsonDocument) -> bool {
+        self.delete_checked(id, Some(expected)).unwrap_or_else(|e| {
+            log::error!("delete failed: {e}");
+            false
+        })
+    }
+
+    fn delete_checked(
+        &self,
+        id: &DocumentId,
+        expected: Option<&BsonDocument>,
+    ) -> Result<bool, DbError> {
+        let mut batch = self.write_batch();
+        let deleted = batch.delete(id, expected);
+        batch.commit()?;
+        Ok(deleted)
     }
 
     pub fn get_all_documents(&self) -> Vec<Document> {
@@ -1,7 +1,8 @@
 use crate::cache::CacheConfig;
 use crate::collection::Collection;
 use crate::document::DocumentType;
-use crate::index::{INDEX_METADATA_VERSION, IndexDescriptor, IndexKind};
+use crate::index::{INDEX_METADATA_VERSION, IndexDescriptor, IndexImpl, IndexKind};
+use crate::query::{HnswGraph, ParallelScan, TokenKey, VectorStore};
 use crate::wasp::{StorageEngine, Wasp};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
@@ -16,6 +17,8 @@ pub struct Engine {
     pub collections: RwLock<HashMap<String, Arc<Collection>>>,
     pub storage: Arc<RwLock<Box<dyn StorageEngine>>>,
     metadata_path: PathBuf,
+    token_key: Arc<TokenKey>,
+    parallel: Arc<RwLock<ParallelScan>>,
 }
 
 impl Engine {
@@ -29,12 +32,20 @@ impl Engine {
         }
     }
 
+    /// Resume tokens are signed with a key kept next to the WASP file.
+    fn token_key_path(wasp_path: &std::path::Path) -> PathBuf {
+        let mut p = wasp_path.as_os_str().to_owned();
+        p.push(".pagekey");
+        PathBuf::from(p)
+    }
+
     /// Create a new Engine backed by the WASP storage engine.
     ///
     /// # Errors
     /// Returns an error if the underlying storage engine fails to initialize.
     pub fn new(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
         // Kept for API compatibility: `new` now constructs a WASP-backed engine.
+        let token_key = Arc::new(TokenKey::persistent(Self::token_key_path(&path)));
         let wasp = Wasp::new(path)?;
         // Resolve and cache metadata path at engine creation to avoid env var races in tests
         let metadata_path = Self::resolve_metadata_path();
@@ -42,6 +53,8 @@ impl Engine {
             collections: RwLock::new(HashMap::new()),
             storage: Arc::new(RwLock::new(Box::new(wasp))),
             metadata_path,
+            token_key,
+            parallel: Arc::new(RwLock::new(ParallelScan::default())),
         };
         // Initialize ephemeral handling
         let __bench_start = std::time::Instant::now();
@@ -57,8 +70,11 @@ impl Engine {
     }
 
     pub fn create_collection(&self, name: String) -> Arc<Collection> {
-        let collection =
-            Arc::new(Collection::new(name.clone(), self.storage.clone(), DEFAULT_CACHE_CAPACITY));
+        let collection = Arc::new(
+            Collection::new(name.clone(), self.storage.clone(), DEFAULT_CACHE_CAPACITY)
+                .with_token_key(self.token_key.clone())
+                .with_parallel_scan(self.parallel.clone()),
+        );
         self.collections.write().insert(name, collection.clone());
         // Attempt to rebuild indexes for this collection if metadata exists
         self.load_collection_indexes(&collection);
@@ -71,12 +87,26 @@ impl Engine {
         config: CacheConfig,
     ) -> Arc<Collection> {
         let mut collections = self.collections.write();
-        let collection =
-            Arc::new(Collection::new_with_config(name.clone(), self.storage.clone(), config));
+        let collection = Arc::new(
+            Collection::new_with_config(name.clone(), self.storage.clone(), config)
+                .with_token_key(self.token_key.clone())
+                .with_parallel_scan(self.parallel.clone()),
+        );
         collections.insert(name, collection.clone());
         collection
     }
 
+    /// How scans are split across threads in every collection of this engine.
+    pub fn parallel_scan(&self) -> ParallelScan {
+        *self.parallel.read()
+    }
+
+    /// Change how scans are split across threads; applies to every collection, including
+    /// ones already open.
+    pub fn set_parallel_scan(&self, config: ParallelScan) {
+        *self.parallel.write() = config;
+    }
+
     pub fn get_collection(&self, name: &str) -> Option<Arc<Collection>> {
         self.collections.read().get(name).cloned()
     }
@@ -128,6 +158,7 @@ impl Engine {
     /// # Errors
     /// Returns an error if the storage engine fails to initialize or if ephemeral cache setup fails.
     pub fn with_wasp(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        let token_key = Arc::new(TokenKey::persistent(Self::token_key_path(&path)));
         let wasp = Wasp::new(path)?;
         // Resolve and cache metadata path at engine creation to avoid env var races in tests
         let metadata_path = Self::resolve_metadata_path();
@@ -135,6 +166,8 @@ impl Engine {
             collections: RwLock::new(HashMap::new()),
             storage: Arc::new(RwLock::new(Box::new(wasp))),
             metadata_path,
+            token_key,
+            parallel: Arc::new(RwLock::new(ParallelScan::default())),
         };
         // Initialize ephemeral handling
         let __bench_start = std::time::Instant::now();
@@ -176,9 +209,12 @@ impl Engine {
                 for (col_name, descs) in meta.collections.clone() {
                     let col = self
                         .get_collection(&col_name)
-                        .map_or_else(|| self.create_collection(col_name.clone()), |c| c);
+                        .unwrap_or_else(|| self.create_collection(col_name.clone()));
+                    let mut graphs = meta.graphs.get(&col_name).cloned().unwrap_or_default();
                     for d in descs {
-                        col.create_index(&d.field, d.kind);
+                        if let Err(e) = Self::rebuild_index(&col, &d, &mut graphs) {
+                            log::warn!("index rebuild skipped: {e}");
+                        }
                     }
                 }
                 if meta.version != INDEX_METADATA_VERSION {
@@ -204,7 +240,21 @@ impl Engine {
                                     "BTree" | "btree" | "Btree" => IndexKind::BTree,
                                     _ => IndexKind::Hash,
                                 };
-                                v.push(IndexDescriptor { field, kind });
+                                let unique = it
+                                    .get("unique")
+                                    .and_then(serde_json::Value::as_bool)
+                                    .unwrap_or(false);
+                                let sparse = it
+                                    .get("sparse")
+                                    .and_then(serde_json::Value::as_bool)
+                                    .unwrap_or(false);
+                                let mut d = IndexDescriptor::new(&field, kind)
+                                    .with_unique(unique)
+                                    .with_sparse(sparse);
+                                d.partial_filter = it
+                                    .get("partial_filter")
+                                    .and_then(|p| serde_json::from_value(p.clone()).ok());
+                                v.push(d);
                             }
                             collections.insert(cname.clone(), v);
                         }
@@ -213,12 +263,18 @@ impl Engine {
                 for (col_name, descs) in collections.clone() {
                     let col = self
                         .get_collection(&col_name)
-                        .map_or_else(|| self.create_collection(col_name.clone()), |c| c);
+                        .unwrap_or_else(|| self.create_collection(col_name.clone()));
                     for d in descs {
-                        col.create_index(&d.field, d.kind);
+                        if let Err(e) = col.create_index_with(&d) {
+                            log::warn!("index rebuild skipped: {e}");
+                        }
                     }
                 }
-                let meta = IndexesMetadata { version: INDEX_METADATA_VERSION, collections };
+                let meta = IndexesMetadata {
+                    version: INDEX_METADATA_VERSION,
+                    collections,
+                    graphs: HashMap::new(),
+                };
                 let _ = fs::write(&path, serde_json::to_vec_pretty(&meta).unwrap_or_default());
             }
         }
@@ -228,32 +284,66 @@ impl Engine {
     /// Returns an error if writing index metadata to disk fails.
     pub fn save_indexes_metadata(&self) -> std::io::Result<()> {
         let mut collections_meta: HashMap<String, Vec<IndexDescriptor>> = HashMap::new();
+        let mut graphs: HashMap<String, HashMap<String, HnswGraph>> = HashMap::new();
         for (name, col) in self.collections.read().iter() {
             let mgr = col.indexes.read();
             collections_meta.insert(name.clone(), mgr.descriptors());
+            let col_graphs: HashMap<String, HnswGraph> = mgr
+                .indexes
+                .iter()
+                .filter(|(_, i)| i.is_ready())
+                .filter_map(|(f, i)| match i {
+                    IndexImpl::Vector(v) => match &v.store {
+                        VectorStore::Hnsw(g) => Some((f.clone(), g.as_ref().clone())),
+                        VectorStore::Flat(_) => None,
+                    },
+                    _ => None,
+                })
+                .collect();
+            if !col_graphs.is_empty() {
+                graphs.insert(name.clone(), col_graphs);
+            }
         }
-        let meta =
-            IndexesMetadata { version: INDEX_METADATA_VERSION, collections: collections_meta };
+        let meta = IndexesMetadata {
+            version: INDEX_METADATA_VERSION,
+            collections: collections_meta,
+            graphs,
+        };
         fs::write(self.indexes_meta_path(), serde_json::to_vec_pretty(&meta).unwrap_or_default())
     }
 
     fn load_collection_indexes(&self, col: &Arc<Collection>) {
         let path = self.indexes_meta_path();
         if let Ok(bytes) = fs::read(&path)
-            && let Ok(meta) = serde_json::from_slice::<IndexesMetadata>(&bytes)
+            && let Ok(mut meta) = serde_json::from_slice::<IndexesMetadata>(&bytes)
         {
             if meta.version != INDEX_METADATA_VERSION {
                 return;
             }
             let name = col.name_str();
+            let mut graphs = meta.graphs.remove(&name).unwrap_or_default();
             if let Some(descs) = meta.collections.get(&name) {
                 for d in descs {
-                    col.create_index(&d.field, d.kind);
+                    if let Err(e) = Self::rebuild_index(col, d, &mut graphs) {
+                        log::warn!("index rebuild skipped: {e}");
+                    }
                 }
             }
         }
     }
 
+    /// Rebuild one persisted index, reusing its saved HNSW graph if it has one.
+    fn rebuild_index(
+        col: &Collection,
+        desc: &IndexDescriptor,
+        graphs: &mut HashMap<String, HnswGraph>,
+    ) -> Result<(), crate::errors::DbError> {
+        match graphs.remove(&desc.field) {
+            Some(g) if desc.kind == IndexKind::Vector => col.restore_vector_index(desc, g),
+            _ => col.create_index_with(desc),
+        }
+    }
+
     /// Persist a checkpoint of data and index metadata into the main DB file when using WASP.
     /// # Errors
     /// Returns an error if the underlying storage engine checkpoint fails.
@@ -273,4 +363,7 @@ impl Engine {
 struct IndexesMetadata {
     version: u32,
     collections: HashMap<String, Vec<IndexDescriptor>>,
+    /// HNSW graphs of vector indexes, by collection and field.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    graphs: HashMap<String, HashMap<String, HnswGraph>>,
 }
@@ -1,88 +1,302 @@
+use crate::errors::DbError;
+use crate::query::{
+    Collation, Filter, Geometry, TextAnalyzer, TextOptions, VectorOptions, VectorStore, geo_cell,
+    geo_geometries,
+};
 use crate::types::DocumentId;
 use bson::{Bson, Document as BsonDocument};
-use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
-use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum IndexKind {
     Hash,
     BTree,
-    // Vector, // TODO: enable when vector index is implemented
+    /// Inverted index of the words in one or more string fields, for `$text` queries.
+    Text,
+    /// Fixed-dimension numeric arrays, for k-nearest-neighbour vector search.
+    Vector,
+    /// Locations keyed by z-order cell, for `$geoWithin`, `$geoIntersects` and `$near`.
+    Geo,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct IndexStats {
     pub keys: usize,
     pub entries: usize,
+    /// Documents holding at least one entry; `entries / docs` is the multikey fan-out.
+    pub docs: usize,
+    /// Set once any document contributed an array of keys.
+    pub multikey: bool,
     pub hits: u64,
     pub misses: u64,
     pub build_time_ms: u128,
+    /// Lifecycle of the index; only `Ready` indexes serve queries.
+    pub state: IndexState,
+    /// Documents scanned so far by a background build, out of `build_total`.
+    pub build_scanned: usize,
+    pub build_total: usize,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// Build lifecycle of an index.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexState {
+    /// A background build is scanning the collection; writes are buffered.
+    Building,
+    #[default]
+    Ready,
+    /// The background build stopped with this error; the index holds no data.
+    Failed(String),
+}
+
+impl IndexStats {
+    /// Fraction of the background build's snapshot scanned so far (1.0 once ready).
+    #[must_use]
+    pub fn progress(&self) -> f64 {
+        match self.state {
+            IndexState::Ready => 1.0,
+            _ if self.build_total == 0 => 0.0,
+            _ => self.build_scanned as f64 / self.build_total as f64,
+        }
+    }
+    /// Average number of entries per indexed document (1.0 for a single-key index).
+    #[must_use]
+    pub fn fanout(&self) -> f64 {
+        if self.docs == 0 { 0.0 } else { self.entries as f64 / self.docs as f64 }
+    }
+}
+
+/// An index key. The derived order matches `eval::compare_bson`: `Null < numbers < Str <
+/// Binary < ObjectId < Bool < DateTime`, with every numeric type compared by value. A
+/// collated index keys strings by their collation sort key (`Collated`) instead of `Str`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum IndexKeyKind {
+    Null,
+    Num(NumKey),
     Str(String),
-    F64(OrderedFloat<f64>),
-    I64(i64),
+    Collated(Vec<u8>),
+    Binary(u8, Vec<u8>),
+    ObjectId([u8; 12]),
     Bool(bool),
+    DateTime(i64),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct EqKey(IndexKeyKind);
+impl IndexKeyKind {
+    /// The BSON value this key was derived from (numbers keep their original type). A
+    /// collated key only has its sort key, returned as generic binary.
+    #[must_use]
+    pub fn to_bson(&self) -> Bson {
+        match self {
+            Self::Null => Bson::Null,
+            Self::Bool(b) => Bson::Boolean(*b),
+            Self::Num(n) => n.exact.to_bson(),
+            Self::Str(s) => Bson::String(s.clone()),
+            Self::Collated(key) => Bson::Binary(bson::Binary {
+                subtype: bson::spec::BinarySubtype::Generic,
+                bytes: key.clone(),
+            }),
+            Self::Binary(subtype, bytes) => Bson::Binary(bson::Binary {
+                subtype: bson::spec::BinarySubtype::from(*subtype),
+                bytes: bytes.clone(),
+            }),
+            Self::ObjectId(b) => Bson::ObjectId(bson::oid::ObjectId::from_bytes(*b)),
+            Self::DateTime(ms) => Bson::DateTime(bson::DateTime::from_millis(*ms)),
+        }
+    }
+}
 
-impl Hash for EqKey {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        match &self.0 {
-            IndexKeyKind::Str(s) => {
-                0u8.hash(state);
-                s.hash(state);
-            }
-            IndexKeyKind::F64(f) => {
-                1u8.hash(state);
-                f.hash(state);
-            }
-            IndexKeyKind::I64(i) => {
-                2u8.hash(state);
-                i.hash(state);
-            }
-            IndexKeyKind::Bool(b) => {
-                3u8.hash(state);
-                b.hash(state);
-            }
+/// A numeric key, ordered by its `f64` approximation, then by its exact value (as
+/// `compare_bson` compares numbers), then by representation, so distinct numbers that share
+/// an approximation sort correctly and equal values of different types stay distinct keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NumKey {
+    approx: i64,
+    exact: NumExact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum NumExact {
+    /// Sorts before every number with the same approximation (range bounds only).
+    Min,
+    I64(i64),
+    F64(u64),
+    Decimal128([u8; 16]),
+    /// Sorts after every number with the same approximation (range bounds only).
+    Max,
+}
+
+impl Ord for NumKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+        self.approx.cmp(&other.approx).then_with(|| {
+            let by_value = match (self.exact, other.exact) {
+                (NumExact::Min, NumExact::Min) | (NumExact::Max, NumExact::Max) => Ordering::Equal,
+                (NumExact::Min, _) | (_, NumExact::Max) => Ordering::Less,
+                (_, NumExact::Min) | (NumExact::Max, _) => Ordering::Greater,
+                (a, b) => crate::query::compare_numbers(&a.to_bson(), &b.to_bson())
+                    .unwrap_or(Ordering::Equal),
+            };
+            by_value.then_with(|| self.exact.cmp(&other.exact))
+        })
+    }
+}
+
+impl PartialOrd for NumKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl NumExact {
+    fn to_bson(self) -> Bson {
+        match self {
+            Self::I64(i) => Bson::Int64(i),
+            Self::F64(bits) => Bson::Double(f64::from_bits(bits)),
+            Self::Decimal128(b) => Bson::Decimal128(bson::Decimal128::from_bytes(b)),
+            Self::Min | Self::Max => Bson::Null,
+        }
+    }
+}
+
+impl NumKey {
+    fn new(approx: f64, exact: NumExact) -> Self {
+        // NaN sorts before every other number, as in `compare_bson`.
+        if approx.is_nan() {
+            return Self { approx: i64::MIN, exact };
         }
+        let approx = if approx == 0.0 { 0.0 } else { approx };
+        // Map the IEEE bits onto an i64 whose integer order is `f64::total_cmp`.
+        let bits = approx.to_bits() as i64;
+        Self { approx: bits ^ ((((bits >> 63) as u64) >> 1) as i64), exact }
+    }
+    /// The same value with its exact component replaced by a range sentinel.
+    const fn with_exact(self, exact: NumExact) -> Self {
+        Self { approx: self.approx, exact }
     }
 }
 
+/// Whether `start..end` selects nothing; `BTreeMap::range` panics on such bounds.
+fn empty_range(start: &std::ops::Bound<OrdKey>, end: &std::ops::Bound<OrdKey>) -> bool {
+    use std::ops::Bound::{Excluded, Included};
+    match (start, end) {
+        (Included(s), Included(e)) => s > e,
+        (Included(s) | Excluded(s), Included(e) | Excluded(e)) => s >= e,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EqKey(IndexKeyKind);
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct OrdKey(IndexKeyKind);
 
 #[must_use]
 pub fn key_from_bson(v: &Bson) -> Option<IndexKeyKind> {
     match v {
-        Bson::String(s) => Some(IndexKeyKind::Str(s.clone())),
-        Bson::Int32(i) => Some(IndexKeyKind::I64(i64::from(*i))),
-        Bson::Int64(i) => Some(IndexKeyKind::I64(*i)),
-        Bson::Double(f) => Some(IndexKeyKind::F64(OrderedFloat(*f))),
+        Bson::Null => Some(IndexKeyKind::Null),
         Bson::Boolean(b) => Some(IndexKeyKind::Bool(*b)),
+        Bson::Int32(i) => {
+            Some(IndexKeyKind::Num(NumKey::new(f64::from(*i), NumExact::I64(i64::from(*i)))))
+        }
+        Bson::Int64(i) => Some(IndexKeyKind::Num(NumKey::new(*i as f64, NumExact::I64(*i)))),
+        Bson::Double(f) => {
+            // -0.0 == 0.0 for filter equality, so both share a key.
+            let f = if *f == 0.0 { 0.0 } else { *f };
+            Some(IndexKeyKind::Num(NumKey::new(f, NumExact::F64(f.to_bits()))))
+        }
+        Bson::Decimal128(d) => {
+            let approx = d.to_string().parse::<f64>().unwrap_or(f64::NAN);
+            Some(IndexKeyKind::Num(NumKey::new(approx, NumExact::Decimal128(d.bytes()))))
+        }
+        Bson::String(s) => Some(IndexKeyKind::Str(s.clone())),
+        Bson::Binary(b) => Some(IndexKeyKind::Binary(u8::from(b.subtype), b.bytes.clone())),
+        Bson::ObjectId(o) => Some(IndexKeyKind::ObjectId(o.bytes())),
+        Bson::DateTime(d) => Some(IndexKeyKind::DateTime(d.timestamp_millis())),
         _ => None,
     }
 }
 
-fn get_path<'a>(doc: &'a BsonDocument, path: &str) -> Option<&'a Bson> {
-    let mut parts = path.split('.');
-    let first = parts.next()?;
-    let mut cur = doc.get(first)?;
-    for p in parts {
-        match cur {
-            Bson::Document(d) => {
-                cur = d.get(p)?;
+/// The key of `v` in an index built with `collation`: strings become collation sort keys.
+#[must_use]
+pub fn key_from_bson_collated(v: &Bson, collation: Option<&Collation>) -> Option<IndexKeyKind> {
+    match (v, collation) {
+        (Bson::String(s), Some(c)) => Some(IndexKeyKind::Collated(c.sort_key(s))),
+        _ => key_from_bson(v),
+    }
+}
+
+/// Turn a range endpoint into a bound over the index. Numeric endpoints cover every key that
+/// `compare_bson` considers equal to them, whatever its exact numeric type.
+fn range_bound(
+    v: &Bson,
+    inclusive: bool,
+    lower: bool,
+    collation: Option<&Collation>,
+) -> Option<std::ops::Bound<OrdKey>> {
+    use std::ops::Bound;
+    let k = key_from_bson_collated(v, collation)?;
+    Some(match k {
+        IndexKeyKind::Num(n) => {
+            let exact = if inclusive == lower { NumExact::Min } else { NumExact::Max };
+            let k = OrdKey(IndexKeyKind::Num(n.with_exact(exact)));
+            if inclusive { Bound::Included(k) } else { Bound::Excluded(k) }
+        }
+        k if inclusive => Bound::Included(OrdKey(k)),
+        k => Bound::Excluded(OrdKey(k)),
+    })
+}
+
+/// Whether `doc` belongs in an index on `field` with the given sparse/partial options.
+fn admits(doc: &BsonDocument, field: &str, sparse: bool, partial: Option<&Filter>) -> bool {
+    if sparse && crate::query::path_values(doc, field).iter().all(|v| matches!(v, Bson::Null)) {
+        return false;
+    }
+    partial.is_none_or(|f| crate::query::eval_filter(doc, f))
+}
+
+/// What `doc` contributes to an index on `field`.
+struct DocKeys<'a> {
+    /// Distinct keys, each with the value it came from. An array contributes one key per
+    /// element (a multikey entry).
+    keys: Vec<(IndexKeyKind, &'a Bson)>,
+    /// Whether any array was seen.
+    multikey: bool,
+    /// Whether some value (e.g. a sub-document) has no key; range scans must still return
+    /// such documents because `compare_bson` orders them among the keyed values.
+    unkeyable: bool,
+}
+
+fn index_keys<'a>(
+    doc: &'a BsonDocument,
+    field: &str,
+    collation: Option<&Collation>,
+) -> DocKeys<'a> {
+    let mut out = DocKeys { keys: Vec::new(), multikey: false, unkeyable: false };
+    for v in crate::query::path_values(doc, field) {
+        let elems: &[Bson] = match v {
+            Bson::Array(items) => {
+                out.multikey = true;
+                items
+            }
+            _ => std::slice::from_ref(v),
+        };
+        for e in elems {
+            match key_from_bson_collated(e, collation) {
+                Some(k) if !out.keys.iter().any(|(seen, _)| *seen == k) => out.keys.push((k, e)),
+                Some(_) => {}
+                None => out.unkeyable = true,
             }
-            _ => return None,
         }
     }
-    Some(cur)
+    out
+}
+
+/// The values `doc` is indexed under for `field`, one per distinct key (array elements are
+/// listed individually).
+#[must_use]
+pub fn index_key_values<'a>(doc: &'a BsonDocument, field: &str) -> Vec<&'a Bson> {
+    index_keys(doc, field, None).keys.into_iter().map(|(_, v)| v).collect()
 }
 
 #[derive(Debug, Clone)]
@@ -90,40 +304,85 @@ pub struct HashIndex {
     pub field: String,
     pub map: HashMap<EqKey, HashSet<DocumentId>>,
     pub stats: IndexStats,
+    pub unique: bool,
+    pub sparse: bool,
+    pub partial: Option<Filter>,
+    pub collation: Option<Collation>,
 }
 
 impl HashIndex {
     #[must_use]
     pub fn new(field: String) -> Self {
-        Self { field, map: HashMap::new(), stats: IndexStats::default() }
+        Self {
+            field,
+            map: HashMap::new(),
+            stats: IndexStats::default(),
+            unique: false,
+            sparse: false,
+            partial: None,
+            collation: None,
+        }
+    }
+    /// Whether `doc` is indexed under this index's sparse/partial options.
+    #[must_use]
+    pub fn admits(&self, doc: &BsonDocument) -> bool {
+        admits(doc, &self.field, self.sparse, self.partial.as_ref())
+    }
+    /// Returns the rendered key and the current holder when `doc` would map the same key to
+    /// a document other than `id`.
+    #[must_use]
+    pub fn find_conflict(
+        &self,
+        doc: &BsonDocument,
+        id: &DocumentId,
+    ) -> Option<(String, DocumentId)> {
+        if !self.admits(doc) {
+            return None;
+        }
+        index_keys(doc, &self.field, self.collation.as_ref()).keys.into_iter().find_map(|(k, v)| {
+            let holder = self.map.get(&EqKey(k))?.iter().find(|other| *other != id)?;
+            Some((v.to_string(), holder.clone()))
+        })
     }
     pub fn insert(&mut self, doc: &BsonDocument, id: &DocumentId) {
-        if let Some(v) = get_path(doc, &self.field)
-            && let Some(k) = key_from_bson(v).map(EqKey)
-        {
-            let set = self.map.entry(k).or_default();
-            if set.insert(id.clone()) {
+        if !self.admits(doc) {
+            return;
+        }
+        let dk = index_keys(doc, &self.field, self.collation.as_ref());
+        let mut added = false;
+        for (k, _) in dk.keys {
+            if self.map.entry(EqKey(k)).or_default().insert(id.clone()) {
                 self.stats.entries += 1;
+                added = true;
             }
-            self.stats.keys = self.map.len();
         }
+        if added {
+            self.stats.docs += 1;
+            self.stats.multikey |= dk.multikey;
+        }
+        self.stats.keys = self.map.len();
     }
     pub fn remove(&mut self, doc: &BsonDocument, id: &DocumentId) {
-        if let Some(v) = get_path(doc, &self.field)
-            && let Some(k) = key_from_bson(v).map(EqKey)
-            && let Some(set) = self.map.get_mut(&k)
-        {
-            if set.remove(id) {
-                self.stats.entries = self.stats.entries.saturating_sub(1);
-            }
-            if set.is_empty() {
-                self.map.remove(&k);
+        let mut removed = false;
+        for (k, _) in index_keys(doc, &self.field, self.collation.as_ref()).keys {
+            let k = EqKey(k);
+            if let Some(set) = self.map.get_mut(&k) {
+                if set.remove(id) {
+                    self.stats.entries = self.stats.entries.saturating_sub(1);
+                    removed = true;
+                }
+                if set.is_empty() {
+                    self.map.remove(&k);
+                }
             }
-            self.stats.keys = self.map.len();
         }
+        if removed {
+            self.stats.docs = self.stats.docs.saturating_sub(1);
+        }
+        self.stats.keys = self.map.len();
     }
     pub fn lookup_eq(&mut self, v: &Bson) -> Option<Vec<DocumentId>> {
-        if let Some(k) = key_from_bson(v).map(EqKey)
+        if let Some(k) = key_from_bson_collated(v, self.collation.as_ref()).map(EqKey)
             && let Some(set) = self.map.get(&k)
         {
             self.stats.hits += 1;
@@ -132,44 +391,173 @@ impl HashIndex {
         self.stats.misses += 1;
         None
     }
+    /// Number of documents stored under `v`'s key.
+    #[must_use]
+    pub fn count_eq(&self, v: &Bson) -> usize {
+        key_from_bson_collated(v, self.collation.as_ref())
+            .and_then(|k| self.map.get(&EqKey(k)))
+            .map_or(0, HashSet::len)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct BTreeIndex {
     pub field: String,
     pub map: BTreeMap<OrdKey, BTreeSet<DocumentId>>,
+    /// Admitted documents holding a value without a key; every range scan includes them.
+    pub unkeyed: HashSet<DocumentId>,
     pub stats: IndexStats,
+    pub unique: bool,
+    pub sparse: bool,
+    pub partial: Option<Filter>,
+    pub collation: Option<Collation>,
 }
 
 impl BTreeIndex {
     #[must_use]
     pub fn new(field: String) -> Self {
-        Self { field, map: BTreeMap::new(), stats: IndexStats::default() }
+        Self {
+            field,
+            map: BTreeMap::new(),
+            unkeyed: HashSet::new(),
+            stats: IndexStats::default(),
+            unique: false,
+            sparse: false,
+            partial: None,
+            collation: None,
+        }
+    }
+    /// Whether `doc` is indexed under this index's sparse/partial options.
+    #[must_use]
+    pub fn admits(&self, doc: &BsonDocument) -> bool {
+        admits(doc, &self.field, self.sparse, self.partial.as_ref())
+    }
+    /// Returns the rendered key and the current holder when `doc` would map the same key to
+    /// a document other than `id`.
+    #[must_use]
+    pub fn find_conflict(
+        &self,
+        doc: &BsonDocument,
+        id: &DocumentId,
+    ) -> Option<(String, DocumentId)> {
+        if !self.admits(doc) {
+            return None;
+        }
+        index_keys(doc, &self.field, self.collation.as_ref()).keys.into_iter().find_map(|(k, v)| {
+            let holder = self.map.get(&OrdKey(k))?.iter().find(|other| *other != id)?;
+            Some((v.to_string(), holder.clone()))
+        })
     }
     pub fn insert(&mut self, doc: &BsonDocument, id: &DocumentId) {
-        if let Some(v) = get_path(doc, &self.field)
-            && let Some(k) = key_from_bson(v).map(OrdKey)
-        {
-            let set = self.map.entry(k).or_default();
-            if set.insert(id.clone()) {
+        if !self.admits(doc) {
+            return;
+        }
+        let dk = index_keys(doc, &self.field, self.collation.as_ref());
+        let mut added = false;
+        for (k, _) in dk.keys {
+            if self.map.entry(OrdKey(k)).or_default().insert(id.clone()) {
                 self.stats.entries += 1;
+                added = true;
             }
-            self.stats.keys = self.map.len();
         }
+        if added {
+            self.stats.docs += 1;
+            self.stats.multikey |= dk.multikey;
+        }
+        if dk.unkeyable {
+            self.unkeyed.insert(id.clone());
+        }
+        self.stats.keys = self.map.len();
     }
     pub fn remove(&mut self, doc: &BsonDocument, id: &DocumentId) {
-        if let Some(v) = get_path(doc, &self.field)
-            && let Some(k) = key_from_bson(v).map(OrdKey)
-            && let Some(set) = self.map.get_mut(&k)
+        let mut removed = false;
+        for (k, _) in index_keys(doc, &self.field, self.collation.as_ref()).keys {
+            let k = OrdKey(k);
+            if let Some(set) = self.map.get_mut(&k) {
+                if set.remove(id) {
+                    self.stats.entries = self.stats.entries.saturating_sub(1);
+                    removed = true;
+                }
+                if set.is_empty() {
+                    self.map.remove(&k);
+                }
+            }
+        }
+        if removed {
+            self.stats.docs = self.stats.docs.saturating_sub(1);
+        }
+        self.unkeyed.remove(id);
+        self.stats.keys = self.map.len();
+    }
+    /// Keys within the given bounds, or `None` when the bounds select nothing.
+    fn range_iter(
+        &self,
+        min: Option<&Bson>,
+        max: Option<&Bson>,
+        inclusive_min: bool,
+        inclusive_max: bool,
+    ) -> Option<std::collections::btree_map::Range<'_, OrdKey, BTreeSet<DocumentId>>> {
+        let collation = self.collation.as_ref();
+        let start = min.and_then(|b| range_bound(b, inclusive_min, true, collation));
+        let end = max.and_then(|b| range_bound(b, inclusive_max, false, collation));
+        if let (Some(s), Some(e)) = (&start, &end)
+            && empty_range(s, e)
         {
-            if set.remove(id) {
-                self.stats.entries = self.stats.entries.saturating_sub(1);
+            return None;
+        }
+        Some(self.map.range((
+            start.unwrap_or(std::ops::Bound::Unbounded),
+            end.unwrap_or(std::ops::Bound::Unbounded),
+        )))
+    }
+    /// Estimate the documents a range scan returns, counting entries (not distinct documents)
+    /// and stopping once the count exceeds `cap`.
+    #[must_use]
+    pub fn estimate_range(
+        &self,
+        min: Option<&Bson>,
+        max: Option<&Bson>,
+        inclusive_min: bool,
+        inclusive_max: bool,
+        cap: usize,
+    ) -> usize {
+        let Some(iter) = self.range_iter(min, max, inclusive_min, inclusive_max) else {
+            return 0;
+        };
+        let mut n = self.unkeyed.len();
+        for (_k, set) in iter {
+            n += set.len();
+            if n > cap {
+                break;
             }
-            if set.is_empty() {
-                self.map.remove(&k);
+        }
+        n
+    }
+    /// Range scan returning the matching documents and the number of keys examined.
+    pub fn scan_range(
+        &mut self,
+        min: Option<&Bson>,
+        max: Option<&Bson>,
+        inclusive_min: bool,
+        inclusive_max: bool,
+    ) -> (Vec<DocumentId>, usize) {
+        let mut out: Vec<DocumentId> = Vec::new();
+        let mut keys = 0usize;
+        if let Some(iter) = self.range_iter(min, max, inclusive_min, inclusive_max) {
+            // A multikey document can sit under several keys in range; report it once.
+            let mut seen: HashSet<&DocumentId> = HashSet::new();
+            for (_k, set) in iter {
+                keys += 1;
+                out.extend(set.iter().filter(|id| seen.insert(*id)).cloned());
             }
-            self.stats.keys = self.map.len();
+            out.extend(self.unkeyed.iter().filter(|id| seen.insert(*id)).cloned());
+        }
+        if out.is_empty() {
+            self.stats.misses += 1;
+        } else {
+            self.stats.hits += 1;
         }
+        (out, keys)
     }
     pub fn lookup_range(
         &mut self,
@@ -178,58 +566,266 @@ impl BTreeIndex {
         inclusive_min: bool,
         inclusive_max: bool,
     ) -> Option<Vec<DocumentId>> {
-        // Build bounds
-        let start = min.and_then(|b| key_from_bson(b).map(OrdKey));
-        let end = max.and_then(|b| key_from_bson(b).map(OrdKey));
-        let mut out: Vec<DocumentId> = Vec::new();
-        let iter: Box<dyn Iterator<Item = (&OrdKey, &BTreeSet<DocumentId>)>> =
-            match (start.as_ref(), end.as_ref()) {
-                (Some(s), Some(e)) => {
-                    if inclusive_min && inclusive_max {
-                        Box::new(self.map.range(s.clone()..=e.clone()))
-                    } else if inclusive_min {
-                        Box::new(self.map.range(s.clone()..e.clone()))
-                    } else if inclusive_max {
-                        Box::new(self.map.range((
-                            std::ops::Bound::Excluded(s.clone()),
-                            std::ops::Bound::Included(e.clone()),
-                        )))
-                    } else {
-                        Box::new(self.map.range((
-                            std::ops::Bound::Excluded(s.clone()),
-                            std::ops::Bound::Excluded(e.clone()),
-                        )))
-                    }
+        let (out, _) = self.scan_range(min, max, inclusive_min, inclusive_max);
+        if out.is_empty() { None } else { Some(out) }
+    }
+}
+
+/// An inverted index from terms to the documents whose text holds them. Terms come from the
+/// index's field and every other field given a weight, analysed as its options say.
+#[derive(Debug, Clone)]
+pub struct TextIndex {
+    pub field: String,
+    pub options: TextOptions,
+    pub postings: HashMap<String, HashSet<DocumentId>>,
+    pub stats: IndexStats,
+    pub partial: Option<Filter>,
+    pub(crate) analyzer: TextAnalyzer,
+}
+
+impl TextIndex {
+    #[must_use]
+    pub fn new(field: String, options: TextOptions) -> Self {
+        let analyzer = TextAnalyzer::new(&field, &options);
+        Self {
+            field,
+            options,
+            postings: HashMap::new(),
+            stats: IndexStats::default(),
+            partial: None,
+            analyzer,
+        }
+    }
+    /// Whether `doc` is indexed under this index's partial filter.
+    #[must_use]
+    pub fn admits(&self, doc: &BsonDocument) -> bool {
+        self.partial.as_ref().is_none_or(|f| crate::query::eval_filter(doc, f))
+    }
+    /// The distinct terms `doc` is indexed under.
+    #[must_use]
+    pub fn terms(&self, doc: &BsonDocument) -> BTreeSet<String> {
+        self.analyzer.doc_terms(doc)
+    }
+    pub fn insert(&mut self, doc: &BsonDocument, id: &DocumentId) {
+        if !self.admits(doc) {
+            return;
+        }
+        let mut added = false;
+        for t in self.terms(doc) {
+            if self.postings.entry(t).or_default().insert(id.clone()) {
+                self.stats.entries += 1;
+                added = true;
+            }
+        }
+        if added {
+            self.stats.docs += 1;
+        }
+        self.stats.keys = self.postings.len();
+    }
+    pub fn remove(&mut self, doc: &BsonDocument, id: &DocumentId) {
+        let mut removed = false;
+        for t in self.terms(doc) {
+            if let Some(set) = self.postings.get_mut(&t) {
+                if set.remove(id) {
+                    self.stats.entries = self.stats.entries.saturating_sub(1);
+                    removed = true;
                 }
-                (Some(s), None) => {
-                    if inclusive_min {
-                        Box::new(self.map.range(s.clone()..))
-                    } else {
-                        Box::new(self.map.range((
-                            std::ops::Bound::Excluded(s.clone()),
-                            std::ops::Bound::Unbounded,
-                        )))
-                    }
+                if set.is_empty() {
+                    self.postings.remove(&t);
                 }
-                (None, Some(e)) => {
-                    if inclusive_max {
-                        Box::new(self.map.range(..=e.clone()))
-                    } else {
-                        Box::new(self.map.range(..e.clone()))
-                    }
+            }
+        }
+        if removed {
+            self.stats.docs = self.stats.docs.saturating_sub(1);
+        }
+        self.stats.keys = self.postings.len();
+    }
+    /// Upper bound on the documents holding any of `terms`.
+    #[must_use]
+    pub fn estimate_terms(&self, terms: &[String]) -> usize {
+        terms.iter().filter_map(|t| self.postings.get(t)).map(HashSet::len).sum()
+    }
+    /// Documents holding any of `terms`, and the number of terms found.
+    pub fn scan_terms(&mut self, terms: &[String]) -> (Vec<DocumentId>, usize) {
+        let mut seen: HashSet<&DocumentId> = HashSet::new();
+        let mut out = Vec::new();
+        let mut keys = 0;
+        for set in terms.iter().filter_map(|t| self.postings.get(t)) {
+            keys += 1;
+            out.extend(set.iter().filter(|id| seen.insert(*id)).cloned());
+        }
+        if keys == 0 {
+            self.stats.misses += 1;
+        } else {
+            self.stats.hits += 1;
+        }
+        (out, keys)
+    }
+}
+
+/// Vectors stored for k-nearest-neighbour search, either in a flat map searched exhaustively
+/// or in an HNSW graph.
+#[derive(Debug, Clone)]
+pub struct VectorIndex {
+    pub field: String,
+    pub options: VectorOptions,
+    pub stats: IndexStats,
+    pub partial: Option<Filter>,
+    pub(crate) store: VectorStore,
+}
+
+impl VectorIndex {
+    #[must_use]
+    pub fn new(field: String, options: VectorOptions) -> Self {
+        let store = VectorStore::new(&options);
+        Self { field, options, stats: IndexStats::default(), partial: None, store }
+    }
+    /// Whether `doc` is indexed under this index's partial filter.
+    #[must_use]
+    pub fn admits(&self, doc: &BsonDocument) -> bool {
+        self.partial.as_ref().is_none_or(|f| crate::query::eval_filter(doc, f))
+    }
+    /// Number of documents holding an indexed vector.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn insert(&mut self, doc: &BsonDocument, id: &DocumentId) {
+        if !self.admits(doc) {
+            return;
+        }
+        if let Some(v) = self.options.vector_of(doc, &self.field) {
+            self.store.insert(id, v);
+            self.sync_stats();
+        }
+    }
+    pub fn remove(&mut self, _doc: &BsonDocument, id: &DocumentId) {
+        if self.store.remove(id) {
+            self.sync_stats();
+        }
+    }
+    /// Drop the nodes of a restored graph that no document confirmed while it was rebuilt.
+    pub(crate) fn prune_unconfirmed(&mut self) {
+        if let VectorStore::Hnsw(g) = &mut self.store {
+            g.prune_unconfirmed();
+            self.sync_stats();
+        }
+    }
+    fn sync_stats(&mut self) {
+        let n = self.store.len();
+        self.stats.keys = n;
+        self.stats.entries = n;
+        self.stats.docs = n;
+    }
+}
+
+/// Points keyed by the z-order cell holding them. Lines and polygons are not keyed: like a
+/// BTree index's unkeyed documents, every scan includes them for the exact check.
+#[derive(Debug, Clone)]
+pub struct GeoIndex {
+    pub field: String,
+    pub cells: BTreeMap<u64, BTreeSet<DocumentId>>,
+    /// Admitted documents holding a line or polygon.
+    pub shapes: HashSet<DocumentId>,
+    pub stats: IndexStats,
+    pub partial: Option<Filter>,
+}
+
+impl GeoIndex {
+    #[must_use]
+    pub fn new(field: String) -> Self {
+        Self {
+            field,
+            cells: BTreeMap::new(),
+            shapes: HashSet::new(),
+            stats: IndexStats::default(),
+            partial: None,
+        }
+    }
+    /// Whether `doc` is indexed under this index's partial filter.
+    #[must_use]
+    pub fn admits(&self, doc: &BsonDocument) -> bool {
+        self.partial.as_ref().is_none_or(|f| crate::query::eval_filter(doc, f))
+    }
+    pub fn insert(&mut self, doc: &BsonDocument, id: &DocumentId) {
+        if !self.admits(doc) {
+            return;
+        }
+        let geometries = geo_geometries(doc, &self.field);
+        let mut added = false;
+        for g in &geometries {
+            added |= match g {
+                Geometry::Point(p) => {
+                    self.cells.entry(geo_cell(*p)).or_default().insert(id.clone())
                 }
-                (None, None) => Box::new(self.map.iter()),
+                _ => self.shapes.insert(id.clone()),
             };
-        for (_k, set) in iter {
-            out.extend(set.iter().cloned());
         }
+        if added {
+            self.stats.entries += geometries.len();
+            self.stats.docs += 1;
+            self.stats.multikey |= geometries.len() > 1;
+        }
+        self.stats.keys = self.cells.len();
+    }
+    pub fn remove(&mut self, doc: &BsonDocument, id: &DocumentId) {
+        let geometries = geo_geometries(doc, &self.field);
+        let mut removed = self.shapes.remove(id);
+        for g in &geometries {
+            if let Geometry::Point(p) = g {
+                let key = geo_cell(*p);
+                if let Some(set) = self.cells.get_mut(&key) {
+                    removed |= set.remove(id);
+                    if set.is_empty() {
+                        self.cells.remove(&key);
+                    }
+                }
+            }
+        }
+        if removed {
+            self.stats.entries = self.stats.entries.saturating_sub(geometries.len());
+            self.stats.docs = self.stats.docs.saturating_sub(1);
+        }
+        self.stats.keys = self.cells.len();
+    }
+    /// Estimate the documents a scan of the inclusive key `ranges` returns, counting entries
+    /// and stopping once the count exceeds `cap`.
+    #[must_use]
+    pub fn estimate_ranges(&self, ranges: &[(u64, u64)], cap: usize) -> usize {
+        let mut n = self.shapes.len();
+        for (lo, hi) in ranges {
+            for set in self.cells.range(lo..=hi).map(|(_, set)| set) {
+                n += set.len();
+                if n > cap {
+                    return n;
+                }
+            }
+        }
+        n
+    }
+    /// Documents under the inclusive key `ranges` plus every line and polygon, and the
+    /// number of keys examined.
+    pub fn scan_ranges(&mut self, ranges: &[(u64, u64)]) -> (Vec<DocumentId>, usize) {
+        let mut seen: HashSet<&DocumentId> = HashSet::new();
+        let mut out = Vec::new();
+        let mut keys = 0;
+        for (lo, hi) in ranges {
+            for set in self.cells.range(lo..=hi).map(|(_, set)| set) {
+                keys += 1;
+                out.extend(set.iter().filter(|id| seen.insert(*id)).cloned());
+            }
+        }
+        out.extend(self.shapes.iter().filter(|id| seen.insert(*id)).cloned());
         if out.is_empty() {
             self.stats.misses += 1;
-            None
         } else {
             self.stats.hits += 1;
-            Some(out)
         }
+        (out, keys)
     }
 }
 
@@ -237,82 +833,483 @@ impl BTreeIndex {
 pub enum IndexImpl {
     Hash(HashIndex),
     BTree(BTreeIndex),
-    // Vector(VectorIndex), // TODO: placeholder for future ANN index
+    Text(TextIndex),
+    Vector(VectorIndex),
+    Geo(GeoIndex),
+}
+
+impl IndexImpl {
+    #[must_use]
+    pub fn from_descriptor(desc: &IndexDescriptor) -> Self {
+        match desc.kind {
+            IndexKind::Hash => {
+                let mut h = HashIndex::new(desc.field.clone());
+                h.unique = desc.unique;
+                h.sparse = desc.sparse;
+                h.partial.clone_from(&desc.partial_filter);
+                h.collation.clone_from(&desc.collation);
+                Self::Hash(h)
+            }
+            IndexKind::BTree => {
+                let mut b = BTreeIndex::new(desc.field.clone());
+                b.unique = desc.unique;
+                b.sparse = desc.sparse;
+                b.partial.clone_from(&desc.partial_filter);
+                b.collation.clone_from(&desc.collation);
+                Self::BTree(b)
+            }
+            IndexKind::Text => {
+                let mut t =
+                    TextIndex::new(desc.field.clone(), desc.text.clone().unwrap_or_default());
+                t.partial.clone_from(&desc.partial_filter);
+                Self::Text(t)
+            }
+            IndexKind::Vector => {
+                let options = desc.vector.clone().unwrap_or_else(|| VectorOptions::new(0));
+                let mut v = VectorIndex::new(desc.field.clone(), options);
+                v.partial.clone_from(&desc.partial_filter);
+                Self::Vector(v)
+            }
+            IndexKind::Geo => {
+                let mut g = GeoIndex::new(desc.field.clone());
+                g.partial.clone_from(&desc.partial_filter);
+                Self::Geo(g)
+            }
+        }
+    }
+    #[must_use]
+    pub const fn kind(&self) -> IndexKind {
+        match self {
+            Self::Hash(_) => IndexKind::Hash,
+            Self::BTree(_) => IndexKind::BTree,
+            Self::Text(_) => IndexKind::Text,
+            Self::Vector(_) => IndexKind::Vector,
+            Self::Geo(_) => IndexKind::Geo,
+        }
+    }
+    #[must_use]
+    pub const fn is_unique(&self) -> bool {
+        match self {
+            Self::Hash(h) => h.unique,
+            Self::BTree(b) => b.unique,
+            Self::Text(_) | Self::Vector(_) | Self::Geo(_) => false,
+        }
+    }
+    /// Documents an equality lookup on `v` returns (exact; stats are not touched).
+    #[must_use]
+    pub fn estimate_eq(&self, v: &Bson) -> usize {
+        match self {
+            Self::Hash(h) => h.count_eq(v),
+            Self::BTree(b) => key_from_bson_collated(v, b.collation.as_ref())
+                .and_then(|k| b.map.get(&OrdKey(k)))
+                .map_or(0, BTreeSet::len),
+            Self::Text(_) | Self::Vector(_) | Self::Geo(_) => 0,
+        }
+    }
+    /// Equality lookup returning the matching documents and the number of keys examined.
+    pub fn scan_eq(&mut self, v: &Bson) -> (Vec<DocumentId>, usize) {
+        let found: Option<Vec<DocumentId>> = match self {
+            Self::Hash(h) => key_from_bson_collated(v, h.collation.as_ref())
+                .and_then(|k| h.map.get(&EqKey(k)))
+                .map(|set| set.iter().cloned().collect()),
+            Self::BTree(b) => key_from_bson_collated(v, b.collation.as_ref())
+                .and_then(|k| b.map.get(&OrdKey(k)))
+                .map(|set| set.iter().cloned().collect()),
+            Self::Text(_) | Self::Vector(_) | Self::Geo(_) => None,
+        };
+        let stats = self.stats_mut();
+        match found {
+            Some(ids) => {
+                stats.hits += 1;
+                (ids, 1)
+            }
+            None => {
+                stats.misses += 1;
+                (Vec::new(), 0)
+            }
+        }
+    }
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.stats().state == IndexState::Ready
+    }
+    /// Every key held by more than one document, for unique-index validation.
+    #[must_use]
+    pub fn duplicate_keys(&self) -> Vec<UniqueConflict> {
+        fn conflict<'a>(
+            key: &IndexKeyKind,
+            ids: impl IntoIterator<Item = &'a DocumentId>,
+        ) -> UniqueConflict {
+            UniqueConflict {
+                key: key.to_bson().to_string(),
+                ids: ids.into_iter().cloned().collect(),
+            }
+        }
+        match self {
+            Self::Hash(h) => h
+                .map
+                .iter()
+                .filter(|(_, ids)| ids.len() > 1)
+                .map(|(k, ids)| conflict(&k.0, ids))
+                .collect(),
+            Self::BTree(b) => b
+                .map
+                .iter()
+                .filter(|(_, ids)| ids.len() > 1)
+                .map(|(k, ids)| conflict(&k.0, ids))
+                .collect(),
+            Self::Text(_) | Self::Vector(_) | Self::Geo(_) => Vec::new(),
+        }
+    }
+    #[must_use]
+    pub const fn is_sparse(&self) -> bool {
+        match self {
+            Self::Hash(h) => h.sparse,
+            Self::BTree(b) => b.sparse,
+            Self::Text(_) | Self::Vector(_) | Self::Geo(_) => false,
+        }
+    }
+    /// The collation string keys are built with; `None` for byte-wise keys.
+    #[must_use]
+    pub const fn collation(&self) -> Option<&Collation> {
+        match self {
+            Self::Hash(h) => h.collation.as_ref(),
+            Self::BTree(b) => b.collation.as_ref(),
+            Self::Text(_) | Self::Vector(_) | Self::Geo(_) => None,
+        }
+    }
+    /// The analysis settings of a text index.
+    #[must_use]
+    pub const fn text_options(&self) -> Option<&TextOptions> {
+        match self {
+            Self::Text(t) => Some(&t.options),
+            _ => None,
+        }
+    }
+    /// The dimensions, metric and graph settings of a vector index.
+    #[must_use]
+    pub const fn vector_options(&self) -> Option<&VectorOptions> {
+        match self {
+            Self::Vector(v) => Some(&v.options),
+            _ => None,
+        }
+    }
+    #[must_use]
+    pub const fn partial_filter(&self) -> Option<&Filter> {
+        match self {
+            Self::Hash(h) => h.partial.as_ref(),
+            Self::BTree(b) => b.partial.as_ref(),
+            Self::Text(t) => t.partial.as_ref(),
+            Self::Vector(v) => v.partial.as_ref(),
+            Self::Geo(g) => g.partial.as_ref(),
+        }
+    }
+    #[must_use]
+    pub fn admits(&self, doc: &BsonDocument) -> bool {
+        match self {
+            Self::Hash(h) => h.admits(doc),
+            Self::BTree(b) => b.admits(doc),
+            Self::Text(t) => t.admits(doc),
+            Self::Vector(v) => v.admits(doc),
+            Self::Geo(g) => g.admits(doc),
+        }
+    }
+    /// Whether this index holds every document that can match `query`: always true for a
+    /// full index, and for a partial index only when `query` implies its predicate.
+    #[must_use]
+    pub fn covers_query(&self, query: &Filter) -> bool {
+        self.partial_filter().is_none_or(|p| crate::query::filter_implies(query, p))
+    }
+    #[must_use]
+    pub const fn stats(&self) -> &IndexStats {
+        match self {
+            Self::Hash(h) => &h.stats,
+            Self::BTree(b) => &b.stats,
+            Self::Text(t) => &t.stats,
+            Self::Vector(v) => &v.stats,
+            Self::Geo(g) => &g.stats,
+        }
+    }
+    pub const fn stats_mut(&mut self) -> &mut IndexStats {
+        match self {
+            Self::Hash(h) => &mut h.stats,
+            Self::BTree(b) => &mut b.stats,
+            Self::Text(t) => &mut t.stats,
+            Self::Vector(v) => &mut v.stats,
+            Self::Geo(g) => &mut g.stats,
+        }
+    }
+    pub fn insert(&mut self, doc: &BsonDocument, id: &DocumentId) {
+        match self {
+            Self::Hash(h) => h.insert(doc, id),
+            Self::BTree(b) => b.insert(doc, id),
+            Self::Text(t) => t.insert(doc, id),
+            Self::Vector(v) => v.insert(doc, id),
+            Self::Geo(g) => g.insert(doc, id),
+        }
+    }
+    pub fn remove(&mut self, doc: &BsonDocument, id: &DocumentId) {
+        match self {
+            Self::Hash(h) => h.remove(doc, id),
+            Self::BTree(b) => b.remove(doc, id),
+            Self::Text(t) => t.remove(doc, id),
+            Self::Vector(v) => v.remove(doc, id),
+            Self::Geo(g) => g.remove(doc, id),
+        }
+    }
+    #[must_use]
+    pub fn find_conflict(
+        &self,
+        doc: &BsonDocument,
+        id: &DocumentId,
+    ) -> Option<(String, DocumentId)> {
+        match self {
+            Self::Hash(h) => h.find_conflict(doc, id),
+            Self::BTree(b) => b.find_conflict(doc, id),
+            Self::Text(_) | Self::Vector(_) | Self::Geo(_) => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexDescriptor {
     pub field: String,
     pub kind: IndexKind,
+    /// Reject writes that would map an existing key to a second document.
+    #[serde(default)]
+    pub unique: bool,
+    /// Skip documents whose field is missing or null.
+    #[serde(default)]
+    pub sparse: bool,
+    /// Only index documents matching this filter (partial index).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_filter: Option<Filter>,
+    /// Key strings by their sort key under this collation. Queries comparing strings only use
+    /// the index under the same collation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collation: Option<Collation>,
+    /// Fields, weights and analysis of a `Text` index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<TextOptions>,
+    /// Dimensions, metric and graph settings of a `Vector` index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<VectorOptions>,
+}
+
+impl IndexDescriptor {
+    #[must_use]
+    pub fn new(field: &str, kind: IndexKind) -> Self {
+        Self {
+            field: field.to_string(),
+            kind,
+            unique: false,
+            sparse: false,
+            partial_filter: None,
+            collation: None,
+            text: None,
+            vector: None,
+        }
+    }
+    #[must_use]
+    pub const fn with_unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+    #[must_use]
+    pub const fn with_sparse(mut self, sparse: bool) -> Self {
+        self.sparse = sparse;
+        self
+    }
+    #[must_use]
+    pub fn with_partial_filter(mut self, filter: Filter) -> Self {
+        self.partial_filter = Some(filter);
+        self
+    }
+    #[must_use]
+    pub fn with_collation(mut self, collation: Collation) -> Self {
+        self.collation = Some(collation);
+        self
+    }
+    #[must_use]
+    pub fn with_text(mut self, options: TextOptions) -> Self {
+        self.text = Some(options);
+        self
+    }
+    #[must_use]
+    pub fn with_vector(mut self, options: VectorOptions) -> Self {
+        self.vector = Some(options);
+        self
+    }
+}
+
+/// A key that more than one document maps to, reported when a unique index cannot be built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniqueConflict {
+    pub key: String,
+    pub ids: Vec<DocumentId>,
 }
 
 pub const INDEX_METADATA_VERSION: u32 = 1;
 
+/// A write captured while a background build scans its snapshot.
+#[derive(Debug, Clone)]
+pub(crate) enum SideOp {
+    Insert(BsonDocument, DocumentId),
+    Remove(BsonDocument, DocumentId),
+}
+
+/// Writes captured for one background build, tagged with the build that owns them.
+#[derive(Debug, Default)]
+pub(crate) struct SideBuffer {
+    pub build: u64,
+    pub ops: Vec<SideOp>,
+}
+
 #[derive(Debug, Default)]
 pub struct IndexManager {
     pub indexes: HashMap<String, IndexImpl>, // key: field path
+    /// Side buffers of indexes in `IndexState::Building`, keyed by field path.
+    pub(crate) side_buffers: HashMap<String, SideBuffer>,
+    /// Token handed to the next background build.
+    next_build: u64,
 }
 
 impl IndexManager {
     #[must_use]
     pub fn new() -> Self {
-        Self { indexes: HashMap::new() }
+        Self { indexes: HashMap::new(), side_buffers: HashMap::new(), next_build: 0 }
     }
     pub fn create_index(&mut self, field: &str, kind: IndexKind) {
-        let idx = match kind {
-            IndexKind::Hash => IndexImpl::Hash(HashIndex::new(field.to_string())),
-            IndexKind::BTree => IndexImpl::BTree(BTreeIndex::new(field.to_string())),
-            // IndexKind::Vector => IndexImpl::Vector(VectorIndex::new(field.to_string())),
-        };
-        self.indexes.insert(field.to_string(), idx);
+        self.create_index_with(&IndexDescriptor::new(field, kind));
+    }
+    pub fn create_index_with(&mut self, desc: &IndexDescriptor) {
+        self.indexes.insert(desc.field.clone(), IndexImpl::from_descriptor(desc));
     }
     pub fn drop_index(&mut self, field: &str) {
         self.indexes.remove(field);
+        self.side_buffers.remove(field);
+    }
+    /// Register `desc` as a building placeholder over a snapshot of `total` documents. Until
+    /// the build finishes, writes touching it are captured in a side buffer. Returns the
+    /// build's token: a build whose token no longer owns the side buffer was dropped or
+    /// replaced and must not install its index.
+    pub(crate) fn begin_build(&mut self, desc: &IndexDescriptor, total: usize) -> u64 {
+        let mut idx = IndexImpl::from_descriptor(desc);
+        let stats = idx.stats_mut();
+        stats.state = IndexState::Building;
+        stats.build_total = total;
+        self.indexes.insert(desc.field.clone(), idx);
+        self.next_build += 1;
+        let build = self.next_build;
+        self.side_buffers.insert(desc.field.clone(), SideBuffer { build, ops: Vec::new() });
+        build
+    }
+    /// Whether `build` still owns the side buffer of `field`.
+    pub(crate) fn owns_build(&self, field: &str, build: u64) -> bool {
+        self.side_buffers.get(field).is_some_and(|b| b.build == build)
     }
     #[must_use]
     pub fn descriptors(&self) -> Vec<IndexDescriptor> {
+        // Building and failed indexes are not persisted; they would be rebuilt from scratch.
         self.indexes
             .iter()
+            .filter(|(_, i)| i.is_ready())
             .map(|(f, i)| IndexDescriptor {
-                field: f.clone(),
-                kind: match i {
-                    IndexImpl::Hash(_) => IndexKind::Hash,
-                    IndexImpl::BTree(_) => IndexKind::BTree,
-                    // IndexImpl::Vector(_) => IndexKind::Vector,
-                },
+                partial_filter: i.partial_filter().cloned(),
+                collation: i.collation().cloned(),
+                text: i.text_options().cloned(),
+                vector: i.vector_options().cloned(),
+                ..IndexDescriptor::new(f, i.kind())
+                    .with_unique(i.is_unique())
+                    .with_sparse(i.is_sparse())
             })
             .collect()
     }
+    /// Check that `desc` can be registered: text, vector and geo indexes cannot be unique, a
+    /// collection has at most one text index, and a vector index needs valid options.
+    ///
+    /// # Errors
+    /// Returns `DbError::QueryError` describing the conflict.
+    pub fn validate(&self, desc: &IndexDescriptor) -> Result<(), DbError> {
+        if desc.kind == IndexKind::Vector {
+            if desc.unique {
+                return Err(DbError::QueryError("a vector index cannot be unique".into()));
+            }
+            return match &desc.vector {
+                Some(opts) => opts.validate(),
+                None => Err(DbError::QueryError("a vector index needs vector options".into())),
+            };
+        }
+        if desc.kind == IndexKind::Geo && desc.unique {
+            return Err(DbError::QueryError("a geo index cannot be unique".into()));
+        }
+        if desc.kind != IndexKind::Text {
+            return Ok(());
+        }
+        if desc.unique {
+            return Err(DbError::QueryError("a text index cannot be unique".into()));
+        }
+        match self.indexes.iter().find(|(f, i)| i.kind() == IndexKind::Text && **f != desc.field) {
+            Some((f, _)) => {
+                Err(DbError::QueryError(format!("collection already has a text index on '{f}'")))
+            }
+            None => Ok(()),
+        }
+    }
+    /// Check every unique index for a key that `doc` would share with a document other than `id`.
+    ///
+    /// # Errors
+    /// Returns `DbError::DuplicateKey` for the first unique index that would be violated.
+    pub fn check_unique(&self, doc: &BsonDocument, id: &DocumentId) -> Result<(), DbError> {
+        for (field, idx) in &self.indexes {
+            if idx.is_unique()
+                && idx.is_ready()
+                && let Some((key, _)) = idx.find_conflict(doc, id)
+            {
+                return Err(DbError::DuplicateKey { field: field.clone(), key });
+            }
+        }
+        Ok(())
+    }
 }
 
 pub fn index_insert_all(mgr: &mut IndexManager, doc: &BsonDocument, id: &DocumentId) {
-    for idx in mgr.indexes.values_mut() {
-        match idx {
-            IndexImpl::Hash(h) => h.insert(doc, id),
-            IndexImpl::BTree(b) => b.insert(doc, id),
-            // IndexImpl::Vector(v) => v.insert(doc, id),
+    let IndexManager { indexes, side_buffers, .. } = mgr;
+    for (field, idx) in indexes.iter_mut() {
+        match idx.stats().state {
+            IndexState::Ready => idx.insert(doc, id),
+            IndexState::Building => {
+                if let Some(buf) = side_buffers.get_mut(field) {
+                    buf.ops.push(SideOp::Insert(doc.clone(), id.clone()));
+                }
+            }
+            IndexState::Failed(_) => {}
         }
     }
 }
 
 pub fn index_remove_all(mgr: &mut IndexManager, doc: &BsonDocument, id: &DocumentId) {
-    for idx in mgr.indexes.values_mut() {
-        match idx {
-            IndexImpl::Hash(h) => h.remove(doc, id),
-            IndexImpl::BTree(b) => b.remove(doc, id),
-            // IndexImpl::Vector(v) => v.remove(doc, id),
+    let IndexManager { indexes, side_buffers, .. } = mgr;
+    for (field, idx) in indexes.iter_mut() {
+        match idx.stats().state {
+            IndexState::Ready => idx.remove(doc, id),
+            IndexState::Building => {
+                if let Some(buf) = side_buffers.get_mut(field) {
+                    buf.ops.push(SideOp::Remove(doc.clone(), id.clone()));
+                }
+            }
+            IndexState::Failed(_) => {}
         }
     }
 }
 
 pub fn lookup_eq(mgr: &mut IndexManager, field: &str, v: &Bson) -> Option<Vec<DocumentId>> {
-    match mgr.indexes.get_mut(field) {
+    match mgr.indexes.get_mut(field).filter(|i| i.is_ready()) {
         Some(IndexImpl::Hash(h)) => h.lookup_eq(v),
         Some(IndexImpl::BTree(b)) => {
             // Equality via BTree exact bound
             b.lookup_range(Some(v), Some(v), true, true)
         }
-        // Some(IndexImpl::Vector(v)) => v.lookup_knn(v),
         _ => None,
     }
 }
@@ -325,23 +1322,8 @@ pub fn lookup_range(
     incl_min: bool,
     incl_max: bool,
 ) -> Option<Vec<DocumentId>> {
-    match mgr.indexes.get_mut(field) {
+    match mgr.indexes.get_mut(field).filter(|i| i.is_ready()) {
         Some(IndexImpl::BTree(b)) => b.lookup_range(min, max, incl_min, incl_max),
-        // Some(IndexImpl::Vector(v)) => v.lookup_range(min, max, incl_min, incl_max),
         _ => None,
     }
 }
-
-// Below is a placeholder for a future approximate nearest neighbor (ANN) vector index.
-// It is intentionally commented out to avoid changing public API until ready.
-//
-// pub struct VectorIndex {
-//     pub field: String,
-//     // TODO: store vector embeddings and an ANN structure (e.g., HNSW)
-// }
-// impl VectorIndex {
-//     pub fn new(field: String) -> Self { Self { field } }
-//     pub fn insert(&mut self, _doc: &BsonDocument, _id: &DocumentId) { /* TODO */ }
-//     pub fn remove(&mut self, _doc: &BsonDocument, _id: &DocumentId) { /* TODO */ }
-//     pub fn lookup_knn(&mut self, _q: &Bson) -> Option<Vec<DocumentId>> { None }
-// }
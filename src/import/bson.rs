@@ -4,16 +4,18 @@ use std::io::{self, Read};
 
 use super::options::ImportOptions;
 use super::options::ImportReport;
-use crate::import::util::apply_ttl;
+use crate::import::util::{BatchInserter, apply_ttl};
 
 pub fn import_bson<R: Read>(
     collection: &std::sync::Arc<Collection>,
     mut reader: R,
     doc_type: DocumentType,
-    _opts: &ImportOptions,
+    opts: &ImportOptions,
     report: &mut ImportReport,
 ) -> io::Result<()> {
     let mut full = Vec::with_capacity(4096);
+    let mut doc_no: usize = 0;
+    let mut batch = BatchInserter::new(collection, opts, "bson")?;
     loop {
         let mut len_buf = [0u8; 4];
         if reader.read_exact(&mut len_buf).is_err() {
@@ -34,15 +36,15 @@ pub fn import_bson<R: Read>(
         let start = full.len();
         full.resize(len, 0);
         reader.read_exact(&mut full[start..])?;
+        doc_no += 1;
         match bson::Document::from_reader(&mut &full[..]) {
             Ok(doc) => {
                 let mut d = Document::new(doc.clone(), doc_type);
                 apply_ttl(&mut d, &doc, None);
-                collection.insert_document(d);
-                report.inserted += 1;
+                batch.push(d, format!("\"doc\":{doc_no}"), None, report)?;
             }
             Err(_) => report.skipped += 1,
         }
     }
-    Ok(())
+    batch.flush(report)
 }
@@ -1,11 +1,11 @@
 use crate::collection::Collection;
 use crate::document::{Document, DocumentType};
 use bson::Document as BsonDocument;
-use std::io::{self, Read, Write};
+use std::io::{self, Read};
 
 use super::options::ImportOptions;
 use super::options::ImportReport;
-use crate::import::util::{apply_ttl, escape_json, field_to_bson};
+use crate::import::util::{BatchInserter, apply_ttl, field_to_bson};
 
 pub fn import_csv<R: Read>(
     collection: &std::sync::Arc<Collection>,
@@ -26,28 +26,15 @@ pub fn import_csv<R: Read>(
         vec![]
     };
     let mut row_no: usize = 0;
-    let mut sidecar = match &opts.error_sidecar {
-        Some(p) if opts.skip_errors => Some(std::fs::File::create(p)?),
-        _ => None,
-    };
+    let mut batch = BatchInserter::new(collection, opts, "csv")?;
     for rec in rdr.records() {
         row_no += 1;
         let rec = match rec {
             Ok(r) => r,
             Err(e) => {
-                if let Some(f) = sidecar.as_mut() {
-                    let _ = writeln!(
-                        f,
-                        "{{\"row\":{},\"error\":\"{}\"}}",
-                        row_no,
-                        escape_json(&e.to_string())
-                    );
-                }
-                if opts.skip_errors {
-                    report.skipped += 1;
-                    continue;
-                }
-                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                let err = io::Error::new(io::ErrorKind::InvalidData, e);
+                batch.reject(&format!("\"row\":{row_no}"), None, err, report)?;
+                continue;
             }
         };
         let mut map = BsonDocument::new();
@@ -63,13 +50,7 @@ pub fn import_csv<R: Read>(
         }
         let mut d = Document::new(map.clone(), doc_type);
         apply_ttl(&mut d, &map, opts.ttl_field.as_deref());
-        collection.insert_document(d);
-        report.inserted += 1;
-        if let Some(n) = opts.progress_every
-            && row_no % n == 0
-        {
-            log::info!("imported {} records (csv)", report.inserted);
-        }
+        batch.push(d, format!("\"row\":{row_no}"), None, report)?;
     }
-    Ok(())
+    batch.flush(report)
 }
@@ -1,11 +1,11 @@
 use crate::collection::Collection;
 use crate::document::{Document, DocumentType};
 use bson::Document as BsonDocument;
-use std::io::{self, BufRead, BufReader, Read, Write};
+use std::io::{self, BufRead, BufReader, Read};
 
 use super::options::ImportOptions;
 use super::options::ImportReport;
-use crate::import::util::{apply_ttl, escape_json};
+use crate::import::util::{BatchInserter, apply_ttl};
 
 pub fn import_ndjson<R: Read>(
     collection: &std::sync::Arc<Collection>,
@@ -14,6 +14,7 @@ pub fn import_ndjson<R: Read>(
     opts: &ImportOptions,
     report: &mut ImportReport,
 ) -> io::Result<()> {
+    let mut batch = BatchInserter::new(collection, opts, "ndjson")?;
     if opts.json.array_mode {
         // Read entire content and parse as JSON array (sufficient for moderate inputs and tests)
         let mut s = String::new();
@@ -24,21 +25,16 @@ pub fn import_ndjson<R: Read>(
         let arr = val
             .as_array()
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "expected JSON array"))?;
-        for v in arr {
-            let bdoc: BsonDocument = crate::utils::json::json_value_to_bson_document(v)?;
+        for (i, v) in arr.iter().enumerate() {
+            let bdoc = to_document(v, &mut batch, report)?;
             let mut d = Document::new(bdoc.clone(), doc_type);
             apply_ttl(&mut d, &bdoc, opts.ttl_field.as_deref());
-            collection.insert_document(d);
-            report.inserted += 1;
+            batch.push(d, format!("\"index\":{i}"), Some(v.to_string()), report)?;
         }
-        return Ok(());
+        return batch.flush(report);
     }
     let mut reader = BufReader::new(reader);
     let mut line_no: usize = 0;
-    let mut sidecar = match &opts.error_sidecar {
-        Some(p) if opts.skip_errors => Some(std::fs::File::create(p)?),
-        _ => None,
-    };
     let mut buf = String::with_capacity(8 * 1024);
     loop {
         buf.clear();
@@ -51,36 +47,32 @@ pub fn import_ndjson<R: Read>(
         if line.is_empty() {
             continue;
         }
+        let locator = format!("\"line\":{line_no}");
+        let record = serde_json::Value::String(line.to_string()).to_string();
         match serde_json::from_str::<serde_json::Value>(line) {
             Ok(v) => {
-                let bdoc: BsonDocument = crate::utils::json::json_value_to_bson_document(&v)?;
+                let bdoc = to_document(&v, &mut batch, report)?;
                 let mut d = Document::new(bdoc.clone(), doc_type);
                 apply_ttl(&mut d, &bdoc, opts.ttl_field.as_deref());
-                collection.insert_document(d);
-                report.inserted += 1;
-                if let Some(n) = opts.progress_every
-                    && line_no % n == 0
-                {
-                    log::info!("imported {} records (ndjson)", report.inserted);
-                }
+                batch.push(d, locator, Some(record), report)?;
             }
             Err(e) => {
-                if let Some(f) = sidecar.as_mut() {
-                    let _ = writeln!(
-                        f,
-                        "{{\"line\":{},\"error\":\"{}\",\"record\":{}}}",
-                        line_no,
-                        escape_json(&e.to_string()),
-                        serde_json::Value::String(line.to_string())
-                    );
-                }
-                if opts.skip_errors {
-                    report.skipped += 1;
-                } else {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
-                }
+                let err = io::Error::new(io::ErrorKind::InvalidData, e);
+                batch.reject(&locator, Some(&record), err, report)?;
             }
         }
     }
-    Ok(())
+    batch.flush(report)
+}
+
+/// Convert a parsed record, inserting what is already queued before failing the import.
+fn to_document(
+    v: &serde_json::Value,
+    batch: &mut BatchInserter<'_>,
+    report: &mut ImportReport,
+) -> io::Result<BsonDocument> {
+    crate::utils::json::json_value_to_bson_document(v).or_else(|e| {
+        batch.flush(report)?;
+        Err(e)
+    })
 }
@@ -1,5 +1,12 @@
+use crate::collection::Collection;
 use crate::document::{Document, DocumentType};
+use crate::query::{BulkOp, BulkOptions, bulk_write};
 use bson::Document as BsonDocument;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use super::options::{ImportOptions, ImportReport};
 
 pub fn apply_ttl(doc: &mut Document, map: &BsonDocument, ttl_field: Option<&str>) {
     if !matches!(doc.metadata.document_type, DocumentType::Ephemeral) {
@@ -43,3 +50,112 @@ pub fn field_to_bson(field: &str, infer: bool) -> bson::Bson {
         _ => bson::Bson::String(field.to_string()),
     }
 }
+
+/// Buffers imported documents and inserts them `batch_size` at a time through
+/// [`crate::query::bulk_write`], keeping each record's sidecar context for error reporting.
+///
+/// With `skip_errors` the batches are unordered and each failed record is skipped; otherwise
+/// they are ordered and the first failure aborts the import.
+pub struct BatchInserter<'a> {
+    collection: &'a Arc<Collection>,
+    opts: &'a ImportOptions,
+    format: &'static str,
+    sidecar: Option<File>,
+    pending: Vec<BulkOp>,
+    // Sidecar context per pending document: the `"key":value` locating it and the record
+    context: Vec<(String, Option<String>)>,
+}
+
+impl<'a> BatchInserter<'a> {
+    pub fn new(
+        collection: &'a Arc<Collection>,
+        opts: &'a ImportOptions,
+        format: &'static str,
+    ) -> io::Result<Self> {
+        let sidecar = match &opts.error_sidecar {
+            Some(p) if opts.skip_errors => Some(File::create(p)?),
+            _ => None,
+        };
+        Ok(Self { collection, opts, format, sidecar, pending: Vec::new(), context: Vec::new() })
+    }
+
+    /// Queue `doc`, flushing once `batch_size` documents are pending.
+    pub fn push(
+        &mut self,
+        doc: Document,
+        locator: String,
+        record: Option<String>,
+        report: &mut ImportReport,
+    ) -> io::Result<()> {
+        self.pending.push(BulkOp::Insert(doc));
+        self.context.push((locator, record));
+        if self.pending.len() >= self.opts.batch_size.max(1) {
+            self.flush(report)?;
+        }
+        Ok(())
+    }
+
+    /// Record a record that failed before insertion (e.g. a parse error). Pending documents
+    /// are flushed first so records are reported and inserted in input order.
+    ///
+    /// # Errors
+    /// Returns `err` unless `skip_errors` is set.
+    pub fn reject(
+        &mut self,
+        locator: &str,
+        record: Option<&str>,
+        err: io::Error,
+        report: &mut ImportReport,
+    ) -> io::Result<()> {
+        self.flush(report)?;
+        self.write_sidecar(locator, record, &err.to_string());
+        if self.opts.skip_errors {
+            report.skipped += 1;
+            return Ok(());
+        }
+        Err(err)
+    }
+
+    /// Insert all pending documents.
+    pub fn flush(&mut self, report: &mut ImportReport) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let ops = std::mem::take(&mut self.pending);
+        let context = std::mem::take(&mut self.context);
+        let before = report.inserted;
+        let bulk_opts = BulkOptions { ordered: !self.opts.skip_errors };
+        let result = bulk_write(self.collection, ops, &bulk_opts).map_err(io::Error::other)?;
+        for (res, (locator, record)) in result.results.into_iter().zip(context) {
+            match res {
+                Ok(_) => report.inserted += 1,
+                Err(e) => {
+                    self.write_sidecar(&locator, record.as_deref(), &e.to_string());
+                    if !self.opts.skip_errors {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                    }
+                    report.skipped += 1;
+                }
+            }
+        }
+        if let Some(n) = self.opts.progress_every.map(crate::utils::num::usize_to_u64)
+            && n > 0
+            && report.inserted / n > before / n
+        {
+            log::info!("imported {} records ({})", report.inserted, self.format);
+        }
+        Ok(())
+    }
+
+    fn write_sidecar(&mut self, locator: &str, record: Option<&str>, error: &str) {
+        let Some(f) = self.sidecar.as_mut() else {
+            return;
+        };
+        let _ = match record {
+            Some(r) => {
+                writeln!(f, "{{{locator},\"error\":\"{}\",\"record\":{r}}}", escape_json(error))
+            }
+            None => writeln!(f, "{{{locator},\"error\":\"{}\"}}", escape_json(error)),
+        };
+    }
+}
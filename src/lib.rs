@@ -1,6 +1,7 @@
 #![forbid(unsafe_code)]
 
 pub mod api;
+pub mod async_db;
 // Load folder-based modules via explicit paths to avoid conflicts with legacy root files
 #[path = "cache/mod.rs"]
 pub mod cache_mod;
@@ -49,6 +50,7 @@ pub use recovery::wasp;
 #[cfg(test)]
 pub mod test_support;
 
+pub use crate::async_db::{AsyncCursor, AsyncDatabase};
 use crate::collection::Collection;
 use crate::document::Document;
 use crate::engine::Engine;
@@ -110,7 +112,9 @@ impl Database {
                         .get_collection(cname)
                         .unwrap_or_else(|| engine_arc.create_collection(cname.clone()));
                     for d in descs {
-                        col.create_index(&d.field, d.kind);
+                        if let Err(e) = col.create_index_with(d) {
+                            log::warn!("index rebuild skipped: {e}");
+                        }
                     }
                 }
             }
@@ -173,7 +177,9 @@ impl Database {
                     .get_collection(cname)
                     .unwrap_or_else(|| engine_arc.create_collection(cname.clone()));
                 for d in descs {
-                    col.create_index(&d.field, d.kind);
+                    if let Err(e) = col.create_index_with(d) {
+                        log::warn!("index rebuild skipped: {e}");
+                    }
                 }
             }
         }
@@ -205,7 +211,8 @@ impl Database {
 
     /// Inserts a document into the specified collection.
     /// # Errors
-    /// Returns an error if the collection doesn't exist.
+    /// Returns an error if the collection doesn't exist, or `DbError::DuplicateKey` if the
+    /// write would violate a unique index.
     pub fn insert_document(
         &self,
         collection_name: &str,
@@ -215,12 +222,13 @@ impl Database {
             .engine
             .get_collection(collection_name)
             .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
-        Ok(collection.insert_document(document))
+        collection.try_insert_document(document)
     }
 
     /// Updates a document in the specified collection.
     /// # Errors
-    /// Returns an error if the collection doesn't exist.
+    /// Returns an error if the collection doesn't exist, or `DbError::DuplicateKey` if the
+    /// write would violate a unique index.
     pub fn update_document(
         &self,
         collection_name: &str,
@@ -231,12 +239,12 @@ impl Database {
             .engine
             .get_collection(collection_name)
             .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
-        Ok(collection.update_document(document_id, new_document))
+        collection.try_update_document(document_id, new_document)
     }
 
     /// Deletes a document from the specified collection by its ID.
     /// # Errors
-    /// Returns an error if the collection doesn't exist.
+    /// Returns an error if the collection doesn't exist or storage rejects the write.
     pub fn delete_document(
         &self,
         collection_name: &str,
@@ -246,7 +254,7 @@ impl Database {
             .engine
             .get_collection(collection_name)
             .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
-        Ok(collection.delete_document(document_id))
+        collection.try_delete_document(document_id)
     }
 
     /// Lists the names of all collections.
@@ -262,9 +270,23 @@ impl Database {
         self.engine.rename_collection(old, new)
     }
 
+    /// How `find` and `count` split large scans across threads.
+    #[must_use]
+    pub fn parallel_scan(&self) -> crate::query::ParallelScan {
+        self.engine.parallel_scan()
+    }
+
+    /// Change how `find` and `count` split large scans across threads, for every collection
+    /// of this database. [`ParallelScan::serial`](crate::query::ParallelScan::serial) turns
+    /// splitting off.
+    pub fn set_parallel_scan(&self, config: crate::query::ParallelScan) {
+        self.engine.set_parallel_scan(config);
+    }
+
     // --- Query API (façade over query module) ---
     /// # Errors
-    /// Returns an error if the collection doesn't exist.
+    /// Returns an error if the collection doesn't exist or `opts.after` is not a valid resume
+    /// token for this sort.
     pub fn find(
         &self,
         collection_name: &str,
@@ -275,7 +297,58 @@ impl Database {
             .engine
             .get_collection(collection_name)
             .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
-        Ok(crate::query::find_docs(&col, filter, opts))
+        crate::query::find_docs_rate_limited(&col, filter, opts)
+    }
+
+    /// Plan and run a find, returning the chosen access path, the candidates it beat, and
+    /// per-stage estimated versus actual counts and timings.
+    /// # Errors
+    /// Returns an error if the collection doesn't exist.
+    pub fn explain(
+        &self,
+        collection_name: &str,
+        filter: &crate::query::Filter,
+        opts: &crate::query::FindOptions,
+    ) -> Result<crate::query::ExplainReport, DbError> {
+        let col = self
+            .engine
+            .get_collection(collection_name)
+            .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
+        Ok(crate::query::explain(&col, filter, opts))
+    }
+
+    /// The documents whose vectors are nearest to the query's, most similar first, with
+    /// their scores.
+    /// # Errors
+    /// Returns an error if the collection doesn't exist, the field has no vector index, or the
+    /// query vector does not match the index's dimensions.
+    pub fn vector_search(
+        &self,
+        collection_name: &str,
+        query: &crate::query::VectorQuery,
+    ) -> Result<Vec<(crate::document::Document, f64)>, DbError> {
+        let col = self
+            .engine
+            .get_collection(collection_name)
+            .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
+        crate::query::vector_search(&col, query)
+    }
+
+    /// Run an aggregation pipeline (`$vectorSearch`, `$match`, `$group`, `$project`, `$sort`,
+    /// `$unwind`, `$limit`, `$count`, `$lookup`) over a collection.
+    /// # Errors
+    /// Returns an error if the collection or a `$lookup` collection doesn't exist, or the
+    /// pipeline is too long.
+    pub fn aggregate(
+        &self,
+        collection_name: &str,
+        pipeline: &[crate::query::Stage],
+    ) -> Result<Vec<bson::Document>, DbError> {
+        let col = self
+            .engine
+            .get_collection(collection_name)
+            .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
+        crate::query::aggregate_with(&col, pipeline, |name| self.engine.get_collection(name))
     }
 
     /// # Errors
@@ -293,7 +366,8 @@ impl Database {
     }
 
     /// # Errors
-    /// Returns an error if the collection doesn't exist.
+    /// Returns an error if the collection doesn't exist, or `DbError::DuplicateKey` if the
+    /// write would violate a unique index.
     pub fn update_many(
         &self,
         collection_name: &str,
@@ -304,11 +378,12 @@ impl Database {
             .engine
             .get_collection(collection_name)
             .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
-        Ok(crate::query::update_many(&col, filter, update))
+        crate::query::update_many(&col, filter, update)
     }
 
     /// # Errors
-    /// Returns an error if the collection doesn't exist.
+    /// Returns an error if the collection doesn't exist, or `DbError::DuplicateKey` if the
+    /// write would violate a unique index.
     pub fn update_one(
         &self,
         collection_name: &str,
@@ -319,7 +394,114 @@ impl Database {
             .engine
             .get_collection(collection_name)
             .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
-        Ok(crate::query::update_one(&col, filter, update))
+        crate::query::update_one(&col, filter, update)
+    }
+
+    /// [`Database::update_many`] with options, e.g. `upsert`.
+    ///
+    /// # Errors
+    /// As for [`Database::update_many`].
+    pub fn update_many_with(
+        &self,
+        collection_name: &str,
+        filter: &crate::query::Filter,
+        update: &crate::query::UpdateDoc,
+        opts: &crate::query::UpdateOptions,
+    ) -> Result<crate::query::UpdateReport, DbError> {
+        let col = self
+            .engine
+            .get_collection(collection_name)
+            .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
+        crate::query::update_many_with(&col, filter, update, opts)
+    }
+
+    /// [`Database::update_one`] with options, e.g. `upsert`.
+    ///
+    /// # Errors
+    /// As for [`Database::update_one`].
+    pub fn update_one_with(
+        &self,
+        collection_name: &str,
+        filter: &crate::query::Filter,
+        update: &crate::query::UpdateDoc,
+        opts: &crate::query::UpdateOptions,
+    ) -> Result<crate::query::UpdateReport, DbError> {
+        let col = self
+            .engine
+            .get_collection(collection_name)
+            .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
+        crate::query::update_one_with(&col, filter, update, opts)
+    }
+
+    /// Atomically update and return one document; see [`crate::query::find_one_and_update`].
+    ///
+    /// # Errors
+    /// Returns an error if the collection doesn't exist, or as for the query function.
+    pub fn find_one_and_update(
+        &self,
+        collection_name: &str,
+        filter: &crate::query::Filter,
+        update: &crate::query::UpdateDoc,
+        opts: &crate::query::FindAndModifyOptions,
+    ) -> Result<Option<crate::document::Document>, DbError> {
+        let col = self
+            .engine
+            .get_collection(collection_name)
+            .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
+        crate::query::find_one_and_update(&col, filter, update, opts)
+    }
+
+    /// Atomically replace and return one document; see [`crate::query::find_one_and_replace`].
+    ///
+    /// # Errors
+    /// Returns an error if the collection doesn't exist, or as for the query function.
+    pub fn find_one_and_replace(
+        &self,
+        collection_name: &str,
+        filter: &crate::query::Filter,
+        replacement: &bson::Document,
+        opts: &crate::query::FindAndModifyOptions,
+    ) -> Result<Option<crate::document::Document>, DbError> {
+        let col = self
+            .engine
+            .get_collection(collection_name)
+            .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
+        crate::query::find_one_and_replace(&col, filter, replacement, opts)
+    }
+
+    /// Atomically delete and return one document; see [`crate::query::find_one_and_delete`].
+    ///
+    /// # Errors
+    /// Returns an error if the collection doesn't exist.
+    pub fn find_one_and_delete(
+        &self,
+        collection_name: &str,
+        filter: &crate::query::Filter,
+        opts: &crate::query::FindAndModifyOptions,
+    ) -> Result<Option<crate::document::Document>, DbError> {
+        let col = self
+            .engine
+            .get_collection(collection_name)
+            .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
+        Ok(crate::query::find_one_and_delete(&col, filter, opts))
+    }
+
+    /// Apply a batch of writes to one collection; see [`crate::query::bulk_write`].
+    ///
+    /// # Errors
+    /// Returns an error if the collection doesn't exist or storage rejects the batch.
+    /// Per-operation failures are reported in the returned [`crate::query::BulkWriteReport`].
+    pub fn bulk_write(
+        &self,
+        collection_name: &str,
+        ops: Vec<crate::query::BulkOp>,
+        opts: &crate::query::BulkOptions,
+    ) -> Result<crate::query::BulkWriteReport, DbError> {
+        let col = self
+            .engine
+            .get_collection(collection_name)
+            .ok_or_else(|| DbError::NoSuchCollection(collection_name.to_string()))?;
+        crate::query::bulk_write(&col, ops, opts)
     }
 
     /// # Errors
@@ -0,0 +1,792 @@
+use crate::collection::Collection;
+use crate::errors::DbError;
+use crate::index::IndexKeyKind;
+use bson::{Bson, Document as BsonDocument};
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::eval::{compare_bson, compare_docs, eval_filter, path_values};
+use super::expr::Expr;
+use super::geo::{self, GEO_DISTANCE};
+use super::numeric::to_f64;
+use super::parse::FilterSerde;
+use super::plan;
+use super::project::{insert_path, project_fields, segments};
+use super::text::{self, TEXT_SCORE};
+use super::types::{
+    Filter, MAX_IN_SET, MAX_PATH_DEPTH, MAX_PIPELINE_STAGES, MAX_PROJECTION_FIELDS, Order, SortSpec,
+};
+use super::vector::{self, VECTOR_SCORE, VectorQuery};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Accumulator {
+    Sum(Expr),
+    Avg(Expr),
+    Min(Expr),
+    Max(Expr),
+    Push(Expr),
+    AddToSet(Expr),
+    First(Expr),
+    Last(Expr),
+}
+
+/// `{"$group": {"_id": <expr>, "<name>": {"$<acc>": <expr>}, ..}}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupSpec {
+    pub id: Expr,
+    pub fields: Vec<(String, Accumulator)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Stage {
+    Match(Filter),
+    Group(GroupSpec),
+    Project(Vec<String>),
+    /// `$addFields`/`$set`: set each path to its expression's value, evaluated against the
+    /// input document. A missing value leaves the field as it was.
+    AddFields(Vec<(String, Expr)>),
+    Sort(Vec<SortSpec>),
+    Unwind {
+        path: String,
+        preserve_null_and_empty: bool,
+    },
+    Limit(usize),
+    Count(String),
+    /// Equi-join: embed the documents of `from` whose `foreign_field` equals a value of
+    /// `local_field` as an array under `as_field`.
+    Lookup {
+        from: String,
+        local_field: String,
+        foreign_field: String,
+        as_field: String,
+    },
+    /// k-nearest-neighbour search of a vector index; only valid as the first stage. Each
+    /// result carries its score for `{"$meta": "vectorSearchScore"}`.
+    VectorSearch(VectorQuery),
+}
+
+type DocStream<'a> = Box<dyn Iterator<Item = BsonDocument> + 'a>;
+
+/// Run `pipeline` over `col`. `$lookup` stages may only join `col` with itself; use
+/// [`aggregate_with`] to reach other collections.
+///
+/// # Errors
+/// Returns `DbError::QueryError` if the pipeline has more than `MAX_PIPELINE_STAGES` stages,
+/// or `DbError::NoSuchCollection` if a `$lookup` names another collection.
+pub fn aggregate(col: &Arc<Collection>, pipeline: &[Stage]) -> Result<Vec<BsonDocument>, DbError> {
+    let name = col.name_str();
+    aggregate_with(col, pipeline, |from| (from == name).then(|| Arc::clone(col)))
+}
+
+/// Run `pipeline` over `col`, resolving `$lookup` collections through `resolve`.
+///
+/// Leading `$match` stages (and a `$sort` directly after them) are pushed down: the combined
+/// filter goes through the index planner and only matching documents are sorted. The rest of
+/// the pipeline streams, except `$group` and `$sort`, which consume their whole input.
+///
+/// # Errors
+/// Returns `DbError::QueryError` if the pipeline has more than `MAX_PIPELINE_STAGES` stages,
+/// has `$vectorSearch` anywhere but first or the vector search fails, or
+/// `DbError::NoSuchCollection` if `resolve` does not know a `$lookup` collection.
+pub fn aggregate_with(
+    col: &Arc<Collection>,
+    pipeline: &[Stage],
+    resolve: impl Fn(&str) -> Option<Arc<Collection>>,
+) -> Result<Vec<BsonDocument>, DbError> {
+    if pipeline.len() > MAX_PIPELINE_STAGES {
+        return Err(DbError::QueryError(format!(
+            "pipeline has {} stages; at most {MAX_PIPELINE_STAGES} are allowed",
+            pipeline.len()
+        )));
+    }
+    let mut foreign: HashMap<&str, Foreign> = HashMap::new();
+    for stage in pipeline {
+        if let Stage::Lookup { from, .. } = stage
+            && !foreign.contains_key(from.as_str())
+        {
+            let col = resolve(from).ok_or_else(|| DbError::NoSuchCollection(from.clone()))?;
+            foreign.insert(from, Foreign { col, all: OnceCell::new() });
+        }
+    }
+    if pipeline.iter().skip(1).any(|s| matches!(s, Stage::VectorSearch(_))) {
+        return Err(DbError::QueryError("$vectorSearch must be the first stage".into()));
+    }
+    if let [Stage::VectorSearch(q), rest @ ..] = pipeline {
+        let hits = vector::vector_search(col, q)?;
+        let mut stream: DocStream<'_> = Box::new(hits.into_iter().map(|(d, score)| {
+            let mut d = d.data.0;
+            d.insert(VECTOR_SCORE, score);
+            d
+        }));
+        for stage in rest {
+            stream = apply_stage(stream, stage, &foreign);
+        }
+        return Ok(stream
+            .map(|mut d| {
+                d.remove(VECTOR_SCORE);
+                d
+            })
+            .collect());
+    }
+
+    let mut rest = pipeline;
+    let mut filters = Vec::new();
+    while let [Stage::Match(f), tail @ ..] = rest {
+        filters.push(f.clone());
+        rest = tail;
+    }
+    let filter = match filters.len() {
+        0 => Filter::True,
+        1 => filters.remove(0),
+        _ => Filter::And(filters),
+    };
+    let sort = match rest {
+        [Stage::Sort(s), tail @ ..] => {
+            rest = tail;
+            Some(s)
+        }
+        _ => None,
+    };
+
+    let filter = text::bind(col, &filter).into_owned();
+    let (chosen, _) = plan::choose_plan(col, &filter);
+    let ids = plan::fetch_ids(col, &chosen.path).unwrap_or_else(|| col.list_ids());
+    let search = text::scoring_search(&filter).cloned();
+    let scored = search.is_some();
+    let near = geo::near_search(&filter).map(|(path, n)| (path.to_string(), n.clone()));
+    let measured = near.is_some();
+    let mut stream: DocStream<'_> = Box::new(
+        ids.into_iter()
+            .filter_map(|id| col.find_document(&id))
+            .map(|d| d.data.0)
+            .filter(move |d| eval_filter(d, &filter))
+            .map(move |mut d| {
+                if let Some(search) = &search {
+                    text::add_score(&mut d, search);
+                }
+                if let Some((path, near)) = &near {
+                    geo::add_distance(&mut d, path, near);
+                }
+                d
+            }),
+    );
+    if let Some(sort) = sort {
+        stream = sorted(stream, sort);
+    }
+    for stage in rest {
+        stream = apply_stage(stream, stage, &foreign);
+    }
+    if scored || measured {
+        stream = Box::new(stream.map(|mut d| {
+            d.remove(TEXT_SCORE);
+            d.remove(GEO_DISTANCE);
+            d
+        }));
+    }
+    Ok(stream.collect())
+}
+
+/// A `$lookup` target. Without a usable index the whole collection is read once, on first
+/// use, and shared by every input document.
+struct Foreign {
+    col: Arc<Collection>,
+    all: OnceCell<Vec<BsonDocument>>,
+}
+
+fn apply_stage<'a>(
+    input: DocStream<'a>,
+    stage: &'a Stage,
+    foreign: &'a HashMap<&str, Foreign>,
+) -> DocStream<'a> {
+    match stage {
+        Stage::Match(f) => Box::new(input.filter(move |d| eval_filter(d, f))),
+        Stage::Group(spec) => Box::new(group(input, spec).into_iter()),
+        Stage::Project(fields) => {
+            let fields = &fields[..fields.len().min(MAX_PROJECTION_FIELDS)];
+            Box::new(input.map(move |d| project_fields(&d, fields)))
+        }
+        Stage::AddFields(fields) => Box::new(input.map(move |mut d| {
+            let values: Vec<Option<Bson>> = fields.iter().map(|(_, e)| e.eval(&d)).collect();
+            for ((path, _), v) in fields.iter().zip(values) {
+                if let Some(v) = v {
+                    insert_path(&mut d, &segments(path), v);
+                }
+            }
+            d
+        })),
+        Stage::Sort(spec) => sorted(input, spec),
+        Stage::Unwind { path, preserve_null_and_empty } => {
+            Box::new(input.flat_map(move |d| unwind(d, path, *preserve_null_and_empty)))
+        }
+        Stage::Limit(n) => Box::new(input.take(*n)),
+        Stage::Count(name) => {
+            let n = input.count();
+            let out = (n > 0).then(|| {
+                let mut d = BsonDocument::new();
+                d.insert(name.clone(), i64::try_from(n).unwrap_or(i64::MAX));
+                d
+            });
+            Box::new(out.into_iter())
+        }
+        Stage::Lookup { from, local_field, foreign_field, as_field } => {
+            let target = &foreign[from.as_str()];
+            Box::new(input.map(move |mut d| {
+                let joined = join(target, &d, local_field, foreign_field);
+                d.insert(as_field.clone(), Bson::Array(joined));
+                d
+            }))
+        }
+        // Rejected by `aggregate_with` anywhere but first, where it is run before the stream
+        Stage::VectorSearch(_) => input,
+    }
+}
+
+/// Foreign documents whose `foreign_field` equals any value of `local_field` (array values
+/// match element-wise). A missing or null local value matches a missing or null foreign one.
+fn join(target: &Foreign, doc: &BsonDocument, local_field: &str, foreign_field: &str) -> Vec<Bson> {
+    let mut values: Vec<Bson> = Vec::new();
+    for v in path_values(doc, local_field) {
+        match v {
+            Bson::Array(items) => values.extend(items.iter().cloned()),
+            other => values.push(other.clone()),
+        }
+    }
+    if values.is_empty() {
+        values.push(Bson::Null);
+    }
+    values.truncate(MAX_IN_SET);
+    let wants_null = values.contains(&Bson::Null);
+    let mut filter = Filter::In { path: foreign_field.to_string(), values };
+    if wants_null {
+        filter = Filter::Or(vec![
+            filter,
+            Filter::Exists { path: foreign_field.to_string(), exists: false },
+        ]);
+    }
+
+    // The planner picks the foreign field's index for the `$in` when there is one.
+    let (chosen, _) = plan::choose_plan(&target.col, &filter);
+    match plan::fetch_ids(&target.col, &chosen.path) {
+        Some(ids) => ids
+            .iter()
+            .filter_map(|id| target.col.find_document(id))
+            .filter(|d| eval_filter(&d.data.0, &filter))
+            .map(|d| Bson::Document(d.data.0))
+            .collect(),
+        None => target
+            .all
+            .get_or_init(|| target.col.get_all_documents().into_iter().map(|d| d.data.0).collect())
+            .iter()
+            .filter(|d| eval_filter(d, &filter))
+            .map(|d| Bson::Document(d.clone()))
+            .collect(),
+    }
+}
+
+fn sorted<'a>(input: DocStream<'a>, spec: &[SortSpec]) -> DocStream<'a> {
+    let mut docs: Vec<BsonDocument> = input.collect();
+    docs.sort_by(|a, b| compare_docs(a, b, spec));
+    Box::new(docs.into_iter())
+}
+
+fn unwind(doc: BsonDocument, path: &str, preserve: bool) -> Vec<BsonDocument> {
+    match lookup(&doc, path) {
+        Some(Bson::Array(items)) if !items.is_empty() => items
+            .clone()
+            .into_iter()
+            .map(|item| {
+                let mut d = doc.clone();
+                set_path(&mut d, path, item);
+                d
+            })
+            .collect(),
+        Some(Bson::Array(_)) if preserve => {
+            let mut d = doc;
+            remove_path(&mut d, path);
+            vec![d]
+        }
+        None | Some(Bson::Null) if preserve => vec![doc],
+        Some(Bson::Array(_) | Bson::Null) | None => Vec::new(),
+        // A scalar unwinds to itself
+        Some(_) => vec![doc],
+    }
+}
+
+/// Follow `path` through sub-documents only.
+fn lookup<'a>(doc: &'a BsonDocument, path: &str) -> Option<&'a Bson> {
+    let (head, tail) = path.split_once('.').map_or((path, None), |(h, t)| (h, Some(t)));
+    match (doc.get(head)?, tail) {
+        (v, None) => Some(v),
+        (Bson::Document(sub), Some(t)) => lookup(sub, t),
+        _ => None,
+    }
+}
+
+fn set_path(doc: &mut BsonDocument, path: &str, value: Bson) {
+    match path.split_once('.') {
+        None => {
+            doc.insert(path, value);
+        }
+        Some((head, tail)) => {
+            if let Some(Bson::Document(sub)) = doc.get_mut(head) {
+                set_path(sub, tail, value);
+            }
+        }
+    }
+}
+
+fn remove_path(doc: &mut BsonDocument, path: &str) {
+    match path.split_once('.') {
+        None => {
+            doc.remove(path);
+        }
+        Some((head, tail)) => {
+            if let Some(Bson::Document(sub)) = doc.get_mut(head) {
+                remove_path(sub, tail);
+            }
+        }
+    }
+}
+
+/// Group keys compare numbers by value (`1`, `1.0` and `1i64` share a group). Other values use
+/// their index key, and values without one (arrays, sub-documents) their encoded bytes.
+#[derive(PartialEq, Eq, Hash)]
+enum GroupKey {
+    Int(i64),
+    Float(u64),
+    Key(IndexKeyKind),
+    Raw(Vec<u8>),
+}
+
+impl GroupKey {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    fn of(v: &Bson) -> Self {
+        match v {
+            Bson::Int32(i) => return Self::Int(i64::from(*i)),
+            Bson::Int64(i) => return Self::Int(*i),
+            Bson::Double(_) | Bson::Decimal128(_) => {
+                let f = to_f64(v).unwrap_or(f64::NAN);
+                let i = f as i64;
+                return if f.fract() == 0.0 && i as f64 == f {
+                    Self::Int(i)
+                } else {
+                    Self::Float(f.to_bits())
+                };
+            }
+            _ => {}
+        }
+        if let Some(k) = crate::index::key_from_bson(v) {
+            return Self::Key(k);
+        }
+        let mut wrapper = BsonDocument::new();
+        wrapper.insert("k", v.clone());
+        Self::Raw(wrapper.to_vec().unwrap_or_default())
+    }
+}
+
+fn group(input: DocStream<'_>, spec: &GroupSpec) -> Vec<BsonDocument> {
+    let mut slots: HashMap<GroupKey, usize> = HashMap::new();
+    let mut groups: Vec<(Bson, Vec<AccState>)> = Vec::new();
+    for doc in input {
+        let id = spec.id.eval(&doc).unwrap_or(Bson::Null);
+        let slot = *slots.entry(GroupKey::of(&id)).or_insert_with(|| {
+            groups.push((id, spec.fields.iter().map(|(_, a)| AccState::new(a)).collect()));
+            groups.len() - 1
+        });
+        for ((_, acc), state) in spec.fields.iter().zip(groups[slot].1.iter_mut()) {
+            state.add(acc.expr().eval(&doc));
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(id, states)| {
+            let mut out = BsonDocument::new();
+            out.insert("_id", id);
+            for ((name, _), state) in spec.fields.iter().zip(states) {
+                out.insert(name.clone(), state.finish());
+            }
+            out
+        })
+        .collect()
+}
+
+impl Accumulator {
+    const fn expr(&self) -> &Expr {
+        match self {
+            Self::Sum(e)
+            | Self::Avg(e)
+            | Self::Min(e)
+            | Self::Max(e)
+            | Self::Push(e)
+            | Self::AddToSet(e)
+            | Self::First(e)
+            | Self::Last(e) => e,
+        }
+    }
+}
+
+enum AccState {
+    /// Integers are summed exactly until a double shows up or the sum overflows.
+    Sum {
+        int: i64,
+        float: f64,
+        long: bool,
+        is_float: bool,
+    },
+    Avg {
+        sum: f64,
+        n: u64,
+    },
+    Min(Option<Bson>),
+    Max(Option<Bson>),
+    Push(Vec<Bson>),
+    AddToSet(Vec<Bson>),
+    First(Option<Bson>),
+    Last(Bson),
+}
+
+impl AccState {
+    const fn new(acc: &Accumulator) -> Self {
+        match acc {
+            Accumulator::Sum(_) => Self::Sum { int: 0, float: 0.0, long: false, is_float: false },
+            Accumulator::Avg(_) => Self::Avg { sum: 0.0, n: 0 },
+            Accumulator::Min(_) => Self::Min(None),
+            Accumulator::Max(_) => Self::Max(None),
+            Accumulator::Push(_) => Self::Push(Vec::new()),
+            Accumulator::AddToSet(_) => Self::AddToSet(Vec::new()),
+            Accumulator::First(_) => Self::First(None),
+            Accumulator::Last(_) => Self::Last(Bson::Null),
+        }
+    }
+
+    fn add(&mut self, v: Option<Bson>) {
+        match self {
+            Self::Sum { int, float, long, is_float } => match v {
+                Some(Bson::Int32(i)) => add_int(int, float, is_float, i64::from(i)),
+                Some(Bson::Int64(i)) => {
+                    *long = true;
+                    add_int(int, float, is_float, i);
+                }
+                Some(other) => {
+                    if let Some(f) = to_f64(&other) {
+                        *is_float = true;
+                        *float += f;
+                    }
+                }
+                None => {}
+            },
+            Self::Avg { sum, n } => {
+                if let Some(f) = v.as_ref().and_then(to_f64) {
+                    *sum += f;
+                    *n += 1;
+                }
+            }
+            Self::Min(cur) => keep_extreme(cur, v, Ordering::Less),
+            Self::Max(cur) => keep_extreme(cur, v, Ordering::Greater),
+            Self::Push(items) => items.extend(v),
+            Self::AddToSet(items) => {
+                if let Some(v) = v
+                    && !items.contains(&v)
+                {
+                    items.push(v);
+                }
+            }
+            Self::First(first) => {
+                if first.is_none() {
+                    *first = Some(v.unwrap_or(Bson::Null));
+                }
+            }
+            Self::Last(last) => *last = v.unwrap_or(Bson::Null),
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn finish(self) -> Bson {
+        match self {
+            Self::Sum { int, float, long, is_float } => {
+                if is_float {
+                    Bson::Double(int as f64 + float)
+                } else if let (false, Ok(i)) = (long, i32::try_from(int)) {
+                    Bson::Int32(i)
+                } else {
+                    Bson::Int64(int)
+                }
+            }
+            Self::Avg { sum, n } => {
+                if n == 0 {
+                    Bson::Null
+                } else {
+                    Bson::Double(sum / n as f64)
+                }
+            }
+            Self::Min(v) | Self::Max(v) | Self::First(v) => v.unwrap_or(Bson::Null),
+            Self::Push(items) | Self::AddToSet(items) => Bson::Array(items),
+            Self::Last(v) => v,
+        }
+    }
+}
+
+/// Add to the exact integer sum, switching to floating point on overflow.
+#[allow(clippy::cast_precision_loss)]
+fn add_int(int: &mut i64, float: &mut f64, is_float: &mut bool, i: i64) {
+    if let Some(sum) = int.checked_add(i) {
+        *int = sum;
+    } else {
+        *is_float = true;
+        *float += i as f64;
+    }
+}
+
+/// `$min`/`$max` ignore missing and null values.
+fn keep_extreme(cur: &mut Option<Bson>, v: Option<Bson>, want: Ordering) {
+    let Some(v) = v.filter(|v| !matches!(v, Bson::Null)) else { return };
+    if cur.as_ref().is_none_or(|c| compare_bson(&v, c) == want) {
+        *cur = Some(v);
+    }
+}
+
+// --- JSON parsing ---
+
+fn invalid(msg: impl Into<String>) -> DbError {
+    DbError::QueryError(msg.into())
+}
+
+/// Parse a JSON array of stage objects, e.g.
+/// `[{"$match": {"field":"age","$gte":18}}, {"$group": {"_id": "$city", "n": {"$sum": 1}}}]`.
+///
+/// `$match` takes the same filter JSON as `parse_filter_json`; `$project` takes either a
+/// field list or `{"field": 1}`, where a field may instead be computed by an expression (see
+/// [`Expr::parse`]), as `$addFields`/`$set` take `{"field": <expr>}`; `$sort` takes
+/// `{"field": 1 | -1}` in priority order; `$lookup` takes `{"from", "localField", "foreignField", "as"}`.
+/// `$vectorSearch` takes the options [`VectorQuery::parse`] reads.
+///
+/// # Errors
+/// Returns `DbError::QueryError` for malformed JSON, unknown stages or accumulators, or bad
+/// stage arguments.
+pub fn parse_pipeline_json(json: &str) -> Result<Vec<Stage>, DbError> {
+    let stages: Vec<BsonDocument> = serde_json::from_str(json)?;
+    if stages.len() > MAX_PIPELINE_STAGES {
+        return Err(invalid(format!("pipeline has more than {MAX_PIPELINE_STAGES} stages")));
+    }
+    let mut out = Vec::with_capacity(stages.len());
+    for stage in stages {
+        parse_stage(stage, &mut out)?;
+    }
+    Ok(out)
+}
+
+/// Append the stage(s) `stage` parses to.
+fn parse_stage(stage: BsonDocument, out: &mut Vec<Stage>) -> Result<(), DbError> {
+    let mut it = stage.into_iter();
+    let (Some((name, arg)), None) = (it.next(), it.next()) else {
+        return Err(invalid("each pipeline stage must have exactly one key"));
+    };
+    let parsed = match name.as_str() {
+        "$match" => {
+            let fs: FilterSerde =
+                bson::deserialize_from_bson(arg).map_err(|e| invalid(format!("$match: {e}")))?;
+            Stage::Match(Filter::try_from(fs)?)
+        }
+        "$group" => Stage::Group(parse_group(arg)?),
+        "$project" => {
+            let (fields, computed) = parse_projection(arg)?;
+            // Computed fields are added first, then kept like any other listed field
+            if !computed.is_empty() {
+                out.push(Stage::AddFields(computed));
+            }
+            Stage::Project(fields)
+        }
+        "$addFields" | "$set" => Stage::AddFields(parse_add_fields(arg)?),
+        "$sort" => Stage::Sort(parse_sort(arg)?),
+        "$unwind" => parse_unwind(arg)?,
+        "$limit" => Stage::Limit(
+            as_count(&arg).ok_or_else(|| invalid("$limit requires a non-negative integer"))?,
+        ),
+        "$lookup" => parse_lookup(arg)?,
+        "$vectorSearch" => Stage::VectorSearch(VectorQuery::parse(arg)?),
+        "$count" => match arg {
+            Bson::String(s) if !s.is_empty() && !s.starts_with('$') && !s.contains('.') => {
+                Stage::Count(s)
+            }
+            _ => return Err(invalid("$count requires a plain field name")),
+        },
+        other => return Err(invalid(format!("unknown pipeline stage '{other}'"))),
+    };
+    out.push(parsed);
+    Ok(())
+}
+
+fn parse_group(arg: Bson) -> Result<GroupSpec, DbError> {
+    let Bson::Document(mut d) = arg else {
+        return Err(invalid("$group requires an object"));
+    };
+    let id = d.remove("_id").ok_or_else(|| invalid("$group requires an _id"))?;
+    let mut fields = Vec::new();
+    for (name, spec) in d {
+        let Bson::Document(spec) = spec else {
+            return Err(invalid(format!("$group field '{name}' must be an accumulator object")));
+        };
+        let mut it = spec.into_iter();
+        let (Some((op, e)), None) = (it.next(), it.next()) else {
+            return Err(invalid(format!("$group field '{name}' must name one accumulator")));
+        };
+        let e = Expr::parse(e)?;
+        let acc = match op.as_str() {
+            "$sum" => Accumulator::Sum(e),
+            "$avg" => Accumulator::Avg(e),
+            "$min" => Accumulator::Min(e),
+            "$max" => Accumulator::Max(e),
+            "$push" => Accumulator::Push(e),
+            "$addToSet" => Accumulator::AddToSet(e),
+            "$first" => Accumulator::First(e),
+            "$last" => Accumulator::Last(e),
+            other => return Err(invalid(format!("unknown accumulator '{other}'"))),
+        };
+        fields.push((name, acc));
+    }
+    Ok(GroupSpec { id: Expr::parse(id)?, fields })
+}
+
+/// The fields `$project` keeps, and those among them it computes.
+type ProjectSpec = (Vec<String>, Vec<(String, Expr)>);
+
+fn parse_projection(arg: Bson) -> Result<ProjectSpec, DbError> {
+    match arg {
+        Bson::Array(items) => Ok((
+            items
+                .into_iter()
+                .map(|v| match v {
+                    Bson::String(s) => Ok(s),
+                    _ => Err(invalid("$project list entries must be field names")),
+                })
+                .collect::<Result<_, _>>()?,
+            Vec::new(),
+        )),
+        Bson::Document(d) => {
+            let (mut fields, mut computed) = (Vec::new(), Vec::new());
+            for (k, v) in d {
+                match v {
+                    Bson::Boolean(b) => {
+                        if b {
+                            fields.push(k);
+                        }
+                    }
+                    Bson::String(ref s) if s.starts_with('$') => {
+                        computed.push((field_name("$project", &k)?, Expr::parse(v)?));
+                        fields.push(k);
+                    }
+                    Bson::Document(ref e) if e.keys().any(|op| op.starts_with('$')) => {
+                        computed.push((field_name("$project", &k)?, Expr::parse(v)?));
+                        fields.push(k);
+                    }
+                    other => {
+                        if as_count(&other).is_some_and(|n| n != 0) {
+                            fields.push(k);
+                        }
+                    }
+                }
+            }
+            Ok((fields, computed))
+        }
+        _ => Err(invalid("$project requires a field list or an object")),
+    }
+}
+
+fn parse_add_fields(arg: Bson) -> Result<Vec<(String, Expr)>, DbError> {
+    let Bson::Document(d) = arg else {
+        return Err(invalid("$addFields requires an object"));
+    };
+    if d.len() > MAX_PROJECTION_FIELDS {
+        return Err(invalid(format!("$addFields sets at most {MAX_PROJECTION_FIELDS} fields")));
+    }
+    d.into_iter().map(|(k, v)| Ok((field_name("$addFields", &k)?, Expr::parse(v)?))).collect()
+}
+
+/// `path` if it can name a computed field: a non-empty dotted path, not starting with `$`,
+/// of at most `MAX_PATH_DEPTH` segments.
+fn field_name(stage: &str, path: &str) -> Result<String, DbError> {
+    let depth = path.split('.').count();
+    if path.starts_with('$') || path.split('.').any(str::is_empty) || depth > MAX_PATH_DEPTH {
+        return Err(invalid(format!("{stage}: invalid field path '{path}'")));
+    }
+    Ok(path.to_string())
+}
+
+fn parse_sort(arg: Bson) -> Result<Vec<SortSpec>, DbError> {
+    let Bson::Document(d) = arg else {
+        return Err(invalid("$sort requires an object"));
+    };
+    if d.is_empty() {
+        return Err(invalid("$sort requires at least one field"));
+    }
+    d.into_iter()
+        .map(|(field, dir)| {
+            if let Bson::Document(m) = &dir
+                && m.len() == 1
+            {
+                match m.get_str("$meta") {
+                    Ok("textScore") => return Ok(SortSpec::text_score()),
+                    Ok("vectorSearchScore") => {
+                        return Ok(SortSpec { field: VECTOR_SCORE.into(), order: Order::Desc });
+                    }
+                    Ok("geoNearDistance") => return Ok(SortSpec::geo_distance()),
+                    _ => {}
+                }
+            }
+            let order = match dir {
+                Bson::Int32(1) | Bson::Int64(1) => Order::Asc,
+                Bson::Int32(-1) | Bson::Int64(-1) => Order::Desc,
+                Bson::Double(1.0) => Order::Asc,
+                Bson::Double(-1.0) => Order::Desc,
+                _ => return Err(invalid(format!("$sort direction for '{field}' must be 1 or -1"))),
+            };
+            Ok(SortSpec { field, order })
+        })
+        .collect()
+}
+
+fn parse_unwind(arg: Bson) -> Result<Stage, DbError> {
+    let (path, preserve) = match arg {
+        Bson::String(s) => (s, false),
+        Bson::Document(d) => {
+            let path = d.get_str("path").map_err(|_| invalid("$unwind requires a path"))?;
+            let preserve = d.get_bool("preserveNullAndEmptyArrays").unwrap_or(false);
+            (path.to_string(), preserve)
+        }
+        _ => return Err(invalid("$unwind requires a \"$field\" path")),
+    };
+    match path.strip_prefix('$') {
+        Some(p) if !p.is_empty() => {
+            Ok(Stage::Unwind { path: p.to_string(), preserve_null_and_empty: preserve })
+        }
+        _ => Err(invalid("$unwind path must start with '$'")),
+    }
+}
+
+fn parse_lookup(arg: Bson) -> Result<Stage, DbError> {
+    let Bson::Document(d) = arg else {
+        return Err(invalid("$lookup requires an object"));
+    };
+    let field = |key: &str| match d.get(key) {
+        Some(Bson::String(s)) if !s.is_empty() && !s.starts_with('$') => Ok(s.clone()),
+        _ => Err(invalid(format!("$lookup requires a '{key}' field name"))),
+    };
+    Ok(Stage::Lookup {
+        from: field("from")?,
+        local_field: field("localField")?,
+        foreign_field: field("foreignField")?,
+        as_field: field("as")?,
+    })
+}
+
+fn as_count(v: &Bson) -> Option<usize> {
+    match v {
+        Bson::Int32(i) => usize::try_from(*i).ok(),
+        Bson::Int64(i) => usize::try_from(*i).ok(),
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        Bson::Double(f) if *f >= 0.0 && f.fract() == 0.0 => Some(*f as usize),
+        _ => None,
+    }
+}
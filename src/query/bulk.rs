@@ -0,0 +1,175 @@
+use crate::collection::{Collection, WriteBatch};
+use crate::document::{Document, DocumentType};
+use crate::errors::DbError;
+use crate::types::DocumentId;
+use std::sync::Arc;
+
+use super::eval::eval_filter;
+use super::text;
+use super::types::{
+    BulkOp, BulkOptions, BulkOutcome, BulkWriteReport, DeleteReport, Filter, UpdateReport,
+};
+use super::update::{apply_update_positional, upsert_document};
+
+/// Run `ops` against `col` as one batch.
+///
+/// The whole batch holds the collection's write lock, so other writers wait for it to finish
+/// and later operations observe the effects of earlier ones. Each write reaches the cache as
+/// it is applied, where readers can see it before the batch is durable; storage receives
+/// every write in a single append with one pass of index deltas at the end. With
+/// [`BulkOptions::ordered`] the batch stops at the first failing operation; otherwise each
+/// failure is recorded and the rest still run. Writes from operations that succeeded are
+/// kept either way; a failed operation keeps none of its own, so the totals count every
+/// document the batch changed.
+///
+/// # Errors
+/// Returns `DbError::Io` if storage rejects the append: the writes are applied in memory
+/// but are not durable.
+pub fn bulk_write(
+    col: &Arc<Collection>,
+    ops: Vec<BulkOp>,
+    opts: &BulkOptions,
+) -> Result<BulkWriteReport, DbError> {
+    let bench_start = std::time::Instant::now();
+    let total = ops.len();
+    // Taken before the batch, in the same order as single-document upserts.
+    let _upsert_guard = ops.iter().any(BulkOp::is_upsert).then(|| col.upsert_lock.lock());
+    let mut batch = col.write_batch();
+    let mut report = BulkWriteReport::default();
+    for op in ops {
+        let result = apply_op(&mut batch, op);
+        let failed = result.is_err();
+        report.record(result);
+        if failed && opts.ordered {
+            break;
+        }
+    }
+    batch.commit()?;
+    let dur_ms = bench_start.elapsed().as_millis();
+    crate::dev6!(
+        "{{\"bench\":\"query\",\"op\":\"bulk_write\",\"collection\":\"{}\",\"duration_ms\":{},\"ops\":{},\"errors\":{}}}",
+        col.name_str(),
+        crate::utils::num::usize_to_u64(dur_ms as usize),
+        total,
+        report.errors().count()
+    );
+    Ok(report)
+}
+
+fn apply_op(batch: &mut WriteBatch<'_>, op: BulkOp) -> Result<BulkOutcome, DbError> {
+    match op {
+        BulkOp::Insert(doc) => batch.insert(doc).map(BulkOutcome::Inserted),
+        BulkOp::UpdateOne { filter, update, upsert } => {
+            let report = match first_match(batch, &filter) {
+                Some(mut doc) => {
+                    let changed = apply_update_positional(&mut doc, &update, &filter)?;
+                    if changed {
+                        batch.replace(&doc.id.clone(), doc, None)?;
+                    }
+                    UpdateReport { matched: 1, modified: u64::from(changed), upserted_id: None }
+                }
+                None if upsert => inserted(batch.insert(upsert_document(&filter, &update)?)?),
+                None => UpdateReport::default(),
+            };
+            Ok(BulkOutcome::Updated(report))
+        }
+        BulkOp::UpdateMany { filter, update, upsert } => {
+            let docs = matches(batch, &filter);
+            if docs.is_empty() && upsert {
+                let id = batch.insert(upsert_document(&filter, &update)?)?;
+                return Ok(BulkOutcome::Updated(inserted(id)));
+            }
+            let matched = crate::utils::num::usize_to_u64(docs.len());
+            // As in `update_many`, an operator error on any match writes nothing
+            let mut changed = Vec::new();
+            for doc in docs {
+                let mut new = doc.clone();
+                if apply_update_positional(&mut new, &update, &filter)? {
+                    changed.push((doc, new));
+                }
+            }
+            let modified = replace_all(batch, changed)?;
+            Ok(BulkOutcome::Updated(UpdateReport { matched, modified, upserted_id: None }))
+        }
+        BulkOp::ReplaceOne { filter, replacement, upsert } => {
+            if replacement.keys().any(|k| k.starts_with('$')) {
+                return Err(DbError::QueryError(
+                    "replacement document must not contain update operators".into(),
+                ));
+            }
+            let report = match first_match(batch, &filter) {
+                Some(mut doc) => {
+                    let changed = doc.data.0 != replacement;
+                    if changed {
+                        doc.data.0 = replacement;
+                        batch.replace(&doc.id.clone(), doc, None)?;
+                    }
+                    UpdateReport { matched: 1, modified: u64::from(changed), upserted_id: None }
+                }
+                None if upsert => {
+                    inserted(batch.insert(Document::new(replacement, DocumentType::Persistent))?)
+                }
+                None => UpdateReport::default(),
+            };
+            Ok(BulkOutcome::Updated(report))
+        }
+        BulkOp::DeleteOne { filter } => {
+            let deleted =
+                first_match(batch, &filter).is_some_and(|doc| batch.delete(&doc.id, None));
+            Ok(BulkOutcome::Deleted(DeleteReport { deleted: u64::from(deleted) }))
+        }
+        BulkOp::DeleteMany { filter } => {
+            let mut deleted = 0u64;
+            for doc in matches(batch, &filter) {
+                deleted += u64::from(batch.delete(&doc.id, None));
+            }
+            Ok(BulkOutcome::Deleted(DeleteReport { deleted }))
+        }
+    }
+}
+
+/// Documents currently matching `filter`. The batch holds the index lock, so this scans.
+/// Write each `(original, updated)` pair through `batch` and return how many were written.
+/// If one is rejected, the earlier pairs are put back before the error is returned, so the
+/// batch keeps none of them.
+pub(super) fn replace_all(
+    batch: &mut WriteBatch<'_>,
+    changed: Vec<(Document, Document)>,
+) -> Result<u64, DbError> {
+    let mut replaced: Vec<Document> = Vec::new();
+    for (old, new) in changed {
+        if let Err(e) = batch.replace(&new.id.clone(), new, None) {
+            // Put back the earlier writes, newest first: each step returns to a state the
+            // batch already held, so none can be a violation
+            for old in replaced.into_iter().rev() {
+                let _ = batch.replace(&old.id.clone(), old, None);
+            }
+            return Err(e);
+        }
+        replaced.push(old);
+    }
+    Ok(crate::utils::num::usize_to_u64(replaced.len()))
+}
+
+fn matches(batch: &WriteBatch<'_>, filter: &Filter) -> Vec<Document> {
+    let col = batch.collection();
+    let filter = &text::bind_in(batch.indexes(), filter);
+    col.list_ids()
+        .into_iter()
+        .filter_map(|id| col.find_document(&id))
+        .filter(|d| eval_filter(&d.data.0, filter))
+        .collect()
+}
+
+fn first_match(batch: &WriteBatch<'_>, filter: &Filter) -> Option<Document> {
+    let col = batch.collection();
+    let filter = &text::bind_in(batch.indexes(), filter);
+    col.list_ids()
+        .into_iter()
+        .filter_map(|id| col.find_document(&id))
+        .find(|d| eval_filter(&d.data.0, filter))
+}
+
+fn inserted(id: DocumentId) -> UpdateReport {
+    UpdateReport { upserted_id: Some(id), ..UpdateReport::default() }
+}
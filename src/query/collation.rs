@@ -0,0 +1,138 @@
+//! Locale-independent string collation.
+//!
+//! A collation turns a string into a byte sort key compared level by level: base letters
+//! (case and Latin accents folded away), then accents, then case. `strength` picks how many
+//! levels take part, so strings equal at the chosen strength match each other and sort
+//! together. Collated indexes store these keys instead of the strings.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// How many levels of difference a [`Collation`] considers.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum Strength {
+    /// Base letters only: `"a" == "A" == "á"`.
+    Primary,
+    /// Base letters and accents: `"a" == "A"`, `"a" < "á"`.
+    Secondary,
+    /// Base letters, accents and case: `"a" < "A" < "á"`.
+    #[default]
+    Tertiary,
+}
+
+impl TryFrom<u8> for Strength {
+    type Error = String;
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            1 => Ok(Self::Primary),
+            2 => Ok(Self::Secondary),
+            3 => Ok(Self::Tertiary),
+            _ => Err(format!("collation strength must be 1, 2 or 3, got {v}")),
+        }
+    }
+}
+
+impl From<Strength> for u8 {
+    fn from(s: Strength) -> Self {
+        match s {
+            Strength::Primary => 1,
+            Strength::Secondary => 2,
+            Strength::Tertiary => 3,
+        }
+    }
+}
+
+/// String comparison rules for filters, sorts and indexes, e.g.
+/// `{"strength": 1, "numeric_ordering": true}`.
+///
+/// Without a collation strings compare byte-wise.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Collation {
+    /// 1 (primary), 2 (secondary) or 3 (tertiary, the default).
+    #[serde(default)]
+    pub strength: Strength,
+    /// Also distinguish case below tertiary strength (`"a" < "A"`, while `"a" == "á"` at
+    /// primary strength).
+    #[serde(default)]
+    pub case_level: bool,
+    /// Compare runs of ASCII digits by numeric value: `"file2" < "file10"`. Leading zeros are
+    /// ignored.
+    #[serde(default)]
+    pub numeric_ordering: bool,
+}
+
+/// Separates levels in a sort key; below every byte a level can contain.
+const LEVEL_SEP: u8 = 0;
+/// Starts an encoded digit run: the run's length, then its digits.
+const DIGITS: u8 = b'0';
+
+impl Collation {
+    /// The sort key of `s`: two strings compare under this collation as their keys compare
+    /// byte-wise. NUL characters are ignored.
+    #[must_use]
+    pub fn sort_key(&self, s: &str) -> Vec<u8> {
+        let chars = || s.chars().filter(|c| *c != '\0');
+        let mut key = Vec::with_capacity(s.len() * 2);
+        self.push_level(&mut key, chars().flat_map(fold_base));
+        if self.strength >= Strength::Secondary {
+            key.push(LEVEL_SEP);
+            self.push_level(&mut key, chars().flat_map(char::to_lowercase));
+        }
+        if self.case_level || self.strength == Strength::Tertiary {
+            // Lowercase (and uncased) sorts first
+            key.push(LEVEL_SEP);
+            key.extend(chars().map(|c| if c.is_uppercase() { 2 } else { 1 }));
+        }
+        key
+    }
+
+    /// Compare `a` and `b` under this collation.
+    #[must_use]
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.sort_key(a).cmp(&self.sort_key(b))
+    }
+
+    fn push_level(&self, key: &mut Vec<u8>, chars: impl Iterator<Item = char>) {
+        let text: String = chars.collect();
+        if !self.numeric_ordering {
+            key.extend_from_slice(text.as_bytes());
+            return;
+        }
+        let bytes = text.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if !bytes[i].is_ascii_digit() {
+                key.push(bytes[i]);
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            // Strip leading zeros but keep a lone "0"; a longer run is a larger number
+            let run = &bytes[start..i];
+            let digits = &run[run.iter().position(|b| *b != b'0').unwrap_or(run.len() - 1)..];
+            let len = u32::try_from(digits.len()).unwrap_or(u32::MAX);
+            key.push(DIGITS);
+            key.extend_from_slice(&len.to_be_bytes());
+            key.extend_from_slice(digits);
+        }
+    }
+}
+
+/// The primary-level form of `c`: lowercase, with accented Latin letters reduced to their
+/// base letters (`"É"` to `"e"`, `"ß"` to `"ss"`).
+fn fold_base(c: char) -> impl Iterator<Item = char> {
+    let latin = c.is_alphabetic() && matches!(c, '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}');
+    let base = if latin { deunicode::deunicode_char(c) } else { None };
+    let folded: Vec<char> = match base {
+        Some(s) => s.chars().flat_map(char::to_lowercase).collect(),
+        None => c.to_lowercase().collect(),
+    };
+    folded.into_iter()
+}
@@ -1,39 +1,204 @@
 use crate::collection::Collection;
 use crate::document::Document;
+use crate::errors::DbError;
 use crate::types::DocumentId;
 use std::sync::Arc;
+use std::time::Instant;
 
-#[derive(Clone)]
+use super::collation::Collation;
+use super::eval::eval_filter;
+use super::geo::{GeoNear, add_distance};
+use super::project::project;
+use super::sort::Sorted;
+use super::text::{TextSearch, add_score};
+use super::token::{ResumePoint, TokenKey};
+use super::types::{Filter, Projection, SortSpec};
+
+/// Lazily yields the results of `find_docs`.
+///
+/// Unsorted results are fetched and filtered one candidate at a time; sorted results come
+/// from a bounded heap or a merge of spilled runs. Skip, limit and projection are applied as
+/// documents are yielded.
 pub struct Cursor {
-    pub collection: Arc<Collection>,
-    pub ids: Vec<DocumentId>,
-    pub pos: usize,
-    pub docs: Option<Vec<Document>>, // when present, iterate these
+    source: Source,
+    projection: Option<Projection>,
+    skip: usize,
+    remaining: usize,
+    bench: Bench,
+    resume: Option<Resume>,
+    /// Scores and distances the scan adds to documents, removed once they are projected.
+    meta: Vec<&'static str>,
 }
 
-impl Cursor {
-    pub fn advance(&mut self) -> Option<Document> {
-        if let Some(ref docs) = self.docs {
-            if self.pos >= docs.len() {
+/// Tracks the last document read from a sorted cursor, to issue resume tokens.
+struct Resume {
+    key: Arc<TokenKey>,
+    sort: Vec<SortSpec>,
+    collation: Option<Collation>,
+    last: Option<ResumePoint>,
+}
+
+pub(crate) enum Source {
+    Scan(Scan),
+    Sorted(Sorted),
+}
+
+/// A find's filter, with what its matches are given: the score of a `$text` query and the
+/// distance of a `$near` one.
+pub(crate) struct Matcher {
+    filter: Filter,
+    search: Option<Box<TextSearch>>,
+    near: Option<Box<(String, GeoNear)>>,
+}
+
+impl Matcher {
+    pub(crate) fn new(filter: Filter) -> Self {
+        let search = super::text::scoring_search(&filter).cloned().map(Box::new);
+        let near = super::geo::near_search(&filter)
+            .map(|(path, near)| Box::new((path.to_string(), near.clone())));
+        Self { filter, search, near }
+    }
+
+    pub(crate) fn matches(&self, d: &Document) -> bool {
+        eval_filter(&d.data.0, &self.filter)
+    }
+
+    /// Add the score or distance of a match.
+    pub(crate) fn decorate(&self, d: &mut Document) {
+        if let Some(search) = &self.search {
+            add_score(&mut d.data.0, search);
+        }
+        if let Some(near) = &self.near {
+            add_distance(&mut d.data.0, &near.0, &near.1);
+        }
+    }
+
+    /// A decorated copy of `d` if it matches.
+    pub(crate) fn accept(&self, d: &Document) -> Option<Document> {
+        self.matches(d).then(|| {
+            let mut d = d.clone();
+            self.decorate(&mut d);
+            d
+        })
+    }
+}
+
+/// Candidate ids from a plan, fetched and filtered as they are pulled. Stops early once the
+/// deadline passes.
+pub(crate) struct Scan {
+    collection: Arc<Collection>,
+    ids: std::vec::IntoIter<DocumentId>,
+    matcher: Matcher,
+    deadline: Option<Instant>,
+}
+
+impl Scan {
+    pub(crate) fn new(
+        collection: Arc<Collection>,
+        ids: Vec<DocumentId>,
+        filter: Filter,
+        deadline: Option<Instant>,
+    ) -> Self {
+        Self { collection, ids: ids.into_iter(), matcher: Matcher::new(filter), deadline }
+    }
+}
+
+impl Iterator for Scan {
+    type Item = Document;
+    fn next(&mut self) -> Option<Document> {
+        loop {
+            if self.deadline.is_some_and(|dl| Instant::now() > dl) {
                 return None;
             }
-            let d = docs[self.pos].clone();
-            self.pos += 1;
-            return Some(d);
+            let id = self.ids.next()?;
+            if let Some(mut d) = self.collection.find_document(&id)
+                && self.matcher.matches(&d)
+            {
+                self.matcher.decorate(&mut d);
+                return Some(d);
+            }
         }
-        if self.pos >= self.ids.len() {
-            return None;
+    }
+}
+
+/// What the `find_results` benchmark line reports once the cursor is dropped.
+pub(crate) struct Bench {
+    pub collection: String,
+    pub started: Instant,
+    pub returned: usize,
+}
+
+impl Cursor {
+    pub(crate) fn new(
+        source: Source,
+        projection: Option<Projection>,
+        skip: usize,
+        limit: usize,
+        bench: Bench,
+    ) -> Self {
+        Self { source, projection, skip, remaining: limit, bench, resume: None, meta: Vec::new() }
+    }
+
+    /// Remove `key`, a score or distance the scan added, from each document as it is yielded.
+    pub(crate) fn with_meta(mut self, key: &'static str) -> Self {
+        self.meta.push(key);
+        self
+    }
+
+    pub(crate) fn with_resume(
+        mut self,
+        key: Arc<TokenKey>,
+        sort: Vec<SortSpec>,
+        collation: Option<Collation>,
+    ) -> Self {
+        self.resume = Some(Resume { key, sort, collation, last: None });
+        self
+    }
+
+    /// A token for `FindOptions::after` that continues after the last document read, or
+    /// `None` if nothing has been read or the query was neither sorted nor resumed.
+    ///
+    /// The token is signed with the database's key: it cannot be forged or edited, and is
+    /// rejected by a query with a different sort or collation.
+    ///
+    /// # Errors
+    /// Returns `DbError::Io` when the database's key file is corrupt or cannot be created.
+    pub fn next_token(&self) -> Result<Option<String>, DbError> {
+        let Some(r) = &self.resume else { return Ok(None) };
+        r.last.as_ref().map(|p| p.encode(&r.key, &r.sort, r.collation.as_ref())).transpose()
+    }
+
+    pub fn advance(&mut self) -> Option<Document> {
+        while self.remaining > 0 {
+            let mut doc = match &mut self.source {
+                Source::Scan(s) => s.next()?,
+                Source::Sorted(s) => s.next()?,
+            };
+            if self.skip > 0 {
+                self.skip -= 1;
+                continue;
+            }
+            self.remaining -= 1;
+            self.bench.returned += 1;
+            if let Some(r) = &mut self.resume {
+                r.last = Some(ResumePoint::of(&doc, &r.sort, r.collation.as_ref()));
+            }
+            if let Some(p) = &self.projection {
+                doc.data.0 = project(&doc.data.0, p);
+            }
+            for key in &self.meta {
+                doc.data.0.remove(*key);
+            }
+            return Some(doc);
         }
-        let id = self.ids[self.pos].clone();
-        self.pos += 1;
-        self.collection.find_document(&id)
+        None
     }
+
+    /// Read the remaining documents. Use [`Cursor::advance`] instead to keep the cursor
+    /// for [`Cursor::next_token`].
     #[must_use]
     pub fn to_vec(mut self) -> Vec<Document> {
-        if let Some(docs) = self.docs.take() {
-            return docs;
-        }
-        let mut out = Vec::with_capacity(self.ids.len());
+        let mut out = Vec::new();
         while let Some(d) = self.advance() {
             out.push(d);
         }
@@ -47,3 +212,17 @@ impl Iterator for Cursor {
         self.advance()
     }
 }
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        let b = &self.bench;
+        let dur_ms = b.started.elapsed().as_millis();
+        // Time from the query to the last read, and how many documents were read
+        crate::dev6!(
+            "{{\"bench\":\"query\",\"op\":\"find_results\",\"collection\":\"{}\",\"duration_ms\":{},\"result_count\":{}}}",
+            b.collection,
+            crate::utils::num::usize_to_u64(dur_ms as usize),
+            crate::utils::num::usize_to_u64(b.returned)
+        );
+    }
+}
@@ -1,149 +1,389 @@
 use bson::{Bson, Document as BsonDocument};
 use std::cmp::Ordering;
 
-use super::types::{CmpOp, Filter, MAX_IN_SET, MAX_PATH_DEPTH, MAX_SORT_FIELDS, SortSpec};
+use super::collation::Collation;
+use super::expr::has_type;
+use super::numeric::compare_numbers;
+use super::types::{CmpOp, Filter, MAX_IN_SET, MAX_PATH_DEPTH, MAX_SORT_FIELDS, Order, SortSpec};
 
 pub fn eval_filter(doc: &BsonDocument, filter: &Filter) -> bool {
+    eval_filter_collated(doc, filter, None)
+}
+
+/// [`eval_filter`], comparing strings under `collation` (byte-wise when `None`). A nested
+/// [`Filter::Collate`] applies its own collation to its part of the filter.
+pub fn eval_filter_collated(
+    doc: &BsonDocument,
+    filter: &Filter,
+    collation: Option<&Collation>,
+) -> bool {
+    let cmp = |a: &Bson, b: &Bson| compare_bson_collated(a, b, collation);
     match filter {
         Filter::True => true,
-        Filter::And(fs) => fs.iter().all(|f| eval_filter(doc, f)),
-        Filter::Or(fs) => fs.iter().any(|f| eval_filter(doc, f)),
-        Filter::Not(f) => !eval_filter(doc, f),
-        Filter::Exists { path, exists } => get_path(doc, path).is_some() == *exists,
-        Filter::In { path, values } => get_path(doc, path).is_some_and(|v| is_in_set(v, values)),
-        Filter::Nin { path, values } => !get_path(doc, path).is_some_and(|v| is_in_set(v, values)),
+        Filter::And(fs) => fs.iter().all(|f| eval_filter_collated(doc, f, collation)),
+        Filter::Or(fs) => fs.iter().any(|f| eval_filter_collated(doc, f, collation)),
+        Filter::Not(f) => !eval_filter_collated(doc, f, collation),
+        Filter::Exists { path, exists } => path_values(doc, path).is_empty() != *exists,
+        Filter::In { path, values } => {
+            any_value(doc, path, true, |v| is_in_set(v, values, collation))
+        }
+        Filter::Nin { path, values } => {
+            !any_value(doc, path, true, |v| is_in_set(v, values, collation))
+        }
         Filter::Cmp { path, op, value } => {
-            if let Some(v) = get_path(doc, path) {
-                match op {
-                    CmpOp::Eq => v == value,
-                    CmpOp::Gt => compare_bson(v, value) == Ordering::Greater,
-                    CmpOp::Gte => {
-                        let c = compare_bson(v, value);
-                        c == Ordering::Greater || c == Ordering::Equal
-                    }
-                    CmpOp::Lt => compare_bson(v, value) == Ordering::Less,
-                    CmpOp::Lte => {
-                        let c = compare_bson(v, value);
-                        c == Ordering::Less || c == Ordering::Equal
-                    }
-                }
-            } else {
-                false
+            // A scalar operand is compared against array elements; an array operand also
+            // against the whole array.
+            let whole = matches!(value, Bson::Array(_));
+            match op {
+                CmpOp::Eq => any_value(doc, path, true, |v| values_equal(v, value, collation)),
+                CmpOp::Gt => any_value(doc, path, whole, |v| cmp(v, value) == Ordering::Greater),
+                CmpOp::Gte => any_value(doc, path, whole, |v| cmp(v, value) != Ordering::Less),
+                CmpOp::Lt => any_value(doc, path, whole, |v| cmp(v, value) == Ordering::Less),
+                CmpOp::Lte => any_value(doc, path, whole, |v| cmp(v, value) != Ordering::Greater),
             }
         }
         #[cfg(feature = "regex")]
-        Filter::Regex { path, pattern, case_insensitive } => {
-            if let Some(bson::Bson::String(s)) = get_path(doc, path) {
-                let mut re = regex::RegexBuilder::new(pattern);
-                re.case_insensitive(*case_insensitive);
-                if let Ok(r) = re.build() { r.is_match(s) } else { false }
-            } else {
-                false
-            }
+        Filter::Regex { path, pattern } => {
+            any_value(doc, path, false, |v| matches!(v, Bson::String(s) if pattern.is_match(s)))
         }
+        Filter::Type { path, type_name } => any_value(doc, path, true, |v| has_type(v, type_name)),
+        Filter::Mod { path, divisor, remainder } => any_value(doc, path, false, |v| {
+            truncated(v).is_some_and(|x| x.checked_rem(*divisor).unwrap_or(0) == *remainder)
+        }),
+        Filter::Expr(e) => e.matches(doc, collation),
+        Filter::Text(t) => t.matches(doc),
+        Filter::Geo { path, op } => op.matches(doc, path),
+        Filter::Collate { collation, filter } => eval_filter_collated(doc, filter, Some(collation)),
     }
 }
 
+/// A number truncated toward zero, for `$mod`; `None` for other values and non-finite or
+/// out-of-range doubles.
+#[allow(clippy::cast_possible_truncation)]
+fn truncated(v: &Bson) -> Option<i64> {
+    match v {
+        Bson::Int32(i) => Some(i64::from(*i)),
+        Bson::Int64(i) => Some(*i),
+        Bson::Double(f) if f.is_finite() && f.abs() < 9.2e18 => Some(f.trunc() as i64),
+        Bson::Decimal128(_) => truncated(&Bson::Double(super::numeric::to_f64(v)?)),
+        _ => None,
+    }
+}
+
+/// Whether any value reached by `path` satisfies `pred`. Array values match when one of their
+/// elements does, or (with `whole`) when the array itself does.
+fn any_value(doc: &BsonDocument, path: &str, whole: bool, pred: impl Fn(&Bson) -> bool) -> bool {
+    path_values(doc, path).into_iter().any(|v| match v {
+        Bson::Array(items) => items.iter().any(&pred) || (whole && pred(v)),
+        _ => pred(v),
+    })
+}
+
+/// Order `a` and `b` by `sort`, using at most `MAX_SORT_FIELDS` fields (see [`sort_value`]).
 pub fn compare_docs(a: &BsonDocument, b: &BsonDocument, sort: &[SortSpec]) -> Ordering {
+    compare_docs_collated(a, b, sort, None)
+}
+
+/// [`compare_docs`] with strings compared under `collation`.
+pub fn compare_docs_collated(
+    a: &BsonDocument,
+    b: &BsonDocument,
+    sort: &[SortSpec],
+    collation: Option<&Collation>,
+) -> Ordering {
     for s in sort.iter().take(MAX_SORT_FIELDS) {
-        let va = a.get(&s.field);
-        let vb = b.get(&s.field);
-        let ord = match (va, vb) {
-            (Some(x), Some(y)) => compare_bson(x, y),
-            (Some(_), None) => Ordering::Greater,
-            (None, Some(_)) => Ordering::Less,
-            (None, None) => Ordering::Equal,
-        };
+        let (x, y) = (sort_value(a, s, collation), sort_value(b, s, collation));
+        let ord = compare_sort_values(x, y, s.order, collation);
         if ord != Ordering::Equal {
-            return if matches!(s.order, super::types::Order::Asc) { ord } else { ord.reverse() };
+            return ord;
         }
     }
     Ordering::Equal
 }
 
-fn is_in_set(v: &Bson, set: &[Bson]) -> bool {
-    set.iter().take(MAX_IN_SET).any(|x| x == v)
+/// The value `doc` sorts by for `s`: the value at its dotted path, or, where that is an
+/// array, its smallest element ascending and largest descending. `None` when the path is
+/// missing or reaches only empty arrays; missing values sort before all others ascending.
+pub(crate) fn sort_value<'a>(
+    doc: &'a BsonDocument,
+    s: &SortSpec,
+    collation: Option<&Collation>,
+) -> Option<&'a Bson> {
+    let values = if s.field.contains('.') {
+        path_values(doc, &s.field)
+    } else {
+        doc.get(&s.field).into_iter().collect()
+    };
+    let flat = values.into_iter().flat_map(|v| match v {
+        Bson::Array(items) => items.as_slice(),
+        _ => std::slice::from_ref(v),
+    });
+    match s.order {
+        Order::Asc => flat.min_by(|a, b| compare_bson_collated(a, b, collation)),
+        Order::Desc => flat.max_by(|a, b| compare_bson_collated(a, b, collation)),
+    }
+}
+
+pub(crate) fn compare_sort_values(
+    a: Option<&Bson>,
+    b: Option<&Bson>,
+    order: Order,
+    collation: Option<&Collation>,
+) -> Ordering {
+    let ord = match (a, b) {
+        (Some(x), Some(y)) => compare_bson_collated(x, y, collation),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => Ordering::Equal,
+    };
+    if matches!(order, Order::Asc) { ord } else { ord.reverse() }
 }
 
-fn get_path<'a>(doc: &'a BsonDocument, path: &str) -> Option<&'a Bson> {
+fn is_in_set(v: &Bson, set: &[Bson], collation: Option<&Collation>) -> bool {
+    set.iter().take(MAX_IN_SET).any(|x| values_equal(v, x, collation))
+}
+
+/// Equality as `$eq` sees it: exact, except that strings (also inside arrays and
+/// sub-documents) are equal when `collation` says so.
+fn values_equal(a: &Bson, b: &Bson, collation: Option<&Collation>) -> bool {
+    let Some(c) = collation else { return a == b };
+    match (a, b) {
+        (Bson::String(x), Bson::String(y)) => c.compare(x, y) == Ordering::Equal,
+        (Bson::Array(x), Bson::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y).all(|(x, y)| values_equal(x, y, collation))
+        }
+        (Bson::Document(x), Bson::Document(y)) => {
+            x.len() == y.len()
+                && x.iter()
+                    .zip(y)
+                    .all(|((kx, vx), (ky, vy))| kx == ky && values_equal(vx, vy, collation))
+        }
+        _ => a == b,
+    }
+}
+
+/// Collect every value `path` reaches in `doc`.
+///
+/// A segment applied to an array descends into each sub-document element (`items.sku` over
+/// `items: [{sku: ..}, ..]`); a numeric segment also selects the element at that position.
+/// Arrays at the end of the path are returned whole, not flattened.
+#[must_use]
+pub fn path_values<'a>(doc: &'a BsonDocument, path: &str) -> Vec<&'a Bson> {
+    let mut out = Vec::new();
     if path.is_empty() || path.len() > 1024 {
-        return None;
+        return out;
     }
-    let mut cur = doc;
-    let mut segs = 0usize;
-    let parts = path.split('.');
-    let last = parts.clone().next_back().unwrap_or("");
-    for part in parts {
-        segs += 1;
-        if segs > MAX_PATH_DEPTH {
-            return None;
+    let parts: Vec<&str> = path.split('.').collect();
+    if parts.len() > MAX_PATH_DEPTH {
+        return out;
+    }
+    if let Some(v) = doc.get(parts[0]) {
+        collect_path(v, &parts[1..], &mut out);
+    }
+    out
+}
+
+fn collect_path<'a>(v: &'a Bson, rest: &[&str], out: &mut Vec<&'a Bson>) {
+    let Some((seg, tail)) = rest.split_first() else {
+        out.push(v);
+        return;
+    };
+    match v {
+        Bson::Document(d) => {
+            if let Some(next) = d.get(*seg) {
+                collect_path(next, tail, out);
+            }
         }
-        match cur.get(part) {
-            Some(Bson::Document(d)) => cur = d,
-            Some(v) if part == last => return Some(v),
-            _ => return None,
+        Bson::Array(items) => {
+            if let Ok(i) = seg.parse::<usize>()
+                && let Some(next) = items.get(i)
+            {
+                collect_path(next, tail, out);
+            }
+            for item in items {
+                if let Bson::Document(d) = item
+                    && let Some(next) = d.get(*seg)
+                {
+                    collect_path(next, tail, out);
+                }
+            }
         }
+        _ => {}
     }
-    None
 }
 
+/// A total order over BSON values: first by type, in MongoDB's order
+///
+/// `MinKey < Null < Undefined < numbers < String/Symbol < Document < Array < Binary < ObjectId
+/// < Boolean < DateTime < Timestamp < Regex < DbPointer < JavaScript < MaxKey`,
+///
+/// then by value. Numbers of every type compare by value (see [`compare_numbers`]); arrays
+/// compare element by element and documents field by field (value type, then name, then
+/// value), a prefix sorting first. Binary compares by subtype then bytes. Index keys use the
+/// same order.
 pub fn compare_bson(a: &Bson, b: &Bson) -> Ordering {
+    compare_bson_collated(a, b, None)
+}
+
+/// [`compare_bson`], with strings (also inside arrays and sub-documents) compared under
+/// `collation` instead of byte-wise.
+pub fn compare_bson_collated(a: &Bson, b: &Bson, collation: Option<&Collation>) -> Ordering {
     use bson::Bson as T;
-    fn is_num(x: &T) -> bool {
-        matches!(x, T::Int32(_) | T::Int64(_) | T::Double(_) | T::Decimal128(_))
-    }
-    fn as_f64_num(x: &T) -> f64 {
-        match x {
-            T::Int32(i) => *i as f64,
-            T::Int64(i) => *i as f64,
-            T::Double(f) => *f,
-            T::Decimal128(d) => d.to_string().parse::<f64>().unwrap_or(f64::NAN),
-            _ => f64::NAN,
-        }
-    }
-    if is_num(a) && is_num(b) {
-        return as_f64_num(a).total_cmp(&as_f64_num(b));
+    let by_type = type_rank(a).cmp(&type_rank(b));
+    if by_type != Ordering::Equal {
+        return by_type;
     }
     match (a, b) {
-        (T::String(x), T::String(y)) => x.cmp(y),
+        (T::String(x) | T::Symbol(x), T::String(y) | T::Symbol(y)) => match collation {
+            Some(c) => c.compare(x, y),
+            None => x.cmp(y),
+        },
+        (T::Document(x), T::Document(y)) => compare_documents(x, y, collation),
+        (T::Array(x), T::Array(y)) => {
+            compare_seq(x.iter(), y.iter(), |x, y| compare_bson_collated(x, y, collation))
+        }
+        (T::Binary(x), T::Binary(y)) => {
+            (u8::from(x.subtype), &x.bytes).cmp(&(u8::from(y.subtype), &y.bytes))
+        }
+        (T::ObjectId(x), T::ObjectId(y)) => x.bytes().cmp(&y.bytes()),
         (T::Boolean(x), T::Boolean(y)) => x.cmp(y),
-        _ => type_rank(a).cmp(&type_rank(b)),
+        (T::DateTime(x), T::DateTime(y)) => x.cmp(y),
+        (T::Timestamp(x), T::Timestamp(y)) => (x.time, x.increment).cmp(&(y.time, y.increment)),
+        (T::RegularExpression(x), T::RegularExpression(y)) => {
+            (x.pattern.as_str(), x.options.as_str()).cmp(&(y.pattern.as_str(), y.options.as_str()))
+        }
+        (T::JavaScriptCode(x), T::JavaScriptCode(y)) => x.cmp(y),
+        (T::JavaScriptCodeWithScope(x), T::JavaScriptCodeWithScope(y)) => {
+            x.code.cmp(&y.code).then_with(|| compare_documents(&x.scope, &y.scope, None))
+        }
+        // Null, Undefined, MinKey, MaxKey and the deprecated DbPointer (whose fields are
+        // private) are equal within their type
+        _ => compare_numbers(a, b).unwrap_or(Ordering::Equal),
+    }
+}
+
+fn compare_documents(
+    a: &BsonDocument,
+    b: &BsonDocument,
+    collation: Option<&Collation>,
+) -> Ordering {
+    compare_seq(a.iter(), b.iter(), |(ka, va), (kb, vb)| {
+        type_rank(va)
+            .cmp(&type_rank(vb))
+            .then_with(|| ka.cmp(kb))
+            .then_with(|| compare_bson_collated(va, vb, collation))
+    })
+}
+
+/// Lexicographic order by `cmp`, a prefix sorting first.
+fn compare_seq<T>(
+    a: impl Iterator<Item = T>,
+    mut b: impl Iterator<Item = T>,
+    cmp: impl Fn(&T, &T) -> Ordering,
+) -> Ordering {
+    for x in a {
+        let Some(y) = b.next() else { return Ordering::Greater };
+        let ord = cmp(&x, &y);
+        if ord != Ordering::Equal {
+            return ord;
+        }
     }
+    if b.next().is_some() { Ordering::Less } else { Ordering::Equal }
 }
 
 fn type_rank(v: &Bson) -> u8 {
     use bson::Bson as T;
     match v {
-        T::Null => 0,
-        T::Boolean(_) => 1,
-        T::Int32(_) => 2,
-        T::Int64(_) => 3,
-        T::Double(_) => 4,
-        T::String(_) => 5,
+        T::MinKey => 0,
+        T::Null => 1,
+        T::Undefined => 2,
+        T::Int32(_) | T::Int64(_) | T::Double(_) | T::Decimal128(_) => 3,
+        T::String(_) | T::Symbol(_) => 4,
+        T::Document(_) => 5,
         T::Array(_) => 6,
-        T::Document(_) => 7,
-        T::Binary(_) => 8,
-        T::ObjectId(_) => 9,
+        T::Binary(_) => 7,
+        T::ObjectId(_) => 8,
+        T::Boolean(_) => 9,
         T::DateTime(_) => 10,
-        T::RegularExpression(_) => 11,
-        T::Timestamp(_) => 12,
-        T::Symbol(_) => 13,
-        T::Decimal128(_) => 14,
-        T::Undefined => 15,
-        T::DbPointer(_) => 16,
-        T::JavaScriptCode(_) => 17,
-        T::JavaScriptCodeWithScope(_) => 18,
-        T::MaxKey => 250,
-        T::MinKey => 251,
+        T::Timestamp(_) => 11,
+        T::RegularExpression(_) => 12,
+        T::DbPointer(_) => 13,
+        T::JavaScriptCode(_) => 14,
+        T::JavaScriptCodeWithScope(_) => 15,
+        T::MaxKey => 255,
+    }
+}
+
+/// Conservatively decide whether every document matching `query` also matches `pred`.
+///
+/// Used by the planner to decide whether a partial index can answer a query. A `false`
+/// answer only means the implication could not be proven.
+pub fn filter_implies(query: &Filter, pred: &Filter) -> bool {
+    match pred {
+        Filter::True => return true,
+        Filter::And(ps) => return ps.iter().all(|p| filter_implies(query, p)),
+        Filter::Or(ps) if ps.iter().any(|p| filter_implies(query, p)) => return true,
+        _ => {}
+    }
+    match query {
+        Filter::And(qs) => qs.iter().any(|q| filter_implies(q, pred)),
+        Filter::Or(qs) => !qs.is_empty() && qs.iter().all(|q| filter_implies(q, pred)),
+        _ => atom_implies(query, pred),
+    }
+}
+
+fn atom_implies(query: &Filter, pred: &Filter) -> bool {
+    match (query, pred) {
+        (Filter::Exists { path: qp, exists: qe }, Filter::Exists { path: pp, exists: pe }) => {
+            qp == pp && qe == pe
+        }
+        // Comparisons and non-empty `$in` only match documents where the path exists.
+        (Filter::Cmp { path: qp, .. }, Filter::Exists { path: pp, exists: true }) => qp == pp,
+        (Filter::In { path: qp, values }, Filter::Exists { path: pp, exists: true }) => {
+            qp == pp && !values.is_empty()
+        }
+        (Filter::Cmp { path: qp, op: CmpOp::Eq, value }, _) => value_satisfies(qp, value, pred),
+        (Filter::In { path: qp, values }, _) => {
+            !values.is_empty() && values.iter().all(|v| value_satisfies(qp, v, pred))
+        }
+        (
+            Filter::Cmp { path: qp, op: qo, value: qv },
+            Filter::Cmp { path: pp, op: po, value: pv },
+        ) if qp == pp => range_implies(qo, qv, po, pv),
+        _ => false,
     }
 }
 
-pub fn project_fields(doc: &BsonDocument, fields: &[String]) -> BsonDocument {
-    let mut out = BsonDocument::new();
-    for f in fields {
-        if let Some(v) = doc.get(f) {
-            out.insert(f.clone(), v.clone());
+/// Whether a document whose only relevant value is `path == value` satisfies `pred`.
+fn value_satisfies(path: &str, value: &Bson, pred: &Filter) -> bool {
+    match pred {
+        Filter::Cmp { path: pp, .. }
+        | Filter::In { path: pp, .. }
+        | Filter::Nin { path: pp, .. }
+            if pp == path =>
+        {
+            let mut doc = BsonDocument::new();
+            doc.insert("v", value.clone());
+            let probe = match pred.clone() {
+                Filter::Cmp { op, value, .. } => Filter::Cmp { path: "v".into(), op, value },
+                Filter::In { values, .. } => Filter::In { path: "v".into(), values },
+                Filter::Nin { values, .. } => Filter::Nin { path: "v".into(), values },
+                _ => return false,
+            };
+            eval_filter(&doc, &probe)
         }
+        _ => false,
+    }
+}
+
+/// Whether `x <qo> qv` guarantees `x <po> pv`.
+fn range_implies(qo: &CmpOp, qv: &Bson, po: &CmpOp, pv: &Bson) -> bool {
+    let c = compare_bson(qv, pv);
+    match (qo, po) {
+        (CmpOp::Gt | CmpOp::Gte, CmpOp::Gte) | (CmpOp::Gt, CmpOp::Gt) => c != Ordering::Less,
+        (CmpOp::Gte, CmpOp::Gt) => c == Ordering::Greater,
+        (CmpOp::Lt | CmpOp::Lte, CmpOp::Lte) | (CmpOp::Lt, CmpOp::Lt) => c != Ordering::Greater,
+        (CmpOp::Lte, CmpOp::Lt) => c == Ordering::Less,
+        _ => false,
     }
-    out
 }
@@ -2,98 +2,202 @@ use crate::collection::Collection;
 use crate::document::Document;
 use crate::errors::DbError;
 use crate::types::DocumentId;
+use bson::Document as BsonDocument;
 use std::sync::Arc;
 
-use super::cursor::Cursor;
-use super::eval::{compare_docs, eval_filter, project_fields};
+use super::bulk;
+use super::collation::Collation;
+use super::cursor::{Bench, Cursor, Matcher, Scan, Source};
+use super::eval::{compare_docs, eval_filter};
+use super::geo::{self, GEO_DISTANCE};
+use super::parallel::{ScanStats, in_order, partitioned};
+use super::sort::{DEFAULT_SORT_MEMORY_BYTES, Sorted, sort_docs};
 use super::telemetry;
+use super::text::{self, TEXT_SCORE};
+use super::token::ResumePoint;
 use super::types::{
-    CmpOp, DeleteReport, Filter, FindOptions, MAX_LIMIT, MAX_PROJECTION_FIELDS, MAX_SORT_FIELDS,
-    UpdateDoc, UpdateReport,
+    CmpOp, DeleteReport, Filter, FindAndModifyOptions, FindOptions, MAX_LIMIT, MAX_SORT_FIELDS,
+    Order, Projection, ReturnDocument, SortSpec, UpdateDoc, UpdateOptions, UpdateReport,
 };
+use super::update::{apply_update_positional, upsert_document};
 
+/// Find documents matching `filter`.
+///
+/// The returned cursor is lazy: without a sort, candidates are fetched and filtered as it is
+/// read. A sort needing at most `MAX_LIMIT` leading results (skip + limit) keeps them in a
+/// bounded heap; sorts needing more, or whose heap grows past
+/// `FindOptions::sort_memory_limit` bytes, spill sorted runs to temp files and merge them as
+/// the cursor advances. Projection
+/// is applied per yielded document. Scans large enough for the database's
+/// [`ParallelScan`](super::ParallelScan) setting are split across threads instead, reading
+/// their matches (up to skip + limit when unsorted) from one snapshot of the collection when
+/// the cursor is opened.
+///
+/// Sorted results (and any query with `FindOptions::after`) are ordered by the sort, then by
+/// document id, and the cursor's [`Cursor::next_token`] resumes after the last document
+/// read. An invalid `after` token is logged and yields no documents; use
+/// [`find_docs_rate_limited`] to get the error instead.
 pub fn find_docs(col: &Arc<Collection>, filter: &Filter, opts: &FindOptions) -> Cursor {
+    open_cursor(col, filter, opts).unwrap_or_else(|e| {
+        log::warn!("find on {} rejected: {e}", col.name_str());
+        let bench =
+            Bench { collection: col.name_str(), started: std::time::Instant::now(), returned: 0 };
+        Cursor::new(Source::Sorted(Sorted::Memory(Vec::new().into_iter())), None, 0, 0, bench)
+    })
+}
+
+fn open_cursor(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    opts: &FindOptions,
+) -> Result<Cursor, DbError> {
     let _ = telemetry::try_consume_token(&col.name_str(), 1);
     let deadline =
         opts.timeout_ms.map(|ms| std::time::Instant::now() + std::time::Duration::from_millis(ms));
-    let needs_projection = opts.projection.is_some();
-    let bench_start = std::time::Instant::now();
-    let mut bench_used_index = false;
-
-    if !needs_projection && opts.sort.is_none() {
-        let mut ids: Vec<DocumentId> = match plan_index_candidates(col, filter) {
-            Some(c) => {
-                bench_used_index = true;
-                c
-            }
-            None => col.list_ids(),
-        };
-        ids.retain(|id| {
-            if let Some(dl) = deadline
-                && std::time::Instant::now() > dl
-            {
-                return false;
-            }
-            col.find_document(id).is_some_and(|d| eval_filter(&d.data.0, filter))
-        });
-        let skip = opts.skip.unwrap_or(0);
-        let limit = opts.limit.unwrap_or(usize::MAX).min(MAX_LIMIT);
-        let end = (skip + limit).min(ids.len());
-        let ids = if skip >= ids.len() { Vec::new() } else { ids[skip..end].to_vec() };
-    let bench_result_count = ids.len();
-        let dur_ms = bench_start.elapsed().as_millis();
-        // Emit a developer-benchmark log line for deterministic capture in tests
-        crate::dev6!(
-            "{{\"bench\":\"query\",\"op\":\"find\",\"collection\":\"{}\",\"duration_ms\":{},\"used_index\":{},\"result_count\":{},\"limit\":{},\"skip\":{}}}",
-            col.name_str(),
-            crate::utils::num::usize_to_u64(dur_ms as usize),
-            bench_used_index,
-            crate::utils::num::usize_to_u64(bench_result_count),
-            crate::utils::num::usize_to_u64(opts.limit.unwrap_or(0)),
-            crate::utils::num::usize_to_u64(opts.skip.unwrap_or(0))
-        );
-        return Cursor { collection: col.clone(), ids, pos: 0, docs: None };
-    }
-
-    let mut docs: Vec<Document> = col
-        .list_ids()
-        .into_iter()
-        .filter_map(|id| col.find_document(&id))
-        .filter(|d| eval_filter(&d.data.0, filter))
-        .collect();
-
-    if let Some(sort) = &opts.sort {
-        if sort.len() > MAX_SORT_FIELDS {
-            log::warn!("sort spec too long: {}", sort.len());
+    let bench =
+        Bench { collection: col.name_str(), started: std::time::Instant::now(), returned: 0 };
+    let near = geo::near_search(filter).is_some();
+    // Keyset paging sorts even without a sort spec: by id alone. `$near` results come nearest
+    // first unless sorted otherwise.
+    let sort = match (&opts.sort, &opts.after) {
+        (Some(sort), _) => Some(sort.clone()),
+        (None, _) if near => Some(vec![SortSpec::geo_distance()]),
+        (None, Some(_)) => Some(Vec::new()),
+        (None, None) => None,
+    };
+    let collation = opts.collation.as_ref();
+    let after = match (&opts.after, &sort) {
+        (Some(token), Some(sort)) => {
+            Some(ResumePoint::decode(token, &col.token_key, sort, collation)?)
         }
-        docs.sort_by(|a, b| compare_docs(&a.data.0, &b.data.0, sort));
+        _ => None,
+    };
+    let filter = &collated(&text::bind(col, filter), collation);
+    let scored = text::scoring_search(filter).is_some();
+    if !scored && sort.as_ref().is_some_and(|s| s.iter().any(|s| s.field == TEXT_SCORE)) {
+        return Err(DbError::QueryError("sorting by text score requires a $text filter".into()));
     }
-
-    if let Some(fields) = &opts.projection {
-        let fields: Vec<String> = fields.iter().take(MAX_PROJECTION_FIELDS).cloned().collect();
-        for d in &mut docs {
-            d.data.0 = project_fields(&d.data.0, &fields);
-        }
+    geo::check_near(filter)?;
+    if !near && sort.as_ref().is_some_and(|s| s.iter().any(|s| s.field == GEO_DISTANCE)) {
+        return Err(DbError::QueryError("sorting by distance requires a $near filter".into()));
     }
-
+    let planned = match (&after, &sort) {
+        // The hint compares under the query's collation, so only an index built with the same
+        // collation can answer it
+        (Some(point), Some(sort)) => resume_hint(point, sort)
+            .map(|hint| Filter::And(vec![filter.clone(), collated(&hint, collation)]))
+            .unwrap_or_else(|| filter.clone()),
+        _ => filter.clone(),
+    };
+    let candidates = plan_index_candidates(col, &planned);
+    let used_index = candidates.is_some();
     let skip = opts.skip.unwrap_or(0);
     let limit = opts.limit.unwrap_or(usize::MAX).min(MAX_LIMIT);
-    let end = (skip + limit).min(docs.len());
-    let docs = if skip >= docs.len() { Vec::new() } else { docs[skip..end].to_vec() };
-    let bench_result_count = docs.len();
-    let dur_ms = bench_start.elapsed().as_millis();
+    let k = skip.saturating_add(limit);
+    let budget = opts.sort_memory_limit.unwrap_or(DEFAULT_SORT_MEMORY_BYTES);
+    if let Some(sort) = &sort
+        && sort.len() > MAX_SORT_FIELDS
+    {
+        log::warn!("sort spec too long: {}", sort.len());
+    }
+    let size = candidates.as_ref().map_or_else(|| col.cache.store.read().len(), Vec::len);
+    let workers = col.parallel_scan().workers(size);
+    let mut stats = ScanStats::serial();
+    let source = if workers > 1 {
+        // Split the scan: the matches are read now, from one snapshot of the collection
+        let matcher = Matcher::new(filter.clone());
+        let keep = |d: &Document| match (&after, &sort) {
+            (Some(point), Some(sort)) => point.precedes(d, sort, collation),
+            _ => true,
+        };
+        let work = |docs: &mut dyn Iterator<Item = &Document>| {
+            docs.filter_map(|d| matcher.accept(d)).filter(keep).collect::<Vec<_>>()
+        };
+        let ids = candidates.as_deref();
+        let (sorted, s) = match &sort {
+            Some(sort) => partitioned(col, ids, workers, deadline, work, |chunks, _| {
+                sort_docs(chunks.flat_map(|(_, docs)| docs), sort, collation, k, budget)
+            }),
+            None => partitioned(col, ids, workers, deadline, work, |chunks, stop| {
+                Sorted::Memory(in_order(chunks, stop, k).into_iter())
+            }),
+        };
+        stats = s;
+        Source::Sorted(sorted)
+    } else {
+        let ids = candidates.unwrap_or_else(|| col.list_ids());
+        let scan = Scan::new(col.clone(), ids, filter.clone(), deadline);
+        match &sort {
+            Some(sort) => Source::Sorted(match &after {
+                Some(point) => {
+                    let rest = scan.filter(|d| point.precedes(d, sort, collation));
+                    sort_docs(rest, sort, collation, k, budget)
+                }
+                None => sort_docs(scan, sort, collation, k, budget),
+            }),
+            None => Source::Scan(scan),
+        }
+    };
+    let projection = opts.projection.clone();
+    let dur_ms = bench.started.elapsed().as_millis();
+    // Emit a developer-benchmark log line for deterministic capture in tests. Results are
+    // produced as the cursor is read, so `result_count` here is the candidates the query
+    // considers; the documents actually returned are logged as `find_results` on drop.
     crate::dev6!(
-        "{{\"bench\":\"query\",\"op\":\"find\",\"collection\":\"{}\",\"duration_ms\":{},\"used_index\":{},\"result_count\":{},\"limit\":{},\"skip\":{}}}",
+        "{{\"bench\":\"query\",\"op\":\"find\",\"collection\":\"{}\",\"duration_ms\":{},\"used_index\":{},\"result_count\":{},\"limit\":{},\"skip\":{},\"workers\":{},\"speedup\":{:.2}}}",
         col.name_str(),
         crate::utils::num::usize_to_u64(dur_ms as usize),
-        bench_used_index,
-        crate::utils::num::usize_to_u64(bench_result_count),
+        used_index,
+        crate::utils::num::usize_to_u64(size),
         crate::utils::num::usize_to_u64(opts.limit.unwrap_or(0)),
-        crate::utils::num::usize_to_u64(opts.skip.unwrap_or(0))
+        crate::utils::num::usize_to_u64(opts.skip.unwrap_or(0)),
+        crate::utils::num::usize_to_u64(stats.workers),
+        stats.speedup()
     );
-    Cursor { collection: col.clone(), ids: Vec::new(), pos: 0, docs: Some(docs) }
+    let mut cursor = Cursor::new(source, projection, skip, limit, bench);
+    if scored {
+        cursor = cursor.with_meta(TEXT_SCORE);
+    }
+    if near {
+        cursor = cursor.with_meta(GEO_DISTANCE);
+    }
+    if let Some(sort) = sort {
+        cursor = cursor.with_resume(col.token_key.clone(), sort, opts.collation.clone());
+    }
+    Ok(cursor)
 }
 
+/// `filter` with its comparisons made under `collation`, if any.
+pub(crate) fn collated(filter: &Filter, collation: Option<&Collation>) -> Filter {
+    match collation {
+        Some(c) => Filter::Collate { collation: c.clone(), filter: Box::new(filter.clone()) },
+        None => filter.clone(),
+    }
+}
+
+/// A predicate the planner can answer from a BTree range: documents after `point` in an
+/// ascending sort have a leading value at least the point's. Missing and null values sort
+/// first, so a point without a plain leading value gives no hint; descending sorts would
+/// need the documents an index omits and get none either.
+fn resume_hint(point: &ResumePoint, sort: &[SortSpec]) -> Option<Filter> {
+    let first = sort.first()?;
+    if !matches!(first.order, Order::Asc) {
+        return None;
+    }
+    let value = point.leading_value()?;
+    if matches!(value, bson::Bson::Null | bson::Bson::Array(_)) {
+        return None;
+    }
+    Some(Filter::Cmp { path: first.field.clone(), op: CmpOp::Gte, value: value.clone() })
+}
+
+/// [`find_docs`], failing instead of returning an empty cursor.
+///
+/// # Errors
+/// Returns `DbError::QueryError` if the timeout has already passed, the `after` token is
+/// invalid, was tampered with, or was issued for a different sort, the sort uses the text
+/// score of a query without `$text` or the distance of one without `$near`, or a `$near` is
+/// not at the top level or under `$and`.
 pub fn find_docs_rate_limited(
     col: &Arc<Collection>,
     filter: &Filter,
@@ -106,78 +210,130 @@ pub fn find_docs_rate_limited(
     {
         return Err(DbError::QueryError("timeout".into()));
     }
-    Ok(find_docs(col, filter, opts))
+    open_cursor(col, filter, opts)
 }
 
 pub fn count_docs_rate_limited(col: &Arc<Collection>, filter: &Filter) -> Result<usize, DbError> {
     let start = std::time::Instant::now();
-    let ids = col.list_ids();
-    let mut n = 0usize;
-    for id in ids {
-        if let Some(d) = col.find_document(&id)
-            && eval_filter(&d.data.0, filter)
-        {
-            n += 1;
-        }
-        if start.elapsed().as_millis() > 5000 {
-            crate::dev6!(
-                "{{\"bench\":\"query\",\"op\":\"count\",\"collection\":\"{}\",\"duration_ms\":{},\"result_count\":{}}}",
-                col.name_str(),
-                crate::utils::num::usize_to_u64(start.elapsed().as_millis() as usize),
-                crate::utils::num::usize_to_u64(n)
-            );
-            return Err(DbError::QueryError("timeout".into()));
-        }
-    }
-    crate::dev6!(
-        "{{\"bench\":\"query\",\"op\":\"count\",\"collection\":\"{}\",\"duration_ms\":{},\"result_count\":{}}}",
-        col.name_str(),
-        crate::utils::num::usize_to_u64(start.elapsed().as_millis() as usize),
-        crate::utils::num::usize_to_u64(n)
-    );
-    Ok(n)
+    let deadline = start + std::time::Duration::from_millis(5000);
+    let (n, stats) = count_matching(col, &text::bind(col, filter), Some(deadline));
+    bench_count(col, start, n.unwrap_or(0), &stats);
+    n.ok_or_else(|| DbError::QueryError("timeout".into()))
 }
 
+/// Count the documents matching `filter`, splitting large scans across threads as
+/// [`find_docs`] does.
 #[must_use]
 pub fn count_docs(col: &Arc<Collection>, filter: &Filter) -> usize {
     let start = std::time::Instant::now();
-    let ids = col.list_ids();
+    let (n, stats) = count_matching(col, &text::bind(col, filter), None);
+    let n = n.unwrap_or(0);
+    bench_count(col, start, n, &stats);
+    n
+}
+
+/// Count the documents matching `filter`, splitting the scan across threads when the
+/// collection is large enough. `None` if `deadline` passed first.
+fn count_matching(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    deadline: Option<std::time::Instant>,
+) -> (Option<usize>, ScanStats) {
+    let timed_out = || deadline.is_some_and(|dl| std::time::Instant::now() > dl);
+    let workers = col.parallel_scan().workers(col.cache.store.read().len());
+    if workers > 1 {
+        let count = |docs: &mut dyn Iterator<Item = &Document>| {
+            docs.filter(|d| eval_filter(&d.data.0, filter)).count()
+        };
+        let (n, stats) = partitioned(col, None, workers, deadline, count, |chunks, _| {
+            chunks.map(|(_, n)| n).sum::<usize>()
+        });
+        return ((!timed_out()).then_some(n), stats);
+    }
     let mut n = 0usize;
-    for id in ids {
+    for id in col.list_ids() {
         if let Some(d) = col.find_document(&id)
             && eval_filter(&d.data.0, filter)
         {
             n += 1;
         }
+        if timed_out() {
+            return (None, ScanStats::serial());
+        }
     }
+    (Some(n), ScanStats::serial())
+}
+
+fn bench_count(col: &Collection, start: std::time::Instant, n: usize, stats: &ScanStats) {
     crate::dev6!(
-        "{{\"bench\":\"query\",\"op\":\"count\",\"collection\":\"{}\",\"duration_ms\":{},\"result_count\":{}}}",
+        "{{\"bench\":\"query\",\"op\":\"count\",\"collection\":\"{}\",\"duration_ms\":{},\"result_count\":{},\"workers\":{},\"speedup\":{:.2}}}",
         col.name_str(),
         crate::utils::num::usize_to_u64(start.elapsed().as_millis() as usize),
-        crate::utils::num::usize_to_u64(n)
+        crate::utils::num::usize_to_u64(n),
+        crate::utils::num::usize_to_u64(stats.workers),
+        stats.speedup()
     );
-    n
 }
 
-pub fn update_many(col: &Arc<Collection>, filter: &Filter, update: &UpdateDoc) -> UpdateReport {
+/// Apply `update` to every matching document.
+///
+/// # Errors
+/// Returns `DbError::QueryError` if an operator fails on any match (e.g. `$inc` on a string),
+/// in which case nothing is written. Returns `DbError::DuplicateKey` if an updated document
+/// would violate a unique index, in which case the earlier matches are put back and nothing
+/// is written either, or `DbError::Io` if storage rejects the append.
+pub fn update_many(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    update: &UpdateDoc,
+) -> Result<UpdateReport, DbError> {
+    update_many_with(col, filter, update, &UpdateOptions::default())
+}
+
+/// [`update_many`] with options; see [`UpdateOptions::upsert`].
+///
+/// # Errors
+/// As for [`update_many`]. An upsert whose insert violates a unique index is retried as an
+/// update once, then fails with `DbError::DuplicateKey`.
+pub fn update_many_with(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    update: &UpdateDoc,
+    opts: &UpdateOptions,
+) -> Result<UpdateReport, DbError> {
+    let filter = &text::bind(col, filter);
+    if opts.upsert {
+        return upsert(col, filter, update, || update_many_inner(col, filter, update));
+    }
+    update_many_inner(col, filter, update)
+}
+
+fn update_many_inner(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    update: &UpdateDoc,
+) -> Result<UpdateReport, DbError> {
     let bench_start = std::time::Instant::now();
-    let mut matched = 0u64;
-    let mut modified = 0u64;
-    let ids: Vec<DocumentId> = col
+    // One batch, as for a bulk `UpdateMany`: matches are read and written under the same lock
+    let mut batch = col.write_batch();
+    let docs: Vec<Document> = col
         .list_ids()
         .into_iter()
-        .filter(|id| col.find_document(id).is_some_and(|d| eval_filter(&d.data.0, filter)))
+        .filter_map(|id| col.find_document(&id))
+        .filter(|d| eval_filter(&d.data.0, filter))
         .collect();
-    for id in ids {
-        if let Some(mut doc) = col.find_document(&id) {
-            matched += 1;
-            let changed = apply_update(&mut doc, update);
-            if changed {
-                modified += 1;
-            }
-            col.update_document(&id, doc);
+    let matched = crate::utils::num::usize_to_u64(docs.len());
+    // Apply to every match before writing any, so an operator error leaves the collection as is
+    let mut changed = Vec::new();
+    for doc in docs {
+        let mut new = doc.clone();
+        // Unchanged documents are neither rewritten nor counted as modified
+        if apply_update_positional(&mut new, update, filter)? {
+            changed.push((doc, new));
         }
     }
+    let modified = bulk::replace_all(&mut batch, changed)?;
+    batch.commit()?;
     let dur_ms = bench_start.elapsed().as_millis();
     crate::dev6!(
         "{{\"bench\":\"query\",\"op\":\"update_many\",\"collection\":\"{}\",\"duration_ms\":{},\"matched\":{},\"modified\":{}}}",
@@ -186,25 +342,86 @@ pub fn update_many(col: &Arc<Collection>, filter: &Filter, update: &UpdateDoc) -
         matched,
         modified
     );
-    UpdateReport { matched, modified }
+    Ok(UpdateReport { matched, modified, upserted_id: None })
+}
+
+/// Apply `update` to the first matching document.
+///
+/// # Errors
+/// Returns `DbError::QueryError` if an operator fails on the document, or
+/// `DbError::DuplicateKey` if the updated document would violate a unique index.
+pub fn update_one(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    update: &UpdateDoc,
+) -> Result<UpdateReport, DbError> {
+    update_one_with(col, filter, update, &UpdateOptions::default())
 }
 
-pub fn update_one(col: &Arc<Collection>, filter: &Filter, update: &UpdateDoc) -> UpdateReport {
+/// [`update_one`] with options; see [`UpdateOptions::upsert`].
+///
+/// # Errors
+/// As for [`update_many_with`].
+pub fn update_one_with(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    update: &UpdateDoc,
+    opts: &UpdateOptions,
+) -> Result<UpdateReport, DbError> {
+    let filter = &text::bind(col, filter);
+    if opts.upsert {
+        return upsert(col, filter, update, || update_one_inner(col, filter, update));
+    }
+    update_one_inner(col, filter, update)
+}
+
+fn update_one_inner(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    update: &UpdateDoc,
+) -> Result<UpdateReport, DbError> {
     if let Some(id) = col
         .list_ids()
         .into_iter()
         .find(|id| col.find_document(id).is_some_and(|d| eval_filter(&d.data.0, filter)))
         && let Some(mut doc) = col.find_document(&id)
     {
-        let changed = apply_update(&mut doc, update);
-        col.update_document(&id, doc);
-        return UpdateReport { matched: 1, modified: u64::from(changed) };
+        let changed = apply_update_positional(&mut doc, update, filter)?;
+        if changed {
+            col.try_update_document(&id, doc)?;
+        }
+        return Ok(UpdateReport { matched: 1, modified: u64::from(changed), upserted_id: None });
+    }
+    Ok(UpdateReport::default())
+}
+
+/// Run `update`, inserting [`upsert_document`] if it matched nothing. Upserts on one
+/// collection are serialized; a plain insert racing in between is caught by a unique index,
+/// and the upsert then updates the document that won.
+fn upsert(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    update: &UpdateDoc,
+    run: impl Fn() -> Result<UpdateReport, DbError>,
+) -> Result<UpdateReport, DbError> {
+    let _guard = col.upsert_lock.lock();
+    let report = run()?;
+    if report.matched > 0 {
+        return Ok(report);
+    }
+    match col.try_insert_document(upsert_document(filter, update)?) {
+        Ok(id) => Ok(UpdateReport { upserted_id: Some(id), ..UpdateReport::default() }),
+        Err(e @ DbError::DuplicateKey { .. }) => {
+            let retry = run()?;
+            if retry.matched > 0 { Ok(retry) } else { Err(e) }
+        }
+        Err(e) => Err(e),
     }
-    UpdateReport { matched: 0, modified: 0 }
 }
 
 pub fn delete_many(col: &Arc<Collection>, filter: &Filter) -> DeleteReport {
     let bench_start = std::time::Instant::now();
+    let filter = &text::bind(col, filter);
     let mut deleted = 0u64;
     let ids: Vec<DocumentId> = col
         .list_ids()
@@ -227,6 +444,7 @@ pub fn delete_many(col: &Arc<Collection>, filter: &Filter) -> DeleteReport {
 }
 
 pub fn delete_one(col: &Arc<Collection>, filter: &Filter) -> DeleteReport {
+    let filter = &text::bind(col, filter);
     if let Some(id) = col
         .list_ids()
         .into_iter()
@@ -238,116 +456,152 @@ pub fn delete_one(col: &Arc<Collection>, filter: &Filter) -> DeleteReport {
     DeleteReport { deleted: 0 }
 }
 
-pub fn apply_update(doc: &mut Document, upd: &UpdateDoc) -> bool {
-    #[allow(clippy::single_call_fn)]
-    fn ensure_subdoc<'a>(root: &'a mut bson::Document, key: &str) -> &'a mut bson::Document {
-        let needs_new = match root.get_mut(key) {
-            Some(bson::Bson::Document(_)) => false,
-            Some(_) => true,
-            None => true,
-        };
-        if needs_new {
-            root.insert(key.to_string(), bson::Bson::Document(bson::Document::new()));
-        }
-        match root.get_mut(key) {
-            Some(bson::Bson::Document(d)) => d,
-            _ => unreachable!(),
-        }
+/// Atomically update the first document matching `filter` (in `opts.sort` order) and return
+/// it as it was before or after the update, per `opts.return_document`.
+///
+/// The document is only written if no other writer changed it since it was selected;
+/// otherwise the selection is retried, so two concurrent calls never both claim the same
+/// document. Returns `None` when nothing matched, or when an upsert inserted a document and
+/// `ReturnDocument::Before` was requested.
+///
+/// # Errors
+/// As for [`update_one_with`].
+pub fn find_one_and_update(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    update: &UpdateDoc,
+    opts: &FindAndModifyOptions,
+) -> Result<Option<Document>, DbError> {
+    find_one_and_modify(
+        col,
+        filter,
+        opts,
+        |doc| apply_update_positional(doc, update, filter),
+        || upsert_document(filter, update),
+    )
+}
+
+/// Like [`find_one_and_update`], replacing the whole document with `replacement`. An upsert
+/// inserts `replacement` as is.
+///
+/// # Errors
+/// Returns `DbError::QueryError` if `replacement` contains `$`-prefixed (operator) keys, and
+/// otherwise as for [`update_one_with`].
+pub fn find_one_and_replace(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    replacement: &BsonDocument,
+    opts: &FindAndModifyOptions,
+) -> Result<Option<Document>, DbError> {
+    if replacement.keys().any(|k| k.starts_with('$')) {
+        return Err(DbError::QueryError(
+            "replacement document must not contain update operators".into(),
+        ));
     }
-    fn traverse_to_parent<'a>(
-        root: &'a mut bson::Document,
-        path: &str,
-    ) -> (&'a mut bson::Document, String) {
-        let mut cur = root;
-        let mut iter = path.split('.').peekable();
-        let mut last = String::new();
-        while let Some(seg) = iter.next() {
-            if iter.peek().is_none() {
-                last = seg.to_string();
-                break;
-            }
-            cur = ensure_subdoc(cur, seg);
+    find_one_and_modify(
+        col,
+        filter,
+        opts,
+        |doc| {
+            let changed = doc.data.0 != *replacement;
+            doc.data.0 = replacement.clone();
+            Ok(changed)
+        },
+        || Ok(Document::new(replacement.clone(), crate::document::DocumentType::Persistent)),
+    )
+}
+
+/// Atomically delete the first document matching `filter` (in `opts.sort` order) and return
+/// it. Only `sort` and `projection` apply.
+pub fn find_one_and_delete(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    opts: &FindAndModifyOptions,
+) -> Option<Document> {
+    loop {
+        let doc = first_match(col, filter, opts.sort.as_deref())?;
+        if col.delete_document_if(&doc.id, &doc.data.0) {
+            return Some(project(doc, opts.projection.as_ref()));
         }
-        (cur, last)
-    }
-    fn set_path(root: &mut bson::Document, path: &str, value: bson::Bson) -> bool {
-        let (parent, last) = traverse_to_parent(root, path);
-        let old = parent.insert(last, value.clone());
-        old.as_ref() != Some(&value)
     }
-    fn get_path(root: &bson::Document, path: &str) -> Option<bson::Bson> {
-        let mut cur = root;
-        let mut iter = path.split('.').peekable();
-        while let Some(seg) = iter.next() {
-            if iter.peek().is_none() {
-                return cur.get(seg).cloned();
+}
+
+fn find_one_and_modify(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    opts: &FindAndModifyOptions,
+    modify: impl Fn(&mut Document) -> Result<bool, DbError>,
+    insert: impl Fn() -> Result<Document, DbError>,
+) -> Result<Option<Document>, DbError> {
+    let pick = |before: Document, after: Document| {
+        let doc = match opts.return_document {
+            ReturnDocument::Before => before,
+            ReturnDocument::After => after,
+        };
+        project(doc, opts.projection.as_ref())
+    };
+    loop {
+        let Some(before) = first_match(col, filter, opts.sort.as_deref()) else {
+            if !opts.upsert {
+                return Ok(None);
             }
-            match cur.get(seg) {
-                Some(bson::Bson::Document(d)) => {
-                    cur = d;
-                }
-                _ => return None,
+            let _guard = col.upsert_lock.lock();
+            // Another upsert may have inserted while we waited for the lock
+            if first_match(col, filter, None).is_some() {
+                continue;
             }
+            let doc = insert()?;
+            return match col.try_insert_document(doc.clone()) {
+                Ok(_) => Ok(match opts.return_document {
+                    ReturnDocument::Before => None,
+                    ReturnDocument::After => Some(project(doc, opts.projection.as_ref())),
+                }),
+                // A plain insert won the unique key; modify that document instead
+                Err(DbError::DuplicateKey { .. }) if first_match(col, filter, None).is_some() => {
+                    continue;
+                }
+                Err(e) => Err(e),
+            };
+        };
+        let mut after = before.clone();
+        if modify(&mut after)?
+            && !col.try_update_document_if(&before.id, &before.data.0, after.clone())?
+        {
+            // Changed or deleted since it was selected
+            continue;
         }
-        None
-    }
-    fn unset_path(root: &mut bson::Document, path: &str) -> bool {
-        let (parent, last) = traverse_to_parent(root, path);
-        parent.remove(&last).is_some()
-    }
-    fn as_f64(v: &bson::Bson) -> f64 {
-        match v {
-            bson::Bson::Double(f) => *f,
-            bson::Bson::Int32(i) => *i as f64,
-            bson::Bson::Int64(i) => *i as f64,
-            bson::Bson::Decimal128(d) => d.to_string().parse::<f64>().unwrap_or(0.0),
-            _ => 0.0,
-        }
-    }
-    fn inc_path(root: &mut bson::Document, path: &str, by: f64) -> bool {
-        let cur = get_path(root, path).unwrap_or(bson::Bson::Double(0.0));
-        let newv = bson::Bson::Double(as_f64(&cur) + by);
-        set_path(root, path, newv)
+        return Ok(Some(pick(before, after)));
     }
+}
 
-    let mut changed = false;
-    for (k, v) in &upd.set {
-        if set_path(&mut doc.data.0, k, v.clone()) {
-            changed = true;
-        }
-    }
-    for (k, inc) in &upd.inc {
-        if inc_path(&mut doc.data.0, k, *inc) {
-            changed = true;
-        }
+/// The first document matching `filter` in `sort` order, or any match without a sort.
+fn first_match(
+    col: &Arc<Collection>,
+    filter: &Filter,
+    sort: Option<&[SortSpec]>,
+) -> Option<Document> {
+    let filter = &text::bind(col, filter);
+    let ids = plan_index_candidates(col, filter).unwrap_or_else(|| col.list_ids());
+    let mut matches = ids
+        .into_iter()
+        .filter_map(|id| col.find_document(&id))
+        .filter(|d| eval_filter(&d.data.0, filter));
+    match sort {
+        Some(sort) => matches.min_by(|a, b| compare_docs(&a.data.0, &b.data.0, sort)),
+        None => matches.next(),
     }
-    for k in &upd.unset {
-        if unset_path(&mut doc.data.0, k) {
-            changed = true;
-        }
+}
+
+fn project(mut doc: Document, projection: Option<&Projection>) -> Document {
+    if let Some(p) = projection {
+        doc.data.0 = super::project::project(&doc.data.0, p);
     }
-    changed
+    doc
 }
 
 fn plan_index_candidates(col: &Arc<Collection>, filter: &Filter) -> Option<Vec<DocumentId>> {
-    match filter {
-        Filter::Cmp { path, op: CmpOp::Eq, value } => {
-            let mut mgr = col.indexes.write();
-            if mgr.indexes.contains_key(path) {
-                return crate::index::lookup_eq(&mut mgr, path, value);
-            }
-            None
-        }
-        Filter::And(fs) => {
-            for f in fs {
-                if let Some(ids) = plan_index_candidates(col, f) {
-                    return Some(ids);
-                }
-            }
-            None
-        }
-        _ => None,
-    }
+    let (chosen, _) = super::plan::choose_plan(col, filter);
+    super::plan::fetch_ids(col, &chosen.path)
 }
 
 #[allow(dead_code)]
@@ -372,21 +626,6 @@ mod tests {
         assert!(matches!(f, Filter::Cmp { path, .. } if path == "x"));
     }
 
-    #[test]
-    fn update_doc_set_inc_unset() {
-        let mut d =
-            Document::new(doc! {"x": 1, "y": 2 }, crate::document::DocumentType::Persistent);
-        let ud = UpdateDoc {
-            set: vec![("y".into(), bson::Bson::Int32(5))],
-            inc: vec![("x".into(), 2.0)],
-            unset: vec!["z".into()],
-        };
-        let changed = super::apply_update(&mut d, &ud);
-        assert!(changed);
-        assert_eq!(d.data.0.get_i32("y").unwrap(), 5);
-        assert_eq!(d.data.0.get_f64("x").unwrap(), 3.0);
-    }
-
     #[test]
     fn find_docs_projection_sort_and_pagination() {
         let e = Engine::new(crate::test_support::temp_wasp("unit_exec_find")).unwrap();
@@ -408,10 +647,12 @@ mod tests {
             op: crate::query::CmpOp::Eq,
             value: bson::Bson::Int32(0),
         };
-        let mut opts = FindOptions::default();
-        opts.projection = Some(vec!["k".into()]);
-        opts.sort = Some(vec![SortSpec { field: "v".into(), order: Order::Asc }]);
-        opts.limit = Some(2);
+        let opts = FindOptions {
+            projection: Some(crate::query::Projection::include(["k"])),
+            sort: Some(vec![SortSpec { field: "v".into(), order: Order::Asc }]),
+            limit: Some(2),
+            ..FindOptions::default()
+        };
         let cur = find_docs(&col, &filter, &opts);
         let docs = cur.to_vec();
         assert_eq!(docs.len(), 2);
@@ -451,7 +692,7 @@ mod tests {
         for n in 0..200 {
             proj.push(format!("p{n}"));
         }
-        opts.projection = Some(proj);
+        opts.projection = Some(proj.into());
         let cur = find_docs(&col, &filter, &opts);
         let docs = cur.to_vec();
         assert!(!docs.is_empty());
@@ -0,0 +1,708 @@
+//! Per-document expressions for `$expr` filters, computed projection fields and aggregation
+//! stages: `"$path"` references, constants, documents and arrays of expressions, and operators
+//! such as `{"$add": ["$price", "$tax"]}`. `{"$meta": "textScore"}` reads the relevance score
+//! of a `$text` query, `{"$meta": "vectorSearchScore"}` the similarity from `$vectorSearch`
+//! and `{"$meta": "geoNearDistance"}` the distance from the point of a `$near` query.
+//!
+//! Evaluation is bounded. Parsing rejects field paths deeper than `MAX_PATH_DEPTH` and nesting
+//! deeper than `MAX_EXPR_DEPTH`; evaluating charges a step per node and per array element an
+//! operator walks, and fails once `MAX_EXPR_STEPS` are spent.
+
+use crate::errors::DbError;
+use bson::{Bson, DateTime, Document as BsonDocument};
+use chrono::{Datelike, Timelike};
+use std::cmp::Ordering;
+
+use super::collation::Collation;
+use super::eval::{compare_bson_collated, path_values};
+use super::geo::GEO_DISTANCE;
+use super::numeric::{self, NumOp, compare_numbers, is_number};
+use super::text::TEXT_SCORE;
+use super::types::{MAX_EXPR_DEPTH, MAX_EXPR_STEPS, MAX_PATH_DEPTH};
+use super::vector::VECTOR_SCORE;
+
+/// `$meta` names and the reserved fields holding their values.
+const META_FIELDS: [(&str, &str); 3] = [
+    ("textScore", TEXT_SCORE),
+    ("vectorSearchScore", VECTOR_SCORE),
+    ("geoNearDistance", GEO_DISTANCE),
+];
+
+/// A value computed per document. Parses from BSON with [`Expr::parse`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// `"$path"`: the value at a dotted path; several values (through arrays) form an array.
+    Field(String),
+    Literal(Bson),
+    /// A sub-document of expressions, e.g. a compound `$group` key.
+    Doc(Vec<(String, Expr)>),
+    Array(Vec<Expr>),
+    /// `{"$op": [args..]}`, or `{"$op": arg}` for a single argument.
+    Op(ExprOp, Vec<Expr>),
+}
+
+/// Expression operators.
+///
+/// Arithmetic, string and date operators yield null when an argument is null or missing, and
+/// fail on arguments of another type. Comparisons order values like [`compare_bson`], with a
+/// missing value as null. `$and`, `$or`, `$not` and `$cond` treat `false`, null, missing and
+/// numeric zero as false and everything else as true.
+///
+/// [`compare_bson`]: super::compare_bson
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExprOp {
+    /// Numbers, plus at most one date that the sum (in milliseconds) is added to.
+    Add,
+    /// Numbers; a date minus milliseconds, or minus a date giving milliseconds.
+    Subtract,
+    Multiply,
+    /// Always a double.
+    Divide,
+    Mod,
+    Abs,
+    Floor,
+    Ceil,
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    /// -1, 0 or 1.
+    Cmp,
+    And,
+    Or,
+    Not,
+    /// `[if, then, else]` or `{"if", "then", "else"}`.
+    Cond,
+    /// The first argument unless it is null or missing, else the second.
+    IfNull,
+    Concat,
+    ToLower,
+    ToUpper,
+    /// `[string, start, count]` in code points.
+    SubstrCP,
+    StrLenCP,
+    Size,
+    /// `[value, array]`
+    In,
+    /// Date parts, in UTC.
+    Year,
+    Month,
+    DayOfMonth,
+    /// 1 (Sunday) to 7 (Saturday).
+    DayOfWeek,
+    DayOfYear,
+    Hour,
+    Minute,
+    Second,
+    Millisecond,
+    /// The BSON type name of the argument (as accepted by the `$type` filter), or
+    /// `"missing"`.
+    Type,
+}
+
+/// Operator names and their argument counts (`None` for any number).
+const OPS: &[(&str, ExprOp, Option<usize>)] = &[
+    ("$add", ExprOp::Add, None),
+    ("$subtract", ExprOp::Subtract, Some(2)),
+    ("$multiply", ExprOp::Multiply, None),
+    ("$divide", ExprOp::Divide, Some(2)),
+    ("$mod", ExprOp::Mod, Some(2)),
+    ("$abs", ExprOp::Abs, Some(1)),
+    ("$floor", ExprOp::Floor, Some(1)),
+    ("$ceil", ExprOp::Ceil, Some(1)),
+    ("$eq", ExprOp::Eq, Some(2)),
+    ("$ne", ExprOp::Ne, Some(2)),
+    ("$gt", ExprOp::Gt, Some(2)),
+    ("$gte", ExprOp::Gte, Some(2)),
+    ("$lt", ExprOp::Lt, Some(2)),
+    ("$lte", ExprOp::Lte, Some(2)),
+    ("$cmp", ExprOp::Cmp, Some(2)),
+    ("$and", ExprOp::And, None),
+    ("$or", ExprOp::Or, None),
+    ("$not", ExprOp::Not, Some(1)),
+    ("$cond", ExprOp::Cond, Some(3)),
+    ("$ifNull", ExprOp::IfNull, Some(2)),
+    ("$concat", ExprOp::Concat, None),
+    ("$toLower", ExprOp::ToLower, Some(1)),
+    ("$toUpper", ExprOp::ToUpper, Some(1)),
+    ("$substrCP", ExprOp::SubstrCP, Some(3)),
+    ("$strLenCP", ExprOp::StrLenCP, Some(1)),
+    ("$size", ExprOp::Size, Some(1)),
+    ("$in", ExprOp::In, Some(2)),
+    ("$year", ExprOp::Year, Some(1)),
+    ("$month", ExprOp::Month, Some(1)),
+    ("$dayOfMonth", ExprOp::DayOfMonth, Some(1)),
+    ("$dayOfWeek", ExprOp::DayOfWeek, Some(1)),
+    ("$dayOfYear", ExprOp::DayOfYear, Some(1)),
+    ("$hour", ExprOp::Hour, Some(1)),
+    ("$minute", ExprOp::Minute, Some(1)),
+    ("$second", ExprOp::Second, Some(1)),
+    ("$millisecond", ExprOp::Millisecond, Some(1)),
+    ("$type", ExprOp::Type, Some(1)),
+];
+
+impl ExprOp {
+    /// The operator's name, e.g. `"$add"`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        OPS.iter().find(|(_, op, _)| *op == self).map_or("", |(name, ..)| name)
+    }
+}
+
+/// BSON type names as `$type` filters and the `$type` operator spell them.
+const TYPE_NAMES: &[&str] = &[
+    "double",
+    "string",
+    "object",
+    "array",
+    "binData",
+    "undefined",
+    "objectId",
+    "bool",
+    "date",
+    "null",
+    "regex",
+    "dbPointer",
+    "javascript",
+    "symbol",
+    "javascriptWithScope",
+    "int",
+    "timestamp",
+    "long",
+    "decimal",
+    "minKey",
+    "maxKey",
+];
+
+pub(crate) fn type_name(v: &Bson) -> &'static str {
+    use bson::Bson as T;
+    match v {
+        T::Double(_) => "double",
+        T::String(_) => "string",
+        T::Document(_) => "object",
+        T::Array(_) => "array",
+        T::Binary(_) => "binData",
+        T::Undefined => "undefined",
+        T::ObjectId(_) => "objectId",
+        T::Boolean(_) => "bool",
+        T::DateTime(_) => "date",
+        T::Null => "null",
+        T::RegularExpression(_) => "regex",
+        T::DbPointer(_) => "dbPointer",
+        T::JavaScriptCode(_) => "javascript",
+        T::Symbol(_) => "symbol",
+        T::JavaScriptCodeWithScope(_) => "javascriptWithScope",
+        T::Int32(_) => "int",
+        T::Timestamp(_) => "timestamp",
+        T::Int64(_) => "long",
+        T::Decimal128(_) => "decimal",
+        T::MinKey => "minKey",
+        T::MaxKey => "maxKey",
+    }
+}
+
+/// Whether `name` is a type `$type` accepts: a [`type_name`], or `"number"` for any numeric
+/// type.
+pub(crate) fn is_type_name(name: &str) -> bool {
+    name == "number" || TYPE_NAMES.contains(&name)
+}
+
+pub(crate) fn has_type(v: &Bson, name: &str) -> bool {
+    if name == "number" { is_number(v) } else { type_name(v) == name }
+}
+
+fn invalid(msg: impl Into<String>) -> DbError {
+    DbError::QueryError(msg.into())
+}
+
+impl Expr {
+    /// Parse an expression: a string starting with `$` is a field path, a document whose
+    /// single key starts with `$` an operator (or `$literal`), other documents and arrays
+    /// hold expressions, and anything else is a constant.
+    ///
+    /// # Errors
+    /// Returns `DbError::QueryError` for unknown operators, wrong argument counts, invalid
+    /// field paths, or expressions nested deeper than `MAX_EXPR_DEPTH`.
+    pub fn parse(v: Bson) -> Result<Self, DbError> {
+        parse(v, 0)
+    }
+
+    /// `None` when the result is missing, e.g. a reference to a missing field, or when
+    /// evaluation fails (see [`Expr::try_eval`]).
+    #[must_use]
+    pub fn eval(&self, doc: &BsonDocument) -> Option<Bson> {
+        self.try_eval(doc).ok().flatten()
+    }
+
+    /// Evaluate against `doc`; `Ok(None)` when the result is missing.
+    ///
+    /// # Errors
+    /// Returns `DbError::QueryError` when an operator gets an argument of the wrong type, on
+    /// division by zero or int64 overflow, or once evaluation exceeds `MAX_EXPR_STEPS`.
+    pub fn try_eval(&self, doc: &BsonDocument) -> Result<Option<Bson>, DbError> {
+        self.eval_collated(doc, None)
+    }
+
+    /// [`Expr::try_eval`], comparing strings under `collation`.
+    pub(crate) fn eval_collated(
+        &self,
+        doc: &BsonDocument,
+        collation: Option<&Collation>,
+    ) -> Result<Option<Bson>, DbError> {
+        eval(self, doc, &mut Ctx { collation, steps: MAX_EXPR_STEPS })
+    }
+
+    /// Whether `doc` matches `{"$expr": self}`: the result is truthy. A failing expression
+    /// matches nothing.
+    pub(crate) fn matches(&self, doc: &BsonDocument, collation: Option<&Collation>) -> bool {
+        self.eval_collated(doc, collation).is_ok_and(|v| truthy(v.as_ref()))
+    }
+
+    /// The BSON form, which [`Expr::parse`] reads back.
+    #[must_use]
+    pub fn to_bson(&self) -> Bson {
+        match self {
+            Self::Field(path) => match META_FIELDS.iter().find(|(_, f)| f == path) {
+                Some((meta, _)) => {
+                    let mut d = BsonDocument::new();
+                    d.insert("$meta", *meta);
+                    Bson::Document(d)
+                }
+                None => Bson::String(format!("${path}")),
+            },
+            Self::Literal(v) => match v {
+                Bson::String(s) if s.starts_with('$') => literal(v),
+                Bson::Document(_) | Bson::Array(_) => literal(v),
+                _ => v.clone(),
+            },
+            Self::Doc(fields) => {
+                Bson::Document(fields.iter().map(|(k, e)| (k.clone(), e.to_bson())).collect())
+            }
+            Self::Array(items) => Bson::Array(items.iter().map(Self::to_bson).collect()),
+            Self::Op(op, args) => {
+                let mut d = BsonDocument::new();
+                d.insert(op.name(), Bson::Array(args.iter().map(Self::to_bson).collect()));
+                Bson::Document(d)
+            }
+        }
+    }
+}
+
+fn literal(v: &Bson) -> Bson {
+    let mut d = BsonDocument::new();
+    d.insert("$literal", v.clone());
+    Bson::Document(d)
+}
+
+fn parse(v: Bson, depth: usize) -> Result<Expr, DbError> {
+    if depth > MAX_EXPR_DEPTH {
+        return Err(invalid(format!("expression nests deeper than {MAX_EXPR_DEPTH} levels")));
+    }
+    let all = |items: Vec<Bson>| -> Result<Vec<Expr>, DbError> {
+        items.into_iter().map(|v| parse(v, depth + 1)).collect()
+    };
+    Ok(match v {
+        Bson::String(s) if s.starts_with('$') => {
+            let path = &s[1..];
+            if path.is_empty() || path.split('.').any(str::is_empty) {
+                return Err(invalid(format!("invalid field path '{s}'")));
+            }
+            if path.split('.').count() > MAX_PATH_DEPTH {
+                return Err(invalid(format!("field path '{s}' is deeper than {MAX_PATH_DEPTH}")));
+            }
+            Expr::Field(path.to_string())
+        }
+        Bson::Array(items) => Expr::Array(all(items)?),
+        Bson::Document(d) if d.keys().all(|k| !k.starts_with('$')) => {
+            let (keys, values): (Vec<String>, Vec<Bson>) = d.into_iter().unzip();
+            Expr::Doc(keys.into_iter().zip(all(values)?).collect())
+        }
+        Bson::Document(d) => {
+            let mut it = d.into_iter();
+            let (Some((name, arg)), None) = (it.next(), it.next()) else {
+                return Err(invalid("an operator object must have exactly one key"));
+            };
+            if name == "$literal" {
+                return Ok(Expr::Literal(arg));
+            }
+            if name == "$meta" {
+                let field = match &arg {
+                    Bson::String(m) => META_FIELDS.iter().find(|(name, _)| name == m),
+                    _ => None,
+                };
+                return field.map(|(_, f)| Expr::Field((*f).into())).ok_or_else(|| {
+                    invalid("$meta supports textScore, vectorSearchScore and geoNearDistance")
+                });
+            }
+            let Some(&(_, op, arity)) = OPS.iter().find(|(n, ..)| *n == name) else {
+                return Err(invalid(format!("unknown expression operator '{name}'")));
+            };
+            let args = match arg {
+                Bson::Array(items) => items,
+                Bson::Document(d) if op == ExprOp::Cond && d.contains_key("if") => cond_args(d)?,
+                other => vec![other],
+            };
+            if let Some(n) = arity
+                && args.len() != n
+            {
+                return Err(invalid(format!("{name} takes {n} argument(s), got {}", args.len())));
+            }
+            Expr::Op(op, all(args)?)
+        }
+        other => Expr::Literal(other),
+    })
+}
+
+/// `{"if": .., "then": .., "else": ..}` as `[if, then, else]`.
+fn cond_args(mut d: BsonDocument) -> Result<Vec<Bson>, DbError> {
+    let mut args = Vec::with_capacity(3);
+    for key in ["if", "then", "else"] {
+        args.push(d.remove(key).ok_or_else(|| invalid(format!("$cond requires '{key}'")))?);
+    }
+    if let Some(extra) = d.keys().next() {
+        return Err(invalid(format!("unknown $cond argument '{extra}'")));
+    }
+    Ok(args)
+}
+
+/// Evaluation state: how strings compare and the steps left.
+struct Ctx<'c> {
+    collation: Option<&'c Collation>,
+    steps: usize,
+}
+
+impl Ctx<'_> {
+    fn charge(&mut self, n: usize) -> Result<(), DbError> {
+        self.steps = self.steps.checked_sub(n).ok_or_else(|| {
+            invalid(format!("expression exceeded {MAX_EXPR_STEPS} evaluation steps"))
+        })?;
+        Ok(())
+    }
+}
+
+fn eval(e: &Expr, doc: &BsonDocument, ctx: &mut Ctx<'_>) -> Result<Option<Bson>, DbError> {
+    ctx.charge(1)?;
+    Ok(match e {
+        Expr::Field(path) => {
+            let mut vals = path_values(doc, path);
+            ctx.charge(vals.len())?;
+            match vals.len() {
+                0 => None,
+                1 => Some(vals.remove(0).clone()),
+                _ => Some(Bson::Array(vals.into_iter().cloned().collect())),
+            }
+        }
+        Expr::Literal(v) => Some(v.clone()),
+        Expr::Doc(fields) => {
+            let mut out = BsonDocument::new();
+            for (k, e) in fields {
+                if let Some(v) = eval(e, doc, ctx)? {
+                    out.insert(k.clone(), v);
+                }
+            }
+            Some(Bson::Document(out))
+        }
+        // Missing elements become null, keeping positions
+        Expr::Array(items) => Some(Bson::Array(
+            items
+                .iter()
+                .map(|e| Ok(eval(e, doc, ctx)?.unwrap_or(Bson::Null)))
+                .collect::<Result<_, DbError>>()?,
+        )),
+        Expr::Op(op, args) => apply(*op, args, doc, ctx)?,
+    })
+}
+
+fn truthy(v: Option<&Bson>) -> bool {
+    match v {
+        None | Some(Bson::Null | Bson::Undefined | Bson::Boolean(false)) => false,
+        Some(v) => compare_numbers(v, &Bson::Int32(0)) != Some(Ordering::Equal),
+    }
+}
+
+fn nullish(v: Option<&Bson>) -> bool {
+    matches!(v, None | Some(Bson::Null | Bson::Undefined))
+}
+
+fn apply(
+    op: ExprOp,
+    args: &[Expr],
+    doc: &BsonDocument,
+    ctx: &mut Ctx<'_>,
+) -> Result<Option<Bson>, DbError> {
+    use ExprOp as O;
+    // These evaluate only the arguments they need
+    match op {
+        O::And | O::Or => {
+            let stop = op == O::Or;
+            for a in args {
+                if truthy(eval(a, doc, ctx)?.as_ref()) == stop {
+                    return Ok(Some(Bson::Boolean(stop)));
+                }
+            }
+            return Ok(Some(Bson::Boolean(!stop)));
+        }
+        O::Cond => {
+            let branch =
+                if truthy(eval(&args[0], doc, ctx)?.as_ref()) { &args[1] } else { &args[2] };
+            return eval(branch, doc, ctx);
+        }
+        O::IfNull => {
+            return match eval(&args[0], doc, ctx)? {
+                v if nullish(v.as_ref()) => eval(&args[1], doc, ctx),
+                v => Ok(v),
+            };
+        }
+        _ => {}
+    }
+    let vals = args.iter().map(|a| eval(a, doc, ctx)).collect::<Result<Vec<_>, _>>()?;
+    if propagates_null(op) && vals.iter().any(|v| nullish(v.as_ref())) {
+        return Ok(Some(Bson::Null));
+    }
+    let name = op.name();
+    let collation = ctx.collation;
+    let type_err = |want: &str| invalid(format!("{name} requires {want}"));
+    let number = |v: &Bson| if is_number(v) { Ok(v.clone()) } else { Err(type_err("numbers")) };
+    let cmp = || {
+        let null = &Bson::Null;
+        let (a, b) = (vals[0].as_ref().unwrap_or(null), vals[1].as_ref().unwrap_or(null));
+        compare_bson_collated(a, b, collation)
+    };
+    Ok(Some(match op {
+        O::Add => {
+            let mut sum = Bson::Int32(0);
+            let mut date = None;
+            for v in vals.iter().flatten() {
+                match v {
+                    Bson::DateTime(d) if date.is_none() => date = Some(d.timestamp_millis()),
+                    Bson::DateTime(_) => return Err(invalid("$add takes at most one date")),
+                    v => sum = arith(name, NumOp::Add, &sum, &number(v)?)?,
+                }
+            }
+            match date {
+                Some(ms) => shift_date(name, ms, &sum, 1)?,
+                None => sum,
+            }
+        }
+        O::Multiply => {
+            let mut product = Bson::Int32(1);
+            for v in vals.iter().flatten() {
+                product = arith(name, NumOp::Mul, &product, &number(v)?)?;
+            }
+            product
+        }
+        O::Subtract => match (vals[0].as_ref(), vals[1].as_ref()) {
+            (Some(Bson::DateTime(a)), Some(Bson::DateTime(b))) => {
+                let diff = a.timestamp_millis().checked_sub(b.timestamp_millis());
+                Bson::Int64(diff.ok_or_else(|| invalid("$subtract overflowed"))?)
+            }
+            (Some(Bson::DateTime(a)), Some(b)) => {
+                shift_date(name, a.timestamp_millis(), &number(b)?, -1)?
+            }
+            (Some(a), Some(b)) => arith(name, NumOp::Sub, &number(a)?, &number(b)?)?,
+            _ => Bson::Null,
+        },
+        O::Divide | O::Mod => {
+            let (a, b) =
+                (vals[0].as_ref().unwrap_or(&Bson::Null), vals[1].as_ref().unwrap_or(&Bson::Null));
+            let zero = || invalid(format!("{name} by zero"));
+            match (a, b) {
+                // Integer remainders keep an integer type; `MIN % -1` wraps to its true value, 0
+                (Bson::Int32(_) | Bson::Int64(_), Bson::Int32(_) | Bson::Int64(_))
+                    if op == O::Mod =>
+                {
+                    let (x, y) = (int_arg(a).unwrap_or(0), int_arg(b).unwrap_or(0));
+                    if y == 0 {
+                        return Err(zero());
+                    }
+                    let r = x.wrapping_rem(y);
+                    match (a, b) {
+                        (Bson::Int32(_), Bson::Int32(_)) => {
+                            Bson::Int32(i32::try_from(r).unwrap_or(0))
+                        }
+                        _ => Bson::Int64(r),
+                    }
+                }
+                _ => {
+                    let float = |v| numeric::to_f64(v).ok_or_else(|| type_err("numbers"));
+                    let (x, y) = (float(a)?, float(b)?);
+                    if y == 0.0 {
+                        return Err(zero());
+                    }
+                    Bson::Double(if op == O::Divide { x / y } else { x % y })
+                }
+            }
+        }
+        O::Abs => {
+            let v = number(vals[0].as_ref().unwrap_or(&Bson::Null))?;
+            if compare_numbers(&v, &Bson::Int32(0)) == Some(Ordering::Less) {
+                arith(name, NumOp::Mul, &v, &Bson::Int32(-1))?
+            } else {
+                v
+            }
+        }
+        O::Floor | O::Ceil => match vals[0].as_ref() {
+            Some(v @ (Bson::Int32(_) | Bson::Int64(_))) => v.clone(),
+            _ => {
+                let f = vals[0].as_ref().and_then(numeric::to_f64);
+                let f = f.ok_or_else(|| type_err("a number"))?;
+                Bson::Double(if op == O::Floor { f.floor() } else { f.ceil() })
+            }
+        },
+        O::Eq => Bson::Boolean(cmp() == Ordering::Equal),
+        O::Ne => Bson::Boolean(cmp() != Ordering::Equal),
+        O::Gt => Bson::Boolean(cmp() == Ordering::Greater),
+        O::Gte => Bson::Boolean(cmp() != Ordering::Less),
+        O::Lt => Bson::Boolean(cmp() == Ordering::Less),
+        O::Lte => Bson::Boolean(cmp() != Ordering::Greater),
+        O::Cmp => Bson::Int32(cmp() as i32),
+        O::Not => Bson::Boolean(!truthy(vals[0].as_ref())),
+        O::Concat => {
+            let mut out = String::new();
+            for v in vals.iter().flatten() {
+                let Bson::String(s) = v else { return Err(type_err("strings")) };
+                out.push_str(s);
+            }
+            Bson::String(out)
+        }
+        O::ToLower | O::ToUpper => match vals[0].as_ref() {
+            v if nullish(v) => Bson::String(String::new()),
+            Some(Bson::String(s)) if op == O::ToLower => Bson::String(s.to_lowercase()),
+            Some(Bson::String(s)) => Bson::String(s.to_uppercase()),
+            _ => return Err(type_err("a string")),
+        },
+        O::SubstrCP => {
+            let s = match vals[0].as_ref() {
+                v if nullish(v) => "",
+                Some(Bson::String(s)) => s.as_str(),
+                _ => return Err(type_err("a string")),
+            };
+            let index = |i: usize| {
+                vals[i]
+                    .as_ref()
+                    .and_then(int_arg)
+                    .and_then(|n| usize::try_from(n).ok())
+                    .ok_or_else(|| type_err("a non-negative integer start and count"))
+            };
+            let (start, count) = (index(1)?, index(2)?);
+            Bson::String(s.chars().skip(start).take(count).collect())
+        }
+        O::StrLenCP => match vals[0].as_ref() {
+            Some(Bson::String(s)) => len_value(s.chars().count()),
+            _ => return Err(type_err("a string")),
+        },
+        O::Size => match vals[0].as_ref() {
+            Some(Bson::Array(items)) => len_value(items.len()),
+            _ => return Err(type_err("an array")),
+        },
+        O::In => {
+            let Some(Bson::Array(items)) = vals[1].as_ref() else {
+                return Err(type_err("an array as its second argument"));
+            };
+            ctx.charge(items.len())?;
+            let needle = vals[0].as_ref().unwrap_or(&Bson::Null);
+            let found = items
+                .iter()
+                .any(|x| compare_bson_collated(needle, x, collation) == Ordering::Equal);
+            Bson::Boolean(found)
+        }
+        O::Year
+        | O::Month
+        | O::DayOfMonth
+        | O::DayOfWeek
+        | O::DayOfYear
+        | O::Hour
+        | O::Minute
+        | O::Second
+        | O::Millisecond => {
+            let Some(Bson::DateTime(d)) = vals[0].as_ref() else {
+                return Err(type_err("a date"));
+            };
+            date_part(op, *d)?
+        }
+        O::Type => Bson::String(vals[0].as_ref().map_or("missing", type_name).to_string()),
+        O::And | O::Or | O::Cond | O::IfNull => unreachable!("evaluated above"),
+    }))
+}
+
+/// Whether `op` yields null when any argument is null or missing.
+const fn propagates_null(op: ExprOp) -> bool {
+    use ExprOp as O;
+    matches!(
+        op,
+        O::Add
+            | O::Subtract
+            | O::Multiply
+            | O::Divide
+            | O::Mod
+            | O::Abs
+            | O::Floor
+            | O::Ceil
+            | O::Concat
+            | O::Year
+            | O::Month
+            | O::DayOfMonth
+            | O::DayOfWeek
+            | O::DayOfYear
+            | O::Hour
+            | O::Minute
+            | O::Second
+            | O::Millisecond
+    )
+}
+
+fn arith(name: &str, op: NumOp, a: &Bson, b: &Bson) -> Result<Bson, DbError> {
+    numeric::apply(op, a, b).ok_or_else(|| invalid(format!("{name} overflowed int64")))
+}
+
+/// The date `ms` plus (or, with `sign` -1, minus) `by` milliseconds, rounded to the
+/// nearest millisecond.
+#[allow(clippy::cast_possible_truncation)]
+fn shift_date(name: &str, ms: i64, by: &Bson, sign: i64) -> Result<Bson, DbError> {
+    let by = match by {
+        Bson::Int32(i) => Some(i64::from(*i)),
+        Bson::Int64(i) => Some(*i),
+        other => numeric::to_f64(other)
+            .filter(|f| f.is_finite() && f.abs() < 9.2e18)
+            .map(|f| f.round() as i64),
+    };
+    by.and_then(|by| by.checked_mul(sign))
+        .and_then(|by| ms.checked_add(by))
+        .map(|ms| Bson::DateTime(DateTime::from_millis(ms)))
+        .ok_or_else(|| invalid(format!("{name} moved a date out of range")))
+}
+
+/// An integral number as an `i64`.
+#[allow(clippy::cast_possible_truncation)]
+fn int_arg(v: &Bson) -> Option<i64> {
+    match v {
+        Bson::Int32(i) => Some(i64::from(*i)),
+        Bson::Int64(i) => Some(*i),
+        Bson::Double(f) if f.fract() == 0.0 && f.abs() < 9e15 => Some(*f as i64),
+        _ => None,
+    }
+}
+
+fn len_value(n: usize) -> Bson {
+    i32::try_from(n).map_or_else(|_| Bson::Int64(i64::try_from(n).unwrap_or(i64::MAX)), Bson::Int32)
+}
+
+fn date_part(op: ExprOp, d: DateTime) -> Result<Bson, DbError> {
+    use ExprOp as O;
+    let t = chrono::DateTime::from_timestamp_millis(d.timestamp_millis())
+        .ok_or_else(|| invalid(format!("{} got a date out of range", op.name())))?;
+    let part = match op {
+        O::Year => return Ok(Bson::Int32(t.year())),
+        O::Month => t.month(),
+        O::DayOfMonth => t.day(),
+        O::DayOfWeek => t.weekday().number_from_sunday(),
+        O::DayOfYear => t.ordinal(),
+        O::Hour => t.hour(),
+        O::Minute => t.minute(),
+        O::Second => t.second(),
+        _ => t.timestamp_subsec_millis(),
+    };
+    Ok(Bson::Int32(i32::try_from(part).unwrap_or(i32::MAX)))
+}
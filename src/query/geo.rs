@@ -0,0 +1,783 @@
+//! Geospatial queries.
+//!
+//! Locations are legacy coordinate pairs (`[lon, lat]`) or GeoJSON `Point`, `LineString` and
+//! `Polygon` objects. `$geoWithin` keeps documents whose geometry lies inside a box, circle or
+//! polygon; `$geoIntersects` those whose geometry meets a GeoJSON geometry; `$near` and
+//! `$nearSphere` those within a distance of a point, giving each its distance so results come
+//! nearest first.
+//!
+//! A geo index keys each point by its z-order cell: 26 bits of longitude interleaved with 26
+//! of latitude, as in a geohash, so nearby points have nearby keys in a BTree. A query region
+//! is covered by a few cells, each a contiguous key range, and the candidates are then checked
+//! exactly.
+//!
+//! Edges of boxes and polygons are straight lines in longitude/latitude. `$center` radii and
+//! legacy `$near` distances are planar, in coordinate units; `$centerSphere` radii and legacy
+//! `$nearSphere` distances are radians on the sphere; distances from a `$geometry` point are
+//! metres.
+
+use crate::errors::DbError;
+use bson::{Bson, Document as BsonDocument, doc};
+
+use super::eval::path_values;
+use super::numeric::to_f64;
+use super::types::Filter;
+
+/// Where a `$near` query leaves each match's distance: sort on this field, or project
+/// `{"$meta": "geoNearDistance"}`. It is removed before documents are returned.
+pub(crate) const GEO_DISTANCE: &str = "$geoNearDistance";
+
+/// Mean equatorial radius used to turn angles into metres.
+const EARTH_RADIUS_M: f64 = 6_378_100.0;
+
+/// Bits of each coordinate in a cell key.
+const CELL_BITS: u32 = 26;
+
+/// Most cells a query region is covered by before the covering stops refining.
+const MAX_COVER_CELLS: usize = 64;
+
+/// Most vertices accepted in a query geometry.
+const MAX_VERTICES: usize = 10_000;
+
+/// Slack added around covered regions so rounding never drops a point on their edge.
+const COVER_SLACK: f64 = 1e-9;
+
+/// A longitude/latitude pair, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lon: f64,
+    pub lat: f64,
+}
+
+impl GeoPoint {
+    #[must_use]
+    pub const fn new(lon: f64, lat: f64) -> Self {
+        Self { lon, lat }
+    }
+
+    fn to_bson(self) -> Bson {
+        Bson::Array(vec![Bson::Double(self.lon), Bson::Double(self.lat)])
+    }
+}
+
+/// A document's location, or the operand of `$geoIntersects`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Point(GeoPoint),
+    LineString(Vec<GeoPoint>),
+    /// The outer ring, then any holes. Rings are closed: the last point repeats the first.
+    Polygon(Vec<Vec<GeoPoint>>),
+}
+
+/// The region of a `$geoWithin` query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoShape {
+    /// `$box`: the bottom-left and top-right corners.
+    Box { min: GeoPoint, max: GeoPoint },
+    /// `$center`: a planar circle; the radius is in coordinate units.
+    Center { center: GeoPoint, radius: f64 },
+    /// `$centerSphere`: a spherical cap; the radius is in radians.
+    CenterSphere { center: GeoPoint, radius: f64 },
+    /// `$polygon`, or a `$geometry` polygon: closed rings, the outer one first.
+    Polygon(Vec<Vec<GeoPoint>>),
+}
+
+/// How `$near` measures distances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceUnit {
+    /// Straight-line distance in coordinate units (legacy `$near`).
+    Planar,
+    /// Angle on the sphere (legacy `$nearSphere`).
+    Radians,
+    /// Great-circle distance on the Earth (a `$geometry` point).
+    Meters,
+}
+
+/// `$near` / `$nearSphere`: documents within `[min_distance, max_distance]` of `point`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoNear {
+    pub point: GeoPoint,
+    pub unit: DistanceUnit,
+    pub min_distance: Option<f64>,
+    pub max_distance: Option<f64>,
+    /// Written as `$nearSphere` rather than `$near`.
+    pub sphere: bool,
+}
+
+/// A geospatial predicate on one field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoOp {
+    Within(GeoShape),
+    Intersects(Geometry),
+    Near(GeoNear),
+}
+
+/// A longitude/latitude rectangle, edges included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    min: GeoPoint,
+    max: GeoPoint,
+}
+
+impl Rect {
+    const WORLD: Self = Self { min: GeoPoint::new(-180.0, -90.0), max: GeoPoint::new(180.0, 90.0) };
+
+    fn intersects(&self, o: &Self) -> bool {
+        self.min.lon <= o.max.lon
+            && o.min.lon <= self.max.lon
+            && self.min.lat <= o.max.lat
+            && o.min.lat <= self.max.lat
+    }
+
+    fn contains(&self, o: &Self) -> bool {
+        self.min.lon <= o.min.lon
+            && o.max.lon <= self.max.lon
+            && self.min.lat <= o.min.lat
+            && o.max.lat <= self.max.lat
+    }
+
+    fn contains_point(&self, p: GeoPoint) -> bool {
+        (self.min.lon..=self.max.lon).contains(&p.lon)
+            && (self.min.lat..=self.max.lat).contains(&p.lat)
+    }
+
+    /// Clipped to the world, or `None` when it lies outside.
+    fn clipped(self) -> Option<Self> {
+        let r = Self {
+            min: GeoPoint::new(self.min.lon.max(-180.0), self.min.lat.max(-90.0)),
+            max: GeoPoint::new(self.max.lon.min(180.0), self.max.lat.min(90.0)),
+        };
+        (r.min.lon <= r.max.lon && r.min.lat <= r.max.lat).then_some(r)
+    }
+
+    fn around(points: &[GeoPoint]) -> Self {
+        let mut r = Self {
+            min: GeoPoint::new(f64::INFINITY, f64::INFINITY),
+            max: GeoPoint::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+        };
+        for p in points {
+            r.min.lon = r.min.lon.min(p.lon);
+            r.min.lat = r.min.lat.min(p.lat);
+            r.max.lon = r.max.lon.max(p.lon);
+            r.max.lat = r.max.lat.max(p.lat);
+        }
+        r
+    }
+}
+
+fn invalid(op: &str, msg: &str) -> DbError {
+    DbError::QueryError(format!("{op}: {msg}"))
+}
+
+fn in_range(p: GeoPoint) -> bool {
+    (-180.0..=180.0).contains(&p.lon) && (-90.0..=90.0).contains(&p.lat)
+}
+
+/// `[lon, lat]` (GeoJSON positions may carry a third, ignored, coordinate).
+fn position(v: &Bson, geojson: bool) -> Option<GeoPoint> {
+    let Bson::Array(items) = v else {
+        return None;
+    };
+    let extra = if geojson { 3 } else { 2 };
+    if !(2..=extra).contains(&items.len()) {
+        return None;
+    }
+    let p = GeoPoint::new(to_f64(&items[0])?, to_f64(&items[1])?);
+    in_range(p).then_some(p)
+}
+
+fn positions(v: &Bson) -> Option<Vec<GeoPoint>> {
+    let Bson::Array(items) = v else {
+        return None;
+    };
+    if items.len() > MAX_VERTICES {
+        return None;
+    }
+    items.iter().map(|p| position(p, true)).collect()
+}
+
+/// Rings of at least four positions whose last repeats the first.
+fn rings(v: &Bson) -> Option<Vec<Vec<GeoPoint>>> {
+    let Bson::Array(items) = v else {
+        return None;
+    };
+    let rings: Vec<Vec<GeoPoint>> = items.iter().map(positions).collect::<Option<_>>()?;
+    let closed = |r: &Vec<GeoPoint>| r.len() >= 4 && r.first() == r.last();
+    (!rings.is_empty() && rings.iter().all(closed)).then_some(rings)
+}
+
+impl Geometry {
+    /// A legacy pair or GeoJSON object, or `None` for any other value.
+    fn from_bson(v: &Bson) -> Option<Self> {
+        if let Some(p) = position(v, false) {
+            return Some(Self::Point(p));
+        }
+        let Bson::Document(d) = v else {
+            return None;
+        };
+        let coords = d.get("coordinates")?;
+        match d.get_str("type").ok()? {
+            "Point" => position(coords, true).map(Self::Point),
+            "LineString" => positions(coords).filter(|l| l.len() >= 2).map(Self::LineString),
+            "Polygon" => rings(coords).map(Self::Polygon),
+            _ => None,
+        }
+    }
+
+    /// The `$geometry` operand of a query.
+    pub(crate) fn parse(op: &str, v: &Bson) -> Result<Self, DbError> {
+        Self::from_bson(v)
+            .filter(|_| matches!(v, Bson::Document(_)))
+            .ok_or_else(|| invalid(op, "$geometry must be a GeoJSON Point, LineString or Polygon"))
+    }
+
+    /// The GeoJSON object.
+    #[must_use]
+    pub fn to_bson(&self) -> Bson {
+        let ring = |r: &[GeoPoint]| Bson::Array(r.iter().map(|p| p.to_bson()).collect());
+        let (kind, coords) = match self {
+            Self::Point(p) => ("Point", p.to_bson()),
+            Self::LineString(l) => ("LineString", ring(l)),
+            Self::Polygon(rs) => ("Polygon", Bson::Array(rs.iter().map(|r| ring(r)).collect())),
+        };
+        Bson::Document(doc! {"type": kind, "coordinates": coords})
+    }
+
+    fn vertices(&self) -> Vec<GeoPoint> {
+        match self {
+            Self::Point(p) => vec![*p],
+            Self::LineString(l) => l.clone(),
+            Self::Polygon(rs) => rs.iter().flatten().copied().collect(),
+        }
+    }
+
+    /// Its edges; a point is a single degenerate edge.
+    fn edges(&self) -> Vec<(GeoPoint, GeoPoint)> {
+        match self {
+            Self::Point(p) => vec![(*p, *p)],
+            Self::LineString(l) => l.windows(2).map(|w| (w[0], w[1])).collect(),
+            Self::Polygon(rs) => {
+                rs.iter().flat_map(|r| r.windows(2).map(|w| (w[0], w[1]))).collect()
+            }
+        }
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        let (a, b) = (self.edges(), other.edges());
+        if a.iter().any(|(p, q)| b.iter().any(|(r, s)| segments_meet(*p, *q, *r, *s))) {
+            return true;
+        }
+        // No edges cross, so one geometry can only meet the other by lying inside it.
+        let inside = |g: &Self, poly: &Self| match poly {
+            Self::Polygon(rs) => g.vertices().first().is_some_and(|p| in_polygon(rs, *p)),
+            _ => false,
+        };
+        inside(self, other) || inside(other, self)
+    }
+}
+
+/// The geometries at `path` in `doc`: a location, or each location of an array of them.
+pub(crate) fn geometries(doc: &BsonDocument, path: &str) -> Vec<Geometry> {
+    let mut out = Vec::new();
+    for v in path_values(doc, path) {
+        match (Geometry::from_bson(v), v) {
+            (Some(g), _) => out.push(g),
+            (None, Bson::Array(items)) => out.extend(items.iter().filter_map(Geometry::from_bson)),
+            _ => {}
+        }
+    }
+    out
+}
+
+impl GeoShape {
+    /// The `$geoWithin` operand: `{"$box": ..}`, `{"$center": ..}`, `{"$centerSphere": ..}`,
+    /// `{"$polygon": ..}` or `{"$geometry": <Polygon>}`.
+    ///
+    /// # Errors
+    /// Returns `DbError::QueryError` for any other shape or invalid coordinates.
+    pub fn parse(v: &Bson) -> Result<Self, DbError> {
+        const OP: &str = "$geoWithin";
+        let d = match v {
+            Bson::Document(d) if d.len() == 1 => d,
+            _ => {
+                return Err(invalid(
+                    OP,
+                    "requires one of $box, $center, $centerSphere, $polygon or $geometry",
+                ));
+            }
+        };
+        let (key, arg) = d.iter().next().ok_or_else(|| invalid(OP, "requires a shape"))?;
+        let pair = |v: &Bson| position(v, false);
+        let circle = |what: &str| -> Result<(GeoPoint, f64), DbError> {
+            let err = || invalid(OP, &format!("{what} requires [[lon, lat], radius]"));
+            let Bson::Array(items) = arg else {
+                return Err(err());
+            };
+            match items.as_slice() {
+                [c, r] => {
+                    let radius =
+                        to_f64(r).filter(|r| r.is_finite() && *r >= 0.0).ok_or_else(err)?;
+                    Ok((pair(c).ok_or_else(err)?, radius))
+                }
+                _ => Err(err()),
+            }
+        };
+        match key.as_str() {
+            "$box" => {
+                let err = || invalid(OP, "$box requires [[lon, lat], [lon, lat]]");
+                let Bson::Array(items) = arg else {
+                    return Err(err());
+                };
+                let [a, b] = items.as_slice() else {
+                    return Err(err());
+                };
+                let (a, b) = (pair(a).ok_or_else(err)?, pair(b).ok_or_else(err)?);
+                Ok(Self::Box {
+                    min: GeoPoint::new(a.lon.min(b.lon), a.lat.min(b.lat)),
+                    max: GeoPoint::new(a.lon.max(b.lon), a.lat.max(b.lat)),
+                })
+            }
+            "$center" => circle("$center").map(|(center, radius)| Self::Center { center, radius }),
+            "$centerSphere" => circle("$centerSphere")
+                .map(|(center, radius)| Self::CenterSphere { center, radius }),
+            "$polygon" => {
+                let err = || invalid(OP, "$polygon requires at least three [lon, lat] points");
+                let Bson::Array(items) = arg else {
+                    return Err(err());
+                };
+                if items.len() > MAX_VERTICES {
+                    return Err(err());
+                }
+                let mut ring: Vec<GeoPoint> =
+                    items.iter().map(pair).collect::<Option<_>>().ok_or_else(err)?;
+                if ring.len() < 3 {
+                    return Err(err());
+                }
+                if ring.first() != ring.last() {
+                    ring.push(ring[0]);
+                }
+                Ok(Self::Polygon(vec![ring]))
+            }
+            "$geometry" => match Geometry::parse(OP, arg)? {
+                Geometry::Polygon(rs) => Ok(Self::Polygon(rs)),
+                _ => Err(invalid(OP, "$geometry must be a Polygon")),
+            },
+            other => Err(invalid(OP, &format!("unsupported shape '{other}'"))),
+        }
+    }
+
+    /// The `$geoWithin` operand.
+    #[must_use]
+    pub fn to_bson(&self) -> Bson {
+        let circle = |c: &GeoPoint, r: f64| Bson::Array(vec![c.to_bson(), Bson::Double(r)]);
+        Bson::Document(match self {
+            Self::Box { min, max } => doc! {"$box": [min.to_bson(), max.to_bson()]},
+            Self::Center { center, radius } => doc! {"$center": circle(center, *radius)},
+            Self::CenterSphere { center, radius } => {
+                doc! {"$centerSphere": circle(center, *radius)}
+            }
+            Self::Polygon(rs) => doc! {"$geometry": Geometry::Polygon(rs.clone()).to_bson()},
+        })
+    }
+
+    fn contains_point(&self, p: GeoPoint) -> bool {
+        match self {
+            Self::Box { min, max } => Rect { min: *min, max: *max }.contains_point(p),
+            Self::Center { center, radius } => planar(*center, p) <= *radius,
+            Self::CenterSphere { center, radius } => angle(*center, p) <= *radius,
+            Self::Polygon(rs) => in_polygon(rs, p),
+        }
+    }
+
+    /// Whether all of `g` lies inside: every vertex does and, for a polygon (which may be
+    /// concave), no edge of `g` crosses its boundary.
+    fn contains(&self, g: &Geometry) -> bool {
+        if !g.vertices().into_iter().all(|p| self.contains_point(p)) {
+            return false;
+        }
+        match (self, g) {
+            (Self::Polygon(rs), Geometry::LineString(_) | Geometry::Polygon(_)) => {
+                let own: Vec<_> =
+                    rs.iter().flat_map(|r| r.windows(2).map(|w| (w[0], w[1]))).collect();
+                !g.edges()
+                    .iter()
+                    .any(|(p, q)| own.iter().any(|(r, s)| segments_cross(*p, *q, *r, *s)))
+            }
+            _ => true,
+        }
+    }
+
+    fn bounds(&self) -> Vec<Rect> {
+        match self {
+            Self::Box { min, max } => vec![Rect { min: *min, max: *max }],
+            Self::Center { center, radius } => planar_bounds(*center, *radius),
+            Self::CenterSphere { center, radius } => cap_bounds(*center, *radius),
+            Self::Polygon(rs) => vec![Rect::around(&rs[0])],
+        }
+    }
+}
+
+impl GeoNear {
+    /// The `$near` (or, with `sphere`, `$nearSphere`) operand: a legacy `[lon, lat]` pair, with
+    /// `min`/`max` from `$minDistance`/`$maxDistance` beside it, or `{"$geometry": <Point>}`
+    /// with distances in metres beside or inside it.
+    ///
+    /// # Errors
+    /// Returns `DbError::QueryError` for an invalid point or a negative distance.
+    pub fn parse(
+        v: &Bson,
+        sphere: bool,
+        min: Option<f64>,
+        max: Option<f64>,
+    ) -> Result<Self, DbError> {
+        let op = if sphere { "$nearSphere" } else { "$near" };
+        let (point, unit, min, max) = match v {
+            Bson::Document(d) => {
+                let mut point = None;
+                let (mut min, mut max) = (min, max);
+                for (k, v) in d {
+                    let distance =
+                        || to_f64(v).ok_or_else(|| invalid(op, &format!("{k} must be a number")));
+                    match k.as_str() {
+                        "$geometry" => match Geometry::parse(op, v)? {
+                            Geometry::Point(p) => point = Some(p),
+                            _ => return Err(invalid(op, "$geometry must be a Point")),
+                        },
+                        "$minDistance" => min = Some(distance()?),
+                        "$maxDistance" => max = Some(distance()?),
+                        other => return Err(invalid(op, &format!("unknown option '{other}'"))),
+                    }
+                }
+                let point = point.ok_or_else(|| invalid(op, "requires a $geometry point"))?;
+                (point, DistanceUnit::Meters, min, max)
+            }
+            _ => {
+                let point = position(v, false)
+                    .ok_or_else(|| invalid(op, "requires [lon, lat] or a $geometry point"))?;
+                let unit = if sphere { DistanceUnit::Radians } else { DistanceUnit::Planar };
+                (point, unit, min, max)
+            }
+        };
+        for d in [min, max].into_iter().flatten() {
+            if !d.is_finite() || d < 0.0 {
+                return Err(invalid(op, "distances must be non-negative numbers"));
+            }
+        }
+        Ok(Self { point, unit, min_distance: min, max_distance: max, sphere })
+    }
+
+    /// The operand and the `$minDistance`/`$maxDistance` written beside it.
+    pub(crate) fn to_bson(&self) -> (Bson, Option<f64>, Option<f64>) {
+        if self.unit != DistanceUnit::Meters {
+            return (self.point.to_bson(), self.min_distance, self.max_distance);
+        }
+        let mut d = doc! {"$geometry": Geometry::Point(self.point).to_bson()};
+        if let Some(m) = self.min_distance {
+            d.insert("$minDistance", m);
+        }
+        if let Some(m) = self.max_distance {
+            d.insert("$maxDistance", m);
+        }
+        (Bson::Document(d), None, None)
+    }
+
+    fn distance(&self, p: GeoPoint) -> f64 {
+        match self.unit {
+            DistanceUnit::Planar => planar(self.point, p),
+            DistanceUnit::Radians => angle(self.point, p),
+            DistanceUnit::Meters => angle(self.point, p) * EARTH_RADIUS_M,
+        }
+    }
+
+    /// Distance from the point to the nearest location at `path` (for lines and polygons, to
+    /// their nearest vertex), or `None` when `doc` has none.
+    pub(crate) fn distance_to(&self, doc: &BsonDocument, path: &str) -> Option<f64> {
+        geometries(doc, path)
+            .iter()
+            .flat_map(Geometry::vertices)
+            .map(|p| self.distance(p))
+            .min_by(f64::total_cmp)
+    }
+
+    fn admits(&self, distance: f64) -> bool {
+        self.min_distance.is_none_or(|m| distance >= m)
+            && self.max_distance.is_none_or(|m| distance <= m)
+    }
+
+    fn bounds(&self) -> Vec<Rect> {
+        match (self.unit, self.max_distance) {
+            (_, None) => vec![Rect::WORLD],
+            (DistanceUnit::Planar, Some(d)) => planar_bounds(self.point, d),
+            (DistanceUnit::Radians, Some(d)) => cap_bounds(self.point, d),
+            (DistanceUnit::Meters, Some(d)) => cap_bounds(self.point, d / EARTH_RADIUS_M),
+        }
+    }
+}
+
+impl GeoOp {
+    /// Whether some location at `path` in `doc` satisfies the predicate.
+    pub(crate) fn matches(&self, doc: &BsonDocument, path: &str) -> bool {
+        match self {
+            Self::Within(s) => geometries(doc, path).iter().any(|g| s.contains(g)),
+            Self::Intersects(q) => geometries(doc, path).iter().any(|g| g.intersects(q)),
+            Self::Near(n) => n.distance_to(doc, path).is_some_and(|d| n.admits(d)),
+        }
+    }
+
+    /// Inclusive ranges of cell keys holding every point that may match.
+    pub(crate) fn covering(&self) -> Vec<(u64, u64)> {
+        let rects = match self {
+            Self::Within(s) => s.bounds(),
+            Self::Intersects(g) => vec![Rect::around(&g.vertices())],
+            Self::Near(n) => n.bounds(),
+        };
+        covering(&rects)
+    }
+}
+
+/// The `$near` every match of `filter` satisfies (one at the top level or under `$and`),
+/// with its path, which gives their distances.
+pub(crate) fn near_search(filter: &Filter) -> Option<(&str, &GeoNear)> {
+    match filter {
+        Filter::Geo { path, op: GeoOp::Near(n) } => Some((path, n)),
+        Filter::And(fs) => fs.iter().find_map(near_search),
+        Filter::Collate { filter, .. } => near_search(filter),
+        _ => None,
+    }
+}
+
+fn count_near(filter: &Filter) -> usize {
+    match filter {
+        Filter::Geo { op: GeoOp::Near(_), .. } => 1,
+        Filter::And(fs) | Filter::Or(fs) => fs.iter().map(count_near).sum(),
+        Filter::Not(f) | Filter::Collate { filter: f, .. } => count_near(f),
+        _ => 0,
+    }
+}
+
+/// Check that `filter` holds at most one `$near`, where [`near_search`] finds it.
+///
+/// # Errors
+/// Returns `DbError::QueryError` otherwise.
+pub(crate) fn check_near(filter: &Filter) -> Result<(), DbError> {
+    match count_near(filter) {
+        0 => Ok(()),
+        1 if near_search(filter).is_some() => Ok(()),
+        1 => Err(DbError::QueryError("$near must be at the top level or under $and".into())),
+        _ => Err(DbError::QueryError("a query may hold only one $near".into())),
+    }
+}
+
+/// Store `doc`'s distance from the point under [`GEO_DISTANCE`].
+pub(crate) fn add_distance(doc: &mut BsonDocument, path: &str, near: &GeoNear) {
+    if let Some(d) = near.distance_to(doc, path) {
+        doc.insert(GEO_DISTANCE, d);
+    }
+}
+
+fn planar(a: GeoPoint, b: GeoPoint) -> f64 {
+    (a.lon - b.lon).hypot(a.lat - b.lat)
+}
+
+/// Great-circle angle between two points, in radians (haversine).
+fn angle(a: GeoPoint, b: GeoPoint) -> f64 {
+    let (la, lb) = (a.lat.to_radians(), b.lat.to_radians());
+    let dlat = lb - la;
+    let dlon = (b.lon - a.lon).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + la.cos() * lb.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * h.sqrt().min(1.0).asin()
+}
+
+fn planar_bounds(c: GeoPoint, r: f64) -> Vec<Rect> {
+    let r =
+        Rect { min: GeoPoint::new(c.lon - r, c.lat - r), max: GeoPoint::new(c.lon + r, c.lat + r) };
+    r.clipped().into_iter().collect()
+}
+
+/// Rectangles covering the spherical cap of `radius` radians around `c`, split where it
+/// crosses the antimeridian.
+fn cap_bounds(c: GeoPoint, radius: f64) -> Vec<Rect> {
+    if radius >= std::f64::consts::PI {
+        return vec![Rect::WORLD];
+    }
+    let dlat = radius.to_degrees();
+    let (lat_min, lat_max) = (c.lat - dlat, c.lat + dlat);
+    let ratio = radius.sin() / c.lat.to_radians().cos();
+    if lat_min <= -90.0 || lat_max >= 90.0 || !(0.0..1.0).contains(&ratio) {
+        // Reaches a pole: every longitude
+        let r = Rect { min: GeoPoint::new(-180.0, lat_min), max: GeoPoint::new(180.0, lat_max) };
+        return r.clipped().into_iter().collect();
+    }
+    let dlon = ratio.asin().to_degrees();
+    let (lon_min, lon_max) = (c.lon - dlon, c.lon + dlon);
+    let rect =
+        |a: f64, b: f64| Rect { min: GeoPoint::new(a, lat_min), max: GeoPoint::new(b, lat_max) };
+    let mut out = vec![rect(lon_min.max(-180.0), lon_max.min(180.0))];
+    if lon_min < -180.0 {
+        out.push(rect(lon_min + 360.0, 180.0));
+    }
+    if lon_max > 180.0 {
+        out.push(rect(-180.0, lon_max - 360.0));
+    }
+    out
+}
+
+/// Whether `p` lies in the polygon: inside or on its outer ring and not strictly inside a hole.
+fn in_polygon(rings: &[Vec<GeoPoint>], p: GeoPoint) -> bool {
+    let Some((outer, holes)) = rings.split_first() else {
+        return false;
+    };
+    let on_edge = |r: &[GeoPoint]| r.windows(2).any(|w| segments_meet(w[0], w[1], p, p));
+    (on_edge(outer) || in_ring(outer, p)) && !holes.iter().any(|h| in_ring(h, p) && !on_edge(h))
+}
+
+/// Even-odd ray cast towards positive longitude.
+fn in_ring(ring: &[GeoPoint], p: GeoPoint) -> bool {
+    let mut inside = false;
+    for w in ring.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        if (a.lat > p.lat) != (b.lat > p.lat) {
+            let x = (b.lon - a.lon) * (p.lat - a.lat) / (b.lat - a.lat) + a.lon;
+            if p.lon < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+fn orientation(a: GeoPoint, b: GeoPoint, c: GeoPoint) -> i8 {
+    let v = (b.lon - a.lon) * (c.lat - a.lat) - (b.lat - a.lat) * (c.lon - a.lon);
+    if v > 0.0 {
+        1
+    } else if v < 0.0 {
+        -1
+    } else {
+        0
+    }
+}
+
+/// Whether `q`, collinear with `p`–`r`, lies within their bounding box.
+fn on_segment(p: GeoPoint, q: GeoPoint, r: GeoPoint) -> bool {
+    q.lon <= p.lon.max(r.lon)
+        && q.lon >= p.lon.min(r.lon)
+        && q.lat <= p.lat.max(r.lat)
+        && q.lat >= p.lat.min(r.lat)
+}
+
+/// Whether segments `p1`–`p2` and `q1`–`q2` share a point, touching included.
+fn segments_meet(p1: GeoPoint, p2: GeoPoint, q1: GeoPoint, q2: GeoPoint) -> bool {
+    let (o1, o2) = (orientation(p1, p2, q1), orientation(p1, p2, q2));
+    let (o3, o4) = (orientation(q1, q2, p1), orientation(q1, q2, p2));
+    (o1 != o2 && o3 != o4)
+        || (o1 == 0 && on_segment(p1, q1, p2))
+        || (o2 == 0 && on_segment(p1, q2, p2))
+        || (o3 == 0 && on_segment(q1, p1, q2))
+        || (o4 == 0 && on_segment(q1, p2, q2))
+}
+
+/// Whether the segments cross at a point interior to both.
+fn segments_cross(p1: GeoPoint, p2: GeoPoint, q1: GeoPoint, q2: GeoPoint) -> bool {
+    let (o1, o2) = (orientation(p1, p2, q1), orientation(p1, p2, q2));
+    let (o3, o4) = (orientation(q1, q2, p1), orientation(q1, q2, p2));
+    o1 * o2 < 0 && o3 * o4 < 0
+}
+
+fn spread(v: u64) -> u64 {
+    let mut x = v & 0x3FF_FFFF;
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+/// Interleave cell coordinates, longitude bits first.
+fn interleave(ix: u64, iy: u64) -> u64 {
+    (spread(ix) << 1) | spread(iy)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn cell_coord(v: f64, min: f64, span: f64) -> u64 {
+    let cells = f64::from(1u32 << CELL_BITS);
+    (((v - min) / span * cells).floor().max(0.0) as u64).min((1 << CELL_BITS) - 1)
+}
+
+/// The key of the finest cell holding `p`.
+pub(crate) fn cell_of(p: GeoPoint) -> u64 {
+    interleave(cell_coord(p.lon, -180.0, 360.0), cell_coord(p.lat, -90.0, 180.0))
+}
+
+/// The rectangle of cell `(ix, iy)` at `level` (a `2^level` by `2^level` grid).
+#[allow(clippy::cast_precision_loss)]
+fn cell_rect(level: u32, ix: u64, iy: u64) -> Rect {
+    let n = (1u64 << level) as f64;
+    let (w, h) = (360.0 / n, 180.0 / n);
+    let (x, y) = (ix as f64, iy as f64);
+    Rect {
+        min: GeoPoint::new(w.mul_add(x, -180.0), h.mul_add(y, -90.0)),
+        max: GeoPoint::new(w.mul_add(x + 1.0, -180.0), h.mul_add(y + 1.0, -90.0)),
+    }
+}
+
+/// The inclusive range of finest-cell keys inside cell `(ix, iy)` at `level`.
+fn cell_range(level: u32, ix: u64, iy: u64) -> (u64, u64) {
+    let shift = 2 * (CELL_BITS - level);
+    let lo = interleave(ix, iy) << shift;
+    (lo, lo + ((1u64 << shift) - 1))
+}
+
+/// Merged key ranges of at most about `MAX_COVER_CELLS` cells covering `rects`: cells are
+/// split while they straddle a region's edge and the budget allows.
+fn covering(rects: &[Rect]) -> Vec<(u64, u64)> {
+    let rects: Vec<Rect> = rects
+        .iter()
+        .map(|r| Rect {
+            min: GeoPoint::new(r.min.lon - COVER_SLACK, r.min.lat - COVER_SLACK),
+            max: GeoPoint::new(r.max.lon + COVER_SLACK, r.max.lat + COVER_SLACK),
+        })
+        .collect();
+    let mut out = Vec::new();
+    let mut frontier = vec![(0u64, 0u64)];
+    for level in 0..=CELL_BITS {
+        let mut partial = Vec::new();
+        for (ix, iy) in frontier {
+            let cell = cell_rect(level, ix, iy);
+            if !rects.iter().any(|r| r.intersects(&cell)) {
+                continue;
+            }
+            if level == CELL_BITS || rects.iter().any(|r| r.contains(&cell)) {
+                out.push(cell_range(level, ix, iy));
+            } else {
+                partial.push((ix, iy));
+            }
+        }
+        if level == CELL_BITS || out.len() + partial.len() * 4 > MAX_COVER_CELLS {
+            out.extend(partial.iter().map(|(ix, iy)| cell_range(level, *ix, *iy)));
+            break;
+        }
+        frontier = partial
+            .iter()
+            .flat_map(|(ix, iy)| {
+                [
+                    (2 * ix, 2 * iy),
+                    (2 * ix + 1, 2 * iy),
+                    (2 * ix, 2 * iy + 1),
+                    (2 * ix + 1, 2 * iy + 1),
+                ]
+            })
+            .collect();
+        if frontier.is_empty() {
+            break;
+        }
+    }
+    out.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(out.len());
+    for (lo, hi) in out {
+        match merged.last_mut() {
+            Some(last) if lo <= last.1.saturating_add(1) => last.1 = last.1.max(hi),
+            _ => merged.push((lo, hi)),
+        }
+    }
+    merged
+}
@@ -2,20 +2,66 @@
 pub mod telemetry;
 
 // Submodules for separation of concerns
+mod aggregate;
+mod bulk;
+mod collation;
 mod cursor;
 mod eval;
 mod exec;
+mod expr;
+mod geo;
+mod numeric;
+mod parallel;
 mod parse;
+#[cfg(feature = "regex")]
+mod pattern;
+mod plan;
+mod project;
+mod sort;
+mod text;
+mod token;
 mod types;
+mod update;
+mod vector;
 
 // Public API re-exports (preserve original paths)
+pub use aggregate::{
+    Accumulator, GroupSpec, Stage, aggregate, aggregate_with, parse_pipeline_json,
+};
+pub use bulk::bulk_write;
+pub use collation::{Collation, Strength};
 pub use cursor::Cursor;
-pub use eval::eval_filter;
+pub use eval::{
+    compare_bson, compare_bson_collated, eval_filter, eval_filter_collated, filter_implies,
+    path_values,
+};
 pub use exec::{
-    apply_update, count_docs, count_docs_rate_limited, delete_many, delete_one, find_docs,
-    find_docs_rate_limited, update_many, update_one,
+    count_docs, count_docs_rate_limited, delete_many, delete_one, find_docs,
+    find_docs_rate_limited, find_one_and_delete, find_one_and_replace, find_one_and_update,
+    update_many, update_many_with, update_one, update_one_with,
+};
+pub use expr::{Expr, ExprOp};
+pub use geo::{DistanceUnit, GeoNear, GeoOp, GeoPoint, GeoShape, Geometry};
+pub(crate) use geo::{cell_of as geo_cell, geometries as geo_geometries};
+pub(crate) use numeric::compare_numbers;
+pub use parallel::ParallelScan;
+pub use parse::{
+    FilterSerde, ProjectionSerde, UpdateDocSerde, parse_filter_json, parse_projection_json,
+    parse_update_json,
+};
+#[cfg(feature = "regex")]
+pub use pattern::RegexPattern;
+pub use plan::{
+    AccessPath, ExplainReport, PlanCandidate, PlanNode, PlanStage, choose_plan, explain,
 };
-pub use parse::{FilterSerde, UpdateDocSerde, parse_filter_json, parse_update_json};
+pub(crate) use text::Analyzer as TextAnalyzer;
+pub use text::{TextOptions, TextSearch};
+pub(crate) use token::TokenKey;
 pub use types::{
-    CmpOp, DeleteReport, Filter, FindOptions, Order, SortSpec, UpdateDoc, UpdateReport,
+    BulkOp, BulkOptions, BulkOutcome, BulkWriteReport, CmpOp, CurrentDateType, DeleteReport,
+    FieldProjection, Filter, FindAndModifyOptions, FindOptions, Order, PopFrom, Projection,
+    PullSpec, PushSort, PushSpec, ReturnDocument, SortSpec, UpdateDoc, UpdateOptions, UpdateReport,
 };
+pub use update::{apply_update, apply_update_positional};
+pub(crate) use vector::{Hnsw as HnswGraph, VectorStore};
+pub use vector::{HnswOptions, Metric, VectorOptions, VectorQuery, vector_search};
@@ -0,0 +1,524 @@
+//! Type-preserving arithmetic for `$inc`, `$mul` and expressions, and exact comparison of
+//! numbers.
+//!
+//! Operands are widened to the "largest" type involved: int32 < int64 < double < decimal128.
+//! An int32 result that overflows is promoted to int64; an int64 overflow is an error rather
+//! than a silent loss of precision. Decimal128 arithmetic is exact up to its 34 significant
+//! digits and rounds half-to-even beyond that, as IEEE 754 decimal does.
+
+use bson::{Bson, Decimal128};
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum NumOp {
+    Add,
+    Sub,
+    Mul,
+}
+
+pub(super) const fn is_number(v: &Bson) -> bool {
+    matches!(v, Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_))
+}
+
+/// `a op b` for numeric operands; `None` when an int64 result overflows or an operand is
+/// not a number.
+pub(super) fn apply(op: NumOp, a: &Bson, b: &Bson) -> Option<Bson> {
+    match (a, b) {
+        (Bson::Int32(x), Bson::Int32(y)) => {
+            let (x, y) = (i64::from(*x), i64::from(*y));
+            // int32 op int32 always fits in an i64
+            let r = match op {
+                NumOp::Add => x + y,
+                NumOp::Sub => x - y,
+                NumOp::Mul => x * y,
+            };
+            Some(i32::try_from(r).map_or(Bson::Int64(r), Bson::Int32))
+        }
+        (Bson::Decimal128(_), _) | (_, Bson::Decimal128(_)) => {
+            let (x, y) = (Dec::from_bson(a)?, Dec::from_bson(b)?);
+            let r = match op {
+                NumOp::Add => x.add(y),
+                NumOp::Sub => x.add(y.neg()),
+                NumOp::Mul => x.mul(y),
+            };
+            Some(Bson::Decimal128(r.encode()))
+        }
+        (Bson::Double(_), _) | (_, Bson::Double(_)) => {
+            let (x, y) = (as_f64(a)?, as_f64(b)?);
+            Some(Bson::Double(match op {
+                NumOp::Add => x + y,
+                NumOp::Sub => x - y,
+                NumOp::Mul => x * y,
+            }))
+        }
+        _ => {
+            let (x, y) = (as_i64(a)?, as_i64(b)?);
+            match op {
+                NumOp::Add => x.checked_add(y),
+                NumOp::Sub => x.checked_sub(y),
+                NumOp::Mul => x.checked_mul(y),
+            }
+            .map(Bson::Int64)
+        }
+    }
+}
+
+/// Compare two numbers of any numeric type by value; `None` if either is not a number.
+///
+/// Integers and decimals compare exactly. A double compares by its exact value when it is an
+/// integer and otherwise by its shortest round-trip decimal, so `0.1` equals
+/// `Decimal128("0.1")` as in `$inc`; each double's value stays between its neighbours', so
+/// the order is consistent across all types. NaN sorts before every other number, and `-0`
+/// equals `0`.
+pub(crate) fn compare_numbers(a: &Bson, b: &Bson) -> Option<Ordering> {
+    Some(Dec::for_compare(a)?.cmp_value(Dec::for_compare(b)?))
+}
+
+/// `v` as a double, rounding decimals to the nearest one; `None` if it is not a number.
+pub(super) fn to_f64(v: &Bson) -> Option<f64> {
+    match v {
+        Bson::Decimal128(d) => d.to_string().parse().ok(),
+        _ => as_f64(v),
+    }
+}
+
+/// Zero with the same numeric type as `v`: what `$mul` writes to a missing field.
+pub(super) fn zero_like(v: &Bson) -> Bson {
+    match v {
+        Bson::Int64(_) => Bson::Int64(0),
+        Bson::Double(_) => Bson::Double(0.0),
+        Bson::Decimal128(_) => Bson::Decimal128(Dec::ZERO.encode()),
+        _ => Bson::Int32(0),
+    }
+}
+
+const fn as_i64(v: &Bson) -> Option<i64> {
+    match v {
+        Bson::Int32(i) => Some(*i as i64),
+        Bson::Int64(i) => Some(*i),
+        _ => None,
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+const fn as_f64(v: &Bson) -> Option<f64> {
+    match v {
+        Bson::Int32(i) => Some(*i as f64),
+        Bson::Int64(i) => Some(*i as f64),
+        Bson::Double(f) => Some(*f),
+        _ => None,
+    }
+}
+
+const MAX_COEFF: u128 = 10u128.pow(34) - 1;
+const EXP_MIN: i32 = -6176;
+const EXP_MAX: i32 = 6111;
+const EXP_BIAS: i32 = 6176;
+/// Digits an addend is scaled by before the other addend only contributes a sticky digit.
+const ALIGN_DIGITS: i32 = 39;
+
+/// A decoded Decimal128 value: `(-1)^neg * coeff * 10^exp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dec {
+    Finite { neg: bool, coeff: u128, exp: i32 },
+    Inf { neg: bool },
+    NaN,
+}
+
+impl Dec {
+    const ZERO: Self = Self::Finite { neg: false, coeff: 0, exp: 0 };
+
+    fn from_bson(v: &Bson) -> Option<Self> {
+        match v {
+            Bson::Int32(i) => {
+                Some(Self::Finite { neg: *i < 0, coeff: u128::from(i.unsigned_abs()), exp: 0 })
+            }
+            Bson::Int64(i) => {
+                Some(Self::Finite { neg: *i < 0, coeff: u128::from(i.unsigned_abs()), exp: 0 })
+            }
+            Bson::Double(f) => Some(Self::from_f64(*f)),
+            Bson::Decimal128(d) => Some(Self::decode(*d)),
+            _ => None,
+        }
+    }
+
+    /// Like `from_bson`, but integral doubles below 2^113 keep their exact value.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn for_compare(v: &Bson) -> Option<Self> {
+        match v {
+            Bson::Double(f) if f.fract() == 0.0 && f.abs() < 2f64.powi(113) => {
+                // An integral double in range converts to u128 exactly
+                Some(Self::Finite { neg: *f < 0.0, coeff: f.abs() as u128, exp: 0 })
+            }
+            _ => Self::from_bson(v),
+        }
+    }
+
+    /// Numeric order, with NaN first and all zeros equal.
+    fn cmp_value(self, other: Self) -> Ordering {
+        let rank = |d: &Self| match d {
+            Self::NaN => 0,
+            Self::Inf { neg: true } => 1,
+            Self::Finite { .. } => 2,
+            Self::Inf { neg: false } => 3,
+        };
+        let (
+            Self::Finite { neg: an, coeff: ac, exp: ae },
+            Self::Finite { neg: bn, coeff: bc, exp: be },
+        ) = (self, other)
+        else {
+            return rank(&self).cmp(&rank(&other));
+        };
+        let sign = |neg: bool, coeff: u128| match (coeff, neg) {
+            (0, _) => 0,
+            (_, true) => -1,
+            (_, false) => 1,
+        };
+        let (sa, sb) = (sign(an, ac), sign(bn, bc));
+        if sa != sb || sa == 0 {
+            return sa.cmp(&sb);
+        }
+        let magnitude = Self::cmp_magnitude(ac, ae, bc, be);
+        if sa < 0 { magnitude.reverse() } else { magnitude }
+    }
+
+    /// Compare `ac * 10^ae` with `bc * 10^be`, both coefficients non-zero.
+    fn cmp_magnitude(ac: u128, ae: i32, bc: u128, be: i32) -> Ordering {
+        let digits = |c: u128| i32::try_from(c.ilog10()).unwrap_or(0) + 1;
+        // The position of the leading digit decides unless both lead at the same place
+        let (la, lb) = (ae + digits(ac), be + digits(bc));
+        if la != lb {
+            return la.cmp(&lb);
+        }
+        // Same leading position: the exponents differ by less than 34, so aligning fits
+        if ae >= be {
+            U256::from(ac).mul_pow10(ae - be).cmp(&U256::from(bc))
+        } else {
+            U256::from(ac).cmp(&U256::from(bc).mul_pow10(be - ae))
+        }
+    }
+
+    /// Uses the shortest decimal representation that round-trips, so `0.1` becomes
+    /// exactly `0.1` rather than its binary expansion.
+    fn from_f64(f: f64) -> Self {
+        if f.is_nan() {
+            return Self::NaN;
+        }
+        if f.is_infinite() {
+            return Self::Inf { neg: f < 0.0 };
+        }
+        let s = format!("{:e}", f.abs());
+        let (mantissa, exp) = s.split_once('e').unwrap_or((&s, "0"));
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let coeff = format!("{int}{frac}").parse().unwrap_or(0);
+        let exp = exp.parse::<i32>().unwrap_or(0) - i32::try_from(frac.len()).unwrap_or(0);
+        Self::round(f.is_sign_negative(), U256::from(coeff), exp)
+    }
+
+    /// Decode the BID (binary integer decimal) encoding; BSON stores it little-endian.
+    fn decode(d: Decimal128) -> Self {
+        let v = u128::from_le_bytes(d.bytes());
+        let neg = v >> 127 == 1;
+        if (v >> 123) & 0xf == 0xf {
+            return if (v >> 122) & 1 == 1 { Self::NaN } else { Self::Inf { neg } };
+        }
+        let (raw_exp, coeff) = if (v >> 125) & 0b11 == 0b11 {
+            ((v >> 111) & 0x3fff, (0b100 << 111) | (v & ((1 << 111) - 1)))
+        } else {
+            ((v >> 113) & 0x3fff, v & ((1 << 113) - 1))
+        };
+        // Non-canonical coefficients are defined to be zero
+        let coeff = if coeff > MAX_COEFF { 0 } else { coeff };
+        let exp = i32::try_from(raw_exp).unwrap_or(0) - EXP_BIAS;
+        Self::Finite { neg, coeff, exp }
+    }
+
+    fn encode(self) -> Decimal128 {
+        let bits: u128 = match self {
+            Self::NaN => 0x7c << 120,
+            Self::Inf { neg } => (u128::from(neg) << 127) | (0x78 << 120),
+            Self::Finite { neg, coeff, exp } => {
+                let raw_exp = u128::try_from(exp + EXP_BIAS).unwrap_or(0);
+                (u128::from(neg) << 127) | (raw_exp << 113) | coeff
+            }
+        };
+        Decimal128::from_bytes(bits.to_le_bytes())
+    }
+
+    /// Round an exact intermediate result to 34 digits (half-to-even) and a representable
+    /// exponent.
+    fn round(neg: bool, mut coeff: U256, mut exp: i32) -> Self {
+        let mut last = 0;
+        let mut sticky = false;
+        let mut small = coeff.to_u128().filter(|c| *c <= MAX_COEFF);
+        while small.is_none() || exp < EXP_MIN {
+            sticky |= last != 0;
+            last = coeff.div10();
+            exp += 1;
+            small = coeff.to_u128().filter(|c| *c <= MAX_COEFF);
+        }
+        let mut c = small.unwrap_or(0);
+        if last > 5 || (last == 5 && (sticky || c % 2 == 1)) {
+            c += 1;
+            if c > MAX_COEFF {
+                c /= 10;
+                exp += 1;
+            }
+        }
+        // Clamp large exponents by padding the coefficient with zeros where it fits
+        while exp > EXP_MAX && (c == 0 || c * 10 <= MAX_COEFF) {
+            if c != 0 {
+                c *= 10;
+            }
+            exp -= 1;
+        }
+        if exp > EXP_MAX {
+            return Self::Inf { neg };
+        }
+        Self::Finite { neg, coeff: c, exp }
+    }
+
+    fn add(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::NaN, _) | (_, Self::NaN) => Self::NaN,
+            (Self::Inf { neg: a }, Self::Inf { neg: b }) if a != b => Self::NaN,
+            (Self::Inf { .. }, _) => self,
+            (_, Self::Inf { .. }) => other,
+            (_, Self::Finite { coeff: 0, .. }) => self,
+            (Self::Finite { coeff: 0, .. }, _) => other,
+            (
+                Self::Finite { neg: an, coeff: ac, exp: ae },
+                Self::Finite { neg: bn, coeff: bc, exp: be },
+            ) => {
+                let ((hn, hc, he), (ln, lc, le)) = if ae >= be {
+                    ((an, ac, ae), (bn, bc, be))
+                } else {
+                    ((bn, bc, be), (an, ac, ae))
+                };
+                Self::add_aligned(hn, hc, he, ln, lc, he - le)
+            }
+        }
+    }
+
+    /// Sum of `hc * 10^he` and an addend `diff` digits further down. Past `ALIGN_DIGITS` the
+    /// low addend cannot reach the kept digits and only decides rounding, so its dropped
+    /// digits collapse into a single sticky unit.
+    fn add_aligned(hn: bool, hc: u128, he: i32, ln: bool, lc: u128, diff: i32) -> Self {
+        let (hi, lo, exp) = if diff <= ALIGN_DIGITS {
+            (U256::from(hc).mul_pow10(diff), U256::from(lc), he - diff)
+        } else {
+            let pow = u32::try_from(diff - ALIGN_DIGITS).ok().and_then(|s| 10u128.checked_pow(s));
+            let (kept, rest) = pow.map_or((0, lc), |p| (lc / p, lc % p));
+            let lo = U256::from(kept * 10 + u128::from(rest != 0));
+            (U256::from(hc).mul_pow10(ALIGN_DIGITS + 1), lo, he - ALIGN_DIGITS - 1)
+        };
+        if hn == ln {
+            return Self::round(hn, hi.add(lo), exp);
+        }
+        match hi.cmp(&lo) {
+            Ordering::Equal => Self::Finite { neg: false, coeff: 0, exp },
+            Ordering::Greater => Self::round(hn, hi.sub(lo), exp),
+            Ordering::Less => Self::round(ln, lo.sub(hi), exp),
+        }
+    }
+
+    const fn neg(self) -> Self {
+        match self {
+            Self::Finite { neg, coeff, exp } => Self::Finite { neg: !neg, coeff, exp },
+            Self::Inf { neg } => Self::Inf { neg: !neg },
+            Self::NaN => Self::NaN,
+        }
+    }
+
+    fn mul(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::NaN, _) | (_, Self::NaN) => Self::NaN,
+            (Self::Inf { .. }, Self::Finite { coeff: 0, .. })
+            | (Self::Finite { coeff: 0, .. }, Self::Inf { .. }) => Self::NaN,
+            (
+                Self::Finite { neg: an, coeff: ac, exp: ae },
+                Self::Finite { neg: bn, coeff: bc, exp: be },
+            ) => Self::round(an != bn, U256::mul(ac, bc), ae + be),
+            (
+                Self::Inf { neg: an } | Self::Finite { neg: an, .. },
+                Self::Inf { neg: bn } | Self::Finite { neg: bn, .. },
+            ) => Self::Inf { neg: an != bn },
+        }
+    }
+}
+
+/// Unsigned 256-bit integer as little-endian `u64` limbs: wide enough for the product of two
+/// 34-digit coefficients, or one scaled by 10^40.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct U256([u64; 4]);
+
+impl From<u128> for U256 {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from(v: u128) -> Self {
+        Self([v as u64, (v >> 64) as u64, 0, 0])
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+impl U256 {
+    fn mul(a: u128, b: u128) -> Self {
+        let (a, b) = (Self::from(a).0, Self::from(b).0);
+        let mut out = [0u64; 4];
+        for i in 0..2 {
+            let mut carry = 0u128;
+            for j in 0..2 {
+                let t = u128::from(a[i]) * u128::from(b[j]) + u128::from(out[i + j]) + carry;
+                out[i + j] = t as u64;
+                carry = t >> 64;
+            }
+            out[i + 2] = carry as u64;
+        }
+        Self(out)
+    }
+
+    fn mul_small(mut self, m: u64) -> Self {
+        let mut carry = 0u128;
+        for limb in &mut self.0 {
+            let t = u128::from(*limb) * u128::from(m) + carry;
+            *limb = t as u64;
+            carry = t >> 64;
+        }
+        self
+    }
+
+    fn mul_pow10(mut self, n: i32) -> Self {
+        for _ in 0..n {
+            self = self.mul_small(10);
+        }
+        self
+    }
+
+    fn add(mut self, other: Self) -> Self {
+        let mut carry = 0u128;
+        for (limb, o) in self.0.iter_mut().zip(other.0) {
+            let t = u128::from(*limb) + u128::from(o) + carry;
+            *limb = t as u64;
+            carry = t >> 64;
+        }
+        self
+    }
+
+    /// `self - other`; callers guarantee `self >= other`.
+    fn sub(mut self, other: Self) -> Self {
+        let mut borrow = false;
+        for (limb, o) in self.0.iter_mut().zip(other.0) {
+            let (t, b1) = limb.overflowing_sub(o);
+            let (t, b2) = t.overflowing_sub(u64::from(borrow));
+            *limb = t;
+            borrow = b1 || b2;
+        }
+        self
+    }
+
+    /// Divide in place by ten, returning the remainder.
+    fn div10(&mut self) -> u8 {
+        let mut rem = 0u128;
+        for limb in self.0.iter_mut().rev() {
+            let cur = (rem << 64) | u128::from(*limb);
+            *limb = (cur / 10) as u64;
+            rem = cur % 10;
+        }
+        rem as u8
+    }
+
+    fn to_u128(self) -> Option<u128> {
+        (self.0[2] == 0 && self.0[3] == 0)
+            .then(|| (u128::from(self.0[1]) << 64) | u128::from(self.0[0]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Bson {
+        Bson::Decimal128(s.parse().unwrap())
+    }
+
+    fn show(v: &Bson) -> String {
+        match v {
+            Bson::Decimal128(d) => d.to_string(),
+            other => panic!("expected decimal, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn decimal_roundtrip_and_exact_arithmetic() {
+        for s in [
+            "0",
+            "1",
+            "-2.50",
+            "1E+10",
+            "9999999999999999999999999999999999",
+            "1E-6176",
+            "-Infinity",
+            "NaN",
+        ] {
+            let d: Decimal128 = s.parse().unwrap();
+            assert_eq!(Dec::decode(d).encode(), d, "{s}");
+        }
+        assert_eq!(show(&apply(NumOp::Add, &dec("0.1"), &dec("0.2")).unwrap()), "0.3");
+        assert_eq!(show(&apply(NumOp::Add, &dec("1.10"), &Bson::Int32(-2)).unwrap()), "-0.90");
+        assert_eq!(show(&apply(NumOp::Mul, &dec("1.5"), &dec("-0.2")).unwrap()), "-0.30");
+        assert_eq!(show(&apply(NumOp::Add, &dec("0.1"), &Bson::Double(0.2)).unwrap()), "0.3");
+        // 35 digits round half-to-even back to 34
+        let big = dec("9999999999999999999999999999999999");
+        assert_eq!(
+            show(&apply(NumOp::Add, &big, &Bson::Int32(1)).unwrap()),
+            "1.000000000000000000000000000000000E+34"
+        );
+        // Exactly half an ulp ties to even; anything more rounds up, even when the addend is
+        // too far down to align digit by digit
+        assert_eq!(
+            show(&apply(NumOp::Add, &dec("1E+40"), &dec("5E+6")).unwrap()),
+            "1.000000000000000000000000000000000E+40"
+        );
+        assert_eq!(
+            show(
+                &apply(NumOp::Add, &dec("1E+40"), &dec("5.000000000000000000000000000000001E+6"))
+                    .unwrap()
+            ),
+            "1.000000000000000000000000000000001E+40"
+        );
+        assert_eq!(
+            show(&apply(NumOp::Add, &dec("1E+100"), &dec("-1E-100")).unwrap()),
+            "1.000000000000000000000000000000000E+100"
+        );
+        assert_eq!(show(&apply(NumOp::Mul, &dec("Infinity"), &dec("0")).unwrap()), "NaN");
+    }
+
+    #[test]
+    fn integer_promotion() {
+        let max = Bson::Int32(i32::MAX);
+        assert_eq!(apply(NumOp::Add, &Bson::Int32(1), &Bson::Int32(2)), Some(Bson::Int32(3)));
+        assert_eq!(
+            apply(NumOp::Add, &max, &Bson::Int32(1)),
+            Some(Bson::Int64(i64::from(i32::MAX) + 1))
+        );
+        assert_eq!(apply(NumOp::Mul, &max, &max), Some(Bson::Int64(i64::from(i32::MAX).pow(2))));
+        assert_eq!(
+            apply(NumOp::Add, &Bson::Int64(i64::MAX - 1), &Bson::Int32(1)),
+            Some(Bson::Int64(i64::MAX))
+        );
+        assert_eq!(apply(NumOp::Add, &Bson::Int64(i64::MAX), &Bson::Int32(1)), None);
+        assert_eq!(apply(NumOp::Mul, &Bson::Int32(3), &Bson::Double(0.5)), Some(Bson::Double(1.5)));
+        assert_eq!(apply(NumOp::Add, &Bson::String("1".into()), &Bson::Int32(1)), None);
+    }
+}
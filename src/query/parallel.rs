@@ -0,0 +1,178 @@
+//! Partitioned scans across worker threads.
+//!
+//! A scan large enough to split holds one read of the collection's cache for its whole run,
+//! so every worker sees the same documents and none takes the write lock `Cache::get` needs
+//! to update recency. Candidate ids are cut into fixed-size chunks that workers claim in
+//! order; the caller consumes each chunk's result as it arrives and may stop the scan early.
+
+use crate::collection::Collection;
+use crate::document::Document;
+use crate::types::DocumentId;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Candidate ids one worker claims at a time.
+const CHUNK_DOCS: usize = 1024;
+
+/// Scans with fewer candidates than this run on the calling thread by default.
+const DEFAULT_MIN_DOCS: usize = 10_000;
+
+/// How a database splits large scans in `find_docs` and `count_docs` across threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelScan {
+    /// Worker threads for one scan; 0 or 1 scans on the calling thread.
+    pub threads: usize,
+    /// Scans of fewer candidates run on the calling thread.
+    pub min_docs: usize,
+}
+
+impl Default for ParallelScan {
+    /// One worker per available CPU for scans of at least 10 000 candidates.
+    fn default() -> Self {
+        let threads = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        Self { threads, min_docs: DEFAULT_MIN_DOCS }
+    }
+}
+
+impl ParallelScan {
+    /// Every scan on the calling thread.
+    #[must_use]
+    pub const fn serial() -> Self {
+        Self { threads: 1, min_docs: usize::MAX }
+    }
+
+    /// Workers for a scan of `candidates` ids: 1 when it should not be split, and never more
+    /// than there are chunks.
+    pub(crate) fn workers(&self, candidates: usize) -> usize {
+        if candidates < self.min_docs.max(1) {
+            return 1;
+        }
+        self.threads.clamp(1, candidates.div_ceil(CHUNK_DOCS).max(1))
+    }
+}
+
+/// What a partitioned scan reports on the dev6 bench lines.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ScanStats {
+    pub workers: usize,
+    /// Time workers spent on chunks, summed.
+    pub busy: Duration,
+    pub wall: Duration,
+}
+
+impl ScanStats {
+    pub(crate) const fn serial() -> Self {
+        Self { workers: 1, busy: Duration::ZERO, wall: Duration::ZERO }
+    }
+
+    /// Chunk time over elapsed time: how many threads' worth of work the scan did at once.
+    pub(crate) fn speedup(&self) -> f64 {
+        if self.workers <= 1 || self.wall.is_zero() {
+            return 1.0;
+        }
+        self.busy.as_secs_f64() / self.wall.as_secs_f64()
+    }
+}
+
+/// Run `work` over the documents of each chunk of `ids` (every document when `None`) on
+/// `workers` threads, against one read of `col`'s cache. Missing and expired documents are
+/// skipped, and a chunk stops early once `deadline` passes.
+///
+/// `consume` receives `(chunk index, result)` pairs as chunks finish, in no particular order;
+/// chunks are claimed in index order, and none is claimed after it sets the flag it is given.
+pub(crate) fn partitioned<T, R>(
+    col: &Collection,
+    ids: Option<&[DocumentId]>,
+    workers: usize,
+    deadline: Option<Instant>,
+    work: impl Fn(&mut dyn Iterator<Item = &Document>) -> T + Sync,
+    consume: impl FnOnce(&mut dyn Iterator<Item = (usize, T)>, &AtomicBool) -> R,
+) -> (R, ScanStats)
+where
+    T: Send,
+{
+    let started = Instant::now();
+    let store = col.cache.store.read();
+    let store = &*store;
+    let all: Vec<DocumentId>;
+    let ids = match ids {
+        Some(ids) => ids,
+        None => {
+            all = store.iter().map(|(id, _)| id.clone()).collect();
+            &all
+        }
+    };
+    let chunks = ids.len().div_ceil(CHUNK_DOCS);
+    let workers = workers.clamp(1, chunks.max(1));
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let busy_ns = AtomicU64::new(0);
+    let expired = |dl: Instant| Instant::now() > dl;
+    let (tx, rx) = mpsc::sync_channel::<(usize, T)>(workers * 2);
+    let result = std::thread::scope(|s| {
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let (next, stop, busy_ns, work) = (&next, &stop, &busy_ns, &work);
+            s.spawn(move || {
+                loop {
+                    if stop.load(Ordering::Relaxed) || deadline.is_some_and(expired) {
+                        break;
+                    }
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= chunks {
+                        break;
+                    }
+                    let t0 = Instant::now();
+                    let chunk = &ids[i * CHUNK_DOCS..ids.len().min((i + 1) * CHUNK_DOCS)];
+                    let mut docs = chunk
+                        .iter()
+                        .take_while(|_| !deadline.is_some_and(expired))
+                        .filter_map(|id| store.peek(id))
+                        .filter(|d| !d.is_expired());
+                    let out = work(&mut docs);
+                    let ns = u64::try_from(t0.elapsed().as_nanos()).unwrap_or(u64::MAX);
+                    busy_ns.fetch_add(ns, Ordering::Relaxed);
+                    // The receiver is gone once `consume` returns: nothing more is wanted
+                    if tx.send((i, out)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+        let mut results = rx.into_iter();
+        let r = consume(&mut results, &stop);
+        stop.store(true, Ordering::Relaxed);
+        r
+    });
+    let stats = ScanStats {
+        workers,
+        busy: Duration::from_nanos(busy_ns.load(Ordering::Relaxed)),
+        wall: started.elapsed(),
+    };
+    (result, stats)
+}
+
+/// Collect chunk results in chunk order, stopping the scan once `wanted` items are in: every
+/// chunk not yet claimed comes after those already returned.
+pub(crate) fn in_order<D>(
+    chunks: &mut dyn Iterator<Item = (usize, Vec<D>)>,
+    stop: &AtomicBool,
+    wanted: usize,
+) -> Vec<D> {
+    let mut done: Vec<(usize, Vec<D>)> = Vec::new();
+    let mut found = 0usize;
+    for (i, items) in chunks {
+        found = found.saturating_add(items.len());
+        done.push((i, items));
+        if found >= wanted {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+    done.sort_unstable_by_key(|(i, _)| *i);
+    let mut out: Vec<D> = done.into_iter().flat_map(|(_, items)| items).collect();
+    out.truncate(wanted);
+    out
+}
@@ -2,7 +2,17 @@ use crate::errors::DbError;
 use bson::Bson;
 use serde::{Deserialize, Serialize};
 
-use super::types::{CmpOp, Filter, MAX_IN_SET, UpdateDoc};
+use super::collation::Collation;
+use super::expr::{Expr, is_type_name};
+use super::geo::{GeoNear, GeoOp, GeoShape, Geometry};
+use super::numeric::is_number;
+#[cfg(feature = "regex")]
+use super::pattern::RegexPattern;
+use super::text::TextSearch;
+use super::types::{
+    CmpOp, CurrentDateType, FieldProjection, Filter, MAX_IN_SET, MAX_PATH_DEPTH, Order, PopFrom,
+    Projection, PullSpec, PushSort, PushSpec, SortSpec, UpdateDoc,
+};
 
 // Serde-facing structures for safe JSON parsing of filters/updates
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,24 +30,28 @@ pub enum FilterSerde {
         #[serde(rename = "$not")]
         not: Box<FilterSerde>,
     },
+    /// `{"collation": {"strength": 2}, "filter": {...}}`
+    Collate {
+        collation: Collation,
+        filter: Box<FilterSerde>,
+    },
+    /// `{"$expr": <expression>}`
+    Expr {
+        #[serde(rename = "$expr")]
+        expr: Bson,
+    },
+    /// `{"$text": {"$search": "printer \"paper jam\" -toner"}}`
+    Text {
+        #[serde(rename = "$text")]
+        text: TextSerde,
+    },
     Exists {
         field: String,
         #[serde(rename = "$exists")]
         exists: bool,
     },
-    Cmp {
-        field: String,
-        #[serde(rename = "$eq")]
-        eq: Box<Option<Bson>>,
-        #[serde(rename = "$gt")]
-        gt: Box<Option<Bson>>,
-        #[serde(rename = "$gte")]
-        gte: Box<Option<Bson>>,
-        #[serde(rename = "$lt")]
-        lt: Box<Option<Bson>>,
-        #[serde(rename = "$lte")]
-        lte: Box<Option<Bson>>,
-    },
+    // `In`/`Nin`/`Type`/`Mod`/`Regex` and the geo operators must precede `Cmp`: untagged
+    // matching would otherwise accept their objects as a `Cmp` with no operator.
     In {
         field: String,
         #[serde(rename = "$in")]
@@ -48,6 +62,18 @@ pub enum FilterSerde {
         #[serde(rename = "$nin")]
         nin_vals: Vec<Bson>,
     },
+    Type {
+        field: String,
+        #[serde(rename = "$type")]
+        type_name: String,
+    },
+    /// `{"field": "n", "$mod": [divisor, remainder]}`
+    Mod {
+        field: String,
+        #[serde(rename = "$mod")]
+        divisor_remainder: [i64; 2],
+    },
+    /// `{"field": "name", "$regex": "^ab", "case_insensitive": true}`
     #[cfg(feature = "regex")]
     Regex {
         field: String,
@@ -56,9 +82,131 @@ pub enum FilterSerde {
         #[serde(default)]
         case_insensitive: bool,
     },
+    /// `{"field": "loc", "$geoWithin": {"$box": [[0, 0], [10, 10]]}}`
+    GeoWithin {
+        field: String,
+        #[serde(rename = "$geoWithin")]
+        within: Bson,
+    },
+    /// `{"field": "loc", "$geoIntersects": {"$geometry": {"type": "Polygon", ...}}}`
+    GeoIntersects {
+        field: String,
+        #[serde(rename = "$geoIntersects")]
+        intersects: Bson,
+    },
+    /// `{"field": "loc", "$near": [2.35, 48.85], "$maxDistance": 0.5}`, or with
+    /// `{"$geometry": <Point>, "$maxDistance": <metres>}` as the operand.
+    Near {
+        field: String,
+        #[serde(rename = "$near")]
+        near: Bson,
+        #[serde(rename = "$minDistance", default, skip_serializing_if = "Option::is_none")]
+        min_distance: Option<f64>,
+        #[serde(rename = "$maxDistance", default, skip_serializing_if = "Option::is_none")]
+        max_distance: Option<f64>,
+    },
+    NearSphere {
+        field: String,
+        #[serde(rename = "$nearSphere")]
+        near: Bson,
+        #[serde(rename = "$minDistance", default, skip_serializing_if = "Option::is_none")]
+        min_distance: Option<f64>,
+        #[serde(rename = "$maxDistance", default, skip_serializing_if = "Option::is_none")]
+        max_distance: Option<f64>,
+    },
+    Cmp {
+        field: String,
+        #[serde(rename = "$eq", skip_serializing_if = "is_unset")]
+        eq: Box<Option<Bson>>,
+        #[serde(rename = "$gt", skip_serializing_if = "is_unset")]
+        gt: Box<Option<Bson>>,
+        #[serde(rename = "$gte", skip_serializing_if = "is_unset")]
+        gte: Box<Option<Bson>>,
+        #[serde(rename = "$lt", skip_serializing_if = "is_unset")]
+        lt: Box<Option<Bson>>,
+        #[serde(rename = "$lte", skip_serializing_if = "is_unset")]
+        lte: Box<Option<Bson>>,
+    },
     True(bool),
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextSerde {
+    #[serde(rename = "$search")]
+    pub search: String,
+}
+
+#[allow(clippy::borrowed_box)]
+fn is_unset(v: &Box<Option<Bson>>) -> bool {
+    v.is_none()
+}
+
+impl From<Filter> for FilterSerde {
+    fn from(f: Filter) -> Self {
+        match f {
+            Filter::True => Self::True(true),
+            Filter::And(fs) => Self::And { and: fs.into_iter().map(Self::from).collect() },
+            Filter::Or(fs) => Self::Or { or: fs.into_iter().map(Self::from).collect() },
+            Filter::Not(inner) => match *inner {
+                Filter::True => Self::True(false),
+                other => Self::Not { not: Box::new(Self::from(other)) },
+            },
+            Filter::Exists { path, exists } => Self::Exists { field: path, exists },
+            Filter::In { path, values } => Self::In { field: path, in_vals: values },
+            Filter::Nin { path, values } => Self::Nin { field: path, nin_vals: values },
+            Filter::Cmp { path, op, value } => {
+                let mut out = (None, None, None, None, None);
+                match op {
+                    CmpOp::Eq => out.0 = Some(value),
+                    CmpOp::Gt => out.1 = Some(value),
+                    CmpOp::Gte => out.2 = Some(value),
+                    CmpOp::Lt => out.3 = Some(value),
+                    CmpOp::Lte => out.4 = Some(value),
+                }
+                Self::Cmp {
+                    field: path,
+                    eq: Box::new(out.0),
+                    gt: Box::new(out.1),
+                    gte: Box::new(out.2),
+                    lt: Box::new(out.3),
+                    lte: Box::new(out.4),
+                }
+            }
+            #[cfg(feature = "regex")]
+            Filter::Regex { path, pattern } => Self::Regex {
+                field: path,
+                case_insensitive: pattern.case_insensitive(),
+                pattern: pattern.as_str().to_string(),
+            },
+            Filter::Type { path, type_name } => Self::Type { field: path, type_name },
+            Filter::Mod { path, divisor, remainder } => {
+                Self::Mod { field: path, divisor_remainder: [divisor, remainder] }
+            }
+            Filter::Expr(e) => Self::Expr { expr: e.to_bson() },
+            Filter::Text(t) => Self::Text { text: TextSerde { search: t.search } },
+            Filter::Geo { path: field, op } => match op {
+                GeoOp::Within(s) => Self::GeoWithin { field, within: s.to_bson() },
+                GeoOp::Intersects(g) => Self::GeoIntersects {
+                    field,
+                    intersects: Bson::Document(bson::doc! {"$geometry": g.to_bson()}),
+                },
+                GeoOp::Near(n) => {
+                    let (near, min_distance, max_distance) = n.to_bson();
+                    if n.sphere {
+                        Self::NearSphere { field, near, min_distance, max_distance }
+                    } else {
+                        Self::Near { field, near, min_distance, max_distance }
+                    }
+                }
+            },
+            Filter::Collate { collation, filter } => {
+                Self::Collate { collation, filter: Box::new(Self::from(*filter)) }
+            }
+        }
+    }
+}
+
 impl TryFrom<FilterSerde> for Filter {
     type Error = DbError;
     fn try_from(fs: FilterSerde) -> Result<Self, Self::Error> {
@@ -71,7 +219,48 @@ impl TryFrom<FilterSerde> for Filter {
                 Self::Or(or.into_iter().map(Self::try_from).collect::<Result<_, _>>()?)
             }
             FS::Not { not } => Self::Not(Box::new(Self::try_from(*not)?)),
+            FS::Collate { collation, filter } => {
+                Self::Collate { collation, filter: Box::new(Self::try_from(*filter)?) }
+            }
+            FS::Expr { expr } => Self::Expr(Expr::parse(expr)?),
+            FS::Text { text } => Self::Text(TextSearch::parse(&text.search)?),
             FS::Exists { field, exists } => Self::Exists { path: field, exists },
+            FS::GeoWithin { field, within } => {
+                Self::Geo { path: field, op: GeoOp::Within(GeoShape::parse(&within)?) }
+            }
+            FS::GeoIntersects { field, intersects } => {
+                let geometry = match &intersects {
+                    Bson::Document(d) if d.len() == 1 && d.contains_key("$geometry") => {
+                        Geometry::parse("$geoIntersects", &d["$geometry"])?
+                    }
+                    _ => {
+                        return Err(DbError::QueryError(
+                            "$geoIntersects requires {\"$geometry\": ...}".into(),
+                        ));
+                    }
+                };
+                Self::Geo { path: field, op: GeoOp::Intersects(geometry) }
+            }
+            FS::Near { field, near, min_distance, max_distance } => {
+                let near = GeoNear::parse(&near, false, min_distance, max_distance)?;
+                Self::Geo { path: field, op: GeoOp::Near(near) }
+            }
+            FS::NearSphere { field, near, min_distance, max_distance } => {
+                let near = GeoNear::parse(&near, true, min_distance, max_distance)?;
+                Self::Geo { path: field, op: GeoOp::Near(near) }
+            }
+            FS::Type { field, type_name } => {
+                if !is_type_name(&type_name) {
+                    return Err(DbError::QueryError(format!("unknown $type '{type_name}'")));
+                }
+                Self::Type { path: field, type_name }
+            }
+            FS::Mod { field, divisor_remainder: [divisor, remainder] } => {
+                if divisor == 0 {
+                    return Err(DbError::QueryError("$mod divisor must not be zero".into()));
+                }
+                Self::Mod { path: field, divisor, remainder }
+            }
             FS::Cmp { field, eq, gt, gte, lt, lte } => {
                 if let Some(v) = *eq {
                     Self::Cmp { path: field, op: CmpOp::Eq, value: v }
@@ -95,7 +284,7 @@ impl TryFrom<FilterSerde> for Filter {
             }
             #[cfg(feature = "regex")]
             FS::Regex { field, pattern, case_insensitive } => {
-                Self::Regex { path: field, pattern, case_insensitive }
+                Self::Regex { path: field, pattern: RegexPattern::new(&pattern, case_insensitive)? }
             }
             FS::True(b) => {
                 if b {
@@ -116,38 +305,313 @@ pub struct UpdateDocSerde {
     pub inc: Option<bson::Document>,
     #[serde(default, rename = "$unset")]
     pub unset: Option<Vec<String>>,
+    #[serde(default, rename = "$mul", skip_serializing_if = "Option::is_none")]
+    pub mul: Option<bson::Document>,
+    #[serde(default, rename = "$min", skip_serializing_if = "Option::is_none")]
+    pub min: Option<bson::Document>,
+    #[serde(default, rename = "$max", skip_serializing_if = "Option::is_none")]
+    pub max: Option<bson::Document>,
+    #[serde(default, rename = "$rename", skip_serializing_if = "Option::is_none")]
+    pub rename: Option<bson::Document>,
+    #[serde(default, rename = "$push", skip_serializing_if = "Option::is_none")]
+    pub push: Option<bson::Document>,
+    #[serde(default, rename = "$addToSet", skip_serializing_if = "Option::is_none")]
+    pub add_to_set: Option<bson::Document>,
+    #[serde(default, rename = "$pull", skip_serializing_if = "Option::is_none")]
+    pub pull: Option<bson::Document>,
+    #[serde(default, rename = "$pop", skip_serializing_if = "Option::is_none")]
+    pub pop: Option<bson::Document>,
+    #[serde(default, rename = "$currentDate", skip_serializing_if = "Option::is_none")]
+    pub current_date: Option<bson::Document>,
+    #[serde(default, rename = "$setOnInsert", skip_serializing_if = "Option::is_none")]
+    pub set_on_insert: Option<bson::Document>,
+}
+
+// Per-operator cap on the number of fields, to bound update cost
+const MAX_UPDATE_FIELDS: usize = 128;
+
+fn update_err(msg: impl Into<String>) -> DbError {
+    DbError::QueryError(msg.into())
+}
+
+fn fields(d: Option<bson::Document>) -> impl Iterator<Item = (String, Bson)> {
+    d.into_iter().flatten().take(MAX_UPDATE_FIELDS)
+}
+
+fn numeric_arg(op: &str, v: Bson) -> Result<Bson, DbError> {
+    if is_number(&v) { Ok(v) } else { Err(update_err(format!("{op} requires numeric"))) }
+}
+
+/// `{"$each": [..]}` yields its elements; any other value is a single element.
+fn each_values(op: &str, v: Bson) -> Result<(Vec<Bson>, Option<bson::Document>), DbError> {
+    match v {
+        Bson::Document(mut d) if d.contains_key("$each") => match d.remove("$each") {
+            Some(Bson::Array(items)) => Ok((items, Some(d))),
+            _ => Err(update_err(format!("{op} $each requires an array"))),
+        },
+        other => Ok((vec![other], None)),
+    }
+}
+
+fn push_spec(v: Bson) -> Result<PushSpec, DbError> {
+    let (each, modifiers) = each_values("$push", v)?;
+    let mut spec = PushSpec { each, ..PushSpec::default() };
+    for (k, m) in modifiers.into_iter().flatten() {
+        match k.as_str() {
+            "$slice" => {
+                spec.slice = Some(match m {
+                    Bson::Int32(n) => i64::from(n),
+                    Bson::Int64(n) => n,
+                    _ => return Err(update_err("$push $slice requires an integer")),
+                });
+            }
+            "$sort" => {
+                spec.sort = Some(match m {
+                    Bson::Document(d) => PushSort::Fields(
+                        d.into_iter()
+                            .map(|(field, dir)| Ok(SortSpec { field, order: sort_order(&dir)? }))
+                            .collect::<Result<_, DbError>>()?,
+                    ),
+                    other => PushSort::Value(sort_order(&other)?),
+                });
+            }
+            other => return Err(update_err(format!("unsupported $push modifier '{other}'"))),
+        }
+    }
+    Ok(spec)
+}
+
+fn sort_order(v: &Bson) -> Result<Order, DbError> {
+    match v {
+        Bson::Int32(1) | Bson::Int64(1) => Ok(Order::Asc),
+        Bson::Int32(-1) | Bson::Int64(-1) => Ok(Order::Desc),
+        _ => Err(update_err("$push $sort direction must be 1 or -1")),
+    }
+}
+
+fn pull_spec(v: Bson) -> Result<PullSpec, DbError> {
+    let Bson::Document(d) = v else {
+        return Ok(PullSpec::Value(v));
+    };
+    if !d.keys().any(|k| k.starts_with('$')) {
+        return Ok(PullSpec::Fields(d.into_iter().collect()));
+    }
+    if d.len() == 1
+        && let Ok(values) = d.get_array("$in")
+    {
+        return Ok(PullSpec::In(values.clone()));
+    }
+    let mut conds = Vec::new();
+    for (k, v) in d {
+        let op = match k.as_str() {
+            "$eq" => CmpOp::Eq,
+            "$gt" => CmpOp::Gt,
+            "$gte" => CmpOp::Gte,
+            "$lt" => CmpOp::Lt,
+            "$lte" => CmpOp::Lte,
+            other => return Err(update_err(format!("unsupported $pull condition '{other}'"))),
+        };
+        conds.push((op, v));
+    }
+    Ok(PullSpec::Cmp(conds))
 }
 
 impl TryFrom<UpdateDocSerde> for UpdateDoc {
     type Error = DbError;
     fn try_from(us: UpdateDocSerde) -> Result<Self, Self::Error> {
-        let mut out = Self::default();
-        if let Some(setd) = us.set {
-            for (k, v) in setd.into_iter().take(128) {
-                out.set.push((k, v));
-            }
-        }
-        if let Some(incd) = us.inc {
-            for (k, v) in incd.into_iter().take(128) {
-                #[allow(clippy::cast_precision_loss)]
-                let f = match v {
-                    Bson::Int32(i) => f64::from(i),
-                    Bson::Int64(i) => i as f64,
-                    Bson::Double(d) => d,
-                    _ => {
-                        return Err(DbError::QueryError("$inc requires numeric".into()));
-                    }
-                };
-                out.inc.push((k, f));
-            }
+        let mut out = Self {
+            set: fields(us.set).collect(),
+            min: fields(us.min).collect(),
+            max: fields(us.max).collect(),
+            set_on_insert: fields(us.set_on_insert).collect(),
+            ..Self::default()
+        };
+        for (k, v) in fields(us.inc) {
+            out.inc.push((k, numeric_arg("$inc", v)?));
         }
         if let Some(unset) = us.unset {
-            out.unset = unset.into_iter().take(128).collect();
+            out.unset = unset.into_iter().take(MAX_UPDATE_FIELDS).collect();
+        }
+        for (k, v) in fields(us.mul) {
+            out.mul.push((k, numeric_arg("$mul", v)?));
+        }
+        for (from, to) in fields(us.rename) {
+            match to {
+                Bson::String(to) if !to.is_empty() => out.rename.push((from, to)),
+                _ => return Err(update_err("$rename target must be a field name")),
+            }
+        }
+        for (k, v) in fields(us.push) {
+            out.push.push((k, push_spec(v)?));
+        }
+        for (k, v) in fields(us.add_to_set) {
+            let (values, modifiers) = each_values("$addToSet", v)?;
+            if modifiers.is_some_and(|m| !m.is_empty()) {
+                return Err(update_err("$addToSet only supports the $each modifier"));
+            }
+            out.add_to_set.push((k, values));
+        }
+        for (k, v) in fields(us.pull) {
+            out.pull.push((k, pull_spec(v)?));
+        }
+        for (k, v) in fields(us.pop) {
+            let from = match v {
+                Bson::Int32(1) | Bson::Int64(1) => PopFrom::Last,
+                Bson::Int32(-1) | Bson::Int64(-1) => PopFrom::First,
+                _ => return Err(update_err("$pop requires 1 or -1")),
+            };
+            out.pop.push((k, from));
+        }
+        for (k, v) in fields(us.current_date) {
+            let ty = match &v {
+                Bson::Boolean(true) => CurrentDateType::Date,
+                Bson::Document(d) => match d.get_str("$type") {
+                    Ok("date") => CurrentDateType::Date,
+                    Ok("timestamp") => CurrentDateType::Timestamp,
+                    _ => return Err(update_err("$currentDate $type must be date or timestamp")),
+                },
+                _ => return Err(update_err("$currentDate requires true or {$type}")),
+            };
+            out.current_date.push((k, ty));
         }
         Ok(out)
     }
 }
 
+/// Serde form of a [`Projection`]: a list of paths to include, or a `{path: spec}` object
+/// where a spec is `1`/`true`, `0`/`false`, `{"$slice": n | [skip, limit]}`,
+/// `{"$elemMatch": <filter>}`, or an expression computing the field (`"$other.path"`,
+/// `{"$concat": [..]}`, `{"$literal": v}`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ProjectionSerde {
+    Paths(Vec<String>),
+    Spec(bson::Document),
+}
+
+fn projection_err(msg: impl Into<String>) -> DbError {
+    DbError::QueryError(msg.into())
+}
+
+fn as_int(v: &Bson) -> Option<i64> {
+    match v {
+        Bson::Int32(i) => Some(i64::from(*i)),
+        Bson::Int64(i) => Some(*i),
+        Bson::Double(f) if f.fract() == 0.0 && f.abs() < 9e15 => Some(*f as i64),
+        _ => None,
+    }
+}
+
+fn field_projection(path: &str, spec: Bson) -> Result<FieldProjection, DbError> {
+    let bad = || projection_err(format!("unsupported projection for '{path}'"));
+    if let Bson::Boolean(b) = spec {
+        return Ok(if b { FieldProjection::Include } else { FieldProjection::Exclude });
+    }
+    if let Some(n) = as_int(&spec) {
+        return Ok(if n == 0 { FieldProjection::Exclude } else { FieldProjection::Include });
+    }
+    let computed = match &spec {
+        Bson::String(s) => s.starts_with('$'),
+        Bson::Document(d) => {
+            !d.contains_key("$slice")
+                && !d.contains_key("$elemMatch")
+                && d.keys().next().is_some_and(|k| k.starts_with('$'))
+        }
+        _ => false,
+    };
+    if computed {
+        return Ok(FieldProjection::Computed(Expr::parse(spec)?));
+    }
+    let Bson::Document(d) = spec else { return Err(bad()) };
+    let mut ops = d.into_iter();
+    let (Some((op, arg)), None) = (ops.next(), ops.next()) else { return Err(bad()) };
+    match (op.as_str(), arg) {
+        ("$slice", Bson::Array(items)) => {
+            let [skip, limit] = items.as_slice() else {
+                return Err(projection_err("$slice takes n or [skip, limit]"));
+            };
+            let limit = as_int(limit).and_then(|l| usize::try_from(l).ok()).filter(|l| *l > 0);
+            match (as_int(skip), limit) {
+                (Some(skip), Some(limit)) => {
+                    Ok(FieldProjection::Slice { skip, limit: Some(limit) })
+                }
+                _ => Err(projection_err("$slice [skip, limit] needs a positive limit")),
+            }
+        }
+        ("$slice", n) => match as_int(&n) {
+            Some(n) if n < 0 => Ok(FieldProjection::Slice { skip: n, limit: None }),
+            Some(n) => Ok(FieldProjection::Slice {
+                skip: 0,
+                limit: Some(usize::try_from(n).unwrap_or(usize::MAX)),
+            }),
+            None => Err(projection_err("$slice takes n or [skip, limit]")),
+        },
+        ("$elemMatch", Bson::Document(f)) => Ok(FieldProjection::ElemMatch(
+            bson::deserialize_from_document(f)
+                .map_err(|e| projection_err(format!("$elemMatch: {e}")))?,
+        )),
+        _ => Err(bad()),
+    }
+}
+
+impl TryFrom<ProjectionSerde> for Projection {
+    type Error = DbError;
+    fn try_from(ps: ProjectionSerde) -> Result<Self, Self::Error> {
+        let fields: Vec<(String, FieldProjection)> = match ps {
+            ProjectionSerde::Paths(paths) => Self::include(paths).fields,
+            ProjectionSerde::Spec(d) => d
+                .into_iter()
+                .map(|(path, spec)| Ok((path.clone(), field_projection(&path, spec)?)))
+                .collect::<Result<_, DbError>>()?,
+        };
+        for (path, _) in &fields {
+            let depth = path.split('.').count();
+            if path.is_empty() || path.starts_with('$') || depth > MAX_PATH_DEPTH {
+                return Err(projection_err(format!("invalid projection path '{path}'")));
+            }
+        }
+        let out = Self { fields };
+        let excludes = out
+            .fields
+            .iter()
+            .any(|(path, p)| path != "_id" && matches!(p, FieldProjection::Exclude));
+        if excludes && out.is_inclusion() {
+            return Err(projection_err("projection cannot mix inclusion and exclusion"));
+        }
+        Ok(out)
+    }
+}
+
+impl From<Projection> for ProjectionSerde {
+    fn from(p: Projection) -> Self {
+        let mut d = bson::Document::new();
+        for (path, spec) in p.fields {
+            let v = match spec {
+                FieldProjection::Include => Bson::Int32(1),
+                FieldProjection::Exclude => Bson::Int32(0),
+                FieldProjection::Slice { skip, limit: Some(limit) } if skip != 0 => {
+                    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+                    bson::bson!({"$slice": [skip, limit]})
+                }
+                FieldProjection::Slice { limit: Some(limit), .. } => {
+                    bson::bson!({"$slice": i64::try_from(limit).unwrap_or(i64::MAX)})
+                }
+                FieldProjection::Slice { skip, limit: None } => bson::bson!({"$slice": skip}),
+                FieldProjection::ElemMatch(f) => {
+                    let f = bson::serialize_to_bson(&f)
+                        .unwrap_or_else(|_| Bson::Document(bson::Document::new()));
+                    bson::bson!({"$elemMatch": f})
+                }
+                // A bare constant would read back as an inclusion or exclusion
+                FieldProjection::Computed(Expr::Literal(v)) => bson::bson!({"$literal": v}),
+                FieldProjection::Computed(e) => e.to_bson(),
+            };
+            d.insert(path, v);
+        }
+        Self::Spec(d)
+    }
+}
+
 /// # Errors
 /// Returns an error if the JSON string cannot be parsed into a filter structure.
 pub fn parse_filter_json(json: &str) -> Result<Filter, DbError> {
@@ -161,3 +625,10 @@ pub fn parse_update_json(json: &str) -> Result<UpdateDoc, DbError> {
     let us: UpdateDocSerde = serde_json::from_str(json)?;
     UpdateDoc::try_from(us)
 }
+
+/// # Errors
+/// Returns an error if the JSON is not a valid projection, or mixes inclusion and exclusion.
+pub fn parse_projection_json(json: &str) -> Result<Projection, DbError> {
+    let ps: ProjectionSerde = serde_json::from_str(json)?;
+    Projection::try_from(ps)
+}
@@ -0,0 +1,105 @@
+//! Compiled `$regex` patterns.
+//!
+//! A pattern is compiled once, when its filter is built, under a size budget: at most
+//! `MAX_REGEX_LEN` characters of source, `MAX_REGEX_SIZE` bytes of compiled program and
+//! `MAX_REGEX_DFA_SIZE` bytes of lazy DFA cache per search. The compiled regex is shared by
+//! every clone of the filter and may be used from several threads at once.
+
+use crate::errors::DbError;
+use regex::{Regex, RegexBuilder};
+
+/// Longest `$regex` source accepted, in characters.
+pub(crate) const MAX_REGEX_LEN: usize = 512;
+
+/// Largest compiled program a pattern may produce.
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// Largest lazy DFA cache a search may build.
+const MAX_REGEX_DFA_SIZE: usize = 1 << 20;
+
+/// A `$regex` pattern with its compiled form.
+#[derive(Debug, Clone)]
+pub struct RegexPattern {
+    case_insensitive: bool,
+    regex: Regex,
+}
+
+impl RegexPattern {
+    /// Compile `pattern`.
+    ///
+    /// # Errors
+    /// Returns `DbError::QueryError` if the pattern is longer than `MAX_REGEX_LEN`
+    /// characters, is not a valid regex, or compiles beyond the size limits.
+    pub fn new(pattern: &str, case_insensitive: bool) -> Result<Self, DbError> {
+        if pattern.chars().count() > MAX_REGEX_LEN {
+            return Err(DbError::QueryError(format!(
+                "$regex pattern is longer than {MAX_REGEX_LEN} characters"
+            )));
+        }
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .size_limit(MAX_REGEX_SIZE)
+            .dfa_size_limit(MAX_REGEX_DFA_SIZE)
+            .build()
+            .map_err(|e| DbError::QueryError(format!("invalid $regex: {e}")))?;
+        Ok(Self { case_insensitive, regex })
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    #[must_use]
+    pub const fn case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    #[must_use]
+    pub fn is_match(&self, s: &str) -> bool {
+        self.regex.is_match(s)
+    }
+
+    /// The literal every match starts with, for a case-sensitive pattern anchored with `^`
+    /// and without alternation: `^abc.*` gives `abc`. `None` when there is no such prefix.
+    pub(crate) fn prefix(&self) -> Option<String> {
+        let source = self.as_str();
+        if self.case_insensitive || source.contains('|') {
+            return None;
+        }
+        let mut chars = source.strip_prefix('^')?.chars().peekable();
+        let mut prefix = String::new();
+        while let Some(&c) = chars.peek() {
+            let literal = match c {
+                '\\' => {
+                    chars.next();
+                    match chars.peek() {
+                        Some(&e) if regex::escape(&e.to_string()) != e.to_string() => e,
+                        _ => break,
+                    }
+                }
+                c if c.is_alphanumeric() || c == ' ' || "-_/:,;=@%&!~'\"<>".contains(c) => c,
+                _ => break,
+            };
+            chars.next();
+            prefix.push(literal);
+        }
+        // A quantifier that allows zero repetitions makes the last literal optional
+        if matches!(chars.peek(), Some('*' | '?' | '{')) {
+            prefix.pop();
+        }
+        (!prefix.is_empty()).then_some(prefix)
+    }
+}
+
+/// The least string above every string starting with `prefix`, or `None` when there is none.
+pub(crate) fn prefix_end(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(c) = chars.pop() {
+        if let Some(next) = (u32::from(c) + 1..=u32::from(char::MAX)).find_map(char::from_u32) {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
@@ -0,0 +1,553 @@
+//! Access-path selection and `explain` reports.
+//!
+//! The planner enumerates every way the ready indexes can produce a superset of the
+//! documents matching a filter, estimates how many documents each would return using the
+//! index contents (`IndexStats` plus exact per-key cardinalities), and picks the cheapest,
+//! falling back to a collection scan.
+
+use crate::collection::Collection;
+use crate::document::Document;
+use crate::index::{IndexImpl, IndexKind, IndexManager};
+use crate::types::DocumentId;
+use bson::Bson;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::collation::Collation;
+use super::eval::{compare_bson, compare_docs_collated, eval_filter};
+use super::exec::collated;
+use super::geo;
+use super::project::project;
+use super::text;
+use super::types::{CmpOp, Filter, FindOptions, MAX_IN_SET, MAX_LIMIT, SortSpec};
+
+/// How candidate documents are produced before the filter is applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)] // built a handful of times per query
+pub enum AccessPath {
+    CollectionScan,
+    /// Equality lookups, one per value (several for `$in`).
+    IndexEq {
+        field: String,
+        values: Vec<Bson>,
+    },
+    IndexRange {
+        field: String,
+        min: Option<Bson>,
+        max: Option<Bson>,
+        min_inclusive: bool,
+        max_inclusive: bool,
+    },
+    /// Documents holding any of `terms` in the text index on `field`.
+    IndexText {
+        field: String,
+        terms: Vec<String>,
+    },
+    /// Documents under the inclusive cell-key `ranges` of the geo index on `field`, which
+    /// cover the query's region, plus its lines and polygons.
+    IndexGeo {
+        field: String,
+        ranges: Vec<(u64, u64)>,
+    },
+    /// Union of the branches of an `$or` that are each answerable by an index.
+    Union {
+        branches: Vec<AccessPath>,
+    },
+}
+
+/// An access path together with the planner's estimate of documents it returns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanCandidate {
+    pub path: AccessPath,
+    pub estimated_docs: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStage {
+    CollectionScan,
+    IndexEq,
+    IndexRange,
+    IndexText,
+    IndexGeo,
+    Union,
+    Filter,
+    Sort,
+    Projection,
+    Limit,
+}
+
+/// One node of an executed plan tree. Children feed their output into the parent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanNode {
+    pub stage: PlanStage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    pub estimated_docs: usize,
+    pub docs_examined: usize,
+    pub keys_examined: usize,
+    pub docs_returned: usize,
+    pub duration_us: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<PlanNode>,
+}
+
+impl PlanNode {
+    fn new(stage: PlanStage, estimated_docs: usize) -> Self {
+        Self {
+            stage,
+            index: None,
+            estimated_docs,
+            docs_examined: 0,
+            keys_examined: 0,
+            docs_returned: 0,
+            duration_us: 0,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Result of `explain`: the chosen plan, executed, plus the candidates it beat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExplainReport {
+    pub collection: String,
+    pub chosen: PlanCandidate,
+    pub rejected: Vec<PlanCandidate>,
+    pub plan: PlanNode,
+    pub total_duration_us: u64,
+}
+
+fn elapsed_us(start: Instant) -> u64 {
+    u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX)
+}
+
+/// Pick the cheapest access path for `filter`. Returns the winner and the other candidates.
+pub fn choose_plan(col: &Collection, filter: &Filter) -> (PlanCandidate, Vec<PlanCandidate>) {
+    let total = col.cache.store.read().len();
+    let mgr = col.indexes.read();
+    let mut all = candidates(&mgr, filter, filter, total, None);
+    // Ties go to the index: its candidates are never more than a scan would examine.
+    all.push(PlanCandidate { path: AccessPath::CollectionScan, estimated_docs: total });
+    let best = all.iter().enumerate().min_by_key(|(_, c)| c.estimated_docs).map_or(0, |(i, _)| i);
+    let chosen = all.remove(best);
+    (chosen, all)
+}
+
+/// Candidate ids for `path`, or `None` for a collection scan.
+pub(crate) fn fetch_ids(col: &Collection, path: &AccessPath) -> Option<Vec<DocumentId>> {
+    if matches!(path, AccessPath::CollectionScan) {
+        return None;
+    }
+    let mut mgr = col.indexes.write();
+    let (ids, node) = run_access(&mut mgr, path, 0);
+    // An equality probe that finds no key at all falls back to a scan, so documents the index
+    // missed (e.g. ones only reachable through the WASP overlay) are still returned.
+    if matches!(path, AccessPath::IndexEq { .. }) && node.keys_examined == 0 {
+        return None;
+    }
+    Some(ids)
+}
+
+/// The index on `field` if it can look up `value` for a query comparing strings under
+/// `collation`: string keys are only usable when the index was built with the same collation.
+fn index_for<'a>(
+    mgr: &'a IndexManager,
+    root: &Filter,
+    field: &str,
+    value: &Bson,
+    collation: Option<&Collation>,
+) -> Option<&'a IndexImpl> {
+    let idx = mgr.indexes.get(field)?;
+    // Text and vector indexes do not hold the field's values
+    let usable = matches!(idx.kind(), IndexKind::Hash | IndexKind::BTree)
+        && idx.is_ready()
+        && idx.covers_query(root)
+        && !matches!(value, Bson::Array(_))
+        && crate::index::key_from_bson(value).is_some()
+        && (!matches!(value, Bson::String(_)) || idx.collation() == collation);
+    usable.then_some(idx)
+}
+
+/// `root` is the query being planned: partial indexes are only eligible when it implies their
+/// predicate. Inside an `$or` branch the branch itself becomes the root. `collation` is the
+/// one in effect for `filter` (set by an enclosing [`Filter::Collate`]).
+fn candidates(
+    mgr: &IndexManager,
+    root: &Filter,
+    filter: &Filter,
+    cap: usize,
+    collation: Option<&Collation>,
+) -> Vec<PlanCandidate> {
+    match filter {
+        Filter::Cmp { path, op: CmpOp::Eq, value } => index_for(mgr, root, path, value, collation)
+            .filter(|idx| !(idx.is_sparse() && matches!(value, Bson::Null)))
+            .map(|idx| PlanCandidate {
+                path: AccessPath::IndexEq { field: path.clone(), values: vec![value.clone()] },
+                estimated_docs: idx.estimate_eq(value),
+            })
+            .into_iter()
+            .collect(),
+        Filter::In { path, values } if !values.is_empty() && values.len() <= MAX_IN_SET => {
+            let mut est = 0usize;
+            for v in values {
+                match index_for(mgr, root, path, v, collation) {
+                    Some(idx) if !(idx.is_sparse() && matches!(v, Bson::Null)) => {
+                        est += idx.estimate_eq(v);
+                    }
+                    _ => return Vec::new(),
+                }
+            }
+            vec![PlanCandidate {
+                path: AccessPath::IndexEq { field: path.clone(), values: values.clone() },
+                estimated_docs: est,
+            }]
+        }
+        Filter::Cmp { path, op, value } => {
+            let mut b = RangeBounds::default();
+            b.apply(op, value);
+            range_candidate(mgr, root, path, &b, cap, collation).into_iter().collect()
+        }
+        // An anchored literal prefix bounds the matches to `[prefix, prefix_end)`. Regexes see
+        // raw strings, so only an index without a collation can serve the range.
+        #[cfg(feature = "regex")]
+        Filter::Regex { path, pattern } => {
+            let Some(prefix) = pattern.prefix() else {
+                return Vec::new();
+            };
+            let mut b = RangeBounds::default();
+            if let Some(end) = super::pattern::prefix_end(&prefix) {
+                b.apply(&CmpOp::Lt, &Bson::String(end));
+            }
+            b.apply(&CmpOp::Gte, &Bson::String(prefix));
+            range_candidate(mgr, root, path, &b, cap, None).into_iter().collect()
+        }
+        Filter::And(fs) => {
+            let mut out: Vec<PlanCandidate> =
+                fs.iter().flat_map(|f| candidates(mgr, root, f, cap, collation)).collect();
+            // Combine several range predicates on one field into a single bounded scan. Not for
+            // multikey indexes: each predicate may be satisfied by a different array element.
+            let mut seen: HashSet<&str> = HashSet::new();
+            for f in fs {
+                if let Filter::Cmp { path, op, .. } = f
+                    && *op != CmpOp::Eq
+                    && seen.insert(path)
+                    && mgr.indexes.get(path).is_some_and(|i| !i.stats().multikey)
+                {
+                    let mut b = RangeBounds::default();
+                    let mut n = 0;
+                    for g in fs {
+                        if let Filter::Cmp { path: p, op, value } = g
+                            && p == path
+                            && *op != CmpOp::Eq
+                        {
+                            b.apply(op, value);
+                            n += 1;
+                        }
+                    }
+                    if n > 1 {
+                        out.extend(range_candidate(mgr, root, path, &b, cap, collation));
+                    }
+                }
+            }
+            out
+        }
+        Filter::Or(fs) if !fs.is_empty() => {
+            let mut branches = Vec::new();
+            let mut est = 0usize;
+            for f in fs {
+                let best = candidates(mgr, f, f, cap, collation)
+                    .into_iter()
+                    .min_by_key(|c| c.estimated_docs);
+                match best {
+                    Some(c) => {
+                        est += c.estimated_docs;
+                        branches.push(c.path);
+                    }
+                    None => return Vec::new(),
+                }
+            }
+            vec![PlanCandidate { path: AccessPath::Union { branches }, estimated_docs: est }]
+        }
+        Filter::Collate { collation, filter } => {
+            candidates(mgr, root, filter, cap, Some(collation))
+        }
+        Filter::Text(t) => t
+            .index_terms()
+            .and_then(|(field, terms)| {
+                let idx = mgr.indexes.get(field).filter(|i| i.is_ready() && i.covers_query(root));
+                let Some(IndexImpl::Text(text)) = idx else {
+                    return None;
+                };
+                Some(PlanCandidate {
+                    estimated_docs: text.estimate_terms(&terms),
+                    path: AccessPath::IndexText { field: field.to_string(), terms },
+                })
+            })
+            .into_iter()
+            .collect(),
+        Filter::Geo { path, op } => {
+            let idx = mgr.indexes.get(path).filter(|i| i.is_ready() && i.covers_query(root));
+            let Some(IndexImpl::Geo(geo)) = idx else {
+                return Vec::new();
+            };
+            let ranges = op.covering();
+            vec![PlanCandidate {
+                estimated_docs: geo.estimate_ranges(&ranges, cap),
+                path: AccessPath::IndexGeo { field: path.clone(), ranges },
+            }]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Tightest lower/upper bounds collected from range comparisons on one field.
+#[derive(Debug, Default)]
+struct RangeBounds {
+    min: Option<(Bson, bool)>,
+    max: Option<(Bson, bool)>,
+}
+
+impl RangeBounds {
+    fn apply(&mut self, op: &CmpOp, value: &Bson) {
+        match op {
+            CmpOp::Gt | CmpOp::Gte => {
+                let incl = *op == CmpOp::Gte;
+                let tighter = self.min.as_ref().is_none_or(|(cur, cur_incl)| {
+                    match compare_bson(value, cur) {
+                        Ordering::Greater => true,
+                        Ordering::Equal => *cur_incl && !incl,
+                        Ordering::Less => false,
+                    }
+                });
+                if tighter {
+                    self.min = Some((value.clone(), incl));
+                }
+            }
+            CmpOp::Lt | CmpOp::Lte => {
+                let incl = *op == CmpOp::Lte;
+                let tighter = self.max.as_ref().is_none_or(|(cur, cur_incl)| {
+                    match compare_bson(value, cur) {
+                        Ordering::Less => true,
+                        Ordering::Equal => *cur_incl && !incl,
+                        Ordering::Greater => false,
+                    }
+                });
+                if tighter {
+                    self.max = Some((value.clone(), incl));
+                }
+            }
+            CmpOp::Eq => {}
+        }
+    }
+}
+
+fn range_candidate(
+    mgr: &IndexManager,
+    root: &Filter,
+    field: &str,
+    b: &RangeBounds,
+    cap: usize,
+    collation: Option<&Collation>,
+) -> Option<PlanCandidate> {
+    let probe = b.min.as_ref().or(b.max.as_ref()).map(|(v, _)| v)?;
+    let IndexImpl::BTree(bt) = index_for(mgr, root, field, probe, collation)? else {
+        return None;
+    };
+    let collation_ok = |v: &Option<(Bson, bool)>| {
+        v.as_ref().is_none_or(|(v, _)| {
+            !matches!(v, Bson::String(_)) || bt.collation.as_ref() == collation
+        })
+    };
+    if !collation_ok(&b.min) || !collation_ok(&b.max) {
+        return None;
+    }
+    let keyable = |v: &Option<(Bson, bool)>| {
+        v.as_ref().is_none_or(|(v, _)| {
+            !matches!(v, Bson::Array(_)) && crate::index::key_from_bson(v).is_some()
+        })
+    };
+    if !keyable(&b.min) || !keyable(&b.max) {
+        return None;
+    }
+    // A sparse index omits nulls, which sort below every other key: only usable when the
+    // range excludes them.
+    if bt.sparse && b.min.as_ref().is_none_or(|(v, incl)| matches!(v, Bson::Null) && *incl) {
+        return None;
+    }
+    let (min, min_inclusive) = b.min.clone().map_or((None, false), |(v, i)| (Some(v), i));
+    let (max, max_inclusive) = b.max.clone().map_or((None, false), |(v, i)| (Some(v), i));
+    let estimated_docs =
+        bt.estimate_range(min.as_ref(), max.as_ref(), min_inclusive, max_inclusive, cap);
+    Some(PlanCandidate {
+        path: AccessPath::IndexRange {
+            field: field.to_string(),
+            min,
+            max,
+            min_inclusive,
+            max_inclusive,
+        },
+        estimated_docs,
+    })
+}
+
+/// Execute an index access path, returning distinct candidate ids and its plan node.
+fn run_access(
+    mgr: &mut IndexManager,
+    path: &AccessPath,
+    estimated_docs: usize,
+) -> (Vec<DocumentId>, PlanNode) {
+    let start = Instant::now();
+    let (ids, mut node) = match path {
+        AccessPath::CollectionScan => (Vec::new(), PlanNode::new(PlanStage::CollectionScan, 0)),
+        AccessPath::IndexEq { field, values } => {
+            let mut node = PlanNode::new(PlanStage::IndexEq, estimated_docs);
+            node.index = Some(field.clone());
+            let mut ids = Vec::new();
+            if let Some(idx) = mgr.indexes.get_mut(field) {
+                let mut seen: HashSet<DocumentId> = HashSet::new();
+                for v in values {
+                    let (found, keys) = idx.scan_eq(v);
+                    node.keys_examined += keys;
+                    ids.extend(found.into_iter().filter(|id| seen.insert(id.clone())));
+                }
+            }
+            (ids, node)
+        }
+        AccessPath::IndexRange { field, min, max, min_inclusive, max_inclusive } => {
+            let mut node = PlanNode::new(PlanStage::IndexRange, estimated_docs);
+            node.index = Some(field.clone());
+            let mut ids = Vec::new();
+            if let Some(IndexImpl::BTree(b)) = mgr.indexes.get_mut(field) {
+                let (found, keys) =
+                    b.scan_range(min.as_ref(), max.as_ref(), *min_inclusive, *max_inclusive);
+                node.keys_examined = keys;
+                ids = found;
+            }
+            (ids, node)
+        }
+        AccessPath::IndexText { field, terms } => {
+            let mut node = PlanNode::new(PlanStage::IndexText, estimated_docs);
+            node.index = Some(field.clone());
+            let mut ids = Vec::new();
+            if let Some(IndexImpl::Text(t)) = mgr.indexes.get_mut(field) {
+                let (found, keys) = t.scan_terms(terms);
+                node.keys_examined = keys;
+                ids = found;
+            }
+            (ids, node)
+        }
+        AccessPath::IndexGeo { field, ranges } => {
+            let mut node = PlanNode::new(PlanStage::IndexGeo, estimated_docs);
+            node.index = Some(field.clone());
+            let mut ids = Vec::new();
+            if let Some(IndexImpl::Geo(g)) = mgr.indexes.get_mut(field) {
+                let (found, keys) = g.scan_ranges(ranges);
+                node.keys_examined = keys;
+                ids = found;
+            }
+            (ids, node)
+        }
+        AccessPath::Union { branches } => {
+            let mut node = PlanNode::new(PlanStage::Union, estimated_docs);
+            let mut seen: HashSet<DocumentId> = HashSet::new();
+            let mut ids = Vec::new();
+            for b in branches {
+                let (found, child) = run_access(mgr, b, 0);
+                node.keys_examined += child.keys_examined;
+                ids.extend(found.into_iter().filter(|id| seen.insert(id.clone())));
+                node.children.push(child);
+            }
+            (ids, node)
+        }
+    };
+    node.docs_returned = ids.len();
+    node.duration_us = elapsed_us(start);
+    (ids, node)
+}
+
+/// Plan and execute a find, reporting the chosen plan and per-stage statistics.
+pub fn explain(col: &Arc<Collection>, filter: &Filter, opts: &FindOptions) -> ExplainReport {
+    let total_start = Instant::now();
+    let filter = &collated(&text::bind(col, filter), opts.collation.as_ref());
+    let (chosen, rejected) = choose_plan(col, filter);
+
+    // Access stage
+    let (ids, access) = if matches!(chosen.path, AccessPath::CollectionScan) {
+        let start = Instant::now();
+        let ids = col.list_ids();
+        let mut node = PlanNode::new(PlanStage::CollectionScan, chosen.estimated_docs);
+        node.docs_returned = ids.len();
+        node.duration_us = elapsed_us(start);
+        (ids, node)
+    } else {
+        let mut mgr = col.indexes.write();
+        run_access(&mut mgr, &chosen.path, chosen.estimated_docs)
+    };
+
+    // Fetch + filter stage
+    let start = Instant::now();
+    let mut node = PlanNode::new(PlanStage::Filter, chosen.estimated_docs);
+    node.docs_examined = ids.len();
+    let mut docs: Vec<Document> = ids
+        .iter()
+        .filter_map(|id| col.find_document(id))
+        .filter(|d| eval_filter(&d.data.0, filter))
+        .collect();
+    if let Some(search) = text::scoring_search(filter) {
+        for d in &mut docs {
+            text::add_score(&mut d.data.0, search);
+        }
+    }
+    if let Some((path, near)) = geo::near_search(filter) {
+        for d in &mut docs {
+            geo::add_distance(&mut d.data.0, path, near);
+        }
+    }
+    node.docs_returned = docs.len();
+    node.duration_us = elapsed_us(start);
+    node.children.push(access);
+
+    // `$near` results come nearest first unless sorted otherwise
+    let near_sort = geo::near_search(filter).map(|_| vec![SortSpec::geo_distance()]);
+    if let Some(sort) = opts.sort.as_ref().or(near_sort.as_ref()) {
+        let start = Instant::now();
+        let collation = opts.collation.as_ref();
+        docs.sort_by(|a, b| compare_docs_collated(&a.data.0, &b.data.0, sort, collation));
+        node = wrap(PlanStage::Sort, node, docs.len(), start);
+    }
+    if let Some(p) = &opts.projection {
+        let start = Instant::now();
+        for d in &mut docs {
+            d.data.0 = project(&d.data.0, p);
+        }
+        node = wrap(PlanStage::Projection, node, docs.len(), start);
+    }
+    if opts.skip.is_some() || opts.limit.is_some() {
+        let start = Instant::now();
+        let skip = opts.skip.unwrap_or(0);
+        let limit = opts.limit.unwrap_or(usize::MAX).min(MAX_LIMIT);
+        let returned = docs.len().saturating_sub(skip).min(limit);
+        node = wrap(PlanStage::Limit, node, returned, start);
+    }
+
+    ExplainReport {
+        collection: col.name_str(),
+        chosen,
+        rejected,
+        plan: node,
+        total_duration_us: elapsed_us(total_start),
+    }
+}
+
+fn wrap(stage: PlanStage, child: PlanNode, returned: usize, start: Instant) -> PlanNode {
+    let mut node = PlanNode::new(stage, child.estimated_docs);
+    node.docs_examined = child.docs_returned;
+    node.docs_returned = returned;
+    node.duration_us = elapsed_us(start);
+    node.children.push(child);
+    node
+}
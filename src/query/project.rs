@@ -0,0 +1,177 @@
+use bson::{Bson, Document as BsonDocument};
+
+use super::eval::eval_filter;
+use super::types::{FieldProjection, MAX_PROJECTION_FIELDS, Projection};
+
+/// Apply `projection` to `doc`. Only the first `MAX_PROJECTION_FIELDS` entries are used.
+pub(crate) fn project(doc: &BsonDocument, projection: &Projection) -> BsonDocument {
+    let fields = &projection.fields[..projection.fields.len().min(MAX_PROJECTION_FIELDS)];
+    let mut out = if projection.is_inclusion() {
+        let mut out = BsonDocument::new();
+        let drop_id =
+            fields.iter().any(|(path, p)| path == "_id" && matches!(p, FieldProjection::Exclude));
+        if !drop_id && let Some(id) = doc.get("_id") {
+            out.insert("_id", id.clone());
+        }
+        for (path, p) in fields {
+            if !matches!(p, FieldProjection::Exclude | FieldProjection::Computed(_)) {
+                copy_path(doc, &segments(path), &mut out);
+            }
+        }
+        out
+    } else {
+        let mut out = doc.clone();
+        for (path, p) in fields {
+            if matches!(p, FieldProjection::Exclude) {
+                remove_path(&mut out, &segments(path));
+            }
+        }
+        out
+    };
+    for (path, p) in fields {
+        match p {
+            FieldProjection::Slice { skip, limit } => {
+                map_path(&mut out, &segments(path), &|v| Some(slice(v, *skip, *limit)));
+            }
+            FieldProjection::ElemMatch(filter) => map_path(&mut out, &segments(path), &|v| {
+                let Bson::Array(items) = v else { return None };
+                let first = items
+                    .iter()
+                    .find(|e| matches!(e, Bson::Document(d) if eval_filter(d, filter)))?;
+                Some(Bson::Array(vec![first.clone()]))
+            }),
+            FieldProjection::Computed(e) => {
+                if let Some(v) = e.eval(doc) {
+                    insert_path(&mut out, &segments(path), v);
+                }
+            }
+            FieldProjection::Include | FieldProjection::Exclude => {}
+        }
+    }
+    out
+}
+
+/// Keep only `paths` (dotted paths allowed), without `_id` unless listed.
+pub(crate) fn project_fields(doc: &BsonDocument, paths: &[String]) -> BsonDocument {
+    let mut out = BsonDocument::new();
+    for path in paths.iter().take(MAX_PROJECTION_FIELDS) {
+        copy_path(doc, &segments(path), &mut out);
+    }
+    out
+}
+
+/// Set `path` in `doc` to `value`, creating (or replacing non-document values with) the
+/// enclosing sub-documents.
+pub(crate) fn insert_path(doc: &mut BsonDocument, path: &[&str], value: Bson) {
+    let Some((head, rest)) = path.split_first() else { return };
+    if rest.is_empty() {
+        doc.insert(*head, value);
+        return;
+    }
+    if !matches!(doc.get(*head), Some(Bson::Document(_))) {
+        doc.insert(*head, BsonDocument::new());
+    }
+    if let Some(Bson::Document(sub)) = doc.get_mut(*head) {
+        insert_path(sub, rest, value);
+    }
+}
+
+pub(crate) fn segments(path: &str) -> Vec<&str> {
+    path.split('.').collect()
+}
+
+/// Copy the value at `path` from `src` into `out`, creating the enclosing sub-documents.
+/// Through an array, each sub-document element is projected in place and other elements
+/// are dropped, so repeated calls for sibling paths line up element by element.
+fn copy_path(src: &BsonDocument, path: &[&str], out: &mut BsonDocument) {
+    let Some((head, rest)) = path.split_first() else { return };
+    let Some(v) = src.get(*head) else { return };
+    if rest.is_empty() {
+        out.insert(*head, v.clone());
+        return;
+    }
+    match v {
+        Bson::Document(d) => {
+            if !matches!(out.get(*head), Some(Bson::Document(_))) {
+                out.insert(*head, BsonDocument::new());
+            }
+            if let Some(Bson::Document(o)) = out.get_mut(*head) {
+                copy_path(d, rest, o);
+            }
+        }
+        Bson::Array(items) => {
+            let docs: Vec<&BsonDocument> = items
+                .iter()
+                .filter_map(|e| match e {
+                    Bson::Document(d) => Some(d),
+                    _ => None,
+                })
+                .collect();
+            if !matches!(out.get(*head), Some(Bson::Array(a)) if a.len() == docs.len()) {
+                let empty = docs.iter().map(|_| Bson::Document(BsonDocument::new())).collect();
+                out.insert(*head, Bson::Array(empty));
+            }
+            if let Some(Bson::Array(o)) = out.get_mut(*head) {
+                for (d, slot) in docs.into_iter().zip(o.iter_mut()) {
+                    if let Bson::Document(slot) = slot {
+                        copy_path(d, rest, slot);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remove_path(doc: &mut BsonDocument, path: &[&str]) {
+    let Some((head, rest)) = path.split_first() else { return };
+    if rest.is_empty() {
+        doc.remove(*head);
+        return;
+    }
+    match doc.get_mut(*head) {
+        Some(Bson::Document(d)) => remove_path(d, rest),
+        Some(Bson::Array(items)) => {
+            for item in items {
+                if let Bson::Document(d) = item {
+                    remove_path(d, rest);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace the value at `path` with `f(value)`, removing the field when `f` returns `None`.
+fn map_path(doc: &mut BsonDocument, path: &[&str], f: &dyn Fn(&Bson) -> Option<Bson>) {
+    let Some((head, rest)) = path.split_first() else { return };
+    if rest.is_empty() {
+        if let Some(v) = doc.get(*head) {
+            match f(v) {
+                Some(next) => doc.insert(*head, next),
+                None => doc.remove(*head),
+            };
+        }
+        return;
+    }
+    match doc.get_mut(*head) {
+        Some(Bson::Document(d)) => map_path(d, rest, f),
+        Some(Bson::Array(items)) => {
+            for item in items {
+                if let Bson::Document(d) = item {
+                    map_path(d, rest, f);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn slice(v: &Bson, skip: i64, limit: Option<usize>) -> Bson {
+    let Bson::Array(items) = v else { return v.clone() };
+    let len = items.len();
+    let magnitude = usize::try_from(skip.unsigned_abs()).unwrap_or(usize::MAX);
+    let start = if skip < 0 { len.saturating_sub(magnitude) } else { magnitude.min(len) };
+    let end = limit.map_or(len, |l| start.saturating_add(l).min(len));
+    Bson::Array(items[start..end].to_vec())
+}
@@ -0,0 +1,357 @@
+use crate::document::Document;
+use bincode::config::standard;
+use bincode::serde::{decode_from_slice, encode_to_vec};
+use bson::{Bson, Document as BsonDocument};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use super::collation::Collation;
+use super::eval::compare_docs_collated;
+use super::types::{MAX_LIMIT, SortSpec};
+
+/// Bytes of matching documents a sort buffers before spilling a sorted run to disk.
+pub(crate) const DEFAULT_SORT_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Sorts needing at most this many leading results keep them in a bounded heap.
+const TOP_K_MAX: usize = MAX_LIMIT;
+
+/// Sorted documents, either held in memory or merged lazily from spilled runs.
+pub(crate) enum Sorted {
+    Memory(std::vec::IntoIter<Document>),
+    Merge(Merge),
+}
+
+impl Iterator for Sorted {
+    type Item = Document;
+    fn next(&mut self) -> Option<Document> {
+        match self {
+            Self::Memory(docs) => docs.next(),
+            Self::Merge(m) => m.next(),
+        }
+    }
+}
+
+/// Sort `docs` by `sort`, keeping only the first `k` results.
+///
+/// Small `k` uses a bounded heap, so memory is O(k) whatever the input size, as long as the
+/// heap stays within `budget` bytes (estimated). Otherwise documents are buffered up to
+/// `budget` bytes, then sorted, cut to `k` and written to a temp file; the runs are merged
+/// lazily as the result is read. Ties are broken by document id, so the order is total.
+pub(crate) fn sort_docs(
+    docs: impl Iterator<Item = Document>,
+    sort: &[SortSpec],
+    collation: Option<&Collation>,
+    k: usize,
+    budget: usize,
+) -> Sorted {
+    sort_docs_spilling_with(docs, sort, collation, k, budget, spill)
+}
+
+/// [`sort_docs`] writing its runs through `spill`, so tests can make spilling fail.
+fn sort_docs_spilling_with(
+    docs: impl Iterator<Item = Document>,
+    sort: &[SortSpec],
+    collation: Option<&Collation>,
+    k: usize,
+    budget: usize,
+    mut spill: impl FnMut(&[Document]) -> io::Result<BufReader<File>>,
+) -> Sorted {
+    if k == 0 {
+        return Sorted::Memory(Vec::new().into_iter());
+    }
+    let by = SortBy { sort, collation };
+    let mut docs = docs;
+    let mut held = Vec::new();
+    if k <= TOP_K_MAX {
+        match top_k(&mut docs, by, k, budget) {
+            TopK::Done(out) => return Sorted::Memory(out.into_iter()),
+            // The heap outgrew the budget: its documents start the first run
+            TopK::OverBudget(docs) => held = docs,
+        }
+    }
+    let mut runs = Vec::new();
+    let mut buf: Vec<Document> = Vec::new();
+    let mut bytes = 0usize;
+    let mut spilling = true;
+    for doc in held.into_iter().chain(docs) {
+        bytes += approx_size(&doc.data.0);
+        buf.push(doc);
+        if spilling && bytes > budget {
+            sort_run(&mut buf, by, k);
+            match spill(&buf) {
+                Ok(file) => {
+                    runs.push(Run::File(file));
+                    buf.clear();
+                    bytes = 0;
+                }
+                // Keep going in memory rather than failing the query, without retrying: the
+                // buffer stays over budget, so every later document would try again
+                Err(e) => {
+                    log::warn!("sort spill failed, continuing in memory: {e}");
+                    spilling = false;
+                }
+            }
+        }
+    }
+    sort_run(&mut buf, by, k);
+    if runs.is_empty() {
+        return Sorted::Memory(buf.into_iter());
+    }
+    runs.push(Run::Memory(buf.into_iter()));
+    Sorted::Merge(Merge::new(runs, sort.to_vec(), collation.cloned()))
+}
+
+/// A sort spec and the collation its string comparisons use.
+#[derive(Clone, Copy)]
+struct SortBy<'a> {
+    sort: &'a [SortSpec],
+    collation: Option<&'a Collation>,
+}
+
+impl SortBy<'_> {
+    /// The order sorted queries return documents in: `sort`, then document id. The id makes
+    /// the order total, which resume tokens rely on.
+    fn order(self, a: &Document, b: &Document) -> Ordering {
+        compare_docs_collated(&a.data.0, &b.data.0, self.sort, self.collation)
+            .then_with(|| a.id.cmp(&b.id))
+    }
+}
+
+struct Ranked<'a> {
+    doc: Document,
+    by: SortBy<'a>,
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.by.order(&self.doc, &other.doc)
+    }
+}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+enum TopK {
+    /// The first `k` documents, sorted.
+    Done(Vec<Document>),
+    /// The heap passed the byte budget; these are its documents, unsorted.
+    OverBudget(Vec<Document>),
+}
+
+/// The first `k` of `docs` in sort order, via a max-heap whose top is evicted by any
+/// smaller newcomer. Stops as soon as the heap holds more than `budget` bytes (estimated),
+/// leaving the rest of `docs` unread.
+fn top_k(
+    docs: &mut impl Iterator<Item = Document>,
+    by: SortBy<'_>,
+    k: usize,
+    budget: usize,
+) -> TopK {
+    let mut heap: BinaryHeap<Ranked<'_>> = BinaryHeap::with_capacity(k.min(1024) + 1);
+    let mut bytes = 0usize;
+    for doc in docs {
+        let size = approx_size(&doc.data.0);
+        let cand = Ranked { doc, by };
+        if heap.len() < k {
+            heap.push(cand);
+            bytes += size;
+        } else if let Some(mut top) = heap.peek_mut()
+            && cand < *top
+        {
+            bytes = bytes - approx_size(&top.doc.data.0) + size;
+            *top = cand;
+        }
+        if bytes > budget {
+            return TopK::OverBudget(heap.into_iter().map(|r| r.doc).collect());
+        }
+    }
+    TopK::Done(heap.into_sorted_vec().into_iter().map(|r| r.doc).collect())
+}
+
+/// Sort a run and drop everything past `k`: it can never reach the result.
+fn sort_run(run: &mut Vec<Document>, by: SortBy<'_>, k: usize) {
+    run.sort_by(|a, b| by.order(a, b));
+    run.truncate(k);
+}
+
+/// Write a sorted run as length-prefixed bincode frames to an anonymous temp file.
+fn spill(run: &[Document]) -> io::Result<BufReader<File>> {
+    let mut w = BufWriter::new(tempfile::tempfile()?);
+    for entry in run {
+        let encoded = encode_to_vec(entry, standard()).map_err(io::Error::other)?;
+        w.write_all(&(crate::utils::num::usize_to_u64(encoded.len())).to_be_bytes())?;
+        w.write_all(&encoded)?;
+    }
+    let mut file = w.into_inner().map_err(io::IntoInnerError::into_error)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(BufReader::new(file))
+}
+
+enum Run {
+    File(BufReader<File>),
+    Memory(std::vec::IntoIter<Document>),
+}
+
+impl Run {
+    fn next(&mut self) -> Option<Document> {
+        match self {
+            Self::Memory(it) => it.next(),
+            Self::File(r) => match read_frame(r) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    log::error!("sort run read failed: {e}");
+                    None
+                }
+            },
+        }
+    }
+}
+
+fn read_frame(r: &mut BufReader<File>) -> io::Result<Option<Document>> {
+    let mut len = [0u8; 8];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = crate::utils::num::u64_to_usize(u64::from_be_bytes(len))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "sort frame too large"))?;
+    let mut bytes = vec![0u8; len];
+    r.read_exact(&mut bytes)?;
+    let (entry, _) = decode_from_slice(&bytes, standard()).map_err(io::Error::other)?;
+    Ok(Some(entry))
+}
+
+/// K-way merge over sorted runs. Runs are few (input size / budget), so the smallest head
+/// is found by a linear scan.
+pub(crate) struct Merge {
+    runs: Vec<Run>,
+    heads: Vec<Option<Document>>,
+    sort: Vec<SortSpec>,
+    collation: Option<Collation>,
+}
+
+impl Merge {
+    fn new(mut runs: Vec<Run>, sort: Vec<SortSpec>, collation: Option<Collation>) -> Self {
+        let heads = runs.iter_mut().map(Run::next).collect();
+        Self { runs, heads, sort, collation }
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Document;
+    fn next(&mut self) -> Option<Document> {
+        let by = SortBy { sort: &self.sort, collation: self.collation.as_ref() };
+        let mut best: Option<usize> = None;
+        for (i, head) in self.heads.iter().enumerate() {
+            let Some(doc) = head else { continue };
+            let better = best
+                .and_then(|b| self.heads[b].as_ref())
+                .is_none_or(|b| by.order(doc, b) == Ordering::Less);
+            if better {
+                best = Some(i);
+            }
+        }
+        let i = best?;
+        let next = self.runs[i].next();
+        std::mem::replace(&mut self.heads[i], next)
+    }
+}
+
+/// Rough in-memory footprint of a document, for the sort budget.
+fn approx_size(doc: &BsonDocument) -> usize {
+    doc.iter().map(|(k, v)| k.len() + 1 + value_size(v)).sum::<usize>() + 64
+}
+
+fn value_size(v: &Bson) -> usize {
+    match v {
+        Bson::String(s) => s.len() + 5,
+        Bson::Document(d) => approx_size(d),
+        Bson::Array(items) => items.iter().map(|v| value_size(v) + 2).sum::<usize>() + 5,
+        Bson::Binary(b) => b.bytes.len() + 5,
+        _ => 16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::DocumentType;
+    use crate::query::Order;
+    use bson::doc;
+
+    fn docs(vals: &[i32]) -> Vec<Document> {
+        vals.iter()
+            .enumerate()
+            .map(|(i, v)| {
+                Document::new(
+                    doc! {"v": *v, "i": i32::try_from(i).unwrap()},
+                    DocumentType::Persistent,
+                )
+            })
+            .collect()
+    }
+
+    fn order(out: impl Iterator<Item = Document>) -> Vec<(i32, i32)> {
+        out.map(|d| (d.data.0.get_i32("v").unwrap(), d.data.0.get_i32("i").unwrap())).collect()
+    }
+
+    #[test]
+    fn heap_spill_and_memory_sorts_agree_and_break_ties_by_id() {
+        let vals: Vec<i32> = (0..500).map(|i| (i * 7919) % 37).collect();
+        let input = docs(&vals);
+        let sort = [SortSpec { field: "v".into(), order: Order::Asc }];
+        let mut by_key = input.clone();
+        by_key.sort_by(|a, b| {
+            let v = |d: &Document| d.data.0.get_i32("v").unwrap();
+            v(a).cmp(&v(b)).then_with(|| a.id.cmp(&b.id))
+        });
+        let expected = order(by_key.into_iter());
+
+        let heap = order(sort_docs(input.clone().into_iter(), &sort, None, 25, usize::MAX));
+        assert_eq!(heap, expected[..25]);
+        let memory =
+            order(sort_docs(input.clone().into_iter(), &sort, None, TOP_K_MAX + 1, usize::MAX));
+        assert_eq!(memory, expected);
+        // A tiny budget spills a run every few documents
+        let spilled = sort_docs(input.clone().into_iter(), &sort, None, TOP_K_MAX + 1, 400);
+        assert!(matches!(spilled, Sorted::Merge(ref m) if m.runs.len() > 10));
+        assert_eq!(order(spilled), expected);
+        // The heap honours the budget too
+        let spilled = sort_docs(input.into_iter(), &sort, None, 25, 400);
+        assert!(matches!(spilled, Sorted::Merge(ref m) if m.runs.len() > 10));
+        assert_eq!(order(spilled.take(25)), expected[..25]);
+    }
+
+    #[test]
+    fn failed_spill_is_not_retried_and_the_sort_finishes_in_memory() {
+        let vals: Vec<i32> = (0..500).map(|i| (i * 7919) % 37).collect();
+        let input = docs(&vals);
+        let sort = [SortSpec { field: "v".into(), order: Order::Asc }];
+        let expected =
+            order(sort_docs(input.clone().into_iter(), &sort, None, TOP_K_MAX + 1, usize::MAX));
+
+        let mut attempts = 0;
+        let out =
+            sort_docs_spilling_with(input.into_iter(), &sort, None, TOP_K_MAX + 1, 400, |_| {
+                attempts += 1;
+                Err(io::Error::other("no space left"))
+            });
+        assert!(matches!(out, Sorted::Memory(_)));
+        assert_eq!(order(out), expected);
+        assert_eq!(attempts, 1);
+    }
+}
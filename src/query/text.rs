@@ -0,0 +1,399 @@
+//! Full-text search.
+//!
+//! Text is split into lowercase alphanumeric words; common English stop words are dropped
+//! and, when enabled, a light suffix-stripping stemmer maps `"tickets"` and `"ticketing"` to
+//! `"ticket"`. A text index stores the resulting terms for the fields it covers, and a
+//! `$text` query is analysed the same way, so only a document's own contents decide whether
+//! it matches and how it scores.
+
+use crate::collection::Collection;
+use crate::errors::DbError;
+use crate::index::{IndexImpl, IndexManager};
+use bson::{Bson, Document as BsonDocument};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use super::eval::path_values;
+use super::types::{Filter, MAX_IN_SET};
+
+/// Where a text query leaves each matching document's relevance score while it is sorted
+/// and projected: sort on this field, or project `{"$meta": "textScore"}`. It is removed
+/// before documents are returned.
+pub(crate) const TEXT_SCORE: &str = "$textScore";
+
+/// Longest `$search` string accepted, in bytes.
+const MAX_SEARCH_LEN: usize = 4096;
+
+/// Words too common to be worth indexing.
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "am", "an", "and", "any", "are", "as", "at", "be",
+    "been", "before", "but", "by", "can", "could", "did", "do", "does", "for", "from", "had",
+    "has", "have", "he", "her", "him", "his", "how", "i", "if", "in", "into", "is", "it", "its",
+    "me", "my", "no", "not", "of", "on", "or", "our", "she", "so", "than", "that", "the", "their",
+    "them", "then", "there", "these", "they", "this", "those", "to", "too", "us", "was", "we",
+    "were", "what", "when", "where", "which", "who", "will", "with", "would", "you", "your",
+];
+
+/// Text index settings, stored in the index descriptor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextOptions {
+    /// Weight of each indexed field (1 when not listed). Fields listed here are indexed
+    /// alongside the index's own field.
+    #[serde(default)]
+    pub weights: BTreeMap<String, u32>,
+    /// Reduce words to a common stem, so `"crashes"` matches `"crashed"`.
+    #[serde(default)]
+    pub stemming: bool,
+    /// Drop common English words such as `"the"` (the default).
+    #[serde(default = "yes")]
+    pub stop_words: bool,
+}
+
+const fn yes() -> bool {
+    true
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self { weights: BTreeMap::new(), stemming: false, stop_words: true }
+    }
+}
+
+impl TextOptions {
+    /// Index `field` too, with `weight`.
+    #[must_use]
+    pub fn with_weight(mut self, field: &str, weight: u32) -> Self {
+        self.weights.insert(field.to_string(), weight);
+        self
+    }
+    #[must_use]
+    pub const fn with_stemming(mut self, stemming: bool) -> Self {
+        self.stemming = stemming;
+        self
+    }
+}
+
+/// How a text index turns documents into terms.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Analyzer {
+    /// The field the index is registered under.
+    pub index: String,
+    /// Indexed fields and their weights; empty means every string in the document.
+    fields: Vec<(String, f64)>,
+    stemming: bool,
+    stop_words: bool,
+}
+
+impl Analyzer {
+    pub(crate) fn new(field: &str, opts: &TextOptions) -> Self {
+        let weight = |f: &str| f64::from(opts.weights.get(f).copied().unwrap_or(1));
+        let mut fields = vec![(field.to_string(), weight(field))];
+        fields.extend(opts.weights.keys().filter(|f| *f != field).map(|f| (f.clone(), weight(f))));
+        Self {
+            index: field.to_string(),
+            fields,
+            stemming: opts.stemming,
+            stop_words: opts.stop_words,
+        }
+    }
+
+    /// Used by `$text` filters evaluated outside a collection with a text index.
+    fn wildcard() -> Self {
+        Self { index: String::new(), fields: Vec::new(), stemming: false, stop_words: true }
+    }
+
+    /// Every word of `s`, stop words included, as the index spells it.
+    fn words(&self, s: &str) -> Vec<String> {
+        s.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| {
+                let w = w.to_lowercase();
+                // Stop words stay whole so they are still recognised after stemming
+                if self.stemming && STOP_WORDS.binary_search(&w.as_str()).is_err() {
+                    stem(w)
+                } else {
+                    w
+                }
+            })
+            .collect()
+    }
+
+    fn is_stop_word(&self, w: &str) -> bool {
+        self.stop_words && STOP_WORDS.binary_search(&w).is_ok()
+    }
+
+    /// The indexed terms of `s`.
+    fn terms(&self, s: &str) -> Vec<String> {
+        self.words(s).into_iter().filter(|w| !self.is_stop_word(w)).collect()
+    }
+
+    /// Each indexed field's weight and the words of every string in it.
+    fn field_words(&self, doc: &BsonDocument) -> Vec<(f64, Vec<Vec<String>>)> {
+        let mut strings = Vec::new();
+        if self.fields.is_empty() {
+            doc.values().for_each(|v| collect_strings(v, &mut strings));
+            return vec![(1.0, strings.iter().map(|s| self.words(s)).collect())];
+        }
+        self.fields
+            .iter()
+            .map(|(field, weight)| {
+                strings.clear();
+                for v in path_values(doc, field) {
+                    collect_strings(v, &mut strings);
+                }
+                (*weight, strings.iter().map(|s| self.words(s)).collect())
+            })
+            .collect()
+    }
+
+    /// The distinct terms a text index stores for `doc`.
+    pub(crate) fn doc_terms(&self, doc: &BsonDocument) -> BTreeSet<String> {
+        self.field_words(doc)
+            .into_iter()
+            .flat_map(|(_, strings)| strings.into_iter().flatten())
+            .filter(|w| !self.is_stop_word(w))
+            .collect()
+    }
+}
+
+fn collect_strings<'a>(v: &'a Bson, out: &mut Vec<&'a str>) {
+    match v {
+        Bson::String(s) => out.push(s),
+        Bson::Array(items) => items.iter().for_each(|v| collect_strings(v, out)),
+        Bson::Document(d) => d.values().for_each(|v| collect_strings(v, out)),
+        _ => {}
+    }
+}
+
+/// Strip common English suffixes: plurals, `-ing`, `-ed` and `-ly`. Stems keep at least
+/// three letters, and a doubled final consonant left by `-ing`/`-ed` is undoubled.
+fn stem(mut w: String) -> String {
+    if !w.is_ascii() || w.len() <= 3 {
+        return w;
+    }
+    let strip = |w: &mut String, suffix: &str, add: &str| {
+        if w.len() + add.len() >= suffix.len() + 3 && w.ends_with(suffix) {
+            w.truncate(w.len() - suffix.len());
+            w.push_str(add);
+            true
+        } else {
+            false
+        }
+    };
+    if !(strip(&mut w, "sses", "ss") || strip(&mut w, "ies", "y")) {
+        let sibilant = ["ches", "shes", "xes", "zes"].iter().any(|s| w.ends_with(s));
+        if sibilant {
+            strip(&mut w, "es", "");
+        } else if !(w.ends_with("ss") || w.ends_with("us") || w.ends_with("is")) {
+            strip(&mut w, "s", "");
+        }
+    }
+    if strip(&mut w, "ing", "") || strip(&mut w, "ed", "") {
+        let b = w.as_bytes();
+        let n = b.len();
+        if n > 3 && b[n - 1] == b[n - 2] && !b"aeioulsz".contains(&b[n - 1]) {
+            w.pop();
+        }
+    } else {
+        strip(&mut w, "ly", "");
+    }
+    w
+}
+
+/// A parsed `$text` search: `coffee "flat white" -decaf`.
+///
+/// Without phrases a document matches when it contains any of the words; with phrases it
+/// must contain every phrase (the words then only add to the score). A word or phrase
+/// prefixed with `-` must not appear.
+#[derive(Debug, Clone)]
+pub struct TextSearch {
+    /// The `$search` string as given.
+    pub search: String,
+    words: Vec<String>,
+    phrases: Vec<String>,
+    negated: Vec<String>,
+    analyzer: Option<Arc<Analyzer>>,
+}
+
+impl TextSearch {
+    /// Parse a `$search` string.
+    ///
+    /// # Errors
+    /// Returns `DbError::QueryError` when the string is longer than 4096 bytes, has an
+    /// unterminated quote, or holds no word or phrase to search for.
+    pub fn parse(search: &str) -> Result<Self, DbError> {
+        if search.len() > MAX_SEARCH_LEN {
+            return Err(DbError::QueryError(format!(
+                "$search is longer than {MAX_SEARCH_LEN} bytes"
+            )));
+        }
+        let mut out = Self {
+            search: search.to_string(),
+            words: Vec::new(),
+            phrases: Vec::new(),
+            negated: Vec::new(),
+            analyzer: None,
+        };
+        let mut rest = search.trim_start();
+        while !rest.is_empty() {
+            let (negate, body) = match rest.strip_prefix('-') {
+                Some(b) if b.starts_with(|c: char| !c.is_whitespace()) => (true, b),
+                _ => (false, rest),
+            };
+            let (item, tail, phrase) = if let Some(quoted) = body.strip_prefix('"') {
+                let end = quoted
+                    .find('"')
+                    .ok_or_else(|| DbError::QueryError("unterminated phrase in $search".into()))?;
+                (&quoted[..end], &quoted[end + 1..], true)
+            } else {
+                let end = body.find(char::is_whitespace).unwrap_or(body.len());
+                (&body[..end], &body[end..], false)
+            };
+            match (negate, phrase) {
+                (true, _) => out.negated.push(item.to_string()),
+                (false, true) => out.phrases.push(item.to_string()),
+                (false, false) => out.words.push(item.to_string()),
+            }
+            rest = tail.trim_start();
+        }
+        if out.words.len() + out.phrases.len() + out.negated.len() > MAX_IN_SET {
+            return Err(DbError::QueryError(format!(
+                "$search has more than {MAX_IN_SET} words and phrases"
+            )));
+        }
+        if out.words.is_empty() && out.phrases.is_empty() {
+            return Err(DbError::QueryError("$search needs a word or phrase to find".into()));
+        }
+        Ok(out)
+    }
+
+    fn analyzer(&self) -> Cow<'_, Analyzer> {
+        self.analyzer.as_deref().map_or_else(|| Cow::Owned(Analyzer::wildcard()), Cow::Borrowed)
+    }
+
+    /// The query words as index terms, including those of the phrases.
+    fn query_terms(&self, a: &Analyzer) -> BTreeSet<String> {
+        self.words.iter().chain(&self.phrases).flat_map(|w| a.terms(w)).collect()
+    }
+
+    /// Whether `doc` satisfies the search.
+    pub(crate) fn matches(&self, doc: &BsonDocument) -> bool {
+        let a = self.analyzer();
+        let fields = a.field_words(doc);
+        let strings = || fields.iter().flat_map(|(_, s)| s);
+        let has_term = |t: &String| strings().any(|words| words.contains(t));
+        let has_phrase = |p: &str| {
+            let p = a.words(p);
+            !p.is_empty() && strings().any(|words| words.windows(p.len()).any(|w| w == p))
+        };
+        let found = if self.phrases.is_empty() {
+            self.words.iter().flat_map(|w| a.terms(w)).any(|t| has_term(&t))
+        } else {
+            self.phrases.iter().all(|p| has_phrase(p))
+        };
+        found && !self.negated.iter().any(|n| has_phrase(n))
+    }
+
+    /// The relevance of `doc`: each query term found in a field adds the field's weight,
+    /// scaled from 0.5 to 1 by the share of the field's words it makes up.
+    #[must_use]
+    pub fn score(&self, doc: &BsonDocument) -> f64 {
+        let a = self.analyzer();
+        let terms = self.query_terms(&a);
+        let mut score = 0.0;
+        for (weight, strings) in a.field_words(doc) {
+            let total: usize = strings.iter().map(Vec::len).sum();
+            for t in &terms {
+                let n = strings.iter().flatten().filter(|w| *w == t).count();
+                if n > 0 {
+                    score += weight * (0.5 + 0.5 * n as f64 / total as f64);
+                }
+            }
+        }
+        score
+    }
+
+    /// The text index this search was bound to and the terms a matching document holds at
+    /// least one of, or `None` when the index cannot narrow the search.
+    pub(crate) fn index_terms(&self) -> Option<(&str, Vec<String>)> {
+        let a = self.analyzer.as_deref()?;
+        let terms: BTreeSet<String> = if self.phrases.is_empty() {
+            self.words.iter().flat_map(|w| a.terms(w)).collect()
+        } else {
+            // Every phrase must appear, so one with an indexed term is enough to narrow by
+            let p = self.phrases.iter().map(|p| a.terms(p)).find(|t| !t.is_empty())?;
+            p.into_iter().collect()
+        };
+        Some((&a.index, terms.into_iter().collect()))
+    }
+
+    fn bound(&self, analyzer: &Arc<Analyzer>) -> Self {
+        Self { analyzer: Some(Arc::clone(analyzer)), ..self.clone() }
+    }
+}
+
+/// `filter` with its `$text` searches analysed like the collection's text index. Without a
+/// text index they search every string in the document with the default settings.
+pub(crate) fn bind<'f>(col: &Collection, filter: &'f Filter) -> Cow<'f, Filter> {
+    if !has_text(filter) {
+        return Cow::Borrowed(filter);
+    }
+    bind_in(&col.indexes.read(), filter)
+}
+
+/// [`bind`] for a caller already holding the collection's index lock.
+pub(crate) fn bind_in<'f>(mgr: &IndexManager, filter: &'f Filter) -> Cow<'f, Filter> {
+    if !has_text(filter) {
+        return Cow::Borrowed(filter);
+    }
+    let analyzer = mgr.indexes.values().find_map(|i| match i {
+        IndexImpl::Text(t) => Some(Arc::new(t.analyzer.clone())),
+        _ => None,
+    });
+    match analyzer {
+        Some(a) => Cow::Owned(bind_to(filter, &a)),
+        None => Cow::Borrowed(filter),
+    }
+}
+
+fn has_text(filter: &Filter) -> bool {
+    match filter {
+        Filter::Text(_) => true,
+        Filter::And(fs) | Filter::Or(fs) => fs.iter().any(has_text),
+        Filter::Not(f) | Filter::Collate { filter: f, .. } => has_text(f),
+        _ => false,
+    }
+}
+
+fn bind_to(filter: &Filter, a: &Arc<Analyzer>) -> Filter {
+    match filter {
+        Filter::Text(t) => Filter::Text(t.bound(a)),
+        Filter::And(fs) => Filter::And(fs.iter().map(|f| bind_to(f, a)).collect()),
+        Filter::Or(fs) => Filter::Or(fs.iter().map(|f| bind_to(f, a)).collect()),
+        Filter::Not(f) => Filter::Not(Box::new(bind_to(f, a))),
+        Filter::Collate { collation, filter } => {
+            Filter::Collate { collation: collation.clone(), filter: Box::new(bind_to(filter, a)) }
+        }
+        other => other.clone(),
+    }
+}
+
+/// The `$text` search every match of `filter` satisfies (one at the top level or under
+/// `$and`), which gives their scores.
+pub(crate) fn scoring_search(filter: &Filter) -> Option<&TextSearch> {
+    match filter {
+        Filter::Text(t) => Some(t),
+        Filter::And(fs) => fs.iter().find_map(scoring_search),
+        Filter::Collate { filter, .. } => scoring_search(filter),
+        _ => None,
+    }
+}
+
+/// Store `doc`'s score under [`TEXT_SCORE`].
+pub(crate) fn add_score(doc: &mut BsonDocument, search: &TextSearch) {
+    let score = search.score(doc);
+    doc.insert(TEXT_SCORE, score);
+}
@@ -0,0 +1,212 @@
+use crate::document::Document;
+use crate::errors::DbError;
+use crate::types::DocumentId;
+use crate::utils::fsutil::create_secure;
+use bson::{Bson, Document as BsonDocument};
+use hmac::{Hmac, Mac};
+use p256::elliptic_curve::rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+use std::cmp::Ordering;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::collation::Collation;
+use super::eval::{compare_sort_values, sort_value};
+use super::types::{MAX_SORT_FIELDS, Order, SortSpec};
+
+const MAC_LEN: usize = 32;
+
+/// The per-database key that signs resume tokens.
+///
+/// A persistent key is read from (or created at) its file on first use, so tokens stay valid
+/// across restarts; an ephemeral key lives only in memory. A key file that cannot be read or
+/// created is reported by every signing and verification rather than replaced.
+pub struct TokenKey {
+    path: Option<PathBuf>,
+    key: OnceLock<Result<[u8; 32], String>>,
+}
+
+impl TokenKey {
+    pub(crate) fn persistent(path: PathBuf) -> Self {
+        Self { path: Some(path), key: OnceLock::new() }
+    }
+
+    pub(crate) fn ephemeral() -> Self {
+        Self { path: None, key: OnceLock::new() }
+    }
+
+    fn get(&self) -> Result<&[u8; 32], DbError> {
+        let key = self.key.get_or_init(|| match &self.path {
+            Some(p) => load_or_create(p),
+            None => Ok(random_key()),
+        });
+        key.as_ref().map_err(|e| DbError::Io(e.clone()))
+    }
+
+    fn mac(&self, payload: &[u8]) -> Result<Hmac<Sha256>, DbError> {
+        // HMAC accepts keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.get()?).expect("hmac key");
+        mac.update(payload);
+        Ok(mac)
+    }
+}
+
+fn random_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// Read the key at `path`, or create it: a fresh key is written to a private temp file and
+/// linked into place, so no reader sees a partial key and a concurrent creator's key wins.
+fn load_or_create(path: &Path) -> Result<[u8; 32], String> {
+    let read = || match std::fs::read(path) {
+        Ok(bytes) => <[u8; 32]>::try_from(bytes).map(Some).map_err(|b| {
+            format!(
+                "resume token key {} is corrupt ({} bytes); remove it to issue a new key",
+                path.display(),
+                b.len()
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("resume token key {} unreadable: {e}", path.display())),
+    };
+    if let Some(key) = read()? {
+        return Ok(key);
+    }
+    let key = random_key();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".tmp-{}-{}", std::process::id(), hex::encode(random_key())));
+    let tmp = PathBuf::from(tmp);
+    let written = create_secure(&tmp).and_then(|mut f| {
+        f.write_all(&key)?;
+        f.sync_all()
+    });
+    let linked = written.and_then(|()| std::fs::hard_link(&tmp, path));
+    let _ = std::fs::remove_file(&tmp);
+    match linked {
+        Ok(()) => Ok(key),
+        // Another process created it first; use theirs
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            read()?.ok_or_else(|| format!("resume token key {} vanished", path.display()))
+        }
+        Err(e) => Err(format!("resume token key not saved to {}: {e}", path.display())),
+    }
+}
+
+/// A position in a sorted result: the sort-key values of the last document returned, and its
+/// id to break ties.
+#[derive(Debug, Clone)]
+pub(crate) struct ResumePoint {
+    /// One entry per sort field; `None` where the document had no value.
+    values: Vec<Option<Bson>>,
+    id: DocumentId,
+}
+
+impl ResumePoint {
+    pub(crate) fn of(doc: &Document, sort: &[SortSpec], collation: Option<&Collation>) -> Self {
+        let values =
+            sort_fields(sort).map(|s| sort_value(&doc.data.0, s, collation).cloned()).collect();
+        Self { values, id: doc.id.clone() }
+    }
+
+    /// Whether `doc` sorts strictly after this point.
+    pub(crate) fn precedes(
+        &self,
+        doc: &Document,
+        sort: &[SortSpec],
+        collation: Option<&Collation>,
+    ) -> bool {
+        sort_fields(sort)
+            .zip(&self.values)
+            .map(|(s, v)| {
+                let mine = sort_value(&doc.data.0, s, collation);
+                compare_sort_values(mine, v.as_ref(), s.order, collation)
+            })
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or_else(|| doc.id.cmp(&self.id))
+            == Ordering::Greater
+    }
+
+    /// The sort-key value of the leading sort field, if the document had one.
+    pub(crate) fn leading_value(&self) -> Option<&Bson> {
+        self.values.first()?.as_ref()
+    }
+
+    /// Sign this point for `sort` (under `collation`) as an opaque, URL-safe token.
+    pub(crate) fn encode(
+        &self,
+        key: &TokenKey,
+        sort: &[SortSpec],
+        collation: Option<&Collation>,
+    ) -> Result<String, DbError> {
+        // Keyed by position, so a missing value is an absent key rather than a null
+        let mut k = BsonDocument::new();
+        for (i, v) in self.values.iter().enumerate() {
+            if let Some(v) = v {
+                k.insert(i.to_string(), v.clone());
+            }
+        }
+        let payload = bson::doc! {
+            "s": sort_fingerprint(sort, collation),
+            "k": k,
+            "i": self.id.0.to_string(),
+        };
+        let mut bytes = Vec::new();
+        // Serializing a document built from valid BSON values cannot fail
+        let _ = payload.to_writer(&mut bytes);
+        let tag = key.mac(&bytes)?.finalize().into_bytes();
+        bytes.extend_from_slice(&tag);
+        Ok(hex::encode(bytes))
+    }
+
+    /// Verify and decode a token issued by [`ResumePoint::encode`] for the same sort and
+    /// collation.
+    pub(crate) fn decode(
+        token: &str,
+        key: &TokenKey,
+        sort: &[SortSpec],
+        collation: Option<&Collation>,
+    ) -> Result<Self, DbError> {
+        let invalid = || DbError::QueryError("invalid resume token".into());
+        let bytes = hex::decode(token).map_err(|_| invalid())?;
+        if bytes.len() <= MAC_LEN {
+            return Err(invalid());
+        }
+        let (payload, tag) = bytes.split_at(bytes.len() - MAC_LEN);
+        key.mac(payload)?.verify_slice(tag).map_err(|_| invalid())?;
+        let doc = BsonDocument::from_reader(payload).map_err(|_| invalid())?;
+        if doc.get("s") != Some(&sort_fingerprint(sort, collation)) {
+            return Err(DbError::QueryError("resume token was issued for a different sort".into()));
+        }
+        let k = doc.get_document("k").map_err(|_| invalid())?;
+        let values =
+            (0..sort_fields(sort).count()).map(|i| k.get(i.to_string()).cloned()).collect();
+        let id = doc
+            .get_str("i")
+            .ok()
+            .and_then(|s| uuid::Uuid::parse_str(s).ok())
+            .ok_or_else(invalid)?;
+        Ok(Self { values, id: DocumentId(id) })
+    }
+}
+
+fn sort_fields(sort: &[SortSpec]) -> impl Iterator<Item = &SortSpec> {
+    sort.iter().take(MAX_SORT_FIELDS)
+}
+
+/// The sort as `[[field, 1 | -1], ..]`, followed by the collation when there is one.
+fn sort_fingerprint(sort: &[SortSpec], collation: Option<&Collation>) -> Bson {
+    let mut parts: Vec<Bson> = sort
+        .iter()
+        .map(|s| {
+            let dir = if matches!(s.order, Order::Asc) { 1 } else { -1 };
+            Bson::Array(vec![Bson::String(s.field.clone()), Bson::Int32(dir)])
+        })
+        .collect();
+    if let Some(c) = collation {
+        parts.push(bson::serialize_to_bson(c).unwrap_or(Bson::Null));
+    }
+    Bson::Array(parts)
+}
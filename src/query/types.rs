@@ -1,12 +1,22 @@
 use bson::Bson;
 use serde::{Deserialize, Serialize};
 
+use super::collation::Collation;
+use super::expr::Expr;
+use super::geo::{GEO_DISTANCE, GeoOp};
+#[cfg(feature = "regex")]
+use super::pattern::RegexPattern;
+use super::text::{TEXT_SCORE, TextSearch};
+
 // Safety limits to prevent resource abuse
 pub(crate) const MAX_PATH_DEPTH: usize = 32;
 pub(crate) const MAX_IN_SET: usize = 1000;
 pub(crate) const MAX_SORT_FIELDS: usize = 8;
 pub(crate) const MAX_PROJECTION_FIELDS: usize = 64;
 pub(crate) const MAX_LIMIT: usize = 10_000;
+pub(crate) const MAX_PIPELINE_STAGES: usize = 64;
+pub(crate) const MAX_EXPR_DEPTH: usize = 32;
+pub(crate) const MAX_EXPR_STEPS: usize = 10_000;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Order {
@@ -20,18 +30,97 @@ pub struct SortSpec {
     pub order: Order,
 }
 
+impl SortSpec {
+    /// Most relevant first, for queries with a `$text` filter. Its field is `"$textScore"`.
+    #[must_use]
+    pub fn text_score() -> Self {
+        Self { field: TEXT_SCORE.to_string(), order: Order::Desc }
+    }
+
+    /// Nearest first, for queries with a `$near` filter. Its field is `"$geoNearDistance"`.
+    #[must_use]
+    pub fn geo_distance() -> Self {
+        Self { field: GEO_DISTANCE.to_string(), order: Order::Asc }
+    }
+}
+
+/// Which fields of a document `find` returns.
+///
+/// Paths may be dotted (`address.city`); through an array they apply to each sub-document
+/// element. A projection either includes fields (`{"a": 1}`, keeping only those plus `_id`)
+/// or excludes them (`{"password": 0}`); `_id` may be excluded from either kind. Parses from
+/// JSON with [`parse_projection_json`](super::parse_projection_json), and from a plain list
+/// of paths to include.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "super::parse::ProjectionSerde", into = "super::parse::ProjectionSerde")]
+pub struct Projection {
+    pub fields: Vec<(String, FieldProjection)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum FieldProjection {
+    Include,
+    Exclude,
+    /// `$slice`: keep `limit` elements (or all the rest) starting at `skip`; a negative
+    /// `skip` counts from the end. Other fields are kept as the rest of the projection says.
+    Slice {
+        skip: i64,
+        limit: Option<usize>,
+    },
+    /// `$elemMatch`: keep only the first sub-document element matching the filter, dropping
+    /// the field when none does. Implies inclusion.
+    ElemMatch(Filter),
+    /// A computed field: `{"total": {"$add": ["$price", "$tax"]}}` or `{"city": "$addr.city"}`.
+    /// Evaluated against the whole document and left out when missing. Implies inclusion.
+    Computed(Expr),
+}
+
+impl Projection {
+    /// Include exactly `paths`.
+    pub fn include<S: Into<String>>(paths: impl IntoIterator<Item = S>) -> Self {
+        Self { fields: paths.into_iter().map(|p| (p.into(), FieldProjection::Include)).collect() }
+    }
+
+    /// Whether only the listed fields are kept, rather than all but the excluded ones.
+    #[must_use]
+    pub fn is_inclusion(&self) -> bool {
+        self.fields.iter().any(|(path, p)| {
+            matches!(p, FieldProjection::ElemMatch(_) | FieldProjection::Computed(_))
+                || (path != "_id" && matches!(p, FieldProjection::Include))
+        })
+    }
+}
+
+impl From<Vec<String>> for Projection {
+    fn from(paths: Vec<String>) -> Self {
+        Self::include(paths)
+    }
+}
+
 /// Options for `find_docs`.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FindOptions {
-    pub projection: Option<Vec<String>>,
+    pub projection: Option<Projection>,
     pub sort: Option<Vec<SortSpec>>,
     pub limit: Option<usize>,
     pub skip: Option<usize>,
     #[serde(default)]
     pub timeout_ms: Option<u64>,
+    /// Bytes of matching documents a sort may hold, in its top-k heap or its buffer, before
+    /// spilling sorted runs to temp files; defaults to 64 MiB.
+    #[serde(default)]
+    pub sort_memory_limit: Option<usize>,
+    /// A resume token from [`Cursor::next_token`](super::Cursor::next_token): return only
+    /// documents after the one it was issued for, in the same sort.
+    #[serde(default)]
+    pub after: Option<String>,
+    /// Compare strings under this collation when filtering and sorting. Indexes on string
+    /// values are only used when their collation is the same.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collation: Option<Collation>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CmpOp {
     Eq,
     Gt,
@@ -40,7 +129,10 @@ pub enum CmpOp {
     Lte,
 }
 
-#[derive(Debug, Clone)]
+/// A query predicate. Serializes through [`FilterSerde`](super::FilterSerde), the same JSON
+/// shape accepted by `parse_filter_json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "super::parse::FilterSerde", into = "super::parse::FilterSerde")]
 pub enum Filter {
     True,
     And(Vec<Filter>),
@@ -63,28 +155,242 @@ pub enum Filter {
         op: CmpOp,
         value: Bson,
     },
+    /// `$regex`: some string at `path` matches the pattern, compiled when the filter is built.
     #[cfg(feature = "regex")]
     Regex {
         path: String,
-        pattern: String,
-        case_insensitive: bool,
+        pattern: RegexPattern,
+    },
+    /// `$type`: some value at `path` has the named BSON type (`"string"`, `"long"`, ..., or
+    /// `"number"` for any numeric type). An array matches `"array"` as well as through its
+    /// elements.
+    Type {
+        path: String,
+        type_name: String,
+    },
+    /// `$mod`: some number at `path`, truncated to an integer, leaves `remainder` when
+    /// divided by `divisor`.
+    Mod {
+        path: String,
+        divisor: i64,
+        remainder: i64,
+    },
+    /// `$expr`: the expression is true for the document, e.g. comparing two of its fields.
+    Expr(Expr),
+    /// `$text`: the document's text matches the search. Analysed like the collection's text
+    /// index, whose fields it searches; without one, every string in the document.
+    Text(TextSearch),
+    /// `$geoWithin`, `$geoIntersects`, `$near` or `$nearSphere` on the location at `path`.
+    Geo {
+        path: String,
+        op: GeoOp,
+    },
+    /// `filter` with its string comparisons (`$eq`, ranges, `$in`, `$nin`, `$expr`) made
+    /// under `collation`.
+    Collate {
+        collation: Collation,
+        filter: Box<Filter>,
     },
 }
 
+/// A parsed update. Paths may index into arrays (`items.0.qty`), address every element
+/// (`items.$[].qty`), or, in `update_one`/`update_many`, the first element matched by the
+/// query (`items.$.qty`).
 #[derive(Debug, Default, Clone)]
 pub struct UpdateDoc {
     pub set: Vec<(String, Bson)>,
-    pub inc: Vec<(String, f64)>,
+    /// Numeric operands; the stored value keeps the widest of the two types (see `$inc`).
+    pub inc: Vec<(String, Bson)>,
     pub unset: Vec<String>,
+    pub mul: Vec<(String, Bson)>,
+    pub min: Vec<(String, Bson)>,
+    pub max: Vec<(String, Bson)>,
+    /// `(from, to)`
+    pub rename: Vec<(String, String)>,
+    pub push: Vec<(String, PushSpec)>,
+    /// Values appended unless an equal element is already present.
+    pub add_to_set: Vec<(String, Vec<Bson>)>,
+    pub pull: Vec<(String, PullSpec)>,
+    pub pop: Vec<(String, PopFrom)>,
+    pub current_date: Vec<(String, CurrentDateType)>,
+    /// Like `set`, but only applied when an upsert inserts a document.
+    pub set_on_insert: Vec<(String, Bson)>,
+}
+
+/// `$push` with its `$each`/`$sort`/`$slice` modifiers, applied in that order.
+#[derive(Debug, Default, Clone)]
+pub struct PushSpec {
+    pub each: Vec<Bson>,
+    pub sort: Option<PushSort>,
+    /// Keep the first `n` elements, or the last `-n` when negative.
+    pub slice: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub enum PushSort {
+    /// Sort the elements themselves.
+    Value(Order),
+    /// Sort sub-document elements by their fields.
+    Fields(Vec<SortSpec>),
+}
+
+/// Which array elements `$pull` removes.
+#[derive(Debug, Clone)]
+pub enum PullSpec {
+    /// Elements equal to the value.
+    Value(Bson),
+    /// Elements satisfying every comparison, e.g. `{"$gte": 6, "$lt": 9}`.
+    Cmp(Vec<(CmpOp, Bson)>),
+    /// Elements equal to any of the values (`{"$in": [..]}`).
+    In(Vec<Bson>),
+    /// Sub-document elements whose fields equal the given ones, e.g. `{"sku": "x"}`.
+    Fields(Vec<(String, Bson)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopFrom {
+    First,
+    Last,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurrentDateType {
+    Date,
+    Timestamp,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct UpdateReport {
     pub matched: u64,
     pub modified: u64,
+    /// Set when an upsert inserted a new document instead of updating.
+    pub upserted_id: Option<crate::types::DocumentId>,
+}
+
+/// Options for `update_one_with`/`update_many_with`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpdateOptions {
+    /// Insert a document built from the filter's equality predicates and the update when
+    /// nothing matches.
+    pub upsert: bool,
+}
+
+/// Which version of the document `find_one_and_update`/`find_one_and_replace` return.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReturnDocument {
+    #[default]
+    Before,
+    After,
+}
+
+/// Options for the `find_one_and_*` operations.
+#[derive(Debug, Clone, Default)]
+pub struct FindAndModifyOptions {
+    /// Picks the first match in this order; otherwise any match may be picked.
+    pub sort: Option<Vec<SortSpec>>,
+    pub projection: Option<Projection>,
+    /// Ignored by `find_one_and_delete`.
+    pub return_document: ReturnDocument,
+    /// Ignored by `find_one_and_delete`.
+    pub upsert: bool,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct DeleteReport {
     pub deleted: u64,
 }
+
+/// One write in a `bulk_write` batch.
+#[derive(Debug, Clone)]
+pub enum BulkOp {
+    Insert(crate::document::Document),
+    UpdateOne {
+        filter: Filter,
+        update: UpdateDoc,
+        upsert: bool,
+    },
+    UpdateMany {
+        filter: Filter,
+        update: UpdateDoc,
+        upsert: bool,
+    },
+    /// Replace the first match's contents; `$`-prefixed keys are rejected.
+    ReplaceOne {
+        filter: Filter,
+        replacement: bson::Document,
+        upsert: bool,
+    },
+    DeleteOne {
+        filter: Filter,
+    },
+    DeleteMany {
+        filter: Filter,
+    },
+}
+
+impl BulkOp {
+    pub(crate) fn is_upsert(&self) -> bool {
+        match self {
+            Self::UpdateOne { upsert, .. }
+            | Self::UpdateMany { upsert, .. }
+            | Self::ReplaceOne { upsert, .. } => *upsert,
+            _ => false,
+        }
+    }
+}
+
+/// Options for `bulk_write`.
+#[derive(Debug, Clone, Copy)]
+pub struct BulkOptions {
+    /// Stop at the first failing operation (the default). When false, every operation is
+    /// attempted and each failure is reported alongside the successes.
+    pub ordered: bool,
+}
+
+impl Default for BulkOptions {
+    fn default() -> Self {
+        Self { ordered: true }
+    }
+}
+
+/// The result of one successful `BulkOp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkOutcome {
+    Inserted(crate::types::DocumentId),
+    Updated(UpdateReport),
+    Deleted(DeleteReport),
+}
+
+/// Per-operation results of a `bulk_write`, plus totals over the successful ones.
+#[derive(Debug, Default)]
+pub struct BulkWriteReport {
+    /// One entry per attempted operation, in input order. An ordered batch stops after the
+    /// first error, so operations after it have no entry.
+    pub results: Vec<Result<BulkOutcome, crate::errors::DbError>>,
+    pub inserted: u64,
+    pub matched: u64,
+    pub modified: u64,
+    pub upserted: u64,
+    pub deleted: u64,
+}
+
+impl BulkWriteReport {
+    /// The failed operations, as (input index, error).
+    pub fn errors(&self) -> impl Iterator<Item = (usize, &crate::errors::DbError)> {
+        self.results.iter().enumerate().filter_map(|(i, r)| r.as_ref().err().map(|e| (i, e)))
+    }
+
+    pub(crate) fn record(&mut self, result: Result<BulkOutcome, crate::errors::DbError>) {
+        match &result {
+            Ok(BulkOutcome::Inserted(_)) => self.inserted += 1,
+            Ok(BulkOutcome::Updated(r)) => {
+                self.matched += r.matched;
+                self.modified += r.modified;
+                self.upserted += u64::from(r.upserted_id.is_some());
+            }
+            Ok(BulkOutcome::Deleted(r)) => self.deleted += r.deleted,
+            Err(_) => {}
+        }
+        self.results.push(result);
+    }
+}
@@ -0,0 +1,463 @@
+use crate::document::Document;
+use crate::errors::DbError;
+use bson::{Bson, Document as BsonDocument};
+use std::cmp::Ordering;
+
+use super::eval::{compare_bson, compare_docs, eval_filter};
+use super::numeric::{self, NumOp, is_number};
+use super::types::{
+    CmpOp, CurrentDateType, Filter, MAX_PATH_DEPTH, Order, PopFrom, PullSpec, PushSort, PushSpec,
+    UpdateDoc,
+};
+
+/// Apply `upd` to `doc`, returning whether the document changed.
+///
+/// Operators run in a fixed order: `$set`, `$inc`, `$mul`, `$min`, `$max`, `$unset`,
+/// `$rename`, `$push`, `$addToSet`, `$pull`, `$pop`, `$currentDate`. An operator whose target
+/// has the wrong type (e.g. `$push` onto a string) is skipped. Positional `$` paths are
+/// skipped too, since there is no query to resolve them against; see
+/// [`apply_update_positional`]. `$setOnInsert` only applies to upserted documents.
+///
+/// `$inc` and `$mul` keep the operand types: int32 stays int32 until it overflows into int64,
+/// int64 stays exact, and Decimal128 arithmetic is exact. A missing field takes the operand
+/// (`$inc`) or a zero of its type (`$mul`).
+///
+/// # Errors
+/// Returns `DbError::QueryError` if `$inc`/`$mul` targets a non-numeric value or an int64
+/// result overflows. `doc` may then be partially updated.
+pub fn apply_update(doc: &mut Document, upd: &UpdateDoc) -> Result<bool, DbError> {
+    apply(doc, upd, None)
+}
+
+/// Like [`apply_update`], resolving `$` path segments to the first array element that makes
+/// `filter` match.
+///
+/// # Errors
+/// As for [`apply_update`].
+pub fn apply_update_positional(
+    doc: &mut Document,
+    upd: &UpdateDoc,
+    filter: &Filter,
+) -> Result<bool, DbError> {
+    apply(doc, upd, Some(filter))
+}
+
+fn apply(doc: &mut Document, upd: &UpdateDoc, filter: Option<&Filter>) -> Result<bool, DbError> {
+    let root = &mut doc.data.0;
+    let before = root.clone();
+    let resolve = |root: &BsonDocument, path: &str| resolve_positional(root, path, filter);
+
+    for (path, v) in &upd.set {
+        if let Some(path) = resolve(root, path) {
+            walk(root, &path, true, &mut |slot| slot.set(v.clone()));
+        }
+    }
+    for (op, ops, kind) in [(NumOp::Add, &upd.inc, "$inc"), (NumOp::Mul, &upd.mul, "$mul")] {
+        for (path, by) in ops {
+            let Some(path) = resolve(root, path) else { continue };
+            let mut err = None;
+            walk(root, &path, true, &mut |slot| {
+                let next = match slot.get() {
+                    _ if err.is_some() => return,
+                    None if matches!(op, NumOp::Add) => by.clone(),
+                    None => numeric::zero_like(by),
+                    Some(cur) if !is_number(cur) => {
+                        err = Some(format!("cannot apply {kind} to non-numeric field '{path}'"));
+                        return;
+                    }
+                    Some(cur) => match numeric::apply(op, cur, by) {
+                        Some(v) => v,
+                        None => {
+                            err = Some(format!("{kind} on '{path}' overflows int64"));
+                            return;
+                        }
+                    },
+                };
+                slot.set(next);
+            });
+            if let Some(msg) = err {
+                return Err(DbError::QueryError(msg));
+            }
+        }
+    }
+    for (ops, want) in [(&upd.min, Ordering::Less), (&upd.max, Ordering::Greater)] {
+        for (path, v) in ops {
+            if let Some(path) = resolve(root, path) {
+                walk(root, &path, true, &mut |slot| {
+                    if slot.get().is_none_or(|cur| compare_bson(v, cur) == want) {
+                        slot.set(v.clone());
+                    }
+                });
+            }
+        }
+    }
+    for path in &upd.unset {
+        if let Some(path) = resolve(root, path) {
+            walk(root, &path, false, &mut |slot| slot.unset());
+        }
+    }
+    for (from, to) in &upd.rename {
+        if let (Some(from), Some(to)) = (resolve(root, from), resolve(root, to))
+            && from != to
+        {
+            let mut taken = None;
+            walk(root, &from, false, &mut |slot| taken = slot.remove());
+            if let Some(v) = taken {
+                walk(root, &to, true, &mut |slot| slot.set(v.clone()));
+            }
+        }
+    }
+    for (path, spec) in &upd.push {
+        if let Some(path) = resolve(root, path) {
+            walk(root, &path, true, &mut |slot| {
+                if let Some(items) = slot.array_or_create() {
+                    push(items, spec);
+                }
+            });
+        }
+    }
+    for (path, values) in &upd.add_to_set {
+        if let Some(path) = resolve(root, path) {
+            walk(root, &path, true, &mut |slot| {
+                if let Some(items) = slot.array_or_create() {
+                    for v in values {
+                        if !items.contains(v) {
+                            items.push(v.clone());
+                        }
+                    }
+                }
+            });
+        }
+    }
+    for (path, spec) in &upd.pull {
+        if let Some(path) = resolve(root, path) {
+            walk(root, &path, false, &mut |slot| {
+                if let Some(Bson::Array(items)) = slot.get_mut() {
+                    items.retain(|e| !pull_matches(e, spec));
+                }
+            });
+        }
+    }
+    for (path, from) in &upd.pop {
+        if let Some(path) = resolve(root, path) {
+            walk(root, &path, false, &mut |slot| {
+                if let Some(Bson::Array(items)) = slot.get_mut()
+                    && !items.is_empty()
+                {
+                    match from {
+                        PopFrom::First => items.remove(0),
+                        PopFrom::Last => items.remove(items.len() - 1),
+                    };
+                }
+            });
+        }
+    }
+    if !upd.current_date.is_empty() {
+        let now = bson::DateTime::now();
+        for (path, ty) in &upd.current_date {
+            let v = match ty {
+                CurrentDateType::Date => Bson::DateTime(now),
+                CurrentDateType::Timestamp => Bson::Timestamp(bson::Timestamp {
+                    time: u32::try_from(now.timestamp_millis() / 1000).unwrap_or(u32::MAX),
+                    increment: 1,
+                }),
+            };
+            if let Some(path) = resolve(root, path) {
+                walk(root, &path, true, &mut |slot| slot.set(v.clone()));
+            }
+        }
+    }
+    Ok(*root != before)
+}
+
+/// The document an upsert inserts: the filter's equality predicates, then `upd`, then
+/// `$setOnInsert`.
+pub(crate) fn upsert_document(filter: &Filter, upd: &UpdateDoc) -> Result<Document, DbError> {
+    let mut seeds = Vec::new();
+    equality_seeds(filter, &mut seeds);
+    let mut doc = Document::new(BsonDocument::new(), crate::document::DocumentType::Persistent);
+    apply(&mut doc, &UpdateDoc { set: seeds, ..UpdateDoc::default() }, None)?;
+    apply(&mut doc, upd, None)?;
+    let on_insert = UpdateDoc { set: upd.set_on_insert.clone(), ..UpdateDoc::default() };
+    apply(&mut doc, &on_insert, None)?;
+    Ok(doc)
+}
+
+/// Fields pinned to a single value by `filter`: equalities, one-element `$in` lists, and
+/// those nested in `$and` (or a one-branch `$or`, or a collation). Other predicates seed
+/// nothing.
+fn equality_seeds(filter: &Filter, out: &mut Vec<(String, Bson)>) {
+    match filter {
+        Filter::Cmp { path, op: CmpOp::Eq, value } => out.push((path.clone(), value.clone())),
+        Filter::In { path, values } if values.len() == 1 => {
+            out.push((path.clone(), values[0].clone()));
+        }
+        Filter::And(parts) => parts.iter().for_each(|f| equality_seeds(f, out)),
+        Filter::Or(parts) if parts.len() == 1 => equality_seeds(&parts[0], out),
+        Filter::Collate { filter, .. } => equality_seeds(filter, out),
+        _ => {}
+    }
+}
+
+/// A location addressed by an update path: a field of a document or an array element.
+enum Slot<'a> {
+    Field(&'a mut BsonDocument, &'a str),
+    Elem(&'a mut Vec<Bson>, usize),
+}
+
+impl Slot<'_> {
+    fn get(&self) -> Option<&Bson> {
+        match self {
+            Self::Field(d, k) => d.get(*k),
+            Self::Elem(items, i) => items.get(*i),
+        }
+    }
+
+    fn get_mut(&mut self) -> Option<&mut Bson> {
+        match self {
+            Self::Field(d, k) => d.get_mut(*k),
+            Self::Elem(items, i) => items.get_mut(*i),
+        }
+    }
+
+    /// Setting past the end of an array pads it with nulls.
+    fn set(&mut self, v: Bson) {
+        match self {
+            Self::Field(d, k) => {
+                d.insert(*k, v);
+            }
+            Self::Elem(items, i) => {
+                if *i >= items.len() {
+                    items.resize(*i + 1, Bson::Null);
+                }
+                items[*i] = v;
+            }
+        }
+    }
+
+    fn remove(&mut self) -> Option<Bson> {
+        match self {
+            Self::Field(d, k) => d.remove(*k),
+            Self::Elem(items, i) => items.get_mut(*i).map(|e| std::mem::replace(e, Bson::Null)),
+        }
+    }
+
+    /// Unsetting an array element leaves a null in its place, keeping positions stable.
+    fn unset(&mut self) {
+        self.remove();
+    }
+
+    /// The array at this location, creating an empty one if the location is missing.
+    fn array_or_create(&mut self) -> Option<&mut Vec<Bson>> {
+        if self.get().is_none() {
+            self.set(Bson::Array(Vec::new()));
+        }
+        match self.get_mut() {
+            Some(Bson::Array(items)) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Call `f` on every location `path` addresses. With `create`, missing (or scalar)
+/// intermediate fields become sub-documents and short arrays are padded with nulls.
+fn walk(root: &mut BsonDocument, path: &str, create: bool, f: &mut dyn FnMut(&mut Slot<'_>)) {
+    let segs: Vec<&str> = path.split('.').collect();
+    if path.is_empty() || segs.len() > MAX_PATH_DEPTH {
+        return;
+    }
+    walk_doc(root, &segs, create, f);
+}
+
+fn walk_doc(doc: &mut BsonDocument, segs: &[&str], create: bool, f: &mut dyn FnMut(&mut Slot<'_>)) {
+    let Some((seg, rest)) = segs.split_first() else { return };
+    if rest.is_empty() {
+        f(&mut Slot::Field(doc, seg));
+        return;
+    }
+    if create && !matches!(doc.get(*seg), Some(Bson::Document(_) | Bson::Array(_))) {
+        doc.insert(*seg, Bson::Document(BsonDocument::new()));
+    }
+    if let Some(v) = doc.get_mut(*seg) {
+        walk_value(v, rest, create, f);
+    }
+}
+
+fn walk_value(v: &mut Bson, segs: &[&str], create: bool, f: &mut dyn FnMut(&mut Slot<'_>)) {
+    match v {
+        Bson::Document(d) => walk_doc(d, segs, create, f),
+        Bson::Array(items) => {
+            let Some((seg, rest)) = segs.split_first() else { return };
+            if *seg == "$[]" {
+                for i in 0..items.len() {
+                    if rest.is_empty() {
+                        f(&mut Slot::Elem(items, i));
+                    } else {
+                        walk_value(&mut items[i], rest, create, f);
+                    }
+                }
+                return;
+            }
+            let Ok(i) = seg.parse::<usize>() else { return };
+            if rest.is_empty() {
+                f(&mut Slot::Elem(items, i));
+                return;
+            }
+            if create {
+                if i >= items.len() {
+                    items.resize(i + 1, Bson::Null);
+                }
+                if !matches!(items[i], Bson::Document(_) | Bson::Array(_)) {
+                    items[i] = Bson::Document(BsonDocument::new());
+                }
+            }
+            if let Some(next) = items.get_mut(i) {
+                walk_value(next, rest, create, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace a `$` segment with the index of the first element of the array before it that is
+/// responsible for `filter` matching: the document matches with only that element in the
+/// array, and does not match with the array emptied. `None` when nothing qualifies.
+fn resolve_positional(root: &BsonDocument, path: &str, filter: Option<&Filter>) -> Option<String> {
+    let segs: Vec<&str> = path.split('.').collect();
+    let Some(pos) = segs.iter().position(|s| *s == "$") else {
+        return Some(path.to_string());
+    };
+    let filter = filter?;
+    if pos == 0 {
+        return None;
+    }
+    let prefix = segs[..pos].join(".");
+    let mut items = None;
+    let mut probe = root.clone();
+    walk(&mut probe, &prefix, false, &mut |slot| {
+        if let Some(Bson::Array(a)) = slot.get() {
+            items = Some(a.clone());
+        }
+    });
+    let items = items?;
+    let with = |probe: &mut BsonDocument, elems: Vec<Bson>| {
+        walk(probe, &prefix, false, &mut |slot| slot.set(Bson::Array(elems.clone())));
+    };
+    with(&mut probe, Vec::new());
+    if eval_filter(&probe, filter) {
+        return None;
+    }
+    let i = items.iter().position(|e| {
+        with(&mut probe, vec![e.clone()]);
+        eval_filter(&probe, filter)
+    })?;
+    let mut out: Vec<String> = segs.iter().map(ToString::to_string).collect();
+    out[pos] = i.to_string();
+    Some(out.join("."))
+}
+
+fn push(items: &mut Vec<Bson>, spec: &PushSpec) {
+    items.extend(spec.each.iter().cloned());
+    match &spec.sort {
+        Some(PushSort::Value(order)) => {
+            items.sort_by(|a, b| directed(compare_bson(a, b), *order));
+        }
+        Some(PushSort::Fields(specs)) => {
+            let empty = BsonDocument::new();
+            let as_doc = |v: &Bson| match v {
+                Bson::Document(d) => d.clone(),
+                _ => empty.clone(),
+            };
+            items.sort_by(|a, b| compare_docs(&as_doc(a), &as_doc(b), specs));
+        }
+        None => {}
+    }
+    if let Some(n) = spec.slice {
+        let len = items.len();
+        let keep = usize::try_from(n.unsigned_abs()).unwrap_or(usize::MAX).min(len);
+        if n >= 0 {
+            items.truncate(keep);
+        } else {
+            items.drain(..len - keep);
+        }
+    }
+}
+
+const fn directed(ord: Ordering, order: Order) -> Ordering {
+    match order {
+        Order::Asc => ord,
+        Order::Desc => ord.reverse(),
+    }
+}
+
+fn pull_matches(e: &Bson, spec: &PullSpec) -> bool {
+    match spec {
+        PullSpec::Value(v) => e == v,
+        PullSpec::In(values) => values.contains(e),
+        PullSpec::Cmp(conds) => conds.iter().all(|(op, v)| {
+            let ord = compare_bson(e, v);
+            match op {
+                CmpOp::Eq => e == v,
+                CmpOp::Gt => ord == Ordering::Greater,
+                CmpOp::Gte => ord != Ordering::Less,
+                CmpOp::Lt => ord == Ordering::Less,
+                CmpOp::Lte => ord != Ordering::Greater,
+            }
+        }),
+        PullSpec::Fields(fields) => match e {
+            Bson::Document(d) => fields.iter().all(|(path, v)| {
+                let f = Filter::Cmp { path: path.clone(), op: CmpOp::Eq, value: v.clone() };
+                eval_filter(d, &f)
+            }),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn update_doc_set_inc_unset() {
+        let mut d =
+            Document::new(doc! {"x": 1, "y": 2 }, crate::document::DocumentType::Persistent);
+        let ud = UpdateDoc {
+            set: vec![("y".into(), bson::Bson::Int32(5))],
+            inc: vec![("x".into(), Bson::Int32(2))],
+            unset: vec!["z".into()],
+            ..Default::default()
+        };
+        let changed = super::apply_update(&mut d, &ud).unwrap();
+        assert!(changed);
+        assert_eq!(d.data.0.get_i32("y").unwrap(), 5);
+        assert_eq!(d.data.0.get_i32("x").unwrap(), 3);
+    }
+
+    #[test]
+    fn array_paths() {
+        let mut d = Document::new(
+            doc! {"items": [{"q": 1}, {"q": 2}], "tags": ["a"]},
+            crate::document::DocumentType::Persistent,
+        );
+        let ud = UpdateDoc {
+            set: vec![("items.1.q".into(), Bson::Int32(9)), ("tags.2".into(), Bson::from("c"))],
+            unset: vec!["items.$[].gone".into()],
+            ..Default::default()
+        };
+        assert!(apply_update(&mut d, &ud).unwrap());
+        assert_eq!(d.data.0, doc! {"items": [{"q": 1}, {"q": 9}], "tags": ["a", Bson::Null, "c"]});
+
+        // `$` needs a query; without one the operation is skipped
+        let ud =
+            UpdateDoc { set: vec![("items.$.q".into(), Bson::Int32(0))], ..Default::default() };
+        assert!(!apply_update(&mut d, &ud).unwrap());
+        let f = Filter::Cmp { path: "items.q".into(), op: CmpOp::Eq, value: Bson::Int32(9) };
+        assert!(apply_update_positional(&mut d, &ud, &f).unwrap());
+        assert_eq!(d.data.0.get_array("items").unwrap()[1], Bson::Document(doc! {"q": 0}));
+        // A query that does not involve the array does not pick an element
+        let f = Filter::Exists { path: "tags".into(), exists: true };
+        assert!(!apply_update_positional(&mut d, &ud, &f).unwrap());
+    }
+}
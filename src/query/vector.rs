@@ -0,0 +1,761 @@
+//! Vector similarity search.
+//!
+//! A vector index stores a fixed-dimension array of numbers per document and answers
+//! k-nearest-neighbour queries under a cosine, L2 or dot-product metric. A flat index
+//! compares the query with every stored vector; an HNSW index walks a layered proximity graph
+//! (Malkov & Yashunin) and only compares a few hundred vectors, trading a little recall for
+//! speed. Node levels are derived from the document id, so the same documents always build
+//! the same graph.
+
+use crate::collection::Collection;
+use crate::document::Document;
+use crate::errors::DbError;
+use crate::index::IndexImpl;
+use crate::types::DocumentId;
+use bson::{Bson, Document as BsonDocument};
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use super::eval::{eval_filter, path_values};
+use super::parse::FilterSerde;
+use super::plan;
+use super::text;
+use super::types::Filter;
+
+/// Where `$vectorSearch` leaves each result's similarity score for the rest of the pipeline:
+/// sort on this field, or project `{"$meta": "vectorSearchScore"}`. It is removed before
+/// documents are returned.
+pub(crate) const VECTOR_SCORE: &str = "$vectorSearchScore";
+
+/// Largest number of dimensions a vector index accepts.
+const MAX_DIMENSIONS: usize = 4096;
+
+/// Largest `limit` and `num_candidates` a vector query accepts.
+const MAX_CANDIDATES: usize = 10_000;
+
+/// Largest HNSW `m` accepted.
+const MAX_HNSW_M: usize = 128;
+
+/// Graph levels are capped so a pathological id cannot create a tall, empty tower.
+const MAX_LEVEL: usize = 16;
+
+/// Tombstoned graph nodes tolerated before the graph is rebuilt from the live ones.
+const MIN_DEAD_FOR_REBUILD: usize = 64;
+
+/// How vectors are compared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// Cosine of the angle between the vectors; the score is in `[-1, 1]`.
+    #[default]
+    Cosine,
+    /// Euclidean distance; the score is `1 / (1 + distance)`.
+    L2,
+    /// Dot product, for vectors that are already normalised; the score is the product.
+    Dot,
+}
+
+impl Metric {
+    /// Distance used to rank neighbours: smaller is closer.
+    pub(crate) fn distance(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Self::Cosine => {
+                let (mut dot, mut na, mut nb) = (0.0f32, 0.0f32, 0.0f32);
+                for (x, y) in a.iter().zip(b) {
+                    dot += x * y;
+                    na += x * x;
+                    nb += y * y;
+                }
+                // A zero vector has no direction: treat it as orthogonal to everything
+                let norm = (na * nb).sqrt();
+                if norm == 0.0 { 1.0 } else { 1.0 - dot / norm }
+            }
+            Self::L2 => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt(),
+            Self::Dot => -a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>(),
+        }
+    }
+
+    /// The similarity reported for a distance: larger is closer.
+    pub(crate) fn score(self, distance: f32) -> f64 {
+        let d = f64::from(distance);
+        match self {
+            Self::Cosine => 1.0 - d,
+            Self::L2 => 1.0 / (1.0 + d),
+            Self::Dot => -d,
+        }
+    }
+}
+
+/// HNSW graph parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HnswOptions {
+    /// Links kept per node on each level above the ground level, which keeps `2 * m`.
+    #[serde(default = "default_m")]
+    pub m: usize,
+    /// Candidates considered when linking a new node; higher builds a better graph, slower.
+    #[serde(default = "default_ef_construction")]
+    pub ef_construction: usize,
+}
+
+const fn default_m() -> usize {
+    16
+}
+
+const fn default_ef_construction() -> usize {
+    100
+}
+
+impl Default for HnswOptions {
+    fn default() -> Self {
+        Self { m: default_m(), ef_construction: default_ef_construction() }
+    }
+}
+
+/// Vector index settings, stored in the index descriptor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VectorOptions {
+    /// Length of every indexed vector. Documents whose field holds an array of another length,
+    /// or of anything but numbers, are not indexed.
+    pub dimensions: usize,
+    #[serde(default)]
+    pub metric: Metric,
+    /// Build an HNSW graph; without it every search is exact.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hnsw: Option<HnswOptions>,
+}
+
+impl VectorOptions {
+    #[must_use]
+    pub fn new(dimensions: usize) -> Self {
+        Self { dimensions, metric: Metric::default(), hnsw: None }
+    }
+    #[must_use]
+    pub const fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+    #[must_use]
+    pub const fn with_hnsw(mut self, hnsw: HnswOptions) -> Self {
+        self.hnsw = Some(hnsw);
+        self
+    }
+
+    /// Check the options a vector index is created with.
+    ///
+    /// # Errors
+    /// Returns `DbError::QueryError` for zero or too many dimensions, or bad HNSW parameters.
+    pub fn validate(&self) -> Result<(), DbError> {
+        if self.dimensions == 0 || self.dimensions > MAX_DIMENSIONS {
+            return Err(DbError::QueryError(format!(
+                "a vector index needs between 1 and {MAX_DIMENSIONS} dimensions"
+            )));
+        }
+        if let Some(h) = &self.hnsw
+            && (h.m < 2 || h.m > MAX_HNSW_M || h.ef_construction == 0)
+        {
+            return Err(DbError::QueryError(format!(
+                "hnsw needs m between 2 and {MAX_HNSW_M} and a positive ef_construction"
+            )));
+        }
+        Ok(())
+    }
+
+    /// The vector `doc` is indexed under: the first array at `field` holding exactly
+    /// `dimensions` finite numbers.
+    pub(crate) fn vector_of(&self, doc: &BsonDocument, field: &str) -> Option<Vec<f32>> {
+        path_values(doc, field).into_iter().find_map(|v| match v {
+            Bson::Array(items) if items.len() == self.dimensions => to_vector(items),
+            _ => None,
+        })
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn to_vector(items: &[Bson]) -> Option<Vec<f32>> {
+    items
+        .iter()
+        .map(|v| match v {
+            Bson::Double(f) => Some(*f as f32),
+            Bson::Int32(i) => Some(*i as f32),
+            Bson::Int64(i) => Some(*i as f32),
+            _ => None,
+        })
+        .map(|f| f.filter(|f| f.is_finite()))
+        .collect()
+}
+
+/// A distance paired with the graph slot it was measured to, ordered by distance.
+#[derive(Debug, Clone, Copy)]
+struct Scored(f32, u32);
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Node {
+    id: DocumentId,
+    vector: Vec<f32>,
+    /// Neighbour slots on each level the node lives on, ground level first.
+    links: Vec<Vec<u32>>,
+    /// Removed nodes stay in the graph as waypoints until the next rebuild.
+    live: bool,
+}
+
+/// A hierarchical navigable small world graph over the vectors of one index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Hnsw {
+    options: HnswOptions,
+    metric: Metric,
+    nodes: Vec<Node>,
+    entry: Option<u32>,
+    dead: usize,
+    /// Slot of each live document.
+    #[serde(skip)]
+    slots: HashMap<DocumentId, u32>,
+    /// Slots of a restored graph that no document has confirmed yet (see [`Hnsw::restored`]).
+    #[serde(skip)]
+    unconfirmed: HashSet<u32>,
+}
+
+impl Hnsw {
+    pub(crate) fn new(options: HnswOptions, metric: Metric) -> Self {
+        Self {
+            options,
+            metric,
+            nodes: Vec::new(),
+            entry: None,
+            dead: 0,
+            slots: HashMap::new(),
+            unconfirmed: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Live documents and their vectors.
+    fn live(&self) -> impl Iterator<Item = (&DocumentId, &[f32])> {
+        self.nodes.iter().filter(|n| n.live).map(|n| (&n.id, n.vector.as_slice()))
+    }
+
+    /// Whether a graph read back from disk was built with `options` and is well formed.
+    pub(crate) fn fits(&self, options: &VectorOptions) -> bool {
+        let n = self.nodes.len();
+        options.hnsw == Some(self.options)
+            && options.metric == self.metric
+            && self.entry.is_none_or(|e| (e as usize) < n)
+            && self.nodes.iter().all(|node| {
+                node.vector.len() == options.dimensions
+                    && !node.links.is_empty()
+                    && node.links.iter().flatten().all(|&s| (s as usize) < n)
+            })
+    }
+
+    /// Prepare a graph read back from disk. Its nodes count as unconfirmed until a document
+    /// with the same id and vector is inserted; [`Hnsw::prune_unconfirmed`] then drops the
+    /// nodes whose documents are gone.
+    pub(crate) fn restored(mut self) -> Self {
+        self.slots = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.live)
+            .map(|(s, n)| (n.id.clone(), slot(s)))
+            .collect();
+        self.unconfirmed = self.slots.values().copied().collect();
+        self
+    }
+
+    pub(crate) fn prune_unconfirmed(&mut self) {
+        let gone: Vec<DocumentId> = std::mem::take(&mut self.unconfirmed)
+            .into_iter()
+            .map(|s| self.nodes[s as usize].id.clone())
+            .collect();
+        for id in gone {
+            self.remove(&id);
+        }
+    }
+
+    pub(crate) fn insert(&mut self, id: &DocumentId, vector: Vec<f32>) {
+        if let Some(&s) = self.slots.get(id) {
+            if self.nodes[s as usize].vector == vector {
+                self.unconfirmed.remove(&s);
+                return;
+            }
+            self.remove(id);
+        }
+        let s = slot(self.nodes.len());
+        let level = self.level_for(id);
+        self.nodes.push(Node {
+            id: id.clone(),
+            vector,
+            links: vec![Vec::new(); level + 1],
+            live: true,
+        });
+        self.slots.insert(id.clone(), s);
+        self.link(s);
+    }
+
+    pub(crate) fn remove(&mut self, id: &DocumentId) -> bool {
+        let Some(s) = self.slots.remove(id) else {
+            return false;
+        };
+        self.unconfirmed.remove(&s);
+        self.nodes[s as usize].live = false;
+        self.dead += 1;
+        if self.dead > MIN_DEAD_FOR_REBUILD && self.dead > self.slots.len() {
+            self.rebuild();
+        }
+        true
+    }
+
+    /// Rebuild the graph from its live nodes, dropping the tombstones.
+    fn rebuild(&mut self) {
+        let pending: Vec<DocumentId> =
+            self.unconfirmed.iter().map(|&s| self.nodes[s as usize].id.clone()).collect();
+        let live: Vec<Node> =
+            std::mem::take(&mut self.nodes).into_iter().filter(|n| n.live).collect();
+        *self = Self::new(self.options, self.metric);
+        for n in live {
+            self.insert(&n.id, n.vector);
+        }
+        self.unconfirmed = pending.iter().filter_map(|id| self.slots.get(id).copied()).collect();
+    }
+
+    /// A level drawn from the exponentially decaying distribution HNSW requires, seeded by
+    /// the document id.
+    fn level_for(&self, id: &DocumentId) -> usize {
+        let bits = id.0.as_u128();
+        #[allow(clippy::cast_possible_truncation)]
+        let mut x = (bits as u64) ^ ((bits >> 64) as u64);
+        // splitmix64 finaliser
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^= x >> 31;
+        #[allow(clippy::cast_precision_loss)]
+        let u = (x >> 11) as f64 / (1u64 << 53) as f64;
+        let ml = 1.0 / (self.options.m as f64).ln();
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let level = (-(1.0 - u).ln() * ml).floor() as usize;
+        level.min(MAX_LEVEL)
+    }
+
+    fn dist(&self, q: &[f32], s: u32) -> f32 {
+        self.metric.distance(q, &self.nodes[s as usize].vector)
+    }
+
+    fn top_level(&self) -> usize {
+        self.entry.map_or(0, |e| self.nodes[e as usize].links.len() - 1)
+    }
+
+    /// Connect the freshly pushed node at slot `s` to its nearest neighbours on every level.
+    fn link(&mut self, s: u32) {
+        let Some(entry) = self.entry else {
+            self.entry = Some(s);
+            return;
+        };
+        let q = self.nodes[s as usize].vector.clone();
+        let level = self.nodes[s as usize].links.len() - 1;
+        let top = self.top_level();
+        let mut eps = vec![entry];
+        for l in (level + 1..=top).rev() {
+            eps = self.greedy(&q, &eps, l);
+        }
+        for l in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&q, &eps, self.options.ef_construction, l, &|t| {
+                t != s && self.nodes[t as usize].live
+            });
+            let neighbours: Vec<u32> = found.iter().take(self.options.m).map(|c| c.1).collect();
+            let cap = if l == 0 { 2 * self.options.m } else { self.options.m };
+            for &n in &neighbours {
+                self.nodes[n as usize].links[l].push(s);
+                if self.nodes[n as usize].links[l].len() > cap {
+                    self.shrink(n, l, cap);
+                }
+            }
+            self.nodes[s as usize].links[l] = neighbours;
+            if !found.is_empty() {
+                eps = found.into_iter().map(|c| c.1).collect();
+            }
+        }
+        if level > top {
+            self.entry = Some(s);
+        }
+    }
+
+    /// Keep only the `cap` links of node `n` on level `l` closest to it.
+    fn shrink(&mut self, n: u32, l: usize, cap: usize) {
+        let v = &self.nodes[n as usize].vector;
+        let mut links: Vec<Scored> = self.nodes[n as usize].links[l]
+            .iter()
+            .map(|&t| Scored(self.metric.distance(v, &self.nodes[t as usize].vector), t))
+            .collect();
+        links.sort_unstable();
+        links.truncate(cap);
+        self.nodes[n as usize].links[l] = links.into_iter().map(|c| c.1).collect();
+    }
+
+    /// The node on level `l` closest to `q`, starting from `eps`.
+    fn greedy(&self, q: &[f32], eps: &[u32], l: usize) -> Vec<u32> {
+        self.search_layer(q, eps, 1, l, &|_| true).into_iter().map(|c| c.1).take(1).collect()
+    }
+
+    /// Best-first search of level `l` from `eps`, returning up to `ef` nodes accepted by
+    /// `accept`, nearest first. Rejected nodes are still walked through.
+    fn search_layer(
+        &self,
+        q: &[f32],
+        eps: &[u32],
+        ef: usize,
+        l: usize,
+        accept: &dyn Fn(u32) -> bool,
+    ) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = HashSet::new();
+        let mut candidates: BinaryHeap<Reverse<Scored>> = BinaryHeap::new();
+        let mut found: BinaryHeap<Scored> = BinaryHeap::new();
+        for &e in eps {
+            if visited.insert(e) {
+                let c = Scored(self.dist(q, e), e);
+                candidates.push(Reverse(c));
+                if accept(e) {
+                    found.push(c);
+                }
+            }
+        }
+        while found.len() > ef {
+            found.pop();
+        }
+        while let Some(Reverse(c)) = candidates.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|worst| c.0 > worst.0) {
+                break;
+            }
+            let Some(links) = self.nodes[c.1 as usize].links.get(l) else {
+                continue;
+            };
+            for &n in links {
+                if !visited.insert(n) {
+                    continue;
+                }
+                let d = self.dist(q, n);
+                if found.len() < ef || found.peek().is_some_and(|worst| d < worst.0) {
+                    candidates.push(Reverse(Scored(d, n)));
+                    if accept(n) {
+                        found.push(Scored(d, n));
+                        if found.len() > ef {
+                            found.pop();
+                        }
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    /// Approximate `k` nearest live documents accepted by `accept`, exploring `ef`
+    /// candidates on the ground level.
+    fn search(
+        &self,
+        q: &[f32],
+        k: usize,
+        ef: usize,
+        accept: &dyn Fn(&DocumentId) -> bool,
+    ) -> Vec<(DocumentId, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let mut eps = vec![entry];
+        for l in (1..=self.top_level()).rev() {
+            eps = self.greedy(q, &eps, l);
+        }
+        let accept = |s: u32| {
+            let n = &self.nodes[s as usize];
+            n.live && accept(&n.id)
+        };
+        self.search_layer(q, &eps, ef.max(k), 0, &accept)
+            .into_iter()
+            .take(k)
+            .map(|c| (self.nodes[c.1 as usize].id.clone(), c.0))
+            .collect()
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn slot(i: usize) -> u32 {
+    i as u32
+}
+
+/// Where a vector index keeps its vectors.
+#[derive(Debug, Clone)]
+pub(crate) enum VectorStore {
+    Flat(HashMap<DocumentId, Vec<f32>>),
+    Hnsw(Box<Hnsw>),
+}
+
+impl VectorStore {
+    pub(crate) fn new(options: &VectorOptions) -> Self {
+        match options.hnsw {
+            Some(h) => Self::Hnsw(Box::new(Hnsw::new(h, options.metric))),
+            None => Self::Flat(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Flat(m) => m.len(),
+            Self::Hnsw(g) => g.len(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, id: &DocumentId, vector: Vec<f32>) {
+        match self {
+            Self::Flat(m) => {
+                m.insert(id.clone(), vector);
+            }
+            Self::Hnsw(g) => g.insert(id, vector),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: &DocumentId) -> bool {
+        match self {
+            Self::Flat(m) => m.remove(id).is_some(),
+            Self::Hnsw(g) => g.remove(id),
+        }
+    }
+
+    /// The `k` nearest documents accepted by `accept`, with their distances, nearest first.
+    /// `ef` is the HNSW search breadth; `None` compares with every stored vector.
+    pub(crate) fn search(
+        &self,
+        metric: Metric,
+        q: &[f32],
+        k: usize,
+        ef: Option<usize>,
+        accept: &dyn Fn(&DocumentId) -> bool,
+    ) -> Vec<(DocumentId, f32)> {
+        if let (Self::Hnsw(g), Some(ef)) = (self, ef) {
+            return g.search(q, k, ef, accept);
+        }
+        let all: Box<dyn Iterator<Item = (&DocumentId, &[f32])>> = match self {
+            Self::Flat(m) => Box::new(m.iter().map(|(id, v)| (id, v.as_slice()))),
+            Self::Hnsw(g) => Box::new(g.live()),
+        };
+        let mut best: BinaryHeap<(Scored, &DocumentId)> = BinaryHeap::new();
+        for (id, v) in all.filter(|(id, _)| accept(id)) {
+            let d = Scored(metric.distance(q, v), 0);
+            if best.len() < k {
+                best.push((d, id));
+            } else if best.peek().is_some_and(|(worst, _)| d < *worst) {
+                best.pop();
+                best.push((d, id));
+            }
+        }
+        best.into_sorted_vec().into_iter().map(|(d, id)| (id.clone(), d.0)).collect()
+    }
+}
+
+/// A k-nearest-neighbour query against a vector index, as in a `$vectorSearch` stage.
+#[derive(Debug, Clone)]
+pub struct VectorQuery {
+    /// Field of the vector index to search.
+    pub path: String,
+    pub vector: Vec<f32>,
+    /// Number of neighbours returned.
+    pub limit: usize,
+    /// Candidates an HNSW search keeps while walking the graph; more find closer neighbours
+    /// at a higher cost. Defaults to `10 * limit`.
+    pub num_candidates: Option<usize>,
+    /// Only documents matching this filter are considered (a pre-filter).
+    pub filter: Option<Filter>,
+    /// Compare with every indexed vector even when the index has a graph.
+    pub exact: bool,
+}
+
+impl VectorQuery {
+    #[must_use]
+    pub fn new(path: &str, vector: Vec<f32>, limit: usize) -> Self {
+        Self {
+            path: path.to_string(),
+            vector,
+            limit,
+            num_candidates: None,
+            filter: None,
+            exact: false,
+        }
+    }
+    #[must_use]
+    pub const fn with_num_candidates(mut self, n: usize) -> Self {
+        self.num_candidates = Some(n);
+        self
+    }
+    #[must_use]
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+    #[must_use]
+    pub const fn with_exact(mut self, exact: bool) -> Self {
+        self.exact = exact;
+        self
+    }
+
+    /// HNSW search breadth.
+    fn candidates(&self) -> usize {
+        self.num_candidates.unwrap_or_else(|| self.limit.saturating_mul(10).min(MAX_CANDIDATES))
+    }
+
+    /// Parse the argument of a `$vectorSearch` stage: `{"path", "queryVector", "limit",
+    /// "numCandidates", "filter", "exact"}`, where `filter` takes the same JSON as `$match`.
+    ///
+    /// # Errors
+    /// Returns `DbError::QueryError` for unknown keys, a missing path, vector or limit, or a
+    /// malformed filter.
+    pub fn parse(arg: Bson) -> Result<Self, DbError> {
+        let invalid = |m: &str| DbError::QueryError(format!("$vectorSearch: {m}"));
+        let Bson::Document(d) = arg else {
+            return Err(invalid("requires an object"));
+        };
+        let count = |v: &Bson| match v {
+            Bson::Int32(i) => usize::try_from(*i).ok(),
+            Bson::Int64(i) => usize::try_from(*i).ok(),
+            _ => None,
+        };
+        let (mut path, mut vector, mut limit) = (None, None, None);
+        let mut q = Self::new("", Vec::new(), 0);
+        for (k, v) in d {
+            match k.as_str() {
+                "path" => match v {
+                    Bson::String(s) if !s.is_empty() && !s.starts_with('$') => path = Some(s),
+                    _ => return Err(invalid("path must be a field name")),
+                },
+                "queryVector" => match &v {
+                    Bson::Array(items) => {
+                        vector = Some(
+                            to_vector(items)
+                                .ok_or_else(|| invalid("queryVector must hold only numbers"))?,
+                        );
+                    }
+                    _ => return Err(invalid("queryVector must be an array")),
+                },
+                "limit" => {
+                    limit = Some(count(&v).ok_or_else(|| invalid("limit must be an integer"))?);
+                }
+                "numCandidates" => {
+                    let n = count(&v).ok_or_else(|| invalid("numCandidates must be an integer"))?;
+                    q.num_candidates = Some(n);
+                }
+                "filter" => {
+                    let fs: FilterSerde = bson::deserialize_from_bson(v)
+                        .map_err(|e| invalid(&format!("filter: {e}")))?;
+                    q.filter = Some(Filter::try_from(fs)?);
+                }
+                "exact" => match v {
+                    Bson::Boolean(b) => q.exact = b,
+                    _ => return Err(invalid("exact must be a boolean")),
+                },
+                other => return Err(invalid(&format!("unknown option '{other}'"))),
+            }
+        }
+        q.path = path.ok_or_else(|| invalid("requires a path"))?;
+        q.vector = vector.ok_or_else(|| invalid("requires a queryVector"))?;
+        q.limit = limit.ok_or_else(|| invalid("requires a limit"))?;
+        Ok(q)
+    }
+
+    fn check(&self) -> Result<(), DbError> {
+        if self.limit == 0 || self.limit > MAX_CANDIDATES {
+            return Err(DbError::QueryError(format!(
+                "vector search limit must be between 1 and {MAX_CANDIDATES}"
+            )));
+        }
+        if self.num_candidates.is_some_and(|n| n < self.limit || n > MAX_CANDIDATES) {
+            return Err(DbError::QueryError(format!(
+                "numCandidates must be between the limit and {MAX_CANDIDATES}"
+            )));
+        }
+        if self.vector.iter().any(|f| !f.is_finite()) {
+            return Err(DbError::QueryError("query vector must hold finite numbers".into()));
+        }
+        Ok(())
+    }
+}
+
+/// The `limit` documents whose vectors at `query.path` are most similar to `query.vector`,
+/// most similar first, each with its score (see [`Metric`]). The field needs a ready vector
+/// index. A pre-filter is resolved first, through the index planner; when it leaves fewer
+/// documents than the search would visit, they are compared exactly.
+///
+/// # Errors
+/// Returns `DbError::QueryError` if the field has no vector index, the query vector's length
+/// differs from the index's dimensions, or the limits are out of range.
+pub fn vector_search(
+    col: &Collection,
+    query: &VectorQuery,
+) -> Result<Vec<(Document, f64)>, DbError> {
+    query.check()?;
+    let allowed: Option<HashSet<DocumentId>> = query.filter.as_ref().map(|f| {
+        let f = text::bind(col, f);
+        let (chosen, _) = plan::choose_plan(col, &f);
+        let ids = plan::fetch_ids(col, &chosen.path).unwrap_or_else(|| col.list_ids());
+        ids.into_iter()
+            .filter(|id| col.find_document(id).is_some_and(|d| eval_filter(&d.data.0, &f)))
+            .collect()
+    });
+    let hits = {
+        let mut mgr = col.indexes.write();
+        let Some(IndexImpl::Vector(v)) = mgr.indexes.get_mut(&query.path).filter(|i| i.is_ready())
+        else {
+            return Err(DbError::QueryError(format!("no vector index on '{}'", query.path)));
+        };
+        if query.vector.len() != v.options.dimensions {
+            return Err(DbError::QueryError(format!(
+                "query vector has {} dimensions; the index on '{}' has {}",
+                query.vector.len(),
+                query.path,
+                v.options.dimensions
+            )));
+        }
+        let ef = query.candidates();
+        let exact = query.exact || allowed.as_ref().is_some_and(|a| a.len() <= ef);
+        let accept = |id: &DocumentId| allowed.as_ref().is_none_or(|a| a.contains(id));
+        let hits = v.store.search(
+            v.options.metric,
+            &query.vector,
+            query.limit,
+            (!exact).then_some(ef),
+            &accept,
+        );
+        let stats = &mut v.stats;
+        if hits.is_empty() {
+            stats.misses += 1;
+        } else {
+            stats.hits += 1;
+        }
+        let metric = v.options.metric;
+        hits.into_iter().map(|(id, d)| (id, metric.score(d))).collect::<Vec<_>>()
+    };
+    Ok(hits
+        .into_iter()
+        .filter_map(|(id, score)| col.find_document(&id).map(|d| (d, score)))
+        .collect())
+}
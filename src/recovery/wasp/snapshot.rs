@@ -4,19 +4,81 @@ use bincode::config::standard;
 use bincode::serde::{decode_from_slice, encode_to_vec};
 use serde::{Deserialize, Serialize};
 
-use crate::index::IndexDescriptor;
+use crate::index::{IndexDescriptor, IndexKind};
 use crate::types::Operation;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DbSnapshot {
     pub version: u32,
     pub operations: Vec<Operation>,
+    // Stored as JSON so descriptor options can grow without another snapshot version bump.
+    #[serde(with = "descriptors_json")]
     pub indexes: std::collections::HashMap<String, Vec<IndexDescriptor>>,
 }
 
 // Snapshot file wrapper with magic + version for forward/backward compatibility
 pub const SNAPSHOT_MAGIC: [u8; 4] = *b"NXL1";
-pub const SNAPSHOT_CURRENT_VERSION: u32 = 1;
+pub const SNAPSHOT_CURRENT_VERSION: u32 = 2;
+
+mod descriptors_json {
+    use super::IndexDescriptor;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::collections::HashMap;
+
+    pub fn serialize<S: Serializer>(
+        map: &HashMap<String, Vec<IndexDescriptor>>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        let json = serde_json::to_string(map).map_err(serde::ser::Error::custom)?;
+        s.serialize_str(&json)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<HashMap<String, Vec<IndexDescriptor>>, D::Error> {
+        let json = String::deserialize(d)?;
+        serde_json::from_str(&json).map_err(serde::de::Error::custom)
+    }
+}
+
+// Version 1 stored descriptors inline as `{ field, kind }`.
+#[derive(Deserialize)]
+struct IndexDescriptorV1 {
+    field: String,
+    kind: IndexKind,
+}
+
+#[derive(Deserialize)]
+struct DbSnapshotV1 {
+    version: u32,
+    operations: Vec<Operation>,
+    indexes: std::collections::HashMap<String, Vec<IndexDescriptorV1>>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotFileV1 {
+    #[allow(dead_code)]
+    magic: [u8; 4],
+    #[allow(dead_code)]
+    version: u32,
+    snapshot: DbSnapshotV1,
+}
+
+impl From<DbSnapshotV1> for DbSnapshot {
+    fn from(v1: DbSnapshotV1) -> Self {
+        Self {
+            version: v1.version,
+            operations: v1.operations,
+            indexes: v1
+                .indexes
+                .into_iter()
+                .map(|(c, ds)| {
+                    (c, ds.into_iter().map(|d| IndexDescriptor::new(&d.field, d.kind)).collect())
+                })
+                .collect(),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnapshotFile {
@@ -48,14 +110,21 @@ pub fn decode_snapshot_from_bytes(bytes: &[u8]) -> io::Result<DbSnapshot> {
             "snapshot decode: missing or invalid magic",
         ));
     }
-    let (file, _) = decode_from_slice::<SnapshotFile, _>(bytes, standard()).map_err(|e| {
-        io::Error::new(io::ErrorKind::InvalidData, format!("snapshot decode failed: {e}"))
-    })?;
-    if file.version > SNAPSHOT_CURRENT_VERSION {
+    let decode_err =
+        |e| io::Error::new(io::ErrorKind::InvalidData, format!("snapshot decode failed: {e}"));
+    let ((_magic, version), _) =
+        decode_from_slice::<([u8; 4], u32), _>(bytes, standard()).map_err(decode_err)?;
+    if version > SNAPSHOT_CURRENT_VERSION {
         return Err(io::Error::new(
             io::ErrorKind::Unsupported,
             "snapshot decode: version newer than this build supports",
         ));
     }
+    if version == 1 {
+        let (file, _) =
+            decode_from_slice::<SnapshotFileV1, _>(bytes, standard()).map_err(decode_err)?;
+        return Ok(file.snapshot.into());
+    }
+    let (file, _) = decode_from_slice::<SnapshotFile, _>(bytes, standard()).map_err(decode_err)?;
     Ok(file.snapshot)
 }
@@ -54,6 +54,12 @@ pub enum DeltaKey {
     F64(f64),
     I64(i64),
     Bool(bool),
+    // Variants below were added later; keep new ones at the end so existing logs still decode.
+    Null,
+    DateTime(i64),
+    ObjectId([u8; 12]),
+    Decimal128([u8; 16]),
+    Binary { subtype: u8, bytes: Vec<u8> },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
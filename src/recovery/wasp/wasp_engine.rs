@@ -34,6 +34,21 @@ pub trait StorageEngine: Send + Sync {
     fn read_index_deltas(&self) -> io::Result<Vec<IndexDelta>> {
         Ok(vec![])
     }
+    /// Append a group of operations followed by their index deltas. Engines that can
+    /// should write the whole group at once; the default appends frame by frame.
+    fn append_batch(
+        &mut self,
+        operations: &[Operation],
+        deltas: Vec<IndexDelta>,
+    ) -> io::Result<()> {
+        for op in operations {
+            self.append(op)?;
+        }
+        for delta in deltas {
+            self.append_index_delta(delta)?;
+        }
+        Ok(())
+    }
 }
 
 /// WASP: a buffered, hybrid crash-consistent storage engine.
@@ -187,6 +202,30 @@ impl StorageEngine for Wasp {
         self.file.flush()
     }
 
+    #[allow(clippy::missing_errors_doc)]
+    fn append_batch(
+        &mut self,
+        operations: &[Operation],
+        deltas: Vec<IndexDelta>,
+    ) -> io::Result<()> {
+        // Encode every frame up front so the batch reaches the file in a single write.
+        let mut buf = Vec::new();
+        let frames = operations
+            .iter()
+            .map(|op| WaspFrame::Op(op.clone()))
+            .chain(deltas.into_iter().map(WaspFrame::Idx));
+        for frame in frames {
+            let encoded = encode_to_vec(&frame, standard()).map_err(io::Error::other)?;
+            buf.extend_from_slice(&(crate::utils::num::usize_to_u64(encoded.len())).to_be_bytes());
+            buf.extend_from_slice(&encoded);
+        }
+        if buf.is_empty() {
+            return Ok(());
+        }
+        self.file.write_all(&buf)?;
+        self.file.flush()
+    }
+
     #[allow(clippy::missing_errors_doc)]
     fn read_index_deltas(&self) -> io::Result<Vec<IndexDelta>> {
         let mut file = self.file.try_clone()?;
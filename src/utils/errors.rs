@@ -56,6 +56,12 @@ pub enum DbError {
     #[error("Query error: {0}")]
     QueryError(String),
 
+    #[error("Duplicate key on unique index '{field}': {key}")]
+    DuplicateKey { field: String, key: String },
+
+    #[error("Unique index on '{field}' cannot be built: {} duplicate key(s)", .conflicts.len())]
+    UniqueIndexConflicts { field: String, conflicts: Vec<crate::index::UniqueConflict> },
+
     #[error("Database Not Found")]
     DatabaseNotFound,
 
@@ -48,7 +48,7 @@ fn benchmark_database_and_metrics() {
 
     // update many
     let upd = query::UpdateDoc { set: vec![("flag".into(), bson::Bson::Boolean(true))], ..Default::default() };
-    let upd_r = query::update_many(&col, &filter, &upd);
+    let upd_r = query::update_many(&col, &filter, &upd).unwrap();
     assert!(upd_r.matched >= upd_r.modified);
 
     // delete many (narrow filter)
@@ -87,6 +87,15 @@ fn benchmark_database_and_metrics() {
     let telemetry_obj_now = nexuslite::telemetry::metrics_json();
     assert!(telemetry_obj_now.get("rate_limited_total").and_then(|v| v.as_u64()).unwrap_or(0) >= 1);
 
+    // A scan split across workers reports their number and the speedup
+    engine.set_parallel_scan(query::ParallelScan { threads: 4, min_docs: 1 });
+    let config = nexuslite::cache::CacheConfig { capacity: 8192, ..Default::default() };
+    let wide = engine.create_collection_with_config("bm_parallel".to_string(), config);
+    for i in 0..5000i32 {
+        wide.insert_document(Document::new(doc! {"k": i, "x": i % 3}, DocumentType::Persistent));
+    }
+    assert_eq!(query::count_docs(&wide, &filter), 1667);
+
     // Collect benchmark logs (query and wasp recovery)
     let logs = drain();
     // WASP recovery init time present
@@ -95,11 +104,13 @@ fn benchmark_database_and_metrics() {
     // Query operations present with result_count and used_index
     assert!(logs.iter().any(|l| l.contains("\"bench\":\"query\"")));
     assert!(logs.iter().any(|l| l.contains("\"op\":\"find\"")));
-    assert!(logs.iter().any(|l| l.contains("\"result_count\"")));
+    assert!(logs.iter().any(|l| l.contains("\"op\":\"find\"") && l.contains("\"result_count\"")));
     assert!(logs.iter().any(|l| l.contains("\"used_index\":true")));
     assert!(logs.iter().any(|l| l.contains("\"op\":\"count\"")));
     assert!(logs.iter().any(|l| l.contains("\"op\":\"update_many\"")));
     assert!(logs.iter().any(|l| l.contains("\"op\":\"delete_many\"")));
+    assert!(logs.iter().any(|l| l.contains("\"op\":\"count\"") && l.contains("\"workers\":4")));
+    assert!(logs.iter().all(|l| !l.contains("\"workers\"") || l.contains("\"speedup\"")));
 
     // --- Persist a benchmarks.json for later inspection ---
     let mut parsed: Vec<Value> = Vec::new();
@@ -1 +1,6 @@
+use nexuslite::document::{Document, DocumentType};
 
+/// Wrap `d` in a persistent document.
+pub fn persistent(d: bson::Document) -> Document {
+    Document::new(d, DocumentType::Persistent)
+}
@@ -31,11 +31,8 @@ fn update_delete_on_missing_collection() {
     let dir = tempfile::tempdir().unwrap();
     let tmp = dir.path().join("api_update_missing.wal");
     let engine = Engine::new(tmp).unwrap();
-    let upd = query::UpdateDoc {
-        set: vec![("a".into(), bson::Bson::Int32(1))],
-        inc: vec![],
-        unset: vec![],
-    };
+    let upd =
+        query::UpdateDoc { set: vec![("a".into(), bson::Bson::Int32(1))], ..Default::default() };
     let f = query::Filter::True;
     let e1 = api::update_one(&engine, "nope", &f, &upd).unwrap_err();
     let e2 = api::delete_one(&engine, "nope", &f).unwrap_err();
@@ -1,10 +1,22 @@
 // Database-specific tests live here and include engine/index/paths/snapshot suites.
+#[path = "mod_async.rs"]
+mod async_tests;
 #[path = "mod_paths.rs"]
 mod db_paths_tests;
 #[path = "mod_engine.rs"]
 mod engine_tests;
+#[path = "mod_index_background.rs"]
+mod index_background_tests;
+#[path = "mod_index_keys.rs"]
+mod index_keys_tests;
+#[path = "mod_index_multikey.rs"]
+mod index_multikey_tests;
+#[path = "mod_index_partial.rs"]
+mod index_partial_tests;
 #[path = "mod_index.rs"]
 mod index_tests;
+#[path = "mod_index_unique.rs"]
+mod index_unique_tests;
 #[path = "mod_snapshot_open.rs"]
 mod snapshot_open_tests;
 #[path = "mod_snapshot.rs"]
@@ -0,0 +1,142 @@
+use bson::doc;
+use futures::StreamExt;
+use nexuslite::AsyncDatabase;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::errors::DbError;
+use nexuslite::export::ExportOptions;
+use nexuslite::import::{ImportFormat, ImportOptions};
+use nexuslite::query::{
+    Filter, FindOptions, Order, SortSpec, UpdateOptions, parse_filter_json, parse_pipeline_json,
+    parse_update_json,
+};
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::io::AsyncWriteExt;
+
+async fn open(dir: &tempfile::TempDir, name: &str) -> AsyncDatabase {
+    let path = dir.path().join(name);
+    AsyncDatabase::create(Some(path.to_str().unwrap())).await.unwrap()
+}
+
+fn doc_of(d: bson::Document) -> Document {
+    Document::new(d, DocumentType::Persistent)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_crud_and_queries_match_the_blocking_api() {
+    let dir = tempdir().unwrap();
+    let db = open(&dir, "async_crud.db").await;
+    db.create_collection("items").await.unwrap();
+    let mut ids = Vec::new();
+    for i in 0..300 {
+        ids.push(db.insert_document("items", doc_of(doc! {"i": i, "odd": i % 2})).await.unwrap());
+    }
+    let odd = parse_filter_json(r#"{"field": "odd", "$eq": 1}"#).unwrap();
+    assert_eq!(db.count("items", &odd).await.unwrap(), 150);
+
+    // Streamed results are the blocking cursor's, in order
+    let opts = FindOptions {
+        sort: Some(vec![SortSpec { field: "i".into(), order: Order::Desc }]),
+        limit: Some(100),
+        ..FindOptions::default()
+    };
+    let streamed: Vec<i32> = db
+        .find("items", &odd, &opts)
+        .await
+        .unwrap()
+        .map(|d| d.data.0.get_i32("i").unwrap())
+        .collect()
+        .await;
+    let blocking: Vec<i32> = db
+        .database()
+        .find("items", &odd, &opts)
+        .unwrap()
+        .map(|d| d.data.0.get_i32("i").unwrap())
+        .collect();
+    assert_eq!(streamed, blocking);
+    assert_eq!(streamed.len(), 100);
+    assert_eq!(streamed[0], 299);
+
+    let set = parse_update_json(r#"{"$set": {"seen": true}}"#).unwrap();
+    let report = db.update_many("items", &odd, &set, &UpdateOptions::default()).await.unwrap();
+    assert_eq!(report.modified, 150);
+    let pipeline =
+        parse_pipeline_json(r#"[{"$match": {"field": "seen", "$eq": true}}, {"$count": "n"}]"#)
+            .unwrap();
+    assert_eq!(db.aggregate("items", &pipeline).await.unwrap(), vec![doc! {"n": 150_i64}]);
+    assert!(db.delete_document("items", &ids[0]).await.unwrap());
+    assert_eq!(db.delete_many("items", &odd).await.unwrap().deleted, 150);
+    assert_eq!(db.count("items", &Filter::True).await.unwrap(), 149);
+
+    assert!(matches!(
+        db.insert_document("missing", doc_of(doc! {"a": 1})).await,
+        Err(DbError::NoSuchCollection(_))
+    ));
+    // A cursor dropped part-way leaves the database usable
+    let all = Filter::True;
+    let mut cursor = db.find("items", &all, &FindOptions::default()).await.unwrap();
+    assert!(cursor.next().await.is_some());
+    drop(cursor);
+    assert_eq!(db.count("items", &Filter::True).await.unwrap(), 149);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_import_and_export_round_trip() {
+    let dir = tempdir().unwrap();
+    let db = open(&dir, "async_io.db").await;
+    let mut data = String::new();
+    for i in 0..1000 {
+        data.push_str(&format!("{{\"i\": {i}}}\n"));
+    }
+    let opts = ImportOptions { collection: "rows".into(), ..ImportOptions::default() };
+    let report = db.import_from_reader(data.as_bytes(), ImportFormat::Ndjson, &opts).await.unwrap();
+    assert_eq!(report.inserted, 1000);
+    let err = db.import_from_reader(data.as_bytes(), ImportFormat::Auto, &opts).await;
+    assert!(matches!(err, Err(DbError::Io(_))));
+
+    let out = dir.path().join("rows.jsonl");
+    let written = db.export("rows", &out, &ExportOptions::default()).await.unwrap();
+    assert_eq!(written.written, 1000);
+    let again = ImportOptions { collection: "copy".into(), ..ImportOptions::default() };
+    assert_eq!(db.import(&out, &again).await.unwrap().inserted, 1000);
+    assert!(matches!(
+        db.export("nope", &out, &ExportOptions::default()).await,
+        Err(DbError::Io(_))
+    ));
+    db.checkpoint(dir.path().join("async_io.db")).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dropping_an_import_future_stops_the_import() {
+    let dir = tempdir().unwrap();
+    let db = open(&dir, "async_cancel.db").await;
+    let (mut tx, rx) = tokio::io::duplex(1 << 16);
+    for i in 0..10 {
+        tx.write_all(format!("{{\"i\": {i}}}\n").as_bytes()).await.unwrap();
+    }
+    // The source never ends: the import is still waiting for more when it is dropped
+    let opts = ImportOptions { collection: "partial".into(), ..ImportOptions::default() };
+    let import = db.import_from_reader(rx, ImportFormat::Ndjson, &opts);
+    assert!(tokio::time::timeout(Duration::from_millis(300), import).await.is_err());
+    // The source was dropped with the future, and the import stopped with it
+    assert!(tx.write_all(b"{\"i\": 10}\n").await.is_err());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let all = Filter::True;
+    assert!(db.count("partial", &all).await.unwrap() <= 10);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn an_import_that_stops_early_does_not_wait_for_the_source() {
+    let dir = tempdir().unwrap();
+    let db = open(&dir, "async_early.db").await;
+    let (mut tx, rx) = tokio::io::duplex(1 << 16);
+    tx.write_all(b"not json\n").await.unwrap();
+    // `tx` stays open, so after the first chunk the source is pending forever
+    let opts =
+        ImportOptions { collection: "bad".into(), skip_errors: false, ..ImportOptions::default() };
+    let import = db.import_from_reader(rx, ImportFormat::Ndjson, &opts);
+    let report = tokio::time::timeout(Duration::from_secs(5), import).await;
+    assert!(matches!(report, Ok(Err(DbError::Io(_)))));
+    // The source was dropped once the import returned
+    assert!(tx.write_all(b"{\"i\": 0}\n").await.is_err());
+}
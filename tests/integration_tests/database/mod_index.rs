@@ -1,9 +1,10 @@
 use bson::{Bson, Document as BsonDocument, doc};
 use nexuslite::engine::Engine;
 use nexuslite::index::{
-    IndexKind, IndexManager, index_insert_all, index_remove_all, lookup_eq, lookup_range,
+    IndexDescriptor, IndexKind, IndexManager, index_insert_all, index_remove_all, lookup_eq,
+    lookup_range,
 };
-use nexuslite::query::{self, CmpOp, Filter, FindOptions};
+use nexuslite::query::{self, CmpOp, Filter, FindOptions, HnswOptions, VectorOptions, VectorQuery};
 use nexuslite::types::DocumentId;
 use std::fs;
 use std::sync::{Mutex, OnceLock};
@@ -144,6 +145,9 @@ fn test_planner_uses_index_equality_stats_hit() {
             match idx {
                 nexuslite::index::IndexImpl::Hash(h) => hits = h.stats.hits,
                 nexuslite::index::IndexImpl::BTree(b) => hits = b.stats.hits,
+                nexuslite::index::IndexImpl::Text(t) => hits = t.stats.hits,
+                nexuslite::index::IndexImpl::Vector(v) => hits = v.stats.hits,
+                nexuslite::index::IndexImpl::Geo(g) => hits = g.stats.hits,
             }
         }
     }
@@ -256,6 +260,49 @@ fn test_index_metadata_version_bump() {
     });
 }
 
+#[test]
+fn test_vector_graph_persisted_with_index_metadata() {
+    with_env_lock(|| {
+        let dir = tempdir().unwrap();
+        let meta_path = dir.path().join("vector_graph_meta.json");
+        unsafe {
+            std::env::set_var("NEXUS_INDEX_META", meta_path.to_string_lossy().to_string());
+        }
+        let _ = fs::remove_file(&meta_path);
+
+        let engine = Engine::new(dir.path().join("wal_vector_meta.bin")).unwrap();
+        let col = engine.create_collection("vcol".into());
+        let ids: Vec<DocumentId> = (0..200i32)
+            .map(|i| {
+                let f = f64::from(i);
+                col.insert_document(nexuslite::document::Document::new(
+                    doc! {"v": [f.sin(), f.cos(), f / 200.0]},
+                    nexuslite::document::DocumentType::Persistent,
+                )).unwrap()
+            })
+            .collect();
+        let opts = VectorOptions::new(3).with_hnsw(HnswOptions::default());
+        let desc = IndexDescriptor::new("v", IndexKind::Vector).with_vector(opts);
+        col.create_index_with(&desc).unwrap();
+        engine.save_indexes_metadata().unwrap();
+        let meta: serde_json::Value =
+            serde_json::from_slice(&fs::read(&meta_path).unwrap()).unwrap();
+        assert_eq!(meta["graphs"]["vcol"]["v"]["nodes"].as_array().unwrap().len(), 200);
+        assert_eq!(meta["collections"]["vcol"][0]["vector"]["dimensions"], 3);
+
+        // Reloading restores the graph and drops the nodes whose documents are gone
+        col.indexes.write().drop_index("v");
+        assert!(col.delete_document(&ids[0]));
+        engine.load_indexes_metadata();
+        assert_eq!(col.indexes.read().indexes["v"].stats().docs, 199);
+        let q = VectorQuery::new("v", vec![0.5, 0.5, 0.5], 5);
+        let found = |q: &VectorQuery| -> Vec<DocumentId> {
+            query::vector_search(&col, q).unwrap().into_iter().map(|(d, _)| d.id).collect()
+        };
+        assert_eq!(found(&q), found(&q.clone().with_exact(true)));
+    });
+}
+
 #[test]
 fn test_index_build_mode_blocks_writes() {
     let dir = tempdir().unwrap();
@@ -0,0 +1,223 @@
+use bson::{Bson, doc};
+use nexuslite::api;
+use nexuslite::cache::CacheConfig;
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::index::{IndexDescriptor, IndexKind, IndexState};
+use nexuslite::query::{self, CmpOp, Filter, FindOptions};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+/// Background builds scan the cache, so size it to hold every test document.
+fn large_collection(engine: &Engine, name: &str) -> Arc<nexuslite::collection::Collection> {
+    let config = CacheConfig { capacity: 50_000, ..CacheConfig::default() };
+    engine.create_collection_with_config(name.into(), config)
+}
+
+fn eq(path: &str, value: impl Into<Bson>) -> Filter {
+    Filter::Cmp { path: path.into(), op: CmpOp::Eq, value: value.into() }
+}
+
+#[test]
+fn background_build_captures_concurrent_writes() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bg.wasp")).unwrap();
+    let col = large_collection(&engine, "events");
+    let mut ids = Vec::new();
+    for i in 0..5_000i32 {
+        ids.push(col.insert_document(persistent(doc! {"k": i % 100})).unwrap());
+    }
+
+    let handle = col.create_index_background(&IndexDescriptor::new("k", IndexKind::BTree));
+    // Writers proceed while the build runs
+    let writer = {
+        let col = Arc::clone(&col);
+        let ids = ids.clone();
+        std::thread::spawn(move || {
+            for id in ids.iter().take(500) {
+                col.delete_document(id);
+            }
+            for i in 0..500i32 {
+                col.insert_document(persistent(doc! {"k": 1_000 + i}));
+            }
+            col.update_document(&ids[600], persistent(doc! {"k": 9_999}));
+        })
+    };
+    writer.join().unwrap();
+    handle.join().unwrap().unwrap();
+
+    let (state, entries, docs) = {
+        let mgr = col.indexes.read();
+        let st = mgr.indexes["k"].stats();
+        (st.state.clone(), st.entries, st.docs)
+    };
+    assert_eq!(state, IndexState::Ready);
+    assert_eq!(entries, 5_000);
+    assert_eq!(docs, 5_000);
+
+    // The finished index agrees with a full scan for every key touched above
+    for k in [0i32, 1, 1_000, 1_499, 9_999] {
+        let by_index = query::find_docs(&col, &eq("k", k), &FindOptions::default()).to_vec();
+        let expected = col
+            .get_all_documents()
+            .into_iter()
+            .filter(|d| d.data.0.get("k") == Some(&Bson::Int32(k)))
+            .count();
+        assert_eq!(by_index.len(), expected, "k={k}");
+    }
+    assert!(col.indexes.read().indexes["k"].stats().hits > 0);
+}
+
+#[test]
+fn planner_ignores_indexes_that_are_not_ready() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bg_plan.wasp")).unwrap();
+    let col = engine.create_collection("events".into());
+    for i in 0..10i32 {
+        col.insert_document(persistent(doc! {"k": i}));
+    }
+    col.create_index("k", IndexKind::Hash);
+    col.indexes.write().indexes.get_mut("k").unwrap().stats_mut().state = IndexState::Building;
+
+    let docs = query::find_docs(&col, &eq("k", 3), &FindOptions::default()).to_vec();
+    assert_eq!(docs.len(), 1);
+    let st = col.indexes.read().indexes["k"].stats().clone();
+    assert_eq!((st.hits, st.misses), (0, 0));
+    // Not persisted either
+    assert!(col.indexes.read().descriptors().is_empty());
+}
+
+#[test]
+fn failed_unique_build_reports_state_in_info() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bg_fail.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.insert_document(persistent(doc! {"email": "a@x"}));
+    col.insert_document(persistent(doc! {"email": "a@x"}));
+    col.insert_document(persistent(doc! {"email": "b@x"}));
+
+    let desc = IndexDescriptor::new("email", IndexKind::Hash).with_unique(true);
+    let err = col.create_index_background(&desc).join().unwrap().unwrap_err();
+    match err {
+        DbError::UniqueIndexConflicts { conflicts, .. } => {
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].ids.len(), 2);
+        }
+        other => panic!("unexpected error: {other}"),
+    }
+
+    let info = api::info(&engine);
+    let users = info.collections.iter().find(|c| c.name == "users").unwrap();
+    assert!(users.indexes.is_empty());
+    let status = &users.index_status[0];
+    assert_eq!(status.field, "email");
+    assert!(matches!(status.state, IndexState::Failed(_)));
+
+    // Writes are not blocked or rejected by the failed index
+    col.try_insert_document(persistent(doc! {"email": "b@x"})).unwrap();
+    col.drop_index("email");
+    assert!(col.indexes.read().indexes.is_empty());
+}
+
+#[test]
+fn dropping_during_build_cancels_it() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bg_drop.wasp")).unwrap();
+    let col = large_collection(&engine, "events");
+    for i in 0..20_000i32 {
+        col.insert_document(persistent(doc! {"k": i}));
+    }
+    let handle = col.create_index_background(&IndexDescriptor::new("k", IndexKind::Hash));
+    col.drop_index("k");
+    // Either the build finished before the drop or it noticed and bailed out; in both cases
+    // the index is gone.
+    let _ = handle.join().unwrap();
+    assert!(!col.indexes.read().indexes.contains_key("k"));
+}
+
+#[test]
+fn ready_index_reports_full_progress() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bg_info.wasp")).unwrap();
+    let col = large_collection(&engine, "events");
+    for i in 0..3_000i32 {
+        col.insert_document(persistent(doc! {"tags": [i, i + 1]}));
+    }
+    col.create_index_background(&IndexDescriptor::new("tags", IndexKind::BTree))
+        .join()
+        .unwrap()
+        .unwrap();
+    let info = api::info(&engine);
+    let events = info.collections.iter().find(|c| c.name == "events").unwrap();
+    let status = &events.index_status[0];
+    assert_eq!(status.state, IndexState::Ready);
+    assert!((status.progress - 1.0).abs() < f64::EPSILON);
+    assert!(status.multikey);
+    assert_eq!(status.entries, 6_000);
+    assert_eq!(events.indexes.len(), 1);
+}
+
+#[test]
+fn overlapping_build_is_rejected_and_keeps_the_first_builds_writes() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bg_overlap.wasp")).unwrap();
+    let col = large_collection(&engine, "events");
+    for i in 0..40_000i32 {
+        col.insert_document(persistent(doc! {"k": i}));
+    }
+    let desc = IndexDescriptor::new("k", IndexKind::BTree);
+    let first = col.create_index_background(&desc);
+    col.insert_document(persistent(doc! {"k": -5}));
+    // Rejected whether the first build is still running or already ready
+    let second = col.create_index_background(&desc).join().unwrap();
+    assert!(matches!(second, Err(DbError::QueryError(_))));
+    first.join().unwrap().unwrap();
+
+    let st = col.indexes.read().indexes["k"].stats().clone();
+    assert_eq!((st.state, st.docs), (IndexState::Ready, 40_001));
+    let lt_zero = Filter::Cmp { path: "k".into(), op: CmpOp::Lt, value: Bson::Int32(0) };
+    assert_eq!(query::find_docs(&col, &lt_zero, &FindOptions::default()).to_vec().len(), 1);
+}
+
+#[test]
+fn rebuild_after_drop_keeps_writes_made_during_either_build() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bg_rebuild.wasp")).unwrap();
+    let col = large_collection(&engine, "events");
+    for i in 0..40_000i32 {
+        col.insert_document(persistent(doc! {"k": i}));
+    }
+    let desc = IndexDescriptor::new("k", IndexKind::BTree);
+    let first = col.create_index_background(&desc);
+    col.insert_document(persistent(doc! {"k": -5}));
+    col.drop_index("k");
+    let second = col.create_index_background(&desc);
+    col.insert_document(persistent(doc! {"k": -6}));
+    // The first build either finished before the drop or bails out without touching the
+    // second build's side buffer
+    let _ = first.join().unwrap();
+    second.join().unwrap().unwrap();
+
+    let st = col.indexes.read().indexes["k"].stats().clone();
+    assert_eq!((st.state, st.docs), (IndexState::Ready, 40_002));
+    let lt_zero = Filter::Cmp { path: "k".into(), op: CmpOp::Lt, value: Bson::Int32(0) };
+    assert_eq!(query::find_docs(&col, &lt_zero, &FindOptions::default()).to_vec().len(), 2);
+}
+
+#[test]
+fn background_build_leaves_a_ready_index_in_service() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bg_ready.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.insert_document(persistent(doc! {"email": "a@x"}));
+    let desc = IndexDescriptor::new("email", IndexKind::Hash).with_unique(true);
+    col.create_index_with(&desc).unwrap();
+
+    let err = col.create_index_background(&desc).join().unwrap().unwrap_err();
+    assert!(matches!(err, DbError::QueryError(_)), "{err}");
+    assert!(col.indexes.read().indexes["email"].is_ready());
+    let dup = col.try_insert_document(persistent(doc! {"email": "a@x"}));
+    assert!(matches!(dup, Err(DbError::DuplicateKey { .. })));
+}
@@ -0,0 +1,135 @@
+use bson::oid::ObjectId;
+use bson::spec::BinarySubtype;
+use bson::{Binary, Bson, DateTime, Decimal128, doc};
+use nexuslite::engine::Engine;
+use nexuslite::index::{IndexKind, key_from_bson, lookup_range};
+use nexuslite::query::{self, CmpOp, Filter, FindOptions, Order, SortSpec, compare_bson};
+use nexuslite::wasp::DeltaKey;
+use std::cmp::Ordering;
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+fn eq(path: &str, value: impl Into<Bson>) -> Filter {
+    Filter::Cmp { path: path.into(), op: CmpOp::Eq, value: value.into() }
+}
+
+fn samples() -> Vec<Bson> {
+    vec![
+        Bson::Null,
+        Bson::Boolean(false),
+        Bson::Boolean(true),
+        Bson::Int32(-3),
+        Bson::Int64(1),
+        Bson::Double(1.0),
+        Bson::Double(2.5),
+        Bson::Decimal128("1".parse::<Decimal128>().unwrap()),
+        Bson::Int64(i64::MAX),
+        Bson::String("a".into()),
+        Bson::String("b".into()),
+        Bson::Binary(Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2] }),
+        Bson::Binary(Binary { subtype: BinarySubtype::Uuid, bytes: vec![0] }),
+        Bson::ObjectId(ObjectId::from_bytes([1; 12])),
+        Bson::ObjectId(ObjectId::from_bytes([2; 12])),
+        Bson::DateTime(DateTime::from_millis(-5)),
+        Bson::DateTime(DateTime::from_millis(1_700_000_000_000)),
+    ]
+}
+
+#[test]
+fn key_order_matches_compare_bson() {
+    let vals = samples();
+    for a in &vals {
+        for b in &vals {
+            let ka = key_from_bson(a).unwrap();
+            let kb = key_from_bson(b).unwrap();
+            let c = compare_bson(a, b);
+            // Numerically equal values of different types are distinct keys but still adjacent.
+            if c != Ordering::Equal {
+                assert_eq!(ka.cmp(&kb), c, "{a:?} vs {b:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn dates_object_ids_decimals_binary_and_null_are_indexed() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("keys.wasp")).unwrap();
+    let col = engine.create_collection("events".into());
+    col.create_index("v", IndexKind::Hash);
+    let vals = samples();
+    for v in &vals {
+        col.insert_document(persistent(doc! {"v": v.clone()}));
+    }
+    assert_eq!(col.indexes.read().indexes["v"].stats().docs, vals.len());
+
+    for (hits, v) in vals.iter().enumerate() {
+        let docs = query::find_docs(&col, &eq("v", v.clone()), &FindOptions::default()).to_vec();
+        assert_eq!(docs.len(), 1, "{v:?}");
+        assert_eq!(col.indexes.read().indexes["v"].stats().hits, hits as u64 + 1);
+    }
+
+    // The on-disk deltas carry the new key types
+    let deltas = col.index_deltas();
+    assert!(deltas.iter().any(|d| matches!(d.key, DeltaKey::DateTime(-5))));
+    assert!(deltas.iter().any(|d| matches!(d.key, DeltaKey::Null)));
+    assert!(deltas.iter().any(|d| matches!(d.key, DeltaKey::Decimal128(_))));
+    assert!(deltas.iter().any(|d| matches!(d.key, DeltaKey::Binary { subtype: 4, .. })));
+}
+
+#[test]
+fn date_and_mixed_numeric_ranges_use_btree() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("ranges.wasp")).unwrap();
+    let col = engine.create_collection("events".into());
+    col.create_index("at", IndexKind::BTree);
+    col.create_index("n", IndexKind::BTree);
+    for day in 0..10i64 {
+        col.insert_document(persistent(doc! {"at": DateTime::from_millis(day * 86_400_000)}));
+    }
+    col.insert_document(persistent(doc! {"n": 1i32}));
+    col.insert_document(persistent(doc! {"n": 1i64}));
+    col.insert_document(persistent(doc! {"n": 1.0}));
+    col.insert_document(persistent(doc! {"n": Bson::Decimal128("1".parse().unwrap())}));
+    col.insert_document(persistent(doc! {"n": 1.5}));
+    col.insert_document(persistent(doc! {"n": "text"}));
+
+    let lo = Bson::DateTime(DateTime::from_millis(2 * 86_400_000));
+    let hi = Bson::DateTime(DateTime::from_millis(5 * 86_400_000));
+    let mut mgr = col.indexes.write();
+    assert_eq!(lookup_range(&mut mgr, "at", Some(&lo), Some(&hi), true, false).unwrap().len(), 3);
+
+    // All four representations of 1 are equal under compare_bson, so bounds treat them alike
+    let one = Bson::Int32(1);
+    assert_eq!(lookup_range(&mut mgr, "n", Some(&one), Some(&one), true, true).unwrap().len(), 4);
+    assert_eq!(lookup_range(&mut mgr, "n", Some(&one), None, false, false).unwrap().len(), 2);
+    assert!(lookup_range(&mut mgr, "n", Some(&one), Some(&one), false, false).is_none());
+    assert!(lookup_range(&mut mgr, "n", Some(&Bson::Int32(5)), Some(&one), true, true).is_none());
+}
+
+#[test]
+fn dates_filter_and_sort_by_value_with_and_without_an_index() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("dates.wasp")).unwrap();
+    let col = engine.create_collection("events".into());
+    for (i, day) in [7i64, -2, 3, 0, 5].into_iter().enumerate() {
+        let at = DateTime::from_millis(day * 86_400_000);
+        col.insert_document(persistent(doc! {"i": i as i32, "at": at}));
+    }
+    let after = Filter::Cmp {
+        path: "at".into(),
+        op: CmpOp::Gt,
+        value: Bson::DateTime(DateTime::from_millis(86_400_000)),
+    };
+    let opts = FindOptions {
+        sort: Some(vec![SortSpec { field: "at".into(), order: Order::Desc }]),
+        ..FindOptions::default()
+    };
+    let order = |col| -> Vec<i32> {
+        query::find_docs(col, &after, &opts).map(|d| d.data.0.get_i32("i").unwrap()).collect()
+    };
+    assert_eq!(order(&col), [0, 4, 2]);
+    col.create_index("at", IndexKind::BTree);
+    assert_eq!(order(&col), [0, 4, 2]);
+}
@@ -0,0 +1,121 @@
+use bson::{Bson, doc};
+use nexuslite::engine::Engine;
+use nexuslite::index::{IndexDescriptor, IndexKind};
+use nexuslite::query::{self, CmpOp, Filter, FindOptions, eval_filter, parse_filter_json};
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+fn eq(path: &str, value: impl Into<Bson>) -> Filter {
+    Filter::Cmp { path: path.into(), op: CmpOp::Eq, value: value.into() }
+}
+
+#[test]
+fn array_field_gets_one_entry_per_element() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("multikey.wasp")).unwrap();
+    let col = engine.create_collection("posts".into());
+    col.create_index("tags", IndexKind::Hash);
+    col.insert_document(persistent(doc! {"tags": ["a", "b", "c"]}));
+    col.insert_document(persistent(doc! {"tags": ["b", "b"]}));
+    col.insert_document(persistent(doc! {"tags": "c"}));
+
+    let stats = col.indexes.read().indexes["tags"].stats().clone();
+    assert_eq!(stats.keys, 3);
+    assert_eq!(stats.entries, 5); // duplicate elements within a document count once
+    assert_eq!(stats.docs, 3);
+    assert!(stats.multikey);
+    assert!((stats.fanout() - 5.0 / 3.0).abs() < 1e-9);
+
+    let docs = query::find_docs(&col, &eq("tags", "b"), &FindOptions::default()).to_vec();
+    assert_eq!(docs.len(), 2);
+    assert_eq!(col.indexes.read().indexes["tags"].stats().hits, 1);
+}
+
+#[test]
+fn nested_paths_through_arrays_of_subdocuments() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("multikey_nested.wasp")).unwrap();
+    let col = engine.create_collection("orders".into());
+    col.create_index("items.sku", IndexKind::BTree);
+    col.insert_document(persistent(doc! {"items": [{"sku": "x1", "qty": 1}, {"sku": "y2"}]}));
+    col.insert_document(persistent(doc! {"items": [{"sku": "y2"}]}));
+    col.insert_document(persistent(doc! {"items": {"sku": "z3"}}));
+
+    assert_eq!(col.indexes.read().indexes["items.sku"].stats().entries, 4);
+    let docs = query::find_docs(&col, &eq("items.sku", "y2"), &FindOptions::default()).to_vec();
+    assert_eq!(docs.len(), 2);
+    assert_eq!(col.indexes.read().indexes["items.sku"].stats().hits, 1);
+
+    // Range lookups report a document once even when several of its keys fall in range
+    let ids = nexuslite::index::lookup_range(
+        &mut col.indexes.write(),
+        "items.sku",
+        Some(&Bson::String("a".into())),
+        None,
+        true,
+        false,
+    )
+    .unwrap();
+    assert_eq!(ids.len(), 3);
+}
+
+#[test]
+fn update_and_delete_remove_every_element_key() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("multikey_rm.wasp")).unwrap();
+    let col = engine.create_collection("posts".into());
+    col.create_index("tags", IndexKind::Hash);
+    let id = col.insert_document(persistent(doc! {"tags": ["a", "b"]})).unwrap();
+    let other = col.insert_document(persistent(doc! {"tags": ["b"]})).unwrap();
+
+    assert!(col.update_document(&id, persistent(doc! {"tags": ["c"]})));
+    let stats = col.indexes.read().indexes["tags"].stats().clone();
+    assert_eq!((stats.keys, stats.entries, stats.docs), (2, 2, 2));
+    assert!(query::find_docs(&col, &eq("tags", "a"), &FindOptions::default()).to_vec().is_empty());
+
+    assert!(col.delete_document(&other));
+    let stats = col.indexes.read().indexes["tags"].stats().clone();
+    assert_eq!((stats.keys, stats.entries, stats.docs), (1, 1, 1));
+
+    // One delta per element key
+    let adds = col
+        .index_deltas()
+        .into_iter()
+        .filter(|d| d.field == "tags" && matches!(d.op, nexuslite::wasp::DeltaOp::Add))
+        .count();
+    assert_eq!(adds, 4);
+}
+
+#[test]
+fn unique_multikey_rejects_element_shared_with_another_document() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("multikey_unique.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.create_index_with(&IndexDescriptor::new("emails", IndexKind::Hash).with_unique(true))
+        .unwrap();
+    col.try_insert_document(persistent(doc! {"emails": ["a@x", "a@x", "b@x"]})).unwrap();
+    let err = col.try_insert_document(persistent(doc! {"emails": ["c@x", "b@x"]})).unwrap_err();
+    assert!(matches!(err, nexuslite::errors::DbError::DuplicateKey { .. }));
+}
+
+#[test]
+fn array_aware_filter_semantics() {
+    let d = doc! {"tags": ["red", "blue"], "scores": [3, 9], "items": [{"sku": "x"}, {"sku": "y"}]};
+    assert!(eval_filter(&d, &eq("tags", "red")));
+    assert!(eval_filter(&d, &eq("tags", Bson::Array(vec!["red".into(), "blue".into()]))));
+    assert!(!eval_filter(&d, &eq("tags", "green")));
+    assert!(eval_filter(&d, &eq("items.sku", "y")));
+    assert!(eval_filter(&d, &eq("items.1.sku", "y")));
+    assert!(!eval_filter(&d, &eq("items.0.sku", "y")));
+    let f = parse_filter_json(r#"{"field":"scores","$gt":5}"#).unwrap();
+    assert!(eval_filter(&d, &f));
+    let f = parse_filter_json(r#"{"field":"scores","$gt":10}"#).unwrap();
+    assert!(!eval_filter(&d, &f));
+    let f = parse_filter_json(r#"{"field":"tags","$in":["green","blue"]}"#).unwrap();
+    assert!(eval_filter(&d, &f));
+    let f = parse_filter_json(r#"{"field":"tags","$nin":["green","blue"]}"#).unwrap();
+    assert!(!eval_filter(&d, &f));
+    let f = parse_filter_json(r#"{"field":"items.sku","$exists":true}"#).unwrap();
+    assert!(eval_filter(&d, &f));
+}
@@ -0,0 +1,124 @@
+use bson::{Bson, doc};
+use nexuslite::Database;
+use nexuslite::engine::Engine;
+use nexuslite::index::{IndexDescriptor, IndexKind};
+use nexuslite::query::{self, CmpOp, Filter, FindOptions, filter_implies, parse_filter_json};
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+fn index_hits(col: &nexuslite::collection::Collection, field: &str) -> u64 {
+    col.indexes.read().indexes.get(field).map_or(0, |i| i.stats().hits)
+}
+
+#[test]
+fn partial_index_only_holds_matching_documents() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("partial.wasp")).unwrap();
+    let col = engine.create_collection("tickets".into());
+    for i in 0..20i32 {
+        let status = if i % 4 == 0 { "error" } else { "ok" };
+        col.insert_document(persistent(doc! {"status": status, "code": i}));
+    }
+    let pred = parse_filter_json(r#"{"field":"status","$eq":"error"}"#).unwrap();
+    col.create_index_with(&IndexDescriptor::new("code", IndexKind::Hash).with_partial_filter(pred))
+        .unwrap();
+    assert_eq!(col.indexes.read().indexes["code"].stats().entries, 5);
+
+    // Later writes respect the predicate too
+    col.insert_document(persistent(doc! {"status": "ok", "code": 100}));
+    col.insert_document(persistent(doc! {"status": "error", "code": 101}));
+    assert_eq!(col.indexes.read().indexes["code"].stats().entries, 6);
+}
+
+#[test]
+fn planner_uses_partial_index_only_when_query_implies_predicate() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("partial_plan.wasp")).unwrap();
+    let col = engine.create_collection("tickets".into());
+    for i in 0..20i32 {
+        let status = if i % 2 == 0 { "error" } else { "ok" };
+        col.insert_document(persistent(doc! {"status": status, "code": i % 5}));
+    }
+    let pred = parse_filter_json(r#"{"field":"status","$eq":"error"}"#).unwrap();
+    col.create_index_with(&IndexDescriptor::new("code", IndexKind::Hash).with_partial_filter(pred))
+        .unwrap();
+
+    // Not implied: must full-scan and still see documents outside the predicate
+    let q = Filter::Cmp { path: "code".into(), op: CmpOp::Eq, value: 1.into() };
+    let docs = query::find_docs(&col, &q, &FindOptions::default()).to_vec();
+    assert_eq!(docs.len(), 4);
+    assert_eq!(index_hits(&col, "code"), 0);
+
+    // Implied: the partial index answers the query
+    let q = parse_filter_json(
+        r#"{"$and":[{"field":"status","$eq":"error"},{"field":"code","$eq":1}]}"#,
+    )
+    .unwrap();
+    let docs = query::find_docs(&col, &q, &FindOptions::default()).to_vec();
+    assert_eq!(docs.len(), 2);
+    assert!(index_hits(&col, "code") > 0);
+}
+
+#[test]
+fn sparse_index_skips_missing_and_null() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("sparse.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.insert_document(persistent(doc! {"deleted_at": 5}));
+    col.insert_document(persistent(doc! {"deleted_at": Bson::Null}));
+    col.insert_document(persistent(doc! {"name": "x"}));
+    col.create_index_with(
+        &IndexDescriptor::new("deleted_at", IndexKind::BTree).with_sparse(true).with_unique(true),
+    )
+    .unwrap();
+    assert_eq!(col.indexes.read().indexes["deleted_at"].stats().entries, 1);
+    // Unindexed documents never collide on a sparse unique index
+    col.try_insert_document(persistent(doc! {"deleted_at": Bson::Null})).unwrap();
+    let q = Filter::Cmp { path: "deleted_at".into(), op: CmpOp::Eq, value: Bson::Null };
+    assert_eq!(query::count_docs(&col, &q), 2);
+}
+
+#[test]
+fn filter_implication_rules() {
+    let p = |j: &str| parse_filter_json(j).unwrap();
+    let pred = p(r#"{"field":"age","$gte":18}"#);
+    assert!(filter_implies(&p(r#"{"field":"age","$gt":30}"#), &pred));
+    assert!(filter_implies(&p(r#"{"field":"age","$eq":18}"#), &pred));
+    assert!(filter_implies(&p(r#"{"field":"age","$in":[20,40]}"#), &pred));
+    assert!(!filter_implies(&p(r#"{"field":"age","$gt":10}"#), &pred));
+    assert!(!filter_implies(&p(r#"{"field":"age","$lt":30}"#), &pred));
+    assert!(!filter_implies(&p(r#"{"field":"other","$gt":30}"#), &pred));
+    assert!(filter_implies(
+        &p(r#"{"$or":[{"field":"age","$eq":20},{"field":"age","$gt":50}]}"#),
+        &pred
+    ));
+    let exists = p(r#"{"field":"error_code","$exists":true}"#);
+    assert!(filter_implies(&p(r#"{"field":"error_code","$eq":7}"#), &exists));
+    assert!(!filter_implies(&p(r#"{"field":"status","$eq":7}"#), &exists));
+}
+
+#[test]
+fn partial_predicate_survives_checkpoint_and_reopen() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("partial.db");
+    let db = Database::new(db_path.to_str()).unwrap();
+    let col = db.create_collection("events");
+    let pred = parse_filter_json(r#"{"field":"error_code","$exists":true}"#).unwrap();
+    col.create_index_with(
+        &IndexDescriptor::new("error_code", IndexKind::Hash)
+            .with_partial_filter(pred)
+            .with_sparse(true),
+    )
+    .unwrap();
+    db.checkpoint(&db_path).unwrap();
+
+    let db2 = Database::open(db_path.to_str().unwrap()).unwrap();
+    let descs = db2.get_collection("events").unwrap().indexes.read().descriptors();
+    let d = descs.iter().find(|d| d.field == "error_code").unwrap();
+    assert!(d.sparse);
+    assert!(matches!(
+        d.partial_filter,
+        Some(Filter::Exists { ref path, exists: true }) if path == "error_code"
+    ));
+}
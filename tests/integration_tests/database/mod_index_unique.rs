@@ -0,0 +1,146 @@
+use bson::doc;
+use nexuslite::Database;
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::import::{ImportFormat, ImportOptions, import_from_reader};
+use nexuslite::index::{IndexDescriptor, IndexKind};
+use nexuslite::query::{self, CmpOp, Filter};
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+#[test]
+fn unique_index_rejects_duplicate_insert() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("uniq_insert.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.create_index_with(&IndexDescriptor::new("email", IndexKind::Hash).with_unique(true))
+        .unwrap();
+
+    col.try_insert_document(persistent(doc! {"email": "a@x.io"})).unwrap();
+    let err = col.try_insert_document(persistent(doc! {"email": "a@x.io"})).unwrap_err();
+    assert!(matches!(err, DbError::DuplicateKey { ref field, .. } if field == "email"));
+    // Rejected documents are not stored
+    assert_eq!(col.get_all_documents().len(), 1);
+    // Documents without the key do not collide with each other
+    col.try_insert_document(persistent(doc! {"name": "x"})).unwrap();
+    col.try_insert_document(persistent(doc! {"name": "y"})).unwrap();
+}
+
+#[test]
+fn unique_btree_index_rejects_duplicate_update() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("uniq_update.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.create_index_with(&IndexDescriptor::new("n", IndexKind::BTree).with_unique(true)).unwrap();
+    let a = col.try_insert_document(persistent(doc! {"n": 1})).unwrap();
+    col.try_insert_document(persistent(doc! {"n": 2})).unwrap();
+
+    // Re-writing a document with its own key is not a violation
+    assert!(col.try_update_document(&a, persistent(doc! {"n": 1, "v": true})).unwrap());
+    let err = col.try_update_document(&a, persistent(doc! {"n": 2})).unwrap_err();
+    assert!(matches!(err, DbError::DuplicateKey { .. }));
+    assert_eq!(col.find_document(&a).unwrap().data.0.get_i32("n").unwrap(), 1);
+
+    let filter = Filter::Cmp { path: "n".into(), op: CmpOp::Eq, value: 1.into() };
+    let upd = query::parse_update_json(r#"{"$set":{"n":2}}"#).unwrap();
+    assert!(matches!(query::update_one(&col, &filter, &upd), Err(DbError::DuplicateKey { .. })));
+}
+
+#[test]
+fn unique_violation_in_update_many_writes_nothing() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("uniq_update_many.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.create_index_with(&IndexDescriptor::new("name", IndexKind::Hash).with_unique(true))
+        .unwrap();
+    for name in ["a", "b", "c"] {
+        col.try_insert_document(persistent(doc! {"name": name, "team": 1})).unwrap();
+    }
+
+    let filter = Filter::Cmp { path: "team".into(), op: CmpOp::Eq, value: 1.into() };
+    let upd = query::parse_update_json(r#"{"$set":{"name":"z"}}"#).unwrap();
+    let err = query::update_many(&col, &filter, &upd).unwrap_err();
+    assert!(matches!(err, DbError::DuplicateKey { ref field, .. } if field == "name"));
+    // The match that was written before the violation is put back as well
+    let mut names: Vec<String> = col
+        .get_all_documents()
+        .iter()
+        .map(|d| d.data.0.get_str("name").unwrap().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["a", "b", "c"]);
+    col.try_insert_document(persistent(doc! {"name": "z"})).unwrap();
+}
+
+#[test]
+fn unique_index_build_reports_conflicts() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("uniq_build.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    for e in ["a", "b", "a", "c", "b", "a"] {
+        col.insert_document(persistent(doc! {"email": e}));
+    }
+    let err = col
+        .create_index_with(&IndexDescriptor::new("email", IndexKind::Hash).with_unique(true))
+        .unwrap_err();
+    match err {
+        DbError::UniqueIndexConflicts { field, conflicts } => {
+            assert_eq!(field, "email");
+            assert_eq!(conflicts.len(), 2);
+            let a = conflicts.iter().find(|c| c.key == "\"a\"").unwrap();
+            assert_eq!(a.ids.len(), 3);
+        }
+        other => panic!("unexpected error: {other}"),
+    }
+    // The failed build does not register the index
+    assert!(col.indexes.read().descriptors().is_empty());
+}
+
+#[test]
+fn unique_index_import_routes_violations_to_sidecar() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("uniq_import.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.create_index_with(&IndexDescriptor::new("email", IndexKind::Hash).with_unique(true))
+        .unwrap();
+    let sidecar = dir.path().join("errors.ndjson");
+    let data = "{\"email\":\"a\"}\n{\"email\":\"b\"}\n{\"email\":\"a\"}\n";
+    let opts = ImportOptions {
+        collection: "users".into(),
+        skip_errors: true,
+        error_sidecar: Some(sidecar.clone()),
+        ..Default::default()
+    };
+    let report = import_from_reader(&engine, data.as_bytes(), ImportFormat::Ndjson, &opts).unwrap();
+    assert_eq!(report.inserted, 2);
+    assert_eq!(report.skipped, 1);
+    let side = std::fs::read_to_string(sidecar).unwrap();
+    assert!(side.contains("\"line\":3"));
+    assert!(side.contains("Duplicate key"));
+
+    let strict =
+        ImportOptions { collection: "users".into(), skip_errors: false, ..Default::default() };
+    let err =
+        import_from_reader(&engine, &b"{\"email\":\"b\"}\n"[..], ImportFormat::Ndjson, &strict)
+            .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn unique_flag_survives_checkpoint_and_reopen() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("uniq.db");
+    let db = Database::new(db_path.to_str()).unwrap();
+    let col = db.create_collection("users");
+    col.create_index_with(&IndexDescriptor::new("email", IndexKind::Hash).with_unique(true))
+        .unwrap();
+    db.insert_document("users", persistent(doc! {"email": "a"})).unwrap();
+    let err = db.insert_document("users", persistent(doc! {"email": "a"})).unwrap_err();
+    assert!(matches!(err, DbError::DuplicateKey { .. }));
+    db.checkpoint(&db_path).unwrap();
+
+    let db2 = Database::open(db_path.to_str().unwrap()).unwrap();
+    let descs = db2.get_collection("users").unwrap().indexes.read().descriptors();
+    assert!(descs.iter().any(|d| d.field == "email" && d.unique));
+}
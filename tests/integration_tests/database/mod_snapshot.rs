@@ -109,3 +109,41 @@ fn snapshot_newer_version_errors_gracefully() {
     let err = nexuslite::wasp::decode_snapshot_from_bytes(&bytes).unwrap_err();
     assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
 }
+
+#[test]
+fn snapshot_v1_descriptors_still_decode() {
+    use bincode::config::standard;
+    use bincode::serde::encode_to_vec;
+
+    #[derive(serde::Serialize)]
+    struct DescV1 {
+        field: String,
+        kind: IndexKind,
+    }
+    #[derive(serde::Serialize)]
+    struct SnapV1 {
+        version: u32,
+        operations: Vec<nexuslite::types::Operation>,
+        indexes: std::collections::HashMap<String, Vec<DescV1>>,
+    }
+    #[derive(serde::Serialize)]
+    struct FileV1 {
+        magic: [u8; 4],
+        version: u32,
+        snapshot: SnapV1,
+    }
+
+    let mut indexes = std::collections::HashMap::new();
+    indexes
+        .insert("users".to_string(), vec![DescV1 { field: "age".into(), kind: IndexKind::BTree }]);
+    let file = FileV1 {
+        magic: *b"NXL1",
+        version: 1,
+        snapshot: SnapV1 { version: 1, operations: Vec::new(), indexes },
+    };
+    let bytes = encode_to_vec(&file, standard()).expect("encode");
+    let snap = nexuslite::wasp::decode_snapshot_from_bytes(&bytes).unwrap();
+    let users = snap.indexes.get("users").unwrap();
+    assert_eq!(users[0].field, "age");
+    assert!(!users[0].unique);
+}
@@ -173,3 +173,51 @@ fn test_import_csv_type_infer() {
     assert_eq!(docs[0].data.0.get_i64("a").unwrap(), 1);
     assert!(docs[0].data.0.get_bool("b").unwrap());
 }
+
+#[test]
+fn test_import_batches_report_per_record_errors() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("imp_batch.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.create_index_with(
+        &nexuslite::index::IndexDescriptor::new("name", nexuslite::index::IndexKind::Hash)
+            .with_unique(true),
+    )
+    .unwrap();
+    // A duplicate inside the first batch, a parse error and a duplicate in the second
+    let data = "{\"name\":\"a\"}\n{\"name\":\"a\"}\n{\"name\":\"b\"}\nnot-json\n{\"name\":\"b\"}\n{\"name\":\"c\"}\n";
+    let sidecar = dir.path().join("errors.ndjson");
+    let opts = ImportOptions {
+        collection: "users".into(),
+        batch_size: 3,
+        skip_errors: true,
+        error_sidecar: Some(sidecar.clone()),
+        ..Default::default()
+    };
+    let report =
+        import_from_reader(&engine, Cursor::new(data.as_bytes()), ImportFormat::Ndjson, &opts)
+            .unwrap();
+    assert_eq!((report.inserted, report.skipped), (3, 3));
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(&sidecar)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    let at: Vec<u64> = lines.iter().map(|v| v["line"].as_u64().unwrap()).collect();
+    assert_eq!(at, [2, 4, 5]);
+
+    // Without skip_errors the import stops at the first failure, keeping earlier records
+    let engine = Engine::new(dir.path().join("imp_batch_strict.wasp")).unwrap();
+    let opts = ImportOptions {
+        collection: "users".into(),
+        batch_size: 3,
+        skip_errors: false,
+        ..Default::default()
+    };
+    let data = "{\"name\":\"a\"}\n{\"name\":\"b\"}\nnot-json\n{\"name\":\"c\"}\n";
+    assert!(
+        import_from_reader(&engine, Cursor::new(data.as_bytes()), ImportFormat::Ndjson, &opts)
+            .is_err()
+    );
+    assert_eq!(engine.get_collection("users").unwrap().get_all_documents().len(), 2);
+}
@@ -1,8 +1,43 @@
+#[path = "mod_query_aggregate.rs"]
+mod query_aggregate_tests;
+#[path = "mod_query_bulk.rs"]
+mod query_bulk_tests;
+#[path = "mod_query_collation.rs"]
+mod query_collation_tests;
+#[path = "mod_query_cursor.rs"]
+mod query_cursor_tests;
+#[path = "mod_query_explain.rs"]
+mod query_explain_tests;
+#[path = "mod_query_expr.rs"]
+mod query_expr_tests;
 #[path = "mod_query_features.rs"]
 mod query_features_tests;
+#[path = "mod_query_find_and_modify.rs"]
+mod query_find_and_modify_tests;
+#[path = "mod_query_geo.rs"]
+mod query_geo_tests;
+#[path = "mod_query_keyset.rs"]
+mod query_keyset_tests;
+#[path = "mod_query_lookup.rs"]
+mod query_lookup_tests;
+#[path = "mod_query_parallel.rs"]
+mod query_parallel_tests;
+#[path = "mod_query_projection.rs"]
+mod query_projection_tests;
+#[cfg(feature = "regex")]
+#[path = "mod_query_regex.rs"]
+mod query_regex_tests;
 #[path = "mod_query.rs"]
 mod query_tests;
+#[path = "mod_query_text.rs"]
+mod query_text_tests;
+#[path = "mod_query_update_ops.rs"]
+mod query_update_ops_tests;
+#[path = "mod_query_upsert.rs"]
+mod query_upsert_tests;
+#[path = "mod_query_vector.rs"]
+mod query_vector_tests;
 // Telemetry lives under query in src; tests live here as well
+mod benchmarks;
 mod telemetry_edges_tests;
 mod telemetry_tests;
-mod benchmarks;
@@ -1,7 +1,8 @@
 use bson::doc;
 use nexuslite::document::{Document, DocumentType};
 use nexuslite::query::{
-    CmpOp, Filter, FindOptions, Order, SortSpec, UpdateDoc, apply_update, count_docs, find_docs,
+    CmpOp, Filter, FindOptions, Order, Projection, SortSpec, UpdateDoc, apply_update, count_docs,
+    find_docs,
 };
 
 #[test]
@@ -23,10 +24,11 @@ fn update_set_inc_unset() {
     let mut d = Document::new(doc! {"age": 30, "info": {"visits": 1}}, DocumentType::Persistent);
     let upd = UpdateDoc {
         set: vec![("name".into(), "alice".into())],
-        inc: vec![("age".into(), 1.0), ("info.visits".into(), 2.0)],
+        inc: vec![("age".into(), 1.0.into()), ("info.visits".into(), 2.0.into())],
         unset: vec!["unused".into()],
+        ..Default::default()
     };
-    let changed = apply_update(&mut d, &upd);
+    let changed = apply_update(&mut d, &upd).unwrap();
     assert!(changed);
     assert_eq!(d.data.0.get_str("name").unwrap(), "alice");
     assert_eq!(d.data.0.get_f64("age").unwrap(), 31.0);
@@ -35,10 +37,10 @@ fn update_set_inc_unset() {
 
 #[test]
 fn update_inc_int64_and_cmp_lt_not() {
-    // Int64 increment should be handled and result stored as Double
+    // A double increment widens the Int64 to a Double
     let mut d = Document::new(doc! {"age": bson::Bson::Int64(30)}, DocumentType::Persistent);
-    let upd = UpdateDoc { set: vec![], inc: vec![("age".into(), 2.0)], unset: vec![] };
-    let changed = apply_update(&mut d, &upd);
+    let upd = UpdateDoc { inc: vec![("age".into(), 2.0.into())], ..Default::default() };
+    let changed = apply_update(&mut d, &upd).unwrap();
     assert!(changed);
     assert_eq!(d.data.0.get_f64("age").unwrap(), 32.0);
 
@@ -64,11 +66,14 @@ fn find_sort_project_paginate() {
 
     let filter = Filter::Cmp { path: "age".into(), op: CmpOp::Gt, value: 30.into() };
     let opts = FindOptions {
-        projection: Some(vec!["name".into()]),
+        projection: Some(Projection::include(["name"])),
         sort: Some(vec![SortSpec { field: "age".into(), order: Order::Desc }]),
         limit: Some(2),
         skip: Some(0),
         timeout_ms: None,
+        sort_memory_limit: None,
+        after: None,
+        collation: None,
     };
     let cur = find_docs(&col, &filter, &opts);
     let docs = cur.to_vec();
@@ -90,7 +95,7 @@ fn test_query_find_redaction() {
     let id = col.insert_document(nexuslite::document::Document::new(
         doc! {"user":"alice","password":"secret"},
         nexuslite::document::DocumentType::Persistent,
-    ));
+    )).unwrap();
     assert!(col.find_document(&id).is_some());
     // Use programmatic CLI path to emit NDJSON with redaction
     let _ = nexuslite::cli::run(
@@ -102,6 +107,7 @@ fn test_query_find_redaction() {
             sort: None,
             limit: None,
             skip: None,
+            after: None,
             redact_fields: Some(vec!["password".into()]),
         },
     );
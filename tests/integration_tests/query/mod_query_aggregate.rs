@@ -0,0 +1,213 @@
+use bson::{Bson, doc};
+use nexuslite::Database;
+use nexuslite::cli::{Command, run};
+use nexuslite::engine::Engine;
+use nexuslite::index::IndexKind;
+use nexuslite::query::{Stage, aggregate, parse_filter_json, parse_pipeline_json};
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+fn orders(engine: &Engine) -> std::sync::Arc<nexuslite::collection::Collection> {
+    let col = engine.create_collection("orders".into());
+    let rows = [
+        doc! {"city": "paris", "qty": 2, "price": 1.5, "tags": ["a", "b"], "who": {"name": "ann"}},
+        doc! {"city": "rome", "qty": 5, "price": 2.0, "tags": ["b"], "who": {"name": "bob"}},
+        doc! {"city": "paris", "qty": 1, "price": 4.0, "tags": [], "who": {"name": "cy"}},
+        doc! {"city": "oslo", "qty": 7, "price": 1.0, "who": {"name": "dee"}},
+        doc! {"city": "rome", "qty": 3, "price": 3.0, "tags": ["a"], "who": {"name": "eve"}},
+    ];
+    for r in rows {
+        col.insert_document(persistent(r));
+    }
+    col
+}
+
+#[test]
+fn group_with_every_accumulator() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("agg_group.wasp")).unwrap();
+    let col = orders(&engine);
+    let pipeline = parse_pipeline_json(
+        r#"[
+            {"$sort": {"qty": 1}},
+            {"$group": {
+                "_id": "$city",
+                "n": {"$sum": 1},
+                "qty": {"$sum": "$qty"},
+                "avg_price": {"$avg": "$price"},
+                "min_qty": {"$min": "$qty"},
+                "max_price": {"$max": "$price"},
+                "names": {"$push": "$who.name"},
+                "tags": {"$addToSet": "$tags"},
+                "first": {"$first": "$who.name"},
+                "last": {"$last": "$who.name"}
+            }},
+            {"$sort": {"qty": -1}}
+        ]"#,
+    )
+    .unwrap();
+    let out = aggregate(&col, &pipeline).unwrap();
+    assert_eq!(out.len(), 3);
+    let rome = &out[0];
+    assert_eq!(rome.get("_id"), Some(&Bson::String("rome".into())));
+    assert_eq!(rome.get_i32("n").unwrap(), 2);
+    assert_eq!(rome.get_i32("qty").unwrap(), 8);
+    assert!((rome.get_f64("avg_price").unwrap() - 2.5).abs() < 1e-9);
+    assert_eq!(rome.get_i32("min_qty").unwrap(), 3);
+    assert_eq!(rome.get_f64("max_price").unwrap(), 3.0);
+    // Input was sorted by qty, so first/last/push follow that order
+    assert_eq!(rome.get_array("names").unwrap(), &vec![Bson::from("eve"), Bson::from("bob")]);
+    assert_eq!(rome.get_str("first").unwrap(), "eve");
+    assert_eq!(rome.get_str("last").unwrap(), "bob");
+    assert_eq!(rome.get_array("tags").unwrap().len(), 2);
+    let oslo = &out[1];
+    assert_eq!(oslo.get_str("_id").unwrap(), "oslo");
+    // A missing field contributes nothing to $addToSet
+    assert!(oslo.get_array("tags").unwrap().is_empty());
+}
+
+#[test]
+fn group_keys_and_sum_types() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("agg_keys.wasp")).unwrap();
+    let col = engine.create_collection("nums".into());
+    col.insert_document(persistent(doc! {"k": 1, "v": i32::MAX}));
+    col.insert_document(persistent(doc! {"k": 1.0, "v": 1}));
+    col.insert_document(persistent(doc! {"k": Bson::Int64(1), "v": 2.5}));
+    col.insert_document(persistent(doc! {"v": 1}));
+
+    let p = parse_pipeline_json(
+        r#"[{"$match": {"field":"k","$exists":true}}, {"$group": {"_id": "$k", "s": {"$sum": "$v"}}}]"#,
+    )
+    .unwrap();
+    let out = aggregate(&col, &p).unwrap();
+    // 1, 1.0 and 1i64 are one group; the double turns the sum into a double
+    assert_eq!(out.len(), 1);
+    assert!((out[0].get_f64("s").unwrap() - (f64::from(i32::MAX) + 3.5)).abs() < 1e-6);
+
+    // Integer sums widen to Int64 instead of overflowing
+    let ints = vec![
+        Stage::Match(parse_filter_json(r#"{"field":"v","$in":[1, 2147483647]}"#).unwrap()),
+        parse_pipeline_json(r#"[{"$group": {"_id": null, "s": {"$sum": "$v"}}}]"#)
+            .unwrap()
+            .remove(0),
+    ];
+    let out = aggregate(&col, &ints).unwrap();
+    assert_eq!(out[0].get("_id"), Some(&Bson::Null));
+    assert_eq!(out[0].get_i64("s").unwrap(), i64::from(i32::MAX) + 2);
+
+    // Compound keys
+    let p = parse_pipeline_json(
+        r#"[{"$group": {"_id": {"k": "$k"}, "n": {"$sum": 1}}}, {"$sort": {"n": -1}}]"#,
+    )
+    .unwrap();
+    let out = aggregate(&col, &p).unwrap();
+    assert_eq!(out.len(), 4, "sub-document keys compare by exact encoding");
+    assert_eq!(out[0].get_i32("n").unwrap(), 1);
+}
+
+#[test]
+fn unwind_project_limit_and_count() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("agg_unwind.wasp")).unwrap();
+    let col = orders(&engine);
+
+    let p = parse_pipeline_json(
+        r#"[{"$unwind": "$tags"}, {"$group": {"_id": "$tags", "n": {"$sum": 1}}}, {"$sort": {"_id": 1}}]"#,
+    )
+    .unwrap();
+    let out = aggregate(&col, &p).unwrap();
+    assert_eq!(out, vec![doc! {"_id": "a", "n": 2}, doc! {"_id": "b", "n": 2}]);
+
+    let p = parse_pipeline_json(
+        r#"[{"$unwind": {"path": "$tags", "preserveNullAndEmptyArrays": true}}, {"$count": "rows"}]"#,
+    )
+    .unwrap();
+    assert_eq!(aggregate(&col, &p).unwrap(), vec![doc! {"rows": Bson::Int64(6)}]);
+
+    let p = parse_pipeline_json(
+        r#"[{"$sort": {"price": -1}}, {"$limit": 2}, {"$project": {"city": 1, "price": true, "qty": 0}}]"#,
+    )
+    .unwrap();
+    let out = aggregate(&col, &p).unwrap();
+    assert_eq!(
+        out,
+        vec![doc! {"city": "paris", "price": 4.0}, doc! {"city": "rome", "price": 3.0}]
+    );
+
+    // $count on an empty input produces no document
+    let p = parse_pipeline_json(r#"[{"$match": {"field":"city","$eq":"lima"}}, {"$count": "n"}]"#)
+        .unwrap();
+    assert!(aggregate(&col, &p).unwrap().is_empty());
+}
+
+#[test]
+fn leading_match_uses_the_index_planner() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("agg_index.wasp")).unwrap();
+    let col = orders(&engine);
+    col.create_index("city", IndexKind::Hash);
+
+    let p = parse_pipeline_json(
+        r#"[{"$match": {"field":"city","$eq":"paris"}}, {"$match": {"field":"qty","$gt":1}},
+            {"$sort": {"qty": -1}}, {"$project": ["qty"]}]"#,
+    )
+    .unwrap();
+    let out = aggregate(&col, &p).unwrap();
+    assert_eq!(out, vec![doc! {"qty": 2}]);
+    assert_eq!(col.indexes.read().indexes["city"].stats().hits, 1);
+}
+
+#[test]
+fn rejects_malformed_pipelines() {
+    for bad in [
+        r#"{"$match": {}}"#,
+        r#"[{"$bogus": 1}]"#,
+        r#"[{"$limit": -1}]"#,
+        r#"[{"$sort": {"a": 2}}]"#,
+        r#"[{"$group": {"n": {"$sum": 1}}}]"#,
+        r#"[{"$group": {"_id": null, "n": {"$median": "$a"}}}]"#,
+        r#"[{"$unwind": "tags"}]"#,
+        r#"[{"$count": "$n"}]"#,
+        r#"[{"$match": {"field":"a"}}]"#,
+        r#"[{"$limit": 1, "$skip": 1}]"#,
+    ] {
+        assert!(parse_pipeline_json(bad).is_err(), "{bad}");
+    }
+    let too_long = format!("[{}]", vec![r#"{"$limit": 1}"#; 65].join(","));
+    assert!(parse_pipeline_json(&too_long).is_err());
+}
+
+#[test]
+fn database_and_cli_aggregate() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("agg_db.db");
+    let db = Database::new(Some(db_path.to_str().unwrap())).unwrap();
+    let col = db.create_collection("events");
+    for i in 0..10i32 {
+        col.insert_document(persistent(doc! {"kind": if i % 2 == 0 { "even" } else { "odd" }}));
+    }
+    let p = parse_pipeline_json(r#"[{"$group": {"_id": "$kind", "n": {"$sum": 1}}}]"#).unwrap();
+    let out = db.aggregate("events", &p).unwrap();
+    assert_eq!(out.iter().map(|d| d.get_i32("n").unwrap()).sum::<i32>(), 10);
+    assert!(db.aggregate("missing", &p).is_err());
+
+    let engine = Engine::new(dir.path().join("agg_cli.wasp")).unwrap();
+    orders(&engine);
+    run(
+        &engine,
+        Command::QueryAggregate {
+            collection: "orders".into(),
+            pipeline_json: r#"[{"$group": {"_id": "$city", "n": {"$sum": 1}}}]"#.into(),
+        },
+    )
+    .unwrap();
+    assert!(
+        run(
+            &engine,
+            Command::QueryAggregate { collection: "orders".into(), pipeline_json: "[1]".into() },
+        )
+        .is_err()
+    );
+}
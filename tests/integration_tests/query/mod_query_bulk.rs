@@ -0,0 +1,251 @@
+use bson::doc;
+use nexuslite::Database;
+use nexuslite::collection::Collection;
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::index::{IndexDescriptor, IndexKind};
+use nexuslite::query::{
+    self, BulkOp, BulkOptions, BulkOutcome, DeleteReport, UpdateReport, parse_filter_json,
+    parse_update_json,
+};
+use nexuslite::types::Operation;
+use nexuslite::wasp::{DeltaOp, StorageEngine};
+use parking_lot::RwLock;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+fn names(col: &Collection) -> Vec<String> {
+    let mut v: Vec<String> = col
+        .get_all_documents()
+        .into_iter()
+        .map(|d| d.data.0.get_str("name").unwrap().to_string())
+        .collect();
+    v.sort();
+    v
+}
+
+#[test]
+fn mixed_ops_see_earlier_writes_and_report_outcomes() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bulk_mixed.wasp")).unwrap();
+    let col = engine.create_collection("people".into());
+    let bob = persistent(doc! {"name": "bob", "age": 40});
+    let bob_id = bob.id.clone();
+    let ops = vec![
+        BulkOp::Insert(persistent(doc! {"name": "ann", "age": 30})),
+        BulkOp::Insert(bob),
+        // Matches the insert above, in the same batch
+        BulkOp::UpdateOne {
+            filter: parse_filter_json(r#"{"field":"name","$eq":"ann"}"#).unwrap(),
+            update: parse_update_json(r#"{"$inc": {"age": 1}}"#).unwrap(),
+            upsert: false,
+        },
+        BulkOp::UpdateMany {
+            filter: parse_filter_json(r#"{"field":"age","$gt":20}"#).unwrap(),
+            update: parse_update_json(r#"{"$set": {"adult": true}}"#).unwrap(),
+            upsert: false,
+        },
+        BulkOp::ReplaceOne {
+            filter: parse_filter_json(r#"{"field":"name","$eq":"bob"}"#).unwrap(),
+            replacement: doc! {"name": "rob"},
+            upsert: false,
+        },
+        BulkOp::UpdateOne {
+            filter: parse_filter_json(r#"{"field":"name","$eq":"cy"}"#).unwrap(),
+            update: parse_update_json(r#"{"$set": {"age": 5}}"#).unwrap(),
+            upsert: true,
+        },
+        BulkOp::DeleteOne { filter: parse_filter_json(r#"{"field":"name","$eq":"ann"}"#).unwrap() },
+        BulkOp::DeleteMany {
+            filter: parse_filter_json(r#"{"field":"name","$eq":"nobody"}"#).unwrap(),
+        },
+    ];
+    let report = query::bulk_write(&col, ops, &BulkOptions::default()).unwrap();
+    assert_eq!(report.errors().count(), 0);
+    assert_eq!(report.results.len(), 8);
+    assert_eq!(report.results[1].as_ref().unwrap(), &BulkOutcome::Inserted(bob_id.clone()));
+    assert_eq!(
+        report.results[2].as_ref().unwrap(),
+        &BulkOutcome::Updated(UpdateReport { matched: 1, modified: 1, upserted_id: None })
+    );
+    assert_eq!(
+        report.results[3].as_ref().unwrap(),
+        &BulkOutcome::Updated(UpdateReport { matched: 2, modified: 2, upserted_id: None })
+    );
+    let Ok(BulkOutcome::Updated(UpdateReport { upserted_id: Some(cy), .. })) = &report.results[5]
+    else {
+        panic!("expected an upsert: {:?}", report.results[5]);
+    };
+    assert_eq!(col.find_document(cy).unwrap().data.0, doc! {"name": "cy", "age": 5});
+    assert_eq!(
+        report.results[6].as_ref().unwrap(),
+        &BulkOutcome::Deleted(DeleteReport { deleted: 1 })
+    );
+    assert_eq!(
+        (report.inserted, report.matched, report.modified, report.upserted, report.deleted),
+        (2, 4, 4, 1, 1)
+    );
+    assert_eq!(names(&col), ["cy", "rob"]);
+    assert_eq!(col.find_document(&bob_id).unwrap().data.0, doc! {"name": "rob"});
+}
+
+#[test]
+fn ordered_stops_at_first_error_and_unordered_continues() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bulk_ordered.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.create_index_with(&IndexDescriptor::new("name", IndexKind::Hash).with_unique(true))
+        .unwrap();
+    let batch = || {
+        vec![
+            BulkOp::Insert(persistent(doc! {"name": "a"})),
+            BulkOp::Insert(persistent(doc! {"name": "a"})),
+            BulkOp::ReplaceOne {
+                filter: parse_filter_json(r#"{"field":"name","$eq":"a"}"#).unwrap(),
+                replacement: doc! {"$set": {"name": "b"}},
+                upsert: false,
+            },
+            BulkOp::Insert(persistent(doc! {"name": "c"})),
+        ]
+    };
+
+    let report = query::bulk_write(&col, batch(), &BulkOptions { ordered: true }).unwrap();
+    assert_eq!(report.results.len(), 2);
+    assert!(report.results[0].is_ok());
+    assert!(matches!(report.results[1], Err(DbError::DuplicateKey { .. })));
+    assert_eq!(report.inserted, 1);
+    assert_eq!(names(&col), ["a"]);
+
+    let report = query::bulk_write(&col, batch(), &BulkOptions { ordered: false }).unwrap();
+    assert_eq!(report.results.len(), 4);
+    let failed: Vec<usize> = report.errors().map(|(i, _)| i).collect();
+    assert_eq!(failed, [0, 1, 2]);
+    assert!(matches!(report.results[2], Err(DbError::QueryError(_))));
+    assert_eq!(report.inserted, 1);
+    assert_eq!(names(&col), ["a", "c"]);
+}
+
+#[test]
+fn operator_error_in_update_many_writes_nothing_for_that_op() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bulk_op_err.wasp")).unwrap();
+    let col = engine.create_collection("items".into());
+    col.insert_document(persistent(doc! {"name": "x", "n": 1}));
+    col.insert_document(persistent(doc! {"name": "y", "n": "one"}));
+    let ops = vec![BulkOp::UpdateMany {
+        filter: parse_filter_json(r#"{"field":"name","$in":["x","y"]}"#).unwrap(),
+        update: parse_update_json(r#"{"$inc": {"n": 1}}"#).unwrap(),
+        upsert: false,
+    }];
+    let report = query::bulk_write(&col, ops, &BulkOptions::default()).unwrap();
+    assert!(matches!(report.results[0], Err(DbError::QueryError(_))));
+    let ns: Vec<bson::Bson> =
+        col.get_all_documents().iter().map(|d| d.data.0.get("n").unwrap().clone()).collect();
+    assert!(ns.contains(&bson::Bson::Int32(1)));
+}
+
+#[test]
+fn unique_violation_in_update_many_undoes_that_ops_writes() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("bulk_dup.wasp")).unwrap();
+    let col = engine.create_collection("people".into());
+    let desc = IndexDescriptor::new("name", IndexKind::Hash).with_unique(true);
+    col.create_index_with(&desc).unwrap();
+    for name in ["a", "b", "c"] {
+        col.insert_document(persistent(doc! {"name": name, "team": 1}));
+    }
+    let ops = vec![
+        BulkOp::UpdateMany {
+            filter: parse_filter_json(r#"{"field":"team","$eq":1}"#).unwrap(),
+            update: parse_update_json(r#"{"$set": {"name": "z"}}"#).unwrap(),
+            upsert: false,
+        },
+        BulkOp::UpdateMany {
+            filter: parse_filter_json(r#"{"field":"name","$eq":"a"}"#).unwrap(),
+            update: parse_update_json(r#"{"$set": {"name": "d"}}"#).unwrap(),
+            upsert: false,
+        },
+    ];
+    let report = query::bulk_write(&col, ops, &BulkOptions { ordered: false }).unwrap();
+    assert!(matches!(report.results[0], Err(DbError::DuplicateKey { .. })));
+    // The totals count only the second operation, which is all that was written
+    assert_eq!((report.matched, report.modified), (1, 1));
+    assert_eq!(names(&col), ["b", "c", "d"]);
+    col.try_insert_document(persistent(doc! {"name": "z"})).unwrap();
+}
+
+#[test]
+fn batch_reaches_storage_with_its_index_deltas() {
+    let dir = tempdir().unwrap();
+    let wasp = nexuslite::wasp::Wasp::new(dir.path().join("bulk_storage.wasp")).unwrap();
+    let storage: Arc<RwLock<Box<dyn StorageEngine>>> = Arc::new(RwLock::new(Box::new(wasp)));
+    let col = Arc::new(Collection::new("bulk".into(), storage.clone(), 100));
+    col.create_index("k", IndexKind::BTree);
+    let ops: Vec<BulkOp> =
+        (0..5).map(|i| BulkOp::Insert(persistent(doc! {"k": i, "name": "d"}))).collect();
+    let report = query::bulk_write(&col, ops, &BulkOptions::default()).unwrap();
+    assert_eq!(report.inserted, 5);
+    let del =
+        vec![BulkOp::DeleteOne { filter: parse_filter_json(r#"{"field":"k","$eq":0}"#).unwrap() }];
+    assert_eq!(query::bulk_write(&col, del, &BulkOptions::default()).unwrap().deleted, 1);
+
+    let logged = storage.read().read_all().unwrap();
+    let kinds: Vec<&str> = logged
+        .iter()
+        .map(|op| match op.as_ref().unwrap() {
+            Operation::Insert { .. } => "insert",
+            Operation::Update { .. } => "update",
+            Operation::Delete { .. } => "delete",
+        })
+        .collect();
+    assert_eq!(kinds, ["insert", "insert", "insert", "insert", "insert", "delete"]);
+    let deltas = col.index_deltas();
+    assert_eq!(deltas.iter().filter(|d| matches!(d.op, DeltaOp::Add)).count(), 5);
+    assert_eq!(deltas.iter().filter(|d| matches!(d.op, DeltaOp::Remove)).count(), 1);
+}
+
+#[test]
+fn database_bulk_write_requires_collection() {
+    let dir = tempdir().unwrap();
+    let db = Database::new(Some(dir.path().join("bulk_db.db").to_str().unwrap())).unwrap();
+    let err = db.bulk_write("missing", vec![], &BulkOptions::default()).unwrap_err();
+    assert!(matches!(err, DbError::NoSuchCollection(_)));
+    let _ = db.create_collection("kv");
+    let ops = vec![BulkOp::Insert(persistent(doc! {"name": "k"}))];
+    let report = db.bulk_write("kv", ops, &BulkOptions::default()).unwrap();
+    assert_eq!(report.inserted, 1);
+}
+
+/// Storage whose every append fails.
+struct FailingStorage;
+
+impl StorageEngine for FailingStorage {
+    fn append(&mut self, _operation: &Operation) -> std::io::Result<()> {
+        Err(std::io::Error::other("disk full"))
+    }
+    fn read_all(&self) -> std::io::Result<Vec<Result<Operation, bincode::error::DecodeError>>> {
+        Ok(Vec::new())
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[test]
+fn storage_failures_are_returned_to_the_writer() {
+    let storage: Arc<RwLock<Box<dyn StorageEngine>>> =
+        Arc::new(RwLock::new(Box::new(FailingStorage)));
+    let col = Arc::new(Collection::new("failing".into(), storage, 100));
+    let ops = vec![BulkOp::Insert(persistent(doc! {"name": "a"}))];
+    let err = query::bulk_write(&col, ops, &BulkOptions::default()).unwrap_err();
+    assert!(matches!(err, DbError::Io(m) if m.contains("disk full")));
+
+    let err = col.try_insert_document(persistent(doc! {"name": "b"})).unwrap_err();
+    assert!(matches!(err, DbError::Io(_)));
+    let id = col.list_ids()[0].clone();
+    let err = col.try_update_document(&id, persistent(doc! {"name": "c"})).unwrap_err();
+    assert!(matches!(err, DbError::Io(_)));
+    assert!(matches!(col.try_delete_document(&id), Err(DbError::Io(_))));
+}
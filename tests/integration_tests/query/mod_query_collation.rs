@@ -0,0 +1,202 @@
+use bson::doc;
+use nexuslite::collection::Collection;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::index::{IndexDescriptor, IndexKind};
+use nexuslite::query::{
+    self, AccessPath, Collation, Filter, FindOptions, Order, SortSpec, Strength, explain,
+    parse_filter_json,
+};
+use std::cmp::Ordering;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn collation(strength: Strength) -> Collation {
+    Collation { strength, ..Collation::default() }
+}
+
+fn seeded(engine: &Engine) -> Arc<Collection> {
+    let col = engine.create_collection("files".into());
+    for name in ["file10", "File2", "file1", "éclair", "Eclair", "eclair", "zeta"] {
+        col.insert_document(Document::new(doc! {"name": name}, DocumentType::Persistent));
+    }
+    col
+}
+
+fn names(col: &Arc<Collection>, filter: &Filter, opts: &FindOptions) -> Vec<String> {
+    query::find_docs(col, filter, opts)
+        .map(|d| d.data.0.get_str("name").unwrap().to_string())
+        .collect()
+}
+
+fn by_name(collation: Option<Collation>) -> FindOptions {
+    FindOptions {
+        sort: Some(vec![SortSpec { field: "name".into(), order: Order::Asc }]),
+        collation,
+        ..FindOptions::default()
+    }
+}
+
+#[test]
+fn strength_case_level_and_numeric_ordering() {
+    let primary = collation(Strength::Primary);
+    let secondary = collation(Strength::Secondary);
+    let tertiary = Collation::default();
+    assert_eq!(primary.compare("Éclair", "eclair"), Ordering::Equal);
+    assert_eq!(primary.compare("straße", "STRASSE"), Ordering::Equal);
+    assert_eq!(secondary.compare("Eclair", "eclair"), Ordering::Equal);
+    assert_eq!(secondary.compare("éclair", "eclair"), Ordering::Greater);
+    // Tertiary: base letters first, then accents, then case with lowercase first
+    assert_eq!(tertiary.compare("a", "A"), Ordering::Less);
+    assert_eq!(tertiary.compare("A", "b"), Ordering::Less);
+    assert_eq!(tertiary.compare("A", "á"), Ordering::Less);
+    let case_level = Collation { case_level: true, ..primary.clone() };
+    assert_eq!(case_level.compare("éclair", "eclair"), Ordering::Equal);
+    assert_eq!(case_level.compare("eclair", "Eclair"), Ordering::Less);
+
+    let numeric = Collation { numeric_ordering: true, ..primary };
+    assert_eq!(numeric.compare("file2", "file10"), Ordering::Less);
+    assert_eq!(numeric.compare("file007", "file7"), Ordering::Equal);
+    assert_eq!(numeric.compare("v1.10", "v1.9"), Ordering::Greater);
+    assert_eq!(Collation::default().compare("file2", "file10"), Ordering::Greater);
+
+    let parsed: Collation =
+        serde_json::from_str(r#"{"strength": 1, "numeric_ordering": true}"#).unwrap();
+    assert_eq!(parsed, numeric);
+    assert!(serde_json::from_str::<Collation>(r#"{"strength": 4}"#).is_err());
+    assert!(serde_json::from_str::<Collation>(r#"{"locale": "fr"}"#).is_err());
+}
+
+#[test]
+fn find_filters_and_sorts_under_a_collation() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("coll_find.wasp")).unwrap();
+    let col = seeded(&engine);
+    let eq = parse_filter_json(r#"{"field":"name","$eq":"ECLAIR"}"#).unwrap();
+    assert!(names(&col, &eq, &FindOptions::default()).is_empty());
+    // Strings equal under the collation tie, so compare these as sets
+    let mut got = names(&col, &eq, &by_name(Some(collation(Strength::Primary))));
+    got.sort();
+    assert_eq!(got, ["Eclair", "eclair", "éclair"]);
+    got = names(&col, &eq, &by_name(Some(collation(Strength::Secondary))));
+    got.sort();
+    assert_eq!(got, ["Eclair", "eclair"]);
+
+    let files = parse_filter_json(
+        r#"{"$and":[{"field":"name","$gte":"file"},{"field":"name","$lt":"FILE9"}]}"#,
+    )
+    .unwrap();
+    let numeric = Collation { numeric_ordering: true, ..Collation::default() };
+    assert_eq!(names(&col, &files, &by_name(Some(numeric.clone()))), ["file1", "File2"]);
+    let all = names(&col, &Filter::True, &by_name(Some(numeric)));
+    assert_eq!(all, ["eclair", "Eclair", "éclair", "file1", "File2", "file10", "zeta"]);
+    // Byte-wise without a collation
+    let all = names(&col, &Filter::True, &by_name(None));
+    assert_eq!(all, ["Eclair", "File2", "eclair", "file1", "file10", "zeta", "éclair"]);
+
+    // A collation can also be attached to part of a filter
+    let f = parse_filter_json(
+        r#"{"$or":[{"collation":{"strength":1},"filter":{"field":"name","$in":["ZETA"]}},
+                   {"field":"name","$eq":"FILE2"}]}"#,
+    )
+    .unwrap();
+    assert_eq!(names(&col, &f, &by_name(None)), ["zeta"]);
+    let back: Filter = serde_json::from_str(&serde_json::to_string(&f).unwrap()).unwrap();
+    assert_eq!(names(&col, &back, &by_name(None)), ["zeta"]);
+}
+
+#[test]
+fn planner_uses_only_indexes_with_the_query_collation() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("coll_index.wasp")).unwrap();
+    let col = seeded(&engine);
+    let primary = collation(Strength::Primary);
+    let desc = IndexDescriptor::new("name", IndexKind::BTree).with_collation(primary.clone());
+    col.create_index_with(&desc).unwrap();
+
+    let eq = parse_filter_json(r#"{"field":"name","$eq":"ECLAIR"}"#).unwrap();
+    let report = explain(&col, &eq, &by_name(Some(primary.clone())));
+    assert!(matches!(report.chosen.path, AccessPath::IndexEq { .. }));
+    assert_eq!(report.plan.children[0].docs_returned, 3);
+    assert_eq!(names(&col, &eq, &by_name(Some(primary.clone()))).len(), 3);
+    // Byte-wise and differently collated string queries scan instead
+    for other in [None, Some(collation(Strength::Secondary))] {
+        let report = explain(&col, &eq, &by_name(other.clone()));
+        assert_eq!(report.chosen.path, AccessPath::CollectionScan);
+    }
+    assert_eq!(names(&col, &eq, &by_name(Some(collation(Strength::Secondary)))).len(), 2);
+
+    let range = parse_filter_json(r#"{"field":"name","$gte":"F"}"#).unwrap();
+    let report = explain(&col, &range, &by_name(Some(primary.clone())));
+    assert!(matches!(report.chosen.path, AccessPath::IndexRange { .. }));
+    let got = names(&col, &range, &by_name(Some(primary.clone())));
+    assert_eq!(got, ["file1", "file10", "File2", "zeta"]);
+
+    // The collation survives in the descriptor, e.g. when indexes are persisted
+    let descs = col.indexes.read().descriptors();
+    assert_eq!(descs[0].collation.as_ref(), Some(&primary));
+    let json = serde_json::to_string(&descs[0]).unwrap();
+    let back: IndexDescriptor = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.collation, Some(primary));
+}
+
+#[test]
+fn unique_collated_index_treats_equal_strings_as_duplicates() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("coll_unique.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    let desc = IndexDescriptor::new("login", IndexKind::Hash)
+        .with_unique(true)
+        .with_collation(collation(Strength::Secondary));
+    col.create_index_with(&desc).unwrap();
+    col.insert_document(Document::new(doc! {"login": "Alice"}, DocumentType::Persistent));
+    let dup = Document::new(doc! {"login": "ALICE"}, DocumentType::Persistent);
+    assert!(matches!(col.try_insert_document(dup), Err(DbError::DuplicateKey { .. })));
+    let accented = Document::new(doc! {"login": "Alicé"}, DocumentType::Persistent);
+    assert!(col.try_insert_document(accented).is_ok());
+}
+
+#[test]
+fn resume_tokens_are_bound_to_the_collation() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("coll_keyset.wasp")).unwrap();
+    let col = seeded(&engine);
+    let numeric = Collation { numeric_ordering: true, ..collation(Strength::Primary) };
+    let opts = FindOptions { limit: Some(4), ..by_name(Some(numeric)) };
+    let mut cur = query::find_docs_rate_limited(&col, &Filter::True, &opts).unwrap();
+    while cur.advance().is_some() {}
+    let token = cur.next_token().unwrap().unwrap();
+    let next = FindOptions { after: Some(token.clone()), ..opts.clone() };
+    assert_eq!(names(&col, &Filter::True, &next), ["File2", "file10", "zeta"]);
+    let bytewise = FindOptions { after: Some(token), collation: None, ..opts };
+    let err = query::find_docs_rate_limited(&col, &Filter::True, &bytewise).err().unwrap();
+    assert!(matches!(err, DbError::QueryError(m) if m.contains("different sort")));
+}
+
+#[test]
+fn resumed_pages_over_an_uncollated_index_keep_every_document() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("coll_keyset_index.wasp")).unwrap();
+    let col = engine.create_collection("people".into());
+    for name in ["alice", "Bob", "BZ", "carl", "Dan", "erin"] {
+        col.insert_document(Document::new(doc! {"name": name}, DocumentType::Persistent));
+    }
+    col.create_index("name", IndexKind::BTree);
+    let opts = FindOptions { limit: Some(2), ..by_name(Some(collation(Strength::Secondary))) };
+    let mut seen = Vec::new();
+    let mut after = None;
+    loop {
+        let page = FindOptions { after: after.take(), ..opts.clone() };
+        let mut cur = query::find_docs_rate_limited(&col, &Filter::True, &page).unwrap();
+        let before = seen.len();
+        while let Some(d) = cur.advance() {
+            seen.push(d.data.0.get_str("name").unwrap().to_string());
+        }
+        if seen.len() == before {
+            break;
+        }
+        after = cur.next_token().unwrap();
+    }
+    assert_eq!(seen, ["alice", "Bob", "BZ", "carl", "Dan", "erin"]);
+}
@@ -0,0 +1,94 @@
+use bson::doc;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::query::{self, Filter, FindOptions, Order, Projection, SortSpec, parse_filter_json};
+use tempfile::tempdir;
+
+fn seeded(engine: &Engine, n: i32) -> std::sync::Arc<nexuslite::collection::Collection> {
+    let col = engine.create_collection("items".into());
+    for i in 0..n {
+        col.insert_document(Document::new(
+            doc! {"i": i, "bucket": (i * 7919) % 97, "pad": "x".repeat(64)},
+            DocumentType::Persistent,
+        ));
+    }
+    col
+}
+
+fn by_bucket() -> Option<Vec<SortSpec>> {
+    Some(vec![
+        SortSpec { field: "bucket".into(), order: Order::Desc },
+        SortSpec { field: "i".into(), order: Order::Asc },
+    ])
+}
+
+fn keys(docs: &[Document]) -> Vec<(i32, i32)> {
+    docs.iter()
+        .map(|d| (d.data.0.get_i32("bucket").unwrap(), d.data.0.get_i32("i").unwrap()))
+        .collect()
+}
+
+#[test]
+fn unsorted_cursor_reads_documents_as_it_advances() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("cursor_lazy.wasp")).unwrap();
+    let col = seeded(&engine, 10);
+    let mut cur = query::find_docs(&col, &Filter::True, &FindOptions::default());
+    let first = cur.next().unwrap();
+    // Deleting documents the cursor has not reached yet removes them from its results
+    let rest: Vec<_> = col.list_ids().into_iter().filter(|id| *id != first.id).collect();
+    for id in &rest[..5] {
+        col.delete_document(id);
+    }
+    assert_eq!(cur.count(), 4);
+
+    // Skip counts matches only, and projection applies per document
+    let odd = parse_filter_json(r#"{"field":"bucket","$gt":40}"#).unwrap();
+    let expected = query::count_docs(&col, &odd).saturating_sub(1);
+    let opts = FindOptions {
+        projection: Some(Projection::include(["i"])),
+        skip: Some(1),
+        ..FindOptions::default()
+    };
+    let docs = query::find_docs(&col, &odd, &opts).to_vec();
+    assert_eq!(docs.len(), expected);
+    assert!(docs.iter().all(|d| d.data.0.keys().eq(["i"])));
+}
+
+#[test]
+fn top_k_and_spilled_sorts_match_a_full_sort() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("cursor_sort.wasp")).unwrap();
+    let col = seeded(&engine, 400);
+    let all = col.get_all_documents();
+    let mut expected = keys(&all);
+    expected.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let top =
+        FindOptions { sort: by_bucket(), limit: Some(15), skip: Some(5), ..Default::default() };
+    let docs = query::find_docs(&col, &Filter::True, &top).to_vec();
+    assert_eq!(keys(&docs), expected[5..20]);
+
+    // skip + limit beyond the heap bound takes the external sort; a 2 KiB budget spills
+    // many runs
+    let spill = FindOptions {
+        sort: by_bucket(),
+        skip: Some(3),
+        sort_memory_limit: Some(2048),
+        projection: Some(Projection::include(["bucket", "i"])),
+        ..Default::default()
+    };
+    let docs = query::find_docs(&col, &Filter::True, &spill).to_vec();
+    assert_eq!(keys(&docs), expected[3..]);
+    assert!(docs.iter().all(|d| d.data.0.get("pad").is_none()));
+
+    // Without a skip the heap path applies the same budget
+    let heap_spill = FindOptions {
+        sort: by_bucket(),
+        limit: Some(40),
+        sort_memory_limit: Some(2048),
+        ..Default::default()
+    };
+    let docs = query::find_docs(&col, &Filter::True, &heap_spill).to_vec();
+    assert_eq!(keys(&docs), expected[..40]);
+}
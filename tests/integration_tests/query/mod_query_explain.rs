@@ -0,0 +1,177 @@
+use bson::doc;
+use nexuslite::Database;
+use nexuslite::cli::{Command, OutputMode, run_with_format};
+use nexuslite::engine::Engine;
+use nexuslite::index::IndexKind;
+use nexuslite::query::{
+    self, AccessPath, FindOptions, Order, PlanStage, SortSpec, explain, parse_filter_json,
+};
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+fn seeded(engine: &Engine) -> std::sync::Arc<nexuslite::collection::Collection> {
+    let col = engine.create_collection("people".into());
+    for i in 0..200i32 {
+        col.insert_document(persistent(doc! {
+            "age": i % 50,
+            "city": if i % 10 == 0 { "paris" } else { "rome" },
+            "tags": [format!("t{}", i % 7), "all"],
+        }));
+    }
+    col
+}
+
+#[test]
+fn collection_scan_without_indexes() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("explain_scan.wasp")).unwrap();
+    let col = seeded(&engine);
+    let f = parse_filter_json(r#"{"field":"city","$eq":"paris"}"#).unwrap();
+    let report = explain(&col, &f, &FindOptions::default());
+    assert_eq!(report.chosen.path, AccessPath::CollectionScan);
+    assert_eq!(report.plan.stage, PlanStage::Filter);
+    assert_eq!(report.plan.docs_examined, 200);
+    assert_eq!(report.plan.docs_returned, 20);
+    assert_eq!(report.plan.children[0].stage, PlanStage::CollectionScan);
+}
+
+#[test]
+fn picks_the_most_selective_index() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("explain_pick.wasp")).unwrap();
+    let col = seeded(&engine);
+    col.create_index("age", IndexKind::BTree);
+    col.create_index("city", IndexKind::Hash);
+
+    // city=rome matches 180 docs, age=7 only 4: the age index wins
+    let f =
+        parse_filter_json(r#"{"$and":[{"field":"city","$eq":"rome"},{"field":"age","$eq":7}]}"#)
+            .unwrap();
+    let report = explain(&col, &f, &FindOptions::default());
+    match &report.chosen.path {
+        AccessPath::IndexEq { field, .. } => assert_eq!(field, "age"),
+        other => panic!("unexpected plan {other:?}"),
+    }
+    assert_eq!(report.chosen.estimated_docs, 4);
+    assert!(report.rejected.iter().any(|c| c.estimated_docs == 180));
+    let access = &report.plan.children[0];
+    assert_eq!(access.index.as_deref(), Some("age"));
+    assert_eq!(access.keys_examined, 1);
+    assert_eq!(report.plan.docs_examined, 4);
+    assert_eq!(report.plan.docs_returned, 4);
+
+    // The same plan drives find()
+    let docs = query::find_docs(&col, &f, &FindOptions::default()).to_vec();
+    assert_eq!(docs.len(), 4);
+}
+
+#[test]
+fn combined_range_and_union_plans() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("explain_range.wasp")).unwrap();
+    let col = seeded(&engine);
+    col.create_index("age", IndexKind::BTree);
+    col.create_index("city", IndexKind::Hash);
+
+    let f = parse_filter_json(r#"{"$and":[{"field":"age","$gte":10},{"field":"age","$lt":12}]}"#)
+        .unwrap();
+    let opts = FindOptions {
+        sort: Some(vec![SortSpec { field: "age".into(), order: Order::Desc }]),
+        limit: Some(3),
+        ..Default::default()
+    };
+    let report = explain(&col, &f, &opts);
+    assert!(matches!(
+        report.chosen.path,
+        AccessPath::IndexRange { min_inclusive: true, max_inclusive: false, .. }
+    ));
+    assert_eq!(report.chosen.estimated_docs, 8);
+    assert_eq!(report.plan.stage, PlanStage::Limit);
+    assert_eq!(report.plan.docs_returned, 3);
+    let sort = &report.plan.children[0];
+    assert_eq!(sort.stage, PlanStage::Sort);
+    let filter = &sort.children[0];
+    assert_eq!((filter.docs_examined, filter.docs_returned), (8, 8));
+    assert_eq!(filter.children[0].keys_examined, 2);
+
+    let f = parse_filter_json(
+        r#"{"$or":[{"field":"city","$eq":"paris"},{"field":"age","$in":[1,2]}]}"#,
+    )
+    .unwrap();
+    let report = explain(&col, &f, &FindOptions::default());
+    let AccessPath::Union { branches } = &report.chosen.path else {
+        panic!("expected union, got {:?}", report.chosen.path);
+    };
+    assert_eq!(branches.len(), 2);
+    let expected = query::count_docs(&col, &f);
+    assert_eq!(report.plan.docs_returned, expected);
+    assert_eq!(report.plan.children[0].children.len(), 2);
+}
+
+#[test]
+fn multikey_ranges_are_not_intersected() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("explain_multikey.wasp")).unwrap();
+    let col = engine.create_collection("scores".into());
+    col.create_index("s", IndexKind::BTree);
+    col.insert_document(persistent(doc! {"s": [1, 20]}));
+    col.insert_document(persistent(doc! {"s": 7}));
+    // [1, 20] has an element > 5 and an element < 10, so it matches both predicates
+    let f =
+        parse_filter_json(r#"{"$and":[{"field":"s","$gt":5},{"field":"s","$lt":10}]}"#).unwrap();
+    let docs = query::find_docs(&col, &f, &FindOptions::default()).to_vec();
+    assert_eq!(docs.len(), 2);
+}
+
+#[test]
+fn range_scans_include_unkeyed_values() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("explain_unkeyed.wasp")).unwrap();
+    let col = engine.create_collection("mixed".into());
+    col.create_index("v", IndexKind::BTree);
+    col.insert_document(persistent(doc! {"v": 3}));
+    col.insert_document(persistent(doc! {"v": {"nested": true}}));
+    for i in 0..50 {
+        col.insert_document(persistent(doc! {"w": i}));
+    }
+    // compare_bson orders sub-documents above numbers, so both documents match
+    let f = parse_filter_json(r#"{"field":"v","$gt":1}"#).unwrap();
+    let report = explain(&col, &f, &FindOptions::default());
+    assert!(matches!(report.chosen.path, AccessPath::IndexRange { .. }));
+    assert_eq!(report.plan.docs_returned, query::count_docs(&col, &f));
+    assert_eq!(report.plan.docs_returned, 2);
+}
+
+#[test]
+fn database_and_cli_explain() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("explain_db.db");
+    let db = Database::new(Some(db_path.to_str().unwrap())).unwrap();
+    let col = db.create_collection("people");
+    for i in 0..10i32 {
+        col.insert_document(persistent(doc! {"age": i}));
+    }
+    col.create_index("age", IndexKind::Hash);
+    let f = parse_filter_json(r#"{"field":"age","$eq":3}"#).unwrap();
+    let report = db.explain("people", &f, &FindOptions::default()).unwrap();
+    assert_eq!(report.collection, "people");
+    assert_eq!(report.plan.docs_returned, 1);
+    assert!(db.explain("missing", &f, &FindOptions::default()).is_err());
+
+    let engine = Engine::new(dir.path().join("explain_cli.wasp")).unwrap();
+    seeded(&engine);
+    run_with_format(
+        &engine,
+        Command::QueryExplain {
+            collection: "people".into(),
+            filter_json: r#"{"field":"age","$eq":3}"#.into(),
+            project: Some("age".into()),
+            sort: Some("-age".into()),
+            limit: Some(2),
+            skip: None,
+        },
+        OutputMode::Json,
+    )
+    .unwrap();
+}
@@ -0,0 +1,260 @@
+use bson::{Bson, DateTime, doc};
+use nexuslite::collection::Collection;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::query::{
+    self, Expr, Filter, FindOptions, Order, SortSpec, aggregate, parse_filter_json,
+    parse_pipeline_json, parse_projection_json,
+};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn tasks(engine: &Engine) -> Arc<Collection> {
+    let col = engine.create_collection("tasks".into());
+    let rows = [
+        doc! {"_id": "a", "title": "Write docs", "created_at": DateTime::from_millis(1_000),
+        "updated_at": DateTime::from_millis(5_000), "done": 3, "total": 4, "price": 2.5},
+        doc! {"_id": "b", "title": "fix bug", "created_at": DateTime::from_millis(2_000),
+        "updated_at": DateTime::from_millis(2_000), "done": 4, "total": 4, "price": 10_i64},
+        doc! {"_id": "c", "title": "Ship", "created_at": DateTime::from_millis(3_000),
+        "done": 0, "total": 0, "price": "n/a"},
+    ];
+    for d in rows {
+        col.insert_document(Document::new(d, DocumentType::Persistent));
+    }
+    col
+}
+
+fn ids(col: &Arc<Collection>, filter: &str) -> Vec<String> {
+    let filter = parse_filter_json(filter).unwrap();
+    let opts = FindOptions {
+        sort: Some(vec![SortSpec { field: "_id".into(), order: Order::Asc }]),
+        ..FindOptions::default()
+    };
+    query::find_docs(col, &filter, &opts)
+        .map(|d| d.data.0.get_str("_id").unwrap().to_string())
+        .collect()
+}
+
+fn eval(expr: Bson, doc: &bson::Document) -> Result<Option<Bson>, DbError> {
+    Expr::parse(expr)?.try_eval(doc)
+}
+
+#[test]
+fn expr_filters_compare_fields_and_compute() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("expr_filter.wasp")).unwrap();
+    let col = tasks(&engine);
+    assert_eq!(ids(&col, r#"{"$expr": {"$gt": ["$updated_at", "$created_at"]}}"#), ["a"]);
+    // A missing field compares as null
+    assert_eq!(ids(&col, r#"{"$expr": {"$lt": ["$updated_at", "$created_at"]}}"#), ["c"]);
+    assert_eq!(ids(&col, r#"{"$expr": {"$eq": ["$done", "$total"]}}"#), ["b", "c"]);
+    let pct = r#"{"$expr": {"$gte": [
+        {"$cond": [{"$eq": ["$total", 0]}, 0, {"$divide": ["$done", "$total"]}]}, 0.5]}}"#;
+    assert_eq!(ids(&col, pct), ["a", "b"]);
+    // A type error fails the expression, which matches nothing
+    assert_eq!(ids(&col, r#"{"$expr": {"$gt": [{"$multiply": ["$price", 2]}, 6]}}"#), ["b"]);
+    let f = parse_filter_json(
+        r#"{"$and": [{"$expr": {"$eq": [{"$toLower": "$title"}, "ship"]}},
+                     {"field": "done", "$eq": 0}]}"#,
+    )
+    .unwrap();
+    let back: Filter = serde_json::from_str(&serde_json::to_string(&f).unwrap()).unwrap();
+    let got: Vec<_> = query::find_docs(&col, &back, &FindOptions::default()).collect();
+    assert_eq!(got.len(), 1);
+
+    for bad in [
+        r#"{"$expr": {"$frobnicate": [1]}}"#,
+        r#"{"$expr": {"$subtract": [1, 2, 3]}}"#,
+        r#"{"$expr": {"$add": 1, "$multiply": 2}}"#,
+        r#"{"$expr": "$"}"#,
+    ] {
+        assert!(matches!(parse_filter_json(bad), Err(DbError::QueryError(_))), "{bad}");
+    }
+}
+
+#[test]
+fn type_and_mod_filters() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("expr_type_mod.wasp")).unwrap();
+    let col = tasks(&engine);
+    assert_eq!(ids(&col, r#"{"field": "price", "$type": "string"}"#), ["c"]);
+    assert_eq!(ids(&col, r#"{"field": "price", "$type": "number"}"#), ["a", "b"]);
+    assert_eq!(ids(&col, r#"{"field": "price", "$type": "long"}"#), ["b"]);
+    assert_eq!(ids(&col, r#"{"field": "updated_at", "$type": "date"}"#), ["a", "b"]);
+    assert!(parse_filter_json(r#"{"field": "price", "$type": "money"}"#).is_err());
+
+    assert_eq!(ids(&col, r#"{"field": "done", "$mod": [2, 0]}"#), ["b", "c"]);
+    // Doubles are truncated before dividing
+    assert_eq!(ids(&col, r#"{"field": "price", "$mod": [2, 0]}"#), ["a", "b"]);
+    assert!(parse_filter_json(r#"{"field": "done", "$mod": [0, 0]}"#).is_err());
+    let f = parse_filter_json(r#"{"field": "done", "$mod": [3, 1]}"#).unwrap();
+    let json = serde_json::to_string(&f).unwrap();
+    assert!(json.contains("$mod"), "{json}");
+
+    let d = doc! {"tags": ["x", 1]};
+    let arr = parse_filter_json(r#"{"field": "tags", "$type": "array"}"#).unwrap();
+    let int = parse_filter_json(r#"{"field": "tags", "$type": "int"}"#).unwrap();
+    assert!(query::eval_filter(&d, &arr) && query::eval_filter(&d, &int));
+}
+
+#[test]
+fn operators_on_numbers_strings_and_dates() {
+    // 2024-02-29T13:45:30.250Z, a Thursday
+    let when = DateTime::from_millis(1_709_214_330_250);
+    let d = doc! {"a": 7, "b": 2_i64, "x": 1.5, "s": "Héllo", "when": when, "tags": ["p", "q"]};
+    let cases = [
+        (doc! {"$add": ["$a", "$b", 1]}, Bson::Int64(10)),
+        (doc! {"$subtract": ["$a", 10]}, Bson::Int32(-3)),
+        (doc! {"$multiply": ["$a", "$x"]}, Bson::Double(10.5)),
+        (doc! {"$divide": ["$a", 2]}, Bson::Double(3.5)),
+        (doc! {"$mod": ["$a", 4]}, Bson::Int32(3)),
+        (doc! {"$abs": {"$subtract": [1, "$b"]}}, Bson::Int64(1)),
+        (doc! {"$floor": "$x"}, Bson::Double(1.0)),
+        (doc! {"$ceil": "$x"}, Bson::Double(2.0)),
+        (doc! {"$add": ["$missing", 1]}, Bson::Null),
+        (doc! {"$cmp": ["$a", "$b"]}, Bson::Int32(1)),
+        (doc! {"$and": [true, {"$ne": ["$a", 0]}]}, Bson::Boolean(true)),
+        (doc! {"$or": [0, "$missing"]}, Bson::Boolean(false)),
+        (doc! {"$not": [""]}, Bson::Boolean(false)),
+        (
+            doc! {"$cond": {"if": {"$in": ["q", "$tags"]}, "then": "yes", "else": "no"}},
+            "yes".into(),
+        ),
+        (doc! {"$ifNull": ["$missing", "$a"]}, Bson::Int32(7)),
+        (doc! {"$concat": ["$s", ", ", {"$toUpper": "world"}]}, "Héllo, WORLD".into()),
+        (doc! {"$toLower": "$s"}, "héllo".into()),
+        (doc! {"$substrCP": ["$s", 1, 3]}, "éll".into()),
+        (doc! {"$strLenCP": "$s"}, Bson::Int32(5)),
+        (doc! {"$size": "$tags"}, Bson::Int32(2)),
+        (doc! {"$year": "$when"}, Bson::Int32(2024)),
+        (doc! {"$month": "$when"}, Bson::Int32(2)),
+        (doc! {"$dayOfMonth": "$when"}, Bson::Int32(29)),
+        (doc! {"$dayOfWeek": "$when"}, Bson::Int32(5)),
+        (doc! {"$dayOfYear": "$when"}, Bson::Int32(60)),
+        (doc! {"$hour": "$when"}, Bson::Int32(13)),
+        (doc! {"$minute": "$when"}, Bson::Int32(45)),
+        (doc! {"$second": "$when"}, Bson::Int32(30)),
+        (doc! {"$millisecond": "$when"}, Bson::Int32(250)),
+        (doc! {"$subtract": ["$when", {"$add": ["$when", 1000]}]}, Bson::Int64(-1000)),
+        (doc! {"$type": "$b"}, "long".into()),
+        (doc! {"$type": "$missing"}, "missing".into()),
+        (doc! {"$literal": "$a"}, "$a".into()),
+    ];
+    for (expr, want) in cases {
+        let got = eval(Bson::Document(expr.clone()), &d).unwrap();
+        assert_eq!(got, Some(want), "{expr}");
+        // The BSON form reads back as the same expression
+        let parsed = Expr::parse(Bson::Document(expr)).unwrap();
+        assert_eq!(Expr::parse(parsed.to_bson()).unwrap(), parsed);
+    }
+    assert_eq!(eval(Bson::String("$missing".into()), &d).unwrap(), None);
+
+    for bad in [
+        doc! {"$divide": ["$a", 0]},
+        doc! {"$mod": ["$a", 0]},
+        doc! {"$add": ["$a", "$s"]},
+        doc! {"$add": ["$when", "$when"]},
+        doc! {"$size": "$a"},
+        doc! {"$year": "$a"},
+        doc! {"$add": [9_223_372_036_854_775_807_i64, 1]},
+    ] {
+        assert!(
+            matches!(eval(Bson::Document(bad.clone()), &d), Err(DbError::QueryError(_))),
+            "{bad}"
+        );
+    }
+}
+
+#[test]
+fn nesting_paths_and_steps_are_bounded() {
+    let d = doc! {"a": 1, "big": (0..2000).collect::<Vec<i32>>()};
+    let mut nested = Bson::String("$a".into());
+    for _ in 0..40 {
+        nested = Bson::Document(doc! {"$abs": nested});
+    }
+    let err = Expr::parse(nested).unwrap_err();
+    assert!(matches!(err, DbError::QueryError(m) if m.contains("deeper")));
+    let deep = format!("${}", vec!["p"; 40].join("."));
+    assert!(Expr::parse(Bson::String(deep)).is_err());
+
+    // Each `$in` over the array costs a step per element
+    let scans: Vec<Bson> = (0..10).map(|_| bson::bson!({"$in": [-1, "$big"]})).collect();
+    let err = eval(bson::bson!({"$or": scans}), &d).unwrap_err();
+    assert!(matches!(err, DbError::QueryError(m) if m.contains("evaluation steps")));
+    let expr = Expr::parse(bson::bson!({"$in": [5, "$big"]})).unwrap();
+    assert_eq!(expr.eval(&d), Some(Bson::Boolean(true)));
+}
+
+#[test]
+fn computed_fields_in_projections() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("expr_project.wasp")).unwrap();
+    let col = tasks(&engine);
+    let projection = parse_projection_json(
+        r#"{"title": 1, "progress": {"$cond": [{"$eq": ["$total", 0]}, null,
+            {"$multiply": [100, {"$divide": ["$done", "$total"]}]}]},
+            "meta.created": "$created_at", "label": {"$literal": 1}}"#,
+    )
+    .unwrap();
+    let opts = FindOptions {
+        sort: Some(vec![SortSpec { field: "_id".into(), order: Order::Asc }]),
+        projection: Some(projection.clone()),
+        ..FindOptions::default()
+    };
+    let out: Vec<_> = query::find_docs(&col, &Filter::True, &opts).map(|d| d.data.0).collect();
+    assert_eq!(
+        out[0],
+        doc! {"_id": "a", "title": "Write docs", "progress": 75.0,
+        "meta": {"created": DateTime::from_millis(1_000)}, "label": 1}
+    );
+    assert_eq!(out[2].get("progress"), Some(&Bson::Null));
+
+    // Computed fields count as inclusions, and survive a serde round trip
+    assert!(parse_projection_json(r#"{"password": 0, "n": {"$add": [1, 2]}}"#).is_err());
+    assert!(parse_projection_json(r#"{"a": "yes"}"#).is_err());
+    let json = serde_json::to_string(&projection).unwrap();
+    let back: query::Projection = serde_json::from_str(&json).unwrap();
+    let opts = FindOptions { projection: Some(back), ..opts };
+    let again: Vec<_> = query::find_docs(&col, &Filter::True, &opts).map(|d| d.data.0).collect();
+    assert_eq!(again, out);
+}
+
+#[test]
+fn expressions_in_aggregation() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("expr_agg.wasp")).unwrap();
+    let col = tasks(&engine);
+    let pipeline = parse_pipeline_json(
+        r#"[
+            {"$match": {"$expr": {"$gt": ["$total", 0]}}},
+            {"$addFields": {"left": {"$subtract": ["$total", "$done"]}, "stats.total": "$total"}},
+            {"$set": {"title": {"$toUpper": "$title"}}},
+            {"$project": {"title": 1, "left": 1, "stats": 1,
+                          "age_ms": {"$subtract": ["$updated_at", "$created_at"]}}},
+            {"$sort": {"left": -1}}
+        ]"#,
+    )
+    .unwrap();
+    assert!(matches!(pipeline[3], query::Stage::AddFields(_)));
+    let out = aggregate(&col, &pipeline).unwrap();
+    assert_eq!(
+        out,
+        [
+            doc! {"title": "WRITE DOCS", "left": 1, "stats": {"total": 4}, "age_ms": 4000_i64},
+            doc! {"title": "FIX BUG", "left": 0, "stats": {"total": 4}, "age_ms": 0_i64},
+        ]
+    );
+
+    let grouped = parse_pipeline_json(
+        r#"[{"$group": {"_id": {"$cond": [{"$gt": ["$done", 2]}, "busy", "idle"]},
+                        "work": {"$sum": {"$multiply": ["$done", 10]}}}},
+            {"$sort": {"_id": 1}}]"#,
+    )
+    .unwrap();
+    let out = aggregate(&col, &grouped).unwrap();
+    assert_eq!(out, [doc! {"_id": "busy", "work": 70}, doc! {"_id": "idle", "work": 0}]);
+    assert!(parse_pipeline_json(r#"[{"$addFields": {"$bad": 1}}]"#).is_err());
+    assert!(parse_pipeline_json(r#"[{"$group": {"_id": {"$nope": 1}}}]"#).is_err());
+}
@@ -1,6 +1,8 @@
 use bson::doc;
 use nexuslite::document::{Document, DocumentType};
 use nexuslite::engine::Engine;
+#[cfg(feature = "regex")]
+use nexuslite::errors::DbError;
 use nexuslite::query::{self, CmpOp, Filter, FindOptions};
 
 #[test]
@@ -12,16 +14,15 @@ fn test_regex_basic_and_length_guard() {
     col.insert_document(Document::new(doc! {"name":"Alice"}, DocumentType::Persistent));
     col.insert_document(Document::new(doc! {"name":"Bob"}, DocumentType::Persistent));
     // Case-insensitive search
-    let f = Filter::Regex { path: "name".into(), pattern: "^a".into(), case_insensitive: true };
+    let pattern = query::RegexPattern::new("^a", true).unwrap();
+    let f = Filter::Regex { path: "name".into(), pattern };
     let cur = query::find_docs(&col, &f, &FindOptions::default());
     let docs = cur.to_vec();
     assert_eq!(docs.len(), 1);
     assert_eq!(docs[0].data.0.get_str("name").unwrap(), "Alice");
     // Over-long pattern should reject
     let long = "a".repeat(600);
-    let f2 = Filter::Regex { path: "name".into(), pattern: long, case_insensitive: false };
-    let docs2 = query::find_docs(&col, &f2, &FindOptions::default()).to_vec();
-    assert_eq!(docs2.len(), 0);
+    assert!(matches!(query::RegexPattern::new(&long, false), Err(DbError::QueryError(_))));
 }
 
 #[test]
@@ -0,0 +1,162 @@
+use bson::doc;
+use nexuslite::Database;
+use nexuslite::engine::Engine;
+use nexuslite::query::{
+    self, FindAndModifyOptions, Order, Projection, ReturnDocument, SortSpec, parse_filter_json,
+    parse_update_json,
+};
+use std::collections::HashSet;
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+fn jobs(engine: &Engine, n: i32) -> std::sync::Arc<nexuslite::collection::Collection> {
+    let col = engine.create_collection("jobs".into());
+    for i in 0..n {
+        col.insert_document(persistent(doc! {"n": i, "state": "pending", "pri": i % 3}));
+    }
+    col
+}
+
+fn by_priority() -> Option<Vec<SortSpec>> {
+    Some(vec![
+        SortSpec { field: "pri".into(), order: Order::Desc },
+        SortSpec { field: "n".into(), order: Order::Asc },
+    ])
+}
+
+#[test]
+fn update_picks_by_sort_and_returns_either_version() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("fam_update.wasp")).unwrap();
+    let col = jobs(&engine, 6);
+    let pending = parse_filter_json(r#"{"field":"state","$eq":"pending"}"#).unwrap();
+    let claim = parse_update_json(r#"{"$set": {"state": "running"}}"#).unwrap();
+
+    let before = FindAndModifyOptions { sort: by_priority(), ..Default::default() };
+    let d = query::find_one_and_update(&col, &pending, &claim, &before).unwrap().unwrap();
+    assert_eq!(d.data.0, doc! {"n": 2, "state": "pending", "pri": 2});
+    assert_eq!(col.find_document(&d.id).unwrap().data.0.get_str("state").unwrap(), "running");
+
+    let after = FindAndModifyOptions {
+        sort: by_priority(),
+        projection: Some(Projection::include(["n", "state"])),
+        return_document: ReturnDocument::After,
+        ..Default::default()
+    };
+    let d = query::find_one_and_update(&col, &pending, &claim, &after).unwrap().unwrap();
+    assert_eq!(d.data.0, doc! {"n": 5, "state": "running"});
+
+    let none = parse_filter_json(r#"{"field":"state","$eq":"missing"}"#).unwrap();
+    assert!(query::find_one_and_update(&col, &none, &claim, &after).unwrap().is_none());
+
+    // Upserts return nothing before and the inserted document after
+    let upsert = FindAndModifyOptions { upsert: true, ..Default::default() };
+    assert!(query::find_one_and_update(&col, &none, &claim, &upsert).unwrap().is_none());
+    let other = parse_filter_json(r#"{"field":"state","$eq":"other"}"#).unwrap();
+    let upsert_after = FindAndModifyOptions { return_document: ReturnDocument::After, ..upsert };
+    let d = query::find_one_and_update(&col, &other, &claim, &upsert_after).unwrap().unwrap();
+    assert_eq!(d.data.0, doc! {"state": "running"});
+    assert!(col.find_document(&d.id).is_some());
+}
+
+#[test]
+fn concurrent_claims_never_share_a_job() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("fam_claims.wasp")).unwrap();
+    let col = jobs(&engine, 40);
+    let pending = parse_filter_json(r#"{"field":"state","$eq":"pending"}"#).unwrap();
+    let claim = parse_update_json(r#"{"$set": {"state": "running"}}"#).unwrap();
+    let opts = FindAndModifyOptions { sort: by_priority(), ..Default::default() };
+
+    let claimed: Vec<i32> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut mine = Vec::new();
+                    while let Some(d) =
+                        query::find_one_and_update(&col, &pending, &claim, &opts).unwrap()
+                    {
+                        mine.push(d.data.0.get_i32("n").unwrap());
+                    }
+                    mine
+                })
+            })
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    });
+    assert_eq!(claimed.len(), 40);
+    assert_eq!(claimed.iter().collect::<HashSet<_>>().len(), 40);
+}
+
+#[test]
+fn replace_and_delete() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("fam_replace.wasp")).unwrap();
+    let col = jobs(&engine, 5);
+    let any = parse_filter_json(r#"{"field":"state","$eq":"pending"}"#).unwrap();
+    let first = FindAndModifyOptions {
+        sort: Some(vec![SortSpec { field: "n".into(), order: Order::Asc }]),
+        return_document: ReturnDocument::After,
+        ..Default::default()
+    };
+    let d = query::find_one_and_replace(&col, &any, &doc! {"n": 0, "state": "done"}, &first)
+        .unwrap()
+        .unwrap();
+    assert_eq!(d.data.0, doc! {"n": 0, "state": "done"});
+    assert!(query::find_one_and_replace(&col, &any, &doc! {"$set": {"a": 1}}, &first).is_err());
+
+    let f = parse_filter_json(r#"{"field":"n","$eq":99}"#).unwrap();
+    let upsert = FindAndModifyOptions { upsert: true, ..first.clone() };
+    let d = query::find_one_and_replace(&col, &f, &doc! {"n": 99}, &upsert).unwrap().unwrap();
+    assert_eq!(d.data.0, doc! {"n": 99});
+
+    let last = FindAndModifyOptions {
+        sort: Some(vec![SortSpec { field: "n".into(), order: Order::Desc }]),
+        projection: Some(Projection::include(["n"])),
+        ..Default::default()
+    };
+    let d = query::find_one_and_delete(&col, &any, &last).unwrap();
+    assert_eq!(d.data.0, doc! {"n": 4});
+    assert!(col.find_document(&d.id).is_none());
+
+    // Concurrent deletes hand out each document once
+    let everything = parse_filter_json(r#"{"field":"n","$gte":0}"#).unwrap();
+    let deleted: usize = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut n = 0;
+                    while query::find_one_and_delete(&col, &everything, &last).is_some() {
+                        n += 1;
+                    }
+                    n
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+    assert_eq!(deleted, 5);
+    assert!(col.list_ids().is_empty());
+}
+
+#[test]
+fn database_find_one_and_modify() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("fam_db.db");
+    let db = Database::new(Some(db_path.to_str().unwrap())).unwrap();
+    db.create_collection("q").insert_document(persistent(doc! {"k": 1}));
+    let f = parse_filter_json(r#"{"field":"k","$eq":1}"#).unwrap();
+    let opts =
+        FindAndModifyOptions { return_document: ReturnDocument::After, ..Default::default() };
+    let inc = parse_update_json(r#"{"$inc": {"k": 1}}"#).unwrap();
+    let d = db.find_one_and_update("q", &f, &inc, &opts).unwrap().unwrap();
+    assert_eq!(d.data.0, doc! {"k": 2});
+    let f = parse_filter_json(r#"{"field":"k","$eq":2}"#).unwrap();
+    let d = db.find_one_and_replace("q", &f, &doc! {"k": 3}, &opts).unwrap().unwrap();
+    assert_eq!(d.data.0, doc! {"k": 3});
+    let f = parse_filter_json(r#"{"field":"k","$eq":3}"#).unwrap();
+    assert!(db.find_one_and_delete("q", &f, &opts).unwrap().is_some());
+    assert!(db.find_one_and_delete("q", &f, &opts).unwrap().is_none());
+    assert!(db.find_one_and_delete("missing", &f, &opts).is_err());
+}
@@ -0,0 +1,292 @@
+use bson::{Bson, doc};
+use nexuslite::collection::Collection;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::index::{IndexDescriptor, IndexImpl, IndexKind};
+use nexuslite::query::{
+    self, AccessPath, Filter, FindOptions, Order, SortSpec, aggregate, parse_filter_json,
+    parse_pipeline_json, parse_projection_json, parse_update_json,
+};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+/// A legacy `[lon, lat]` point at every whole degree in `[-10, 10]` on both axes.
+fn grid(engine: &Engine, name: &str) -> Arc<Collection> {
+    let col = engine.create_collection(name.into());
+    for lon in -10..=10 {
+        for lat in -10..=10 {
+            let d = doc! {"lon": lon, "lat": lat, "loc": [f64::from(lon), f64::from(lat)]};
+            col.insert_document(Document::new(d, DocumentType::Persistent));
+        }
+    }
+    col
+}
+
+fn geo_index(col: &Arc<Collection>, field: &str) {
+    col.create_index_with(&IndexDescriptor::new(field, IndexKind::Geo)).unwrap();
+}
+
+fn names(col: &Arc<Collection>, filter: &str) -> Vec<String> {
+    let f = parse_filter_json(filter).unwrap();
+    query::find_docs(col, &f, &FindOptions::default())
+        .map(|d| d.data.0.get_str("name").unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn geo_within_shapes_agree_with_and_without_index() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("geo_within.wasp")).unwrap();
+    let plain = grid(&engine, "plain");
+    let indexed = grid(&engine, "indexed");
+    geo_index(&indexed, "loc");
+
+    let cases = [
+        (r#"{"$box": [[0, 0], [2, 3]]}"#, 12),
+        (r#"{"$center": [[0, 0], 1.5]}"#, 9),
+        (r#"{"$polygon": [[0, 0], [4, 0], [0, 4]]}"#, 15),
+        // A 9x9 square with a 3x3 hole: 81 points less the 1 strictly inside the hole
+        (
+            r#"{"$geometry": {"type": "Polygon", "coordinates": [
+                [[-4, -4], [4, -4], [4, 4], [-4, 4], [-4, -4]],
+                [[-1, -1], [1, -1], [1, 1], [-1, 1], [-1, -1]]]}}"#,
+            80,
+        ),
+        // Two degrees of arc around the origin
+        (r#"{"$centerSphere": [[0, 0], 0.0349066]}"#, 13),
+    ];
+    for (shape, expected) in cases {
+        let f =
+            parse_filter_json(&format!(r#"{{"field": "loc", "$geoWithin": {shape}}}"#)).unwrap();
+        assert_eq!(query::count_docs(&plain, &f), expected, "{shape}");
+        let found = query::find_docs(&indexed, &f, &FindOptions::default()).count();
+        assert_eq!(found, expected, "{shape}");
+        let report = query::explain(&indexed, &f, &FindOptions::default());
+        assert!(matches!(report.chosen.path, AccessPath::IndexGeo { .. }), "{shape}");
+        assert!(report.chosen.estimated_docs < 441, "{shape}");
+    }
+
+    // Documents whose location is missing or not a point never match, indexed or not
+    for col in [&plain, &indexed] {
+        col.insert_document(Document::new(doc! {"loc": "nowhere"}, DocumentType::Persistent));
+        col.insert_document(Document::new(doc! {"loc": [500.0, 0.0]}, DocumentType::Persistent));
+        let f = parse_filter_json(
+            r#"{"field": "loc", "$geoWithin": {"$box": [[-180, -90], [180, 90]]}}"#,
+        )
+        .unwrap();
+        assert_eq!(query::find_docs(col, &f, &FindOptions::default()).count(), 441);
+    }
+}
+
+fn cities(engine: &Engine, name: &str) -> Arc<Collection> {
+    let col = engine.create_collection(name.into());
+    for (name, lon, lat) in [
+        ("paris", 2.3522, 48.8566),
+        ("versailles", 2.1301, 48.8049),
+        ("orleans", 1.9093, 47.9030),
+        ("lyon", 4.8357, 45.7640),
+        ("london", -0.1276, 51.5072),
+        ("suva", 178.4419, -18.1416),
+        ("apia", -171.7514, -13.8333),
+    ] {
+        let loc = doc! {"type": "Point", "coordinates": [lon, lat]};
+        let d = doc! {"name": name, "loc": loc, "legacy": [lon, lat]};
+        col.insert_document(Document::new(d, DocumentType::Persistent));
+    }
+    col
+}
+
+#[test]
+fn near_results_are_sorted_by_distance() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("geo_near.wasp")).unwrap();
+    let col = cities(&engine, "cities");
+    let paris = r#"{"$geometry": {"type": "Point", "coordinates": [2.3522, 48.8566]}}"#;
+
+    // Nearest first without an index, then with one
+    let within_500km = format!(r#"{{"field": "loc", "$near": {paris}, "$maxDistance": 500000}}"#);
+    let expected = ["paris", "versailles", "orleans", "london", "lyon"];
+    assert_eq!(names(&col, &within_500km), expected);
+    geo_index(&col, "loc");
+    assert_eq!(names(&col, &within_500km), expected);
+    let f = parse_filter_json(&within_500km).unwrap();
+    let report = query::explain(&col, &f, &FindOptions::default());
+    assert!(matches!(report.chosen.path, AccessPath::IndexGeo { .. }));
+
+    // A minimum distance, limits, and the distance itself
+    let ring = format!(
+        r#"{{"field": "loc", "$near": {paris}, "$minDistance": 10000, "$maxDistance": 200000}}"#
+    );
+    assert_eq!(names(&col, &ring), ["versailles", "orleans"]);
+    let opts = FindOptions {
+        projection: Some(
+            parse_projection_json(r#"{"name": 1, "km": {"$meta": "geoNearDistance"}}"#).unwrap(),
+        ),
+        limit: Some(2),
+        skip: Some(1),
+        ..FindOptions::default()
+    };
+    let docs: Vec<_> = query::find_docs(&col, &f, &opts).map(|d| d.data.0).collect();
+    assert_eq!(docs.len(), 2);
+    assert_eq!(docs[0].get_str("name").unwrap(), "versailles");
+    let metres = docs[1].get_f64("km").unwrap();
+    assert!((110_000.0..112_000.0).contains(&metres), "{metres}");
+    assert!(docs.iter().all(|d| !d.contains_key("$geoNearDistance")));
+
+    // Another sort replaces the distance order; sorting by distance needs a $near
+    let by_name = FindOptions {
+        sort: Some(vec![SortSpec { field: "name".into(), order: Order::Desc }]),
+        ..FindOptions::default()
+    };
+    let first = query::find_docs(&col, &f, &by_name).next().unwrap();
+    assert_eq!(first.data.0.get_str("name").unwrap(), "versailles");
+    let by_distance =
+        FindOptions { sort: Some(vec![SortSpec::geo_distance()]), ..FindOptions::default() };
+    let err = query::find_docs_rate_limited(&col, &Filter::True, &by_distance).err().unwrap();
+    assert!(matches!(err, DbError::QueryError(m) if m.contains("$near")));
+
+    // Legacy pairs: planar degrees for $near, radians for $nearSphere
+    assert_eq!(
+        names(&col, r#"{"field": "legacy", "$near": [2.3522, 48.8566], "$maxDistance": 1.1}"#),
+        ["paris", "versailles", "orleans"]
+    );
+    assert_eq!(
+        names(
+            &col,
+            r#"{"field": "legacy", "$nearSphere": [2.3522, 48.8566], "$maxDistance": 0.01}"#
+        ),
+        ["paris", "versailles"]
+    );
+
+    // Across the antimeridian, with the index
+    let fiji = r#"{"$geometry": {"type": "Point", "coordinates": [179.9, -16.0]}}"#;
+    let f = format!(r#"{{"field": "loc", "$nearSphere": {fiji}, "$maxDistance": 1500000}}"#);
+    assert_eq!(names(&col, &f), ["suva", "apia"]);
+
+    // Only one $near per query, reachable through $and
+    let nested = format!(r#"{{"$or": [{within_500km}, {{"field": "name", "$eq": "x"}}]}}"#);
+    let f = parse_filter_json(&nested).unwrap();
+    let err = query::find_docs_rate_limited(&col, &f, &FindOptions::default()).err().unwrap();
+    assert!(matches!(err, DbError::QueryError(m) if m.contains("top level")));
+    let and = format!(r#"{{"$and": [{within_500km}, {{"field": "name", "$eq": "lyon"}}]}}"#);
+    assert_eq!(names(&col, &and), ["lyon"]);
+}
+
+#[test]
+fn geo_intersects_lines_and_polygons() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("geo_intersects.wasp")).unwrap();
+    let col = engine.create_collection("areas".into());
+    let shapes = [
+        ("point", doc! {"type": "Point", "coordinates": [1.0, 1.0]}),
+        ("road", doc! {"type": "LineString", "coordinates": [[-5.0, 0.0], [5.0, 0.0]]}),
+        (
+            "park",
+            doc! {"type": "Polygon", "coordinates": [[
+                [10.0, 10.0], [20.0, 10.0], [20.0, 20.0], [10.0, 20.0], [10.0, 10.0]
+            ]]},
+        ),
+        ("far", doc! {"type": "Point", "coordinates": [50.0, 50.0]}),
+    ];
+    for (name, geo) in shapes {
+        let d = doc! {"name": name, "area": geo};
+        col.insert_document(Document::new(d, DocumentType::Persistent));
+    }
+    let intersects = |geometry: &str| {
+        let mut found = names(
+            &col,
+            &format!(r#"{{"field": "area", "$geoIntersects": {{"$geometry": {geometry}}}}}"#),
+        );
+        found.sort();
+        found
+    };
+    let square =
+        r#"{"type": "Polygon", "coordinates": [[[0, -1], [2, -1], [2, 2], [0, 2], [0, -1]]]}"#;
+    let crossing = r#"{"type": "LineString", "coordinates": [[15, 0], [15, 30]]}"#;
+    let inside_park = r#"{"type": "Point", "coordinates": [12, 12]}"#;
+    for pass in 0..2 {
+        assert_eq!(intersects(square), ["point", "road"], "pass {pass}");
+        assert_eq!(intersects(crossing), ["park"], "pass {pass}");
+        assert_eq!(intersects(inside_park), ["park"], "pass {pass}");
+        // Lines and polygons are within a region only when all of them is
+        let within =
+            names(&col, r#"{"field": "area", "$geoWithin": {"$box": [[-6, -1], [21, 21]]}}"#);
+        assert_eq!(within.len(), 3, "pass {pass}");
+        assert!(
+            names(&col, r#"{"field": "area", "$geoWithin": {"$box": [[0, -1], [21, 21]]}}"#)
+                .iter()
+                .all(|n| n != "road")
+        );
+        if pass == 0 {
+            geo_index(&col, "area");
+        }
+    }
+    let mgr = col.indexes.read();
+    let IndexImpl::Geo(g) = &mgr.indexes["area"] else { panic!("not a geo index") };
+    assert_eq!((g.cells.len(), g.shapes.len(), g.stats.docs), (2, 2, 4));
+}
+
+#[test]
+fn geo_index_maintenance_round_trips_and_validation() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("geo_admin.wasp")).unwrap();
+    let col = cities(&engine, "devices");
+    geo_index(&col, "legacy");
+    let stats = |col: &Arc<Collection>| col.indexes.read().indexes["legacy"].stats().clone();
+    assert_eq!((stats(&col).docs, stats(&col).entries), (7, 7));
+
+    // Moving, removing and adding devices keeps the index in step
+    let near_paris = r#"{"field": "legacy", "$near": [2.35, 48.85], "$maxDistance": 0.5}"#;
+    let move_lyon = parse_update_json(r#"{"$set": {"legacy": [2.36, 48.86]}}"#).unwrap();
+    let lyon = parse_filter_json(r#"{"field": "name", "$eq": "lyon"}"#).unwrap();
+    query::update_many(&col, &lyon, &move_lyon).unwrap();
+    assert_eq!(names(&col, near_paris), ["paris", "lyon", "versailles"]);
+    query::delete_many(&col, &parse_filter_json(r#"{"field": "name", "$eq": "paris"}"#).unwrap());
+    let d = doc! {"name": "phone", "legacy": [[2.35, 48.85], [100.0, 0.0]]};
+    col.insert_document(Document::new(d, DocumentType::Persistent));
+    assert_eq!(names(&col, near_paris), ["phone", "lyon", "versailles"]);
+    assert_eq!((stats(&col).docs, stats(&col).entries), (7, 8));
+    assert!(stats(&col).multikey);
+
+    // Filters serialize back to the same query
+    let f = parse_filter_json(near_paris).unwrap();
+    let again: Filter = serde_json::from_str(&serde_json::to_string(&f).unwrap()).unwrap();
+    let ids = |f: &Filter| -> Vec<_> {
+        query::find_docs(&col, f, &FindOptions::default()).map(|d| d.id).collect()
+    };
+    assert_eq!(ids(&f), ids(&again));
+    let box_filter = r#"{"field": "legacy", "$geoWithin": {"$box": [[0, 40], [5, 50]]}}"#;
+    let f = parse_filter_json(box_filter).unwrap();
+    let again: Filter = serde_json::from_str(&serde_json::to_string(&f).unwrap()).unwrap();
+    assert_eq!(ids(&f), ids(&again));
+
+    // Pipelines can sort by the distance of a leading $match
+    let pipeline = parse_pipeline_json(&format!(
+        r#"[{{"$match": {near_paris}}}, {{"$sort": {{"d": {{"$meta": "geoNearDistance"}}}}}},
+            {{"$project": {{"name": 1, "d": {{"$meta": "geoNearDistance"}}}}}}]"#
+    ))
+    .unwrap();
+    let out = aggregate(&col, &pipeline).unwrap();
+    let got: Vec<&str> = out.iter().map(|d| d.get_str("name").unwrap()).collect();
+    assert_eq!(got, ["phone", "lyon", "versailles"]);
+    assert!(matches!(out[0].get("d"), Some(Bson::Double(d)) if *d < 1e-9));
+
+    // Invalid queries and indexes
+    for bad in [
+        r#"{"field": "loc", "$geoWithin": {"$box": [[0, 0]]}}"#,
+        r#"{"field": "loc", "$geoWithin": {"$center": [[0, 0], -1]}}"#,
+        r#"{"field": "loc", "$geoWithin": {"$polygon": [[0, 0], [1, 1]]}}"#,
+        r#"{"field": "loc", "$geoWithin": {"$sphere": [0, 0]}}"#,
+        r#"{"field": "loc", "$geoIntersects": {"$geometry": {"type": "Point",
+            "coordinates": [0, 95]}}}"#,
+        r#"{"field": "loc", "$geoIntersects": [0, 0]}"#,
+        r#"{"field": "loc", "$near": [0, 0], "$maxDistance": -1}"#,
+        r#"{"field": "loc", "$nearSphere": {"$geometry": {"type": "LineString",
+            "coordinates": [[0, 0], [1, 1]]}}}"#,
+    ] {
+        assert!(matches!(parse_filter_json(bad), Err(DbError::QueryError(_))), "{bad}");
+    }
+    let unique = IndexDescriptor::new("loc", IndexKind::Geo).with_unique(true);
+    assert!(matches!(col.create_index_with(&unique), Err(DbError::QueryError(_))));
+}
@@ -0,0 +1,210 @@
+use bson::doc;
+use nexuslite::Database;
+use nexuslite::collection::Collection;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::index::IndexKind;
+use nexuslite::query::{self, Filter, FindOptions, Order, SortSpec};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn seeded(engine: &Engine, n: i32) -> Arc<Collection> {
+    let col = engine.create_collection("pages".into());
+    for i in 0..n {
+        // Every fifth document lacks the leading sort field; those sort first ascending
+        let d = if i % 5 == 0 {
+            doc! {"i": i}
+        } else {
+            doc! {"grp": i % 7, "i": i}
+        };
+        col.insert_document(Document::new(d, DocumentType::Persistent));
+    }
+    col
+}
+
+fn sort(first: Order) -> Vec<SortSpec> {
+    vec![
+        SortSpec { field: "grp".into(), order: first },
+        SortSpec { field: "i".into(), order: Order::Desc },
+    ]
+}
+
+/// Read every page of `limit` documents, following resume tokens.
+fn pages(col: &Arc<Collection>, sort: &[SortSpec], limit: usize) -> Vec<Document> {
+    let mut out = Vec::new();
+    let mut after = None;
+    loop {
+        let opts = FindOptions {
+            sort: Some(sort.to_vec()),
+            limit: Some(limit),
+            after: after.take(),
+            ..FindOptions::default()
+        };
+        let mut cur = query::find_docs_rate_limited(col, &Filter::True, &opts).unwrap();
+        let before = out.len();
+        while let Some(d) = cur.advance() {
+            out.push(d);
+        }
+        if out.len() == before {
+            return out;
+        }
+        after = cur.next_token().unwrap();
+    }
+}
+
+fn ids(docs: &[Document]) -> Vec<String> {
+    docs.iter().map(|d| d.id.0.to_string()).collect()
+}
+
+#[test]
+fn pages_concatenate_to_the_full_sort() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("keyset_pages.wasp")).unwrap();
+    let col = seeded(&engine, 60);
+    for first in [Order::Asc, Order::Desc] {
+        let sort = sort(first);
+        let opts = FindOptions { sort: Some(sort.clone()), ..FindOptions::default() };
+        let full = query::find_docs(&col, &Filter::True, &opts).to_vec();
+        assert_eq!(full.len(), 60);
+        assert_eq!(ids(&pages(&col, &sort, 7)), ids(&full));
+    }
+    // The BTree range on the leading field serves ascending pages, including the ones that
+    // resume from a document missing the field
+    col.create_index("grp", IndexKind::BTree);
+    let sort = sort(Order::Asc);
+    let opts = FindOptions { sort: Some(sort.clone()), ..FindOptions::default() };
+    let full = query::find_docs(&col, &Filter::True, &opts).to_vec();
+    assert_eq!(ids(&pages(&col, &sort, 4)), ids(&full));
+
+    // Without a sort, `after` pages in id order
+    let mut by_id = ids(&col.get_all_documents());
+    by_id.sort();
+    assert_eq!(ids(&pages(&col, &[], 9)), by_id);
+}
+
+#[test]
+fn pages_stay_stable_while_documents_are_inserted() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("keyset_inserts.wasp")).unwrap();
+    let col = seeded(&engine, 30);
+    let sort = vec![SortSpec { field: "i".into(), order: Order::Asc }];
+    let opts = FindOptions { sort: Some(sort.clone()), limit: Some(10), ..FindOptions::default() };
+    let mut cur = query::find_docs(&col, &Filter::True, &opts);
+    let first: Vec<i32> = cur.by_ref().map(|d| d.data.0.get_i32("i").unwrap()).collect();
+    assert_eq!(first, (0..10).collect::<Vec<_>>());
+    let token = cur.next_token().unwrap().unwrap();
+    // New documents before the resume point do not shift the next page; later ones appear
+    for i in [-5, 3, 12] {
+        col.insert_document(Document::new(doc! {"i": i}, DocumentType::Persistent));
+    }
+    let next = FindOptions { after: Some(token), ..opts };
+    let docs = query::find_docs(&col, &Filter::True, &next).to_vec();
+    let got: Vec<i32> = docs.iter().map(|d| d.data.0.get_i32("i").unwrap()).collect();
+    assert_eq!(got, [10, 11, 12, 12, 13, 14, 15, 16, 17, 18]);
+}
+
+#[test]
+fn tampered_and_mismatched_tokens_are_rejected() {
+    let dir = tempdir().unwrap();
+    let db = Database::new(Some(dir.path().join("keyset_tamper.db").to_str().unwrap())).unwrap();
+    let col = db.create_collection("t");
+    for i in 0..5 {
+        col.insert_document(Document::new(doc! {"i": i}, DocumentType::Persistent));
+    }
+    let sort = vec![SortSpec { field: "i".into(), order: Order::Asc }];
+    let opts = FindOptions { sort: Some(sort), limit: Some(2), ..FindOptions::default() };
+    let mut cur = db.find("t", &Filter::True, &opts).unwrap();
+    while cur.advance().is_some() {}
+    let token = cur.next_token().unwrap().unwrap();
+
+    let mut tampered = token.clone().into_bytes();
+    tampered[10] = if tampered[10] == b'0' { b'1' } else { b'0' };
+    let tampered = String::from_utf8(tampered).unwrap();
+    for bad in [tampered.as_str(), "zz", ""] {
+        let opts = FindOptions { after: Some(bad.into()), ..opts.clone() };
+        let err = db.find("t", &Filter::True, &opts).err().unwrap();
+        assert!(matches!(err, DbError::QueryError(m) if m == "invalid resume token"));
+        // The infallible entry point yields nothing rather than restarting from the top
+        assert_eq!(query::find_docs(&col, &Filter::True, &opts).count(), 0);
+    }
+
+    let desc = vec![SortSpec { field: "i".into(), order: Order::Desc }];
+    let other = FindOptions { sort: Some(desc), after: Some(token.clone()), ..opts.clone() };
+    let err = db.find("t", &Filter::True, &other).err().unwrap();
+    assert!(matches!(err, DbError::QueryError(m) if m.contains("different sort")));
+
+    let ok = FindOptions { after: Some(token), ..opts };
+    let rest: Vec<i32> =
+        db.find("t", &Filter::True, &ok).unwrap().map(|d| d.data.0.get_i32("i").unwrap()).collect();
+    assert_eq!(rest, [2, 3]);
+}
+
+#[test]
+fn tokens_survive_reopening_the_database() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("keyset_reopen.wasp");
+    let sort = vec![SortSpec { field: "i".into(), order: Order::Asc }];
+    let opts = FindOptions { sort: Some(sort), limit: Some(1), ..FindOptions::default() };
+    let token = {
+        let engine = Engine::new(path.clone()).unwrap();
+        let col = engine.create_collection("r".into());
+        for i in 0..3 {
+            col.insert_document(Document::new(doc! {"i": i}, DocumentType::Persistent));
+        }
+        let mut cur = query::find_docs(&col, &Filter::True, &opts);
+        assert!(cur.advance().is_some());
+        cur.next_token().unwrap().unwrap()
+    };
+    let engine = Engine::new(path).unwrap();
+    // Only the key matters here: the token's position is compared against new documents
+    let col = engine.create_collection("r2".into());
+    for i in 1..3 {
+        col.insert_document(Document::new(doc! {"i": i}, DocumentType::Persistent));
+    }
+    let next = FindOptions { after: Some(token.clone()), ..opts.clone() };
+    let docs = query::find_docs_rate_limited(&col, &Filter::True, &next).unwrap().to_vec();
+    assert_eq!(docs[0].data.0.get_i32("i").unwrap(), 1);
+
+    // A different database has its own key
+    let other = Engine::new(dir.path().join("keyset_other.wasp")).unwrap();
+    let col = other.create_collection("r".into());
+    assert!(query::find_docs_rate_limited(&col, &Filter::True, &next).is_err());
+}
+
+#[test]
+fn key_file_is_private_and_a_corrupt_one_is_reported() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("keyset_key.wasp");
+    let key_path = dir.path().join("keyset_key.wasp.pagekey");
+    let sort = vec![SortSpec { field: "i".into(), order: Order::Asc }];
+    let opts = FindOptions { sort: Some(sort), limit: Some(1), ..FindOptions::default() };
+    let token = {
+        let engine = Engine::new(path.clone()).unwrap();
+        let col = engine.create_collection("k".into());
+        col.insert_document(Document::new(doc! {"i": 1}, DocumentType::Persistent));
+        let mut cur = query::find_docs(&col, &Filter::True, &opts);
+        assert!(cur.advance().is_some());
+        cur.next_token().unwrap().unwrap()
+    };
+    assert_eq!(std::fs::read(&key_path).unwrap().len(), 32);
+    // Only the key itself is left behind
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&key_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    std::fs::write(&key_path, b"short").unwrap();
+    let engine = Engine::new(path).unwrap();
+    let col = engine.create_collection("k".into());
+    col.insert_document(Document::new(doc! {"i": 1}, DocumentType::Persistent));
+    let next = FindOptions { after: Some(token), ..opts.clone() };
+    let err = query::find_docs_rate_limited(&col, &Filter::True, &next).err().unwrap();
+    assert!(matches!(err, DbError::Io(m) if m.contains("corrupt")));
+    let mut cur = query::find_docs(&col, &Filter::True, &opts);
+    assert!(cur.advance().is_some());
+    assert!(matches!(cur.next_token(), Err(DbError::Io(_))));
+}
@@ -0,0 +1,129 @@
+use bson::{Bson, doc};
+use nexuslite::Database;
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::index::IndexKind;
+use nexuslite::query::{aggregate, aggregate_with, parse_pipeline_json};
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+const JOIN: &str = r#"[
+    {"$lookup": {"from": "customers", "localField": "cust", "foreignField": "cid", "as": "customer"}},
+    {"$sort": {"n": 1}}
+]"#;
+
+fn seed(engine: &Engine) -> std::sync::Arc<nexuslite::collection::Collection> {
+    let customers = engine.create_collection("customers".into());
+    customers.insert_document(persistent(doc! {"cid": 1, "name": "ann"}));
+    customers.insert_document(persistent(doc! {"cid": 2, "name": "bob"}));
+    customers.insert_document(persistent(doc! {"cid": 2, "name": "bob-dup"}));
+    customers.insert_document(persistent(doc! {"name": "nobody"}));
+    let orders = engine.create_collection("orders".into());
+    orders.insert_document(persistent(doc! {"n": 1, "cust": 1}));
+    orders.insert_document(persistent(doc! {"n": 2, "cust": 2}));
+    orders.insert_document(persistent(doc! {"n": 3, "cust": 9}));
+    orders.insert_document(persistent(doc! {"n": 4, "cust": [1, 2]}));
+    orders.insert_document(persistent(doc! {"n": 5}));
+    orders
+}
+
+fn names(d: &bson::Document) -> Vec<String> {
+    let mut out: Vec<String> = d
+        .get_array("customer")
+        .unwrap()
+        .iter()
+        .map(|c| c.as_document().unwrap().get_str("name").unwrap().to_string())
+        .collect();
+    out.sort();
+    out
+}
+
+fn check_join(out: &[bson::Document]) {
+    assert_eq!(out.len(), 5);
+    assert_eq!(names(&out[0]), ["ann"]);
+    assert_eq!(names(&out[1]), ["bob", "bob-dup"]);
+    assert!(names(&out[2]).is_empty());
+    // Array local values match element-wise
+    assert_eq!(names(&out[3]), ["ann", "bob", "bob-dup"]);
+    // A missing local field joins documents missing the foreign field
+    assert_eq!(names(&out[4]), ["nobody"]);
+}
+
+#[test]
+fn lookup_embeds_matching_documents() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("lookup_scan.wasp")).unwrap();
+    let orders = seed(&engine);
+    let p = parse_pipeline_json(JOIN).unwrap();
+    let out = aggregate_with(&orders, &p, |n| engine.get_collection(n)).unwrap();
+    check_join(&out);
+}
+
+#[test]
+fn lookup_uses_the_foreign_hash_index() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("lookup_index.wasp")).unwrap();
+    let orders = seed(&engine);
+    let customers = engine.get_collection("customers").unwrap();
+    customers.create_index("cid", IndexKind::Hash);
+
+    let p = parse_pipeline_json(JOIN).unwrap();
+    let out = aggregate_with(&orders, &p, |n| engine.get_collection(n)).unwrap();
+    check_join(&out);
+    let st = customers.indexes.read().indexes["cid"].stats().clone();
+    // Orders 1, 2 and 4 are answered by the index
+    assert!(st.hits >= 3, "hits={}", st.hits);
+}
+
+#[test]
+fn lookup_then_unwind_and_group() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("lookup_group.wasp")).unwrap();
+    let orders = seed(&engine);
+    let p = parse_pipeline_json(
+        r#"[
+            {"$match": {"field":"n","$lte":2}},
+            {"$lookup": {"from": "customers", "localField": "cust", "foreignField": "cid", "as": "c"}},
+            {"$unwind": "$c"},
+            {"$group": {"_id": "$c.name", "orders": {"$sum": 1}}},
+            {"$sort": {"_id": 1}}
+        ]"#,
+    )
+    .unwrap();
+    let out = aggregate_with(&orders, &p, |n| engine.get_collection(n)).unwrap();
+    let ids: Vec<&Bson> = out.iter().map(|d| d.get("_id").unwrap()).collect();
+    assert_eq!(ids, [&Bson::from("ann"), &Bson::from("bob"), &Bson::from("bob-dup")]);
+}
+
+#[test]
+fn lookup_collection_resolution() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("lookup_resolve.wasp")).unwrap();
+    let orders = seed(&engine);
+    let p = parse_pipeline_json(JOIN).unwrap();
+    // Plain `aggregate` only resolves the source collection itself
+    assert!(
+        matches!(aggregate(&orders, &p), Err(DbError::NoSuchCollection(n)) if n == "customers")
+    );
+    let self_join = parse_pipeline_json(
+        r#"[{"$lookup": {"from": "orders", "localField": "n", "foreignField": "cust", "as": "by"}}]"#,
+    )
+    .unwrap();
+    assert_eq!(aggregate(&orders, &self_join).unwrap().len(), 5);
+
+    for bad in [
+        r#"[{"$lookup": {"from": "customers", "localField": "cust", "as": "c"}}]"#,
+        r#"[{"$lookup": {"from": "customers", "localField": "$cust", "foreignField": "cid", "as": "c"}}]"#,
+        r#"[{"$lookup": "customers"}]"#,
+    ] {
+        assert!(parse_pipeline_json(bad).is_err(), "{bad}");
+    }
+
+    let db_path = dir.path().join("lookup_db.db");
+    let db = Database::new(Some(db_path.to_str().unwrap())).unwrap();
+    db.create_collection("customers").insert_document(persistent(doc! {"cid": 1}));
+    db.create_collection("orders").insert_document(persistent(doc! {"n": 1, "cust": 1}));
+    let out = db.aggregate("orders", &p).unwrap();
+    assert_eq!(out[0].get_array("customer").unwrap().len(), 1);
+}
@@ -0,0 +1,143 @@
+use bson::doc;
+use nexuslite::Database;
+use nexuslite::cache::CacheConfig;
+use nexuslite::collection::Collection;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::index::IndexKind;
+use nexuslite::query::{
+    self, Filter, FindOptions, Order, ParallelScan, SortSpec, parse_filter_json,
+};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+const SPLIT: ParallelScan = ParallelScan { threads: 4, min_docs: 1 };
+
+fn seeded(engine: &Engine, n: i32) -> Arc<Collection> {
+    let config = CacheConfig { capacity: 10_000, ..CacheConfig::default() };
+    let col = engine.create_collection_with_config("wide".into(), config);
+    for i in 0..n {
+        let d = doc! {"i": i, "grp": i % 7, "tag": if i % 3 == 0 { "fizz" } else { "plain" }};
+        col.insert_document(Document::new(d, DocumentType::Persistent));
+    }
+    col
+}
+
+fn ids(docs: &[Document]) -> Vec<String> {
+    docs.iter().map(|d| d.id.0.to_string()).collect()
+}
+
+/// Run `f` with scans on the calling thread, then split across workers.
+fn serial_then_split<T>(engine: &Engine, f: impl Fn() -> T) -> (T, T) {
+    engine.set_parallel_scan(ParallelScan::serial());
+    let serial = f();
+    engine.set_parallel_scan(SPLIT);
+    (serial, f())
+}
+
+#[test]
+fn split_scans_match_serial_results() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("parallel_results.wasp")).unwrap();
+    let col = seeded(&engine, 6000);
+    assert_eq!(col.parallel_scan(), ParallelScan::default());
+    let fizz = parse_filter_json(r#"{"field": "tag", "$eq": "fizz"}"#).unwrap();
+
+    let (serial, split) = serial_then_split(&engine, || query::count_docs(&col, &fizz));
+    assert_eq!((serial, split), (2000, 2000));
+    let (serial, split) =
+        serial_then_split(&engine, || query::count_docs_rate_limited(&col, &fizz).unwrap());
+    assert_eq!(serial, split);
+
+    // Unsorted results find the same documents. A serial scan's `Cache::get` calls reorder
+    // the cache, so only a split scan keeps the candidate order between runs; a limit takes
+    // the leading matches in that order.
+    let unsorted = FindOptions::default();
+    let (mut serial, mut split) =
+        serial_then_split(&engine, || ids(&query::find_docs(&col, &fizz, &unsorted).to_vec()));
+    let order = split.clone();
+    serial.sort();
+    split.sort();
+    assert_eq!(serial, split);
+    for (skip, limit) in [(5, 10), (1990, 50)] {
+        let opts = FindOptions { skip: Some(skip), limit: Some(limit), ..FindOptions::default() };
+        let page = ids(&query::find_docs(&col, &fizz, &opts).to_vec());
+        assert_eq!(page, order[skip..order.len().min(skip + limit)], "{skip} {limit}");
+    }
+
+    // Sorted, projected and resumed
+    let sort = vec![
+        SortSpec { field: "grp".into(), order: Order::Desc },
+        SortSpec { field: "i".into(), order: Order::Asc },
+    ];
+    let opts = FindOptions { sort: Some(sort.clone()), limit: Some(100), ..FindOptions::default() };
+    let (serial, split) = serial_then_split(&engine, || {
+        let mut cur = query::find_docs(&col, &fizz, &opts);
+        let first: Vec<Document> = cur.by_ref().collect();
+        (first, cur.next_token().unwrap())
+    });
+    assert_eq!(ids(&serial.0), ids(&split.0));
+    assert_eq!(split.0.len(), 100);
+    let after = FindOptions { after: split.1, ..opts.clone() };
+    let (serial, split) =
+        serial_then_split(&engine, || query::find_docs(&col, &fizz, &after).to_vec());
+    assert_eq!(ids(&serial), ids(&split));
+    assert_eq!(split[0].data.0.get_i32("grp").unwrap(), 6);
+
+    // Index candidates are split too
+    col.create_index("grp", IndexKind::BTree);
+    let f = Filter::And(vec![
+        parse_filter_json(r#"{"field": "grp", "$gte": 2}"#).unwrap(),
+        fizz.clone(),
+    ]);
+    let opts = FindOptions {
+        sort: Some(vec![SortSpec { field: "i".into(), order: Order::Desc }]),
+        ..FindOptions::default()
+    };
+    let (serial, split) = serial_then_split(&engine, || query::find_docs(&col, &f, &opts).to_vec());
+    assert_eq!(ids(&serial), ids(&split));
+    assert!(split.len() > 1000);
+}
+
+#[test]
+fn split_scans_honour_timeouts_and_see_a_consistent_snapshot() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("parallel_snapshot.wasp")).unwrap();
+    let col = seeded(&engine, 6000);
+    engine.set_parallel_scan(SPLIT);
+
+    let opts = FindOptions { timeout_ms: Some(0), ..FindOptions::default() };
+    assert!(query::find_docs(&col, &Filter::True, &opts).count() < 6000);
+
+    // Counts taken while a writer inserts see each insert whole and never go backwards
+    let all = Filter::True;
+    std::thread::scope(|s| {
+        let writer = s.spawn(|| {
+            for i in 0..200 {
+                col.insert_document(Document::new(doc! {"late": i}, DocumentType::Persistent));
+            }
+        });
+        let mut last = 0;
+        while !writer.is_finished() {
+            let n = query::count_docs(&col, &all);
+            assert!((6000..=6200).contains(&n) && n >= last, "{n} after {last}");
+            last = n;
+        }
+    });
+    assert_eq!(query::count_docs(&col, &all), 6200);
+}
+
+#[test]
+fn parallel_scan_is_configured_per_database() {
+    let dir = tempdir().unwrap();
+    let a = Database::new(Some(dir.path().join("a.db").to_str().unwrap())).unwrap();
+    let b = Database::new(Some(dir.path().join("b.db").to_str().unwrap())).unwrap();
+    let before = a.create_collection("c");
+    a.set_parallel_scan(ParallelScan::serial());
+    // Existing and new collections follow their database's setting
+    assert_eq!(before.parallel_scan(), ParallelScan::serial());
+    assert_eq!(a.create_collection("d").parallel_scan(), ParallelScan::serial());
+    assert_eq!(a.parallel_scan(), ParallelScan::serial());
+    assert_eq!(b.parallel_scan(), ParallelScan::default());
+    assert_eq!(b.create_collection("c").parallel_scan(), ParallelScan::default());
+}
@@ -0,0 +1,171 @@
+use bson::{Bson, doc};
+use nexuslite::collection::Collection;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::query::{
+    self, Filter, FindOptions, Order, Projection, SortSpec, parse_projection_json,
+};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn people(engine: &Engine) -> Arc<Collection> {
+    let col = engine.create_collection("people".into());
+    let rows = [
+        doc! {"_id": "a", "name": "ann", "password": "pw1",
+        "profile": {"age": 31, "secret": "s1"}, "scores": [4, 9, 1],
+        "address": {"city": "Oslo", "zip": "0150"},
+        "items": [{"sku": "x", "qty": 1}, {"sku": "y", "qty": 5}, 7]},
+        doc! {"_id": "b", "name": "bob", "password": "pw2",
+        "profile": {"age": 25, "secret": "s2"}, "scores": [6],
+        "address": {"city": "Bergen", "zip": "5003"},
+        "items": [{"sku": "z", "qty": 2}]},
+        doc! {"_id": "c", "name": "cy", "scores": [],
+        "profile": {"age": 40}, "items": []},
+    ];
+    for d in rows {
+        col.insert_document(Document::new(d, DocumentType::Persistent));
+    }
+    col
+}
+
+fn find(col: &Arc<Collection>, sort: &[(&str, Order)], projection: &str) -> Vec<bson::Document> {
+    let opts = FindOptions {
+        sort: Some(sort.iter().map(|(f, o)| SortSpec { field: (*f).into(), order: *o }).collect()),
+        projection: (!projection.is_empty()).then(|| parse_projection_json(projection).unwrap()),
+        ..FindOptions::default()
+    };
+    query::find_docs(col, &Filter::True, &opts).map(|d| d.data.0).collect()
+}
+
+fn names(docs: &[bson::Document]) -> Vec<&str> {
+    docs.iter().map(|d| d.get_str("name").unwrap()).collect()
+}
+
+#[test]
+fn sorts_by_nested_paths_and_array_extremes() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("proj_sort.wasp")).unwrap();
+    let col = people(&engine);
+    assert_eq!(names(&find(&col, &[("profile.age", Order::Asc)], "")), ["bob", "ann", "cy"]);
+    assert_eq!(names(&find(&col, &[("profile.age", Order::Desc)], "")), ["cy", "ann", "bob"]);
+    // Arrays sort by their smallest element ascending and largest descending; an empty
+    // array sorts like a missing field
+    assert_eq!(names(&find(&col, &[("scores", Order::Asc)], "")), ["cy", "ann", "bob"]);
+    assert_eq!(names(&find(&col, &[("scores", Order::Desc)], "")), ["ann", "bob", "cy"]);
+    // Through an array of sub-documents
+    assert_eq!(names(&find(&col, &[("items.qty", Order::Desc)], "")), ["ann", "bob", "cy"]);
+    assert_eq!(names(&find(&col, &[("items.sku", Order::Asc)], "")), ["cy", "ann", "bob"]);
+}
+
+#[test]
+fn inclusion_and_exclusion_follow_dotted_paths() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("proj_paths.wasp")).unwrap();
+    let col = people(&engine);
+    let by_name = [("name", Order::Asc)];
+
+    let docs = find(&col, &by_name, r#"{"name": 1, "address.city": 1, "items.sku": true}"#);
+    assert_eq!(
+        docs[0],
+        doc! {"_id": "a", "name": "ann", "address": {"city": "Oslo"},
+        "items": [{"sku": "x"}, {"sku": "y"}]}
+    );
+    assert_eq!(docs[2], doc! {"_id": "c", "name": "cy", "items": []});
+
+    // `_id` is kept by inclusion unless excluded, and may be excluded alongside
+    let docs = find(&col, &by_name, r#"{"_id": 0, "profile.age": 1}"#);
+    assert_eq!(docs[1], doc! {"profile": {"age": 25}});
+
+    let docs = find(&col, &by_name, r#"{"password": 0, "profile.secret": 0, "items": 0}"#);
+    assert_eq!(docs[0].get_document("profile").unwrap(), &doc! {"age": 31});
+    assert!(docs.iter().all(|d| !d.contains_key("password") && !d.contains_key("items")));
+    assert_eq!(docs[0].get_str("_id").unwrap(), "a");
+    let docs = find(&col, &by_name, r#"{"_id": 0}"#);
+    assert!(docs.iter().all(|d| !d.contains_key("_id") && d.contains_key("name")));
+}
+
+#[test]
+fn slice_and_elem_match_project_array_elements() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("proj_arrays.wasp")).unwrap();
+    let col = people(&engine);
+    let by_name = [("name", Order::Asc)];
+    let scores = |proj: &str| -> Vec<Bson> {
+        find(&col, &by_name, proj)[0].get("scores").cloned().into_iter().collect()
+    };
+    assert_eq!(scores(r#"{"scores": {"$slice": 2}}"#), [bson::bson!([4, 9])]);
+    assert_eq!(scores(r#"{"scores": {"$slice": -1}}"#), [bson::bson!([1])]);
+    assert_eq!(scores(r#"{"scores": {"$slice": [1, 5]}}"#), [bson::bson!([9, 1])]);
+    assert_eq!(scores(r#"{"scores": {"$slice": [-2, 1]}}"#), [bson::bson!([9])]);
+
+    // $slice alone keeps the other fields; next to an inclusion it is included too
+    let docs = find(&col, &by_name, r#"{"scores": {"$slice": 1}}"#);
+    assert!(docs[0].contains_key("password"));
+    let docs = find(&col, &by_name, r#"{"name": 1, "scores": {"$slice": 1}}"#);
+    assert_eq!(docs[0], doc! {"_id": "a", "name": "ann", "scores": [4]});
+
+    let docs =
+        find(&col, &by_name, r#"{"name": 1, "items": {"$elemMatch": {"field": "qty", "$gt": 1}}}"#);
+    assert_eq!(docs[0], doc! {"_id": "a", "name": "ann", "items": [{"sku": "y", "qty": 5}]});
+    assert_eq!(docs[1], doc! {"_id": "b", "name": "bob", "items": [{"sku": "z", "qty": 2}]});
+    // No matching element: the field is left out
+    assert_eq!(docs[2], doc! {"_id": "c", "name": "cy"});
+}
+
+#[test]
+fn invalid_projections_are_rejected() {
+    for bad in [
+        r#"{"a": 1, "b": 0}"#,
+        r#"{"a": "yes"}"#,
+        r#"{"a": {"$slice": [1]}}"#,
+        r#"{"a": {"$slice": [0, 0]}}"#,
+        r#"{"a": {"$elemMatch": 1}}"#,
+        r#"{"$where": 1}"#,
+        r#"{"": 1}"#,
+    ] {
+        assert!(matches!(parse_projection_json(bad), Err(DbError::QueryError(_))), "{bad}");
+    }
+    // `_id: 0` is the one exclusion allowed beside inclusions
+    assert!(parse_projection_json(r#"{"_id": 0, "a": 1}"#).unwrap().is_inclusion());
+    // Lists of paths still deserialize, and specs round-trip through serde
+    let opts: FindOptions = serde_json::from_str(r#"{"projection": ["a", "b.c"]}"#).unwrap();
+    assert!(opts.projection.unwrap().is_inclusion());
+    let p = parse_projection_json(r#"{"a": 0, "s": {"$slice": [-2, 1]}}"#).unwrap();
+    let back: Projection = serde_json::from_str(&serde_json::to_string(&p).unwrap()).unwrap();
+    assert_eq!(format!("{back:?}"), format!("{p:?}"));
+}
+
+#[test]
+fn keyset_pages_follow_nested_sort_paths() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("proj_keyset.wasp")).unwrap();
+    let col = engine.create_collection("nested".into());
+    for i in 0..20 {
+        let d = doc! {"i": i, "m": {"k": (i * 7) % 5, "tags": [i % 3, 10 - i % 4]}};
+        col.insert_document(Document::new(d, DocumentType::Persistent));
+    }
+    let sort = vec![
+        SortSpec { field: "m.k".into(), order: Order::Desc },
+        SortSpec { field: "m.tags".into(), order: Order::Asc },
+    ];
+    let opts = FindOptions { sort: Some(sort), ..FindOptions::default() };
+    let full: Vec<i32> = query::find_docs(&col, &Filter::True, &opts)
+        .map(|d| d.data.0.get_i32("i").unwrap())
+        .collect();
+    let mut paged = Vec::new();
+    let mut after = None;
+    loop {
+        let page = FindOptions { limit: Some(3), after: after.take(), ..opts.clone() };
+        let mut cur = query::find_docs_rate_limited(&col, &Filter::True, &page).unwrap();
+        let before = paged.len();
+        while let Some(d) = cur.advance() {
+            paged.push(d.data.0.get_i32("i").unwrap());
+        }
+        if paged.len() == before {
+            break;
+        }
+        after = cur.next_token().unwrap();
+    }
+    assert_eq!(paged, full);
+}
@@ -0,0 +1,92 @@
+use bson::doc;
+use nexuslite::collection::Collection;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::index::{IndexDescriptor, IndexKind};
+use nexuslite::query::{self, AccessPath, Filter, FindOptions, RegexPattern, parse_filter_json};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn words(engine: &Engine, name: &str) -> Arc<Collection> {
+    let col = engine.create_collection(name.into());
+    for i in 0..500 {
+        let word = format!("{}{i:03}", ["apple", "apricot", "banana", "abc", "ab"][i % 5]);
+        col.insert_document(Document::new(doc! {"w": word}, DocumentType::Persistent));
+    }
+    col.insert_document(Document::new(doc! {"w": ["zzz", "abcdef"]}, DocumentType::Persistent));
+    col.insert_document(Document::new(doc! {"w": 7}, DocumentType::Persistent));
+    col
+}
+
+fn regex(pattern: &str) -> Filter {
+    parse_filter_json(&format!(r#"{{"field": "w", "$regex": "{pattern}"}}"#)).unwrap()
+}
+
+#[test]
+fn invalid_and_oversized_patterns_are_query_errors() {
+    for bad in [r#"{"field": "w", "$regex": "a("}"#, r#"{"field": "w", "$regex": "[z-a]"}"#] {
+        assert!(matches!(parse_filter_json(bad), Err(DbError::QueryError(_))), "{bad}");
+    }
+    assert!(RegexPattern::new(&"a".repeat(512), false).is_ok());
+    assert!(matches!(RegexPattern::new(&"a".repeat(513), false), Err(DbError::QueryError(_))));
+    // Within the length limit but beyond the compiled size budget
+    assert!(matches!(RegexPattern::new(r"\w{1000}\w{1000}", false), Err(DbError::QueryError(_))));
+
+    let f =
+        parse_filter_json(r#"{"field": "w", "$regex": "^AB", "case_insensitive": true}"#).unwrap();
+    let Filter::Regex { pattern, .. } = &f else { panic!("expected a regex filter: {f:?}") };
+    assert_eq!(pattern.as_str(), "^AB");
+    assert!(pattern.case_insensitive() && pattern.is_match("abc"));
+}
+
+#[test]
+fn anchored_prefix_uses_btree_range_with_same_results() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("regex_prefix.wasp")).unwrap();
+    let plain = words(&engine, "plain");
+    let indexed = words(&engine, "indexed");
+    indexed.create_index_with(&IndexDescriptor::new("w", IndexKind::BTree)).unwrap();
+
+    for (pattern, expected) in
+        [("^abc", 101), ("^ap", 200), ("^apple00[0-4]", 1), ("^abc?d", 1), ("^b\\\\w+", 100)]
+    {
+        let f = regex(pattern);
+        assert_eq!(query::count_docs(&plain, &f), expected, "{pattern}");
+        let found = query::find_docs(&indexed, &f, &FindOptions::default()).count();
+        assert_eq!(found, expected, "{pattern}");
+        let report = query::explain(&indexed, &f, &FindOptions::default());
+        assert!(matches!(report.chosen.path, AccessPath::IndexRange { .. }), "{pattern}");
+    }
+
+    // Unanchored, alternating or case-insensitive patterns have no usable prefix
+    for json in [
+        r#"{"field": "w", "$regex": "abc"}"#,
+        r#"{"field": "w", "$regex": "^abc|^ap"}"#,
+        r#"{"field": "w", "$regex": "^ABC", "case_insensitive": true}"#,
+    ] {
+        let f = parse_filter_json(json).unwrap();
+        let found = query::find_docs(&indexed, &f, &FindOptions::default()).count();
+        assert_eq!(found, query::count_docs(&plain, &f), "{json}");
+        let report = query::explain(&indexed, &f, &FindOptions::default());
+        assert!(matches!(report.chosen.path, AccessPath::CollectionScan), "{json}");
+    }
+}
+
+#[test]
+fn compiled_filter_is_shared_across_threads() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("regex_threads.wasp")).unwrap();
+    let col = words(&engine, "words");
+    let f = regex("an+a");
+    let expected = query::count_docs(&col, &f);
+    assert_eq!(expected, 100);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| s.spawn(|| query::find_docs(&col, &f, &FindOptions::default()).count()))
+            .collect();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), expected);
+        }
+    });
+}
@@ -0,0 +1,180 @@
+use bson::{Bson, doc};
+use nexuslite::collection::Collection;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::index::{IndexDescriptor, IndexKind};
+use nexuslite::query::{
+    self, AccessPath, Filter, FindOptions, Order, SortSpec, TextOptions, aggregate, explain,
+    parse_filter_json, parse_pipeline_json, parse_projection_json, parse_update_json,
+};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn tickets(engine: &Engine) -> Arc<Collection> {
+    let col = engine.create_collection("tickets".into());
+    let rows = [
+        doc! {"_id": "t1", "title": "Printer jams", "body": "The paper jams in tray two"},
+        doc! {"_id": "t2", "title": "Toner", "body": "Printer toner is empty, the printer stopped"},
+        doc! {"_id": "t3", "title": "Login", "body": "Login crashes after the update"},
+        doc! {"_id": "t4", "title": "Crash report", "body": "App crashed while printing a jam report"},
+        doc! {"_id": "t5", "title": "Misc", "body": 42},
+    ];
+    for d in rows {
+        col.insert_document(Document::new(d, DocumentType::Persistent));
+    }
+    col
+}
+
+fn text_index() -> IndexDescriptor {
+    let opts = TextOptions::default().with_weight("title", 5).with_stemming(true);
+    IndexDescriptor::new("body", IndexKind::Text).with_text(opts)
+}
+
+fn search(s: &str) -> Filter {
+    Filter::Text(query::TextSearch::parse(s).unwrap())
+}
+
+fn ids(col: &Arc<Collection>, filter: &Filter) -> Vec<String> {
+    let opts = FindOptions {
+        sort: Some(vec![SortSpec { field: "_id".into(), order: Order::Asc }]),
+        ..FindOptions::default()
+    };
+    query::find_docs(col, filter, &opts)
+        .map(|d| d.data.0.get_str("_id").unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn text_queries_match_words_phrases_and_negations() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("text_match.wasp")).unwrap();
+    let col = tickets(&engine);
+    col.create_index_with(&text_index()).unwrap();
+
+    // Any word matches, in the body or in a weighted field; case does not matter
+    assert_eq!(ids(&col, &search("PRINTER login")), ["t1", "t2", "t3"]);
+    // Stemming: "crashes", "crashed" and "crash" share a stem
+    assert_eq!(ids(&col, &search("crash")), ["t3", "t4"]);
+    assert_eq!(ids(&col, &search("jammed")), ["t1", "t4"]);
+    // Every phrase must appear, its words adjacent and in order (stop words included)
+    assert_eq!(ids(&col, &search(r#""paper jams""#)), ["t1"]);
+    assert_eq!(ids(&col, &search(r#""jams in the tray""#)), Vec::<String>::new());
+    assert_eq!(ids(&col, &search(r#"printer "the printer""#)), ["t2"]);
+    // Negated words and phrases exclude
+    assert_eq!(ids(&col, &search("printer -toner")), ["t1"]);
+    assert_eq!(ids(&col, &search(r#"crash -"jam report""#)), ["t3"]);
+    // Stop words are not indexed, so they find nothing on their own
+    assert!(ids(&col, &search("the")).is_empty());
+
+    let f = parse_filter_json(r#"{"$text": {"$search": "toner"}}"#).unwrap();
+    let report = explain(&col, &f, &FindOptions::default());
+    assert!(
+        matches!(&report.chosen.path, AccessPath::IndexText { terms, .. } if terms == &["toner"])
+    );
+    assert_eq!(report.plan.children[0].docs_returned, 1);
+    // Under $and the text index competes with the other predicates
+    let f = parse_filter_json(
+        r#"{"$and": [{"$text": {"$search": "printer"}}, {"field": "title", "$eq": "Toner"}]}"#,
+    )
+    .unwrap();
+    assert_eq!(ids(&col, &f), ["t2"]);
+    let back: Filter = serde_json::from_str(&serde_json::to_string(&f).unwrap()).unwrap();
+    assert_eq!(ids(&col, &back), ["t2"]);
+
+    for bad in [
+        r#"{"$text": {"$search": "\"unterminated"}}"#,
+        r#"{"$text": {"$search": "-only -negations"}}"#,
+        r#"{"$text": {"$search": "printer", "$language": "fr"}}"#,
+    ] {
+        assert!(parse_filter_json(bad).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn text_score_sorts_and_projects() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("text_score.wasp")).unwrap();
+    let col = tickets(&engine);
+    col.create_index_with(&text_index()).unwrap();
+
+    let opts = FindOptions {
+        sort: Some(vec![SortSpec::text_score()]),
+        projection: Some(parse_projection_json(r#"{"score": {"$meta": "textScore"}}"#).unwrap()),
+        ..FindOptions::default()
+    };
+    let docs = query::find_docs(&col, &search("printer jam"), &opts).to_vec();
+    let scored: Vec<(&str, f64)> = docs
+        .iter()
+        .map(|d| (d.data.0.get_str("_id").unwrap(), d.data.0.get_f64("score").unwrap()))
+        .collect();
+    // Title matches weigh 5x; "jam" and "printer" in t1's title beat the body-only matches
+    assert_eq!(scored.iter().map(|(id, _)| *id).collect::<Vec<_>>(), ["t1", "t2", "t4"]);
+    assert!(scored.windows(2).all(|w| w[0].1 > w[1].1));
+    assert!(scored[0].1 > 5.0 && scored[1].1 < 2.0);
+    // The score is only present where projected
+    let plain = FindOptions { projection: None, ..opts.clone() };
+    let docs = query::find_docs(&col, &search("printer jam"), &plain).to_vec();
+    assert_eq!(docs.len(), 3);
+    assert!(docs.iter().all(|d| d.data.0.keys().all(|k| !k.starts_with('$'))));
+
+    let err = query::find_docs_rate_limited(&col, &Filter::True, &plain).err().unwrap();
+    assert!(matches!(err, DbError::QueryError(m) if m.contains("$text")));
+
+    let pipeline = parse_pipeline_json(
+        r#"[{"$match": {"$text": {"$search": "printer jam"}}},
+            {"$sort": {"score": {"$meta": "textScore"}}},
+            {"$project": {"title": 1, "score": {"$meta": "textScore"}}},
+            {"$limit": 2}]"#,
+    )
+    .unwrap();
+    let out = aggregate(&col, &pipeline).unwrap();
+    assert_eq!(out.len(), 2);
+    assert_eq!(out[0].get_str("title").unwrap(), "Printer jams");
+    assert!(out[0].get_f64("score").unwrap() > out[1].get_f64("score").unwrap());
+    assert!(out.iter().all(|d| !d.contains_key("$textScore")));
+}
+
+#[test]
+fn text_index_follows_writes_and_is_persisted() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("text_writes.wasp")).unwrap();
+    let col = tickets(&engine);
+    col.create_index_with(&text_index()).unwrap();
+    let keys = |col: &Arc<Collection>| col.indexes.read().indexes["body"].stats().keys;
+    let before = keys(&col);
+
+    let d = Document::new(doc! {"_id": "t6", "body": "Scanner offline"}, DocumentType::Persistent);
+    col.insert_document(d);
+    assert_eq!(ids(&col, &search("scanner")), ["t6"]);
+    assert_eq!(keys(&col), before + 2);
+
+    let upd = parse_update_json(r#"{"$set": {"body": "Scanner fixed"}}"#).unwrap();
+    query::update_one(&col, &search("offline"), &upd).unwrap();
+    assert!(ids(&col, &search("offline")).is_empty());
+    assert_eq!(ids(&col, &search("fixed")), ["t6"]);
+    assert_eq!(query::delete_many(&col, &search("scanner")).deleted, 1);
+    assert_eq!(keys(&col), before);
+    assert_eq!(query::count_docs(&col, &search("printer")), 2);
+
+    // One text index per collection, and never unique
+    let second = IndexDescriptor::new("title", IndexKind::Text);
+    assert!(matches!(col.create_index_with(&second), Err(DbError::QueryError(_))));
+    let unique = text_index().with_unique(true);
+    assert!(matches!(col.create_index_with(&unique), Err(DbError::QueryError(_))));
+
+    let descs = col.indexes.read().descriptors();
+    assert_eq!(descs[0].text.as_ref().unwrap().weights["title"], 5);
+    let json = serde_json::to_string(&descs[0]).unwrap();
+    let back: IndexDescriptor = serde_json::from_str(&json).unwrap();
+    assert!(back.text.unwrap().stemming);
+
+    // Without a text index, $text searches every string field without stemming
+    col.indexes.write().drop_index("body");
+    assert_eq!(ids(&col, &search("toner")), ["t2"]);
+    assert_eq!(ids(&col, &search("crashed")), ["t4"]);
+    let report = explain(&col, &search("toner"), &FindOptions::default());
+    assert_eq!(report.chosen.path, AccessPath::CollectionScan);
+    let doc = doc! {"notes": ["a", {"deep": "Toner low"}], "n": Bson::Null};
+    assert!(query::eval_filter(&doc, &search("toner")));
+}
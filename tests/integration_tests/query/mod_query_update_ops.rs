@@ -0,0 +1,200 @@
+use bson::{Bson, doc};
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::query::{
+    self, Filter, FindOptions, apply_update, parse_filter_json, parse_update_json,
+};
+use tempfile::tempdir;
+
+use super::super::_support::common::persistent;
+
+fn updated(d: bson::Document, update: &str) -> bson::Document {
+    let mut doc = persistent(d);
+    apply_update(&mut doc, &parse_update_json(update).unwrap()).unwrap();
+    doc.data.0
+}
+
+#[test]
+fn scalar_operators() {
+    let d = updated(
+        doc! {"a": 2, "b": 10, "c": 5, "old": "x", "keep": 1},
+        r#"{"$mul": {"a": 3, "missing": 2}, "$min": {"b": 4, "c": 9}, "$max": {"c": 7, "new": 1},
+            "$rename": {"old": "info.renamed", "absent": "z"}}"#,
+    );
+    assert_eq!(d.get_i32("a").unwrap(), 6);
+    // $mul on a missing field creates a zero
+    assert_eq!(d.get_i32("missing").unwrap(), 0);
+    assert_eq!(d.get_i32("b").unwrap(), 4);
+    assert_eq!(d.get_i32("c").unwrap(), 7);
+    assert_eq!(d.get_i32("new").unwrap(), 1);
+    assert!(!d.contains_key("old") && !d.contains_key("z"));
+    assert_eq!(d.get_document("info").unwrap().get_str("renamed").unwrap(), "x");
+
+    let d = updated(doc! {}, r#"{"$currentDate": {"at": true, "ts": {"$type": "timestamp"}}}"#);
+    assert!(matches!(d.get("at"), Some(Bson::DateTime(_))));
+    assert!(matches!(d.get("ts"), Some(Bson::Timestamp(_))));
+}
+
+#[test]
+fn push_with_modifiers() {
+    let d = updated(doc! {"s": [5, 1]}, r#"{"$push": {"s": 3, "fresh": "a"}}"#);
+    assert_eq!(d.get_array("s").unwrap(), &vec![Bson::Int32(5), Bson::Int32(1), Bson::Int32(3)]);
+    assert_eq!(d.get_array("fresh").unwrap(), &vec![Bson::from("a")]);
+
+    // $each, then $sort, then $slice
+    let d = updated(
+        doc! {"s": [5, 1]},
+        r#"{"$push": {"s": {"$each": [9, 3, 7], "$sort": -1, "$slice": 3}}}"#,
+    );
+    assert_eq!(d.get_array("s").unwrap(), &vec![Bson::Int32(9), Bson::Int32(7), Bson::Int32(5)]);
+    let d = updated(doc! {"s": [1, 2, 3]}, r#"{"$push": {"s": {"$each": [4], "$slice": -2}}}"#);
+    assert_eq!(d.get_array("s").unwrap(), &vec![Bson::Int32(3), Bson::Int32(4)]);
+
+    let d = updated(
+        doc! {"scores": [{"n": "a", "v": 3}, {"n": "b", "v": 1}]},
+        r#"{"$push": {"scores": {"$each": [{"n": "c", "v": 2}], "$sort": {"v": 1}}}}"#,
+    );
+    let order: Vec<&str> = d
+        .get_array("scores")
+        .unwrap()
+        .iter()
+        .map(|s| s.as_document().unwrap().get_str("n").unwrap())
+        .collect();
+    assert_eq!(order, ["b", "c", "a"]);
+
+    // Pushing onto a non-array is a no-op
+    let d = updated(doc! {"s": "str"}, r#"{"$push": {"s": 1}}"#);
+    assert_eq!(d.get_str("s").unwrap(), "str");
+}
+
+#[test]
+fn set_like_and_removal_operators() {
+    let d = updated(
+        doc! {"t": ["a", "b"]},
+        r#"{"$addToSet": {"t": "a", "u": {"$each": ["x", "x", "y"]}}}"#,
+    );
+    assert_eq!(d.get_array("t").unwrap().len(), 2);
+    assert_eq!(d.get_array("u").unwrap(), &vec![Bson::from("x"), Bson::from("y")]);
+
+    let d = updated(
+        doc! {"n": [1, 5, 8, 5], "t": ["a", "b", "c"], "o": [{"k": 1, "x": 0}, {"k": 2}]},
+        r#"{"$pull": {"n": {"$gte": 5, "$lt": 8}, "t": {"$in": ["a", "c"]}, "o": {"k": 1}}}"#,
+    );
+    assert_eq!(d.get_array("n").unwrap(), &vec![Bson::Int32(1), Bson::Int32(8)]);
+    assert_eq!(d.get_array("t").unwrap(), &vec![Bson::from("b")]);
+    assert_eq!(d.get_array("o").unwrap(), &vec![Bson::Document(doc! {"k": 2})]);
+
+    let d = updated(doc! {"a": [1, 2, 3], "b": [1, 2, 3]}, r#"{"$pop": {"a": 1, "b": -1}}"#);
+    assert_eq!(d.get_array("a").unwrap(), &vec![Bson::Int32(1), Bson::Int32(2)]);
+    assert_eq!(d.get_array("b").unwrap(), &vec![Bson::Int32(2), Bson::Int32(3)]);
+}
+
+#[test]
+fn array_paths() {
+    let d = updated(
+        doc! {"items": [{"q": 1}, {"q": 2}], "grid": [[1, 2], [3]]},
+        r#"{"$set": {"items.1.q": 20, "items.$[].seen": true}, "$inc": {"grid.0.1": 1}}"#,
+    );
+    assert_eq!(
+        d.get_array("items").unwrap(),
+        &vec![
+            Bson::Document(doc! {"q": 1, "seen": true}),
+            Bson::Document(doc! {"q": 20, "seen": true})
+        ]
+    );
+    assert_eq!(d.get_array("grid").unwrap()[0], Bson::Array(vec![1.into(), 3.into()]));
+
+    // Positional `$` targets the first element matched by the query
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("update_positional.wasp")).unwrap();
+    let col = engine.create_collection("carts".into());
+    col.insert_document(persistent(doc! {"items": [{"sku": "a", "q": 1}, {"sku": "b", "q": 1}]}));
+    let f = parse_filter_json(r#"{"field":"items.sku","$eq":"b"}"#).unwrap();
+    let report =
+        query::update_one(&col, &f, &parse_update_json(r#"{"$inc": {"items.$.q": 4}}"#).unwrap())
+            .unwrap();
+    assert_eq!((report.matched, report.modified), (1, 1));
+    let docs = query::find_docs(&col, &Filter::True, &FindOptions::default()).to_vec();
+    let items = docs[0].data.0.get_array("items").unwrap();
+    assert_eq!(items[0].as_document().unwrap().get_i32("q").unwrap(), 1);
+    assert_eq!(items[1].as_document().unwrap().get_i32("q").unwrap(), 5);
+}
+
+#[test]
+fn modified_counts_only_changed_documents() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("update_counts.wasp")).unwrap();
+    let col = engine.create_collection("tags".into());
+    col.insert_document(persistent(doc! {"t": ["a"]}));
+    col.insert_document(persistent(doc! {"t": ["b"]}));
+    col.insert_document(persistent(doc! {"t": ["a", "b"]}));
+
+    let add = parse_update_json(r#"{"$addToSet": {"t": "a"}}"#).unwrap();
+    let r = query::update_many(&col, &Filter::True, &add).unwrap();
+    assert_eq!((r.matched, r.modified), (3, 1));
+    let r = query::update_many(&col, &Filter::True, &add).unwrap();
+    assert_eq!((r.matched, r.modified), (3, 0));
+
+    // Setting a field to its current value matches but does not modify
+    let same = parse_update_json(r#"{"$set": {"t": ["a"]}}"#).unwrap();
+    let f = parse_filter_json(r#"{"field":"t","$nin":["b"]}"#).unwrap();
+    let r = query::update_one(&col, &f, &same).unwrap();
+    assert_eq!((r.matched, r.modified), (1, 0));
+}
+
+#[test]
+fn inc_and_mul_preserve_numeric_types() {
+    let d = updated(
+        doc! {"i": 1, "big": i32::MAX, "l": Bson::Int64(1 << 60), "f": 1.5, "s": "x"},
+        r#"{"$inc": {"i": 2, "big": 1, "l": 1, "f": 1, "new": 7}, "$mul": {"zero": 2.5}}"#,
+    );
+    assert_eq!(d.get("i"), Some(&Bson::Int32(3)));
+    assert_eq!(d.get("big"), Some(&Bson::Int64(i64::from(i32::MAX) + 1)));
+    assert_eq!(d.get("l"), Some(&Bson::Int64((1 << 60) + 1)));
+    assert_eq!(d.get("f"), Some(&Bson::Double(2.5)));
+    assert_eq!(d.get("new"), Some(&Bson::Int32(7)));
+    assert_eq!(d.get("zero"), Some(&Bson::Double(0.0)));
+
+    let price: bson::Decimal128 = "19.99".parse().unwrap();
+    let d = updated(
+        doc! {"p": price},
+        r#"{"$inc": {"p": {"$numberDecimal": "0.01"}}, "$mul": {"q": {"$numberDecimal": "3"}}}"#,
+    );
+    assert_eq!(d.get("p"), Some(&Bson::Decimal128("20.00".parse().unwrap())));
+    assert_eq!(d.get("q"), Some(&Bson::Decimal128("0".parse().unwrap())));
+
+    let mut doc = persistent(doc! {"s": "x", "l": i64::MAX});
+    for bad in [r#"{"$inc": {"s": 1}}"#, r#"{"$mul": {"s": 2}}"#, r#"{"$inc": {"l": 1}}"#] {
+        let err = apply_update(&mut doc, &parse_update_json(bad).unwrap()).unwrap_err();
+        assert!(matches!(err, DbError::QueryError(_)), "{bad}: {err:?}");
+    }
+
+    // A failing document aborts update_many before anything is written
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("update_types.wasp")).unwrap();
+    let col = engine.create_collection("counters".into());
+    col.insert_document(persistent(doc! {"n": 1}));
+    col.insert_document(persistent(doc! {"n": "one"}));
+    let inc = parse_update_json(r#"{"$inc": {"n": 1}}"#).unwrap();
+    assert!(query::update_many(&col, &Filter::True, &inc).is_err());
+    let f = parse_filter_json(r#"{"field":"n","$eq":1}"#).unwrap();
+    assert_eq!(query::count_docs(&col, &f), 1);
+}
+
+#[test]
+fn rejects_malformed_operators() {
+    for bad in [
+        r#"{"$mul": {"a": "x"}}"#,
+        r#"{"$inc": {"a": null}}"#,
+        r#"{"$rename": {"a": 1}}"#,
+        r#"{"$push": {"a": {"$each": 1}}}"#,
+        r#"{"$push": {"a": {"$each": [1], "$sort": 2}}}"#,
+        r#"{"$push": {"a": {"$each": [1], "$position": 0}}}"#,
+        r#"{"$addToSet": {"a": {"$each": [1], "$slice": 1}}}"#,
+        r#"{"$pull": {"a": {"$regex": "x"}}}"#,
+        r#"{"$pop": {"a": 2}}"#,
+        r#"{"$currentDate": {"a": {"$type": "clock"}}}"#,
+    ] {
+        assert!(parse_update_json(bad).is_err(), "{bad}");
+    }
+}
@@ -0,0 +1,143 @@
+use bson::{Bson, doc};
+use nexuslite::Database;
+use nexuslite::cli::{Command, OutputMode, run_with_format};
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::index::{IndexDescriptor, IndexKind};
+use nexuslite::query::{
+    self, Filter, FindOptions, UpdateOptions, parse_filter_json, parse_update_json,
+};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+const UPSERT: UpdateOptions = UpdateOptions { upsert: true };
+
+fn all(col: &Arc<nexuslite::collection::Collection>) -> Vec<bson::Document> {
+    query::find_docs(col, &Filter::True, &FindOptions::default())
+        .to_vec()
+        .into_iter()
+        .map(|d| d.data.0)
+        .collect()
+}
+
+#[test]
+fn upsert_seeds_from_equality_predicates() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("upsert_seed.wasp")).unwrap();
+    let col = engine.create_collection("jobs".into());
+    let f = parse_filter_json(
+        r#"{"$and":[{"field":"name","$eq":"sync"},{"field":"owner.team","$eq":"core"},
+            {"field":"tier","$in":[2]},{"field":"runs","$gt":5},{"field":"x","$in":[1,2]}]}"#,
+    )
+    .unwrap();
+    let upd = parse_update_json(
+        r#"{"$inc": {"runs": 1}, "$set": {"state": "ok"}, "$setOnInsert": {"created": "first"}}"#,
+    )
+    .unwrap();
+
+    let r = query::update_one_with(&col, &f, &upd, &UPSERT).unwrap();
+    assert_eq!((r.matched, r.modified), (0, 0));
+    let id = r.upserted_id.expect("inserted");
+    let stored = col.find_document(&id).unwrap().data.0;
+    // Range and multi-value predicates seed nothing
+    assert_eq!(
+        stored,
+        doc! {"name": "sync", "owner": {"team": "core"}, "tier": 2, "state": "ok", "runs": 1,
+        "created": "first"}
+    );
+
+    // The inserted document does not match `runs > 5`, so a second upsert inserts again;
+    // a filter it does match updates it and leaves $setOnInsert fields alone
+    let f = parse_filter_json(r#"{"field":"name","$eq":"sync"}"#).unwrap();
+    let again =
+        parse_update_json(r#"{"$inc": {"runs": 1}, "$setOnInsert": {"created": "second"}}"#)
+            .unwrap();
+    let r = query::update_many_with(&col, &f, &again, &UPSERT).unwrap();
+    assert_eq!((r.matched, r.modified, r.upserted_id), (1, 1, None));
+    let docs = all(&col);
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].get_i32("runs").unwrap(), 2);
+    assert_eq!(docs[0].get_str("created").unwrap(), "first");
+
+    // Without the option nothing is inserted
+    let none = parse_filter_json(r#"{"field":"name","$eq":"other"}"#).unwrap();
+    let r = query::update_one(&col, &none, &again).unwrap();
+    assert_eq!(r, query::UpdateReport::default());
+    assert_eq!(all(&col).len(), 1);
+}
+
+#[test]
+fn concurrent_upserts_insert_once() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("upsert_race.wasp")).unwrap();
+    let col = engine.create_collection("counters".into());
+    col.create_index_with(&IndexDescriptor::new("key", IndexKind::Hash).with_unique(true)).unwrap();
+    let f = parse_filter_json(r#"{"field":"key","$eq":"hits"}"#).unwrap();
+    let upd = parse_update_json(r#"{"$inc": {"n": 1}}"#).unwrap();
+
+    let upserted: usize = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                s.spawn(|| {
+                    let r = query::update_one_with(&col, &f, &upd, &UPSERT).unwrap();
+                    usize::from(r.upserted_id.is_some())
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+    assert_eq!(upserted, 1);
+    let docs = all(&col);
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].get_i32("n").unwrap(), 8);
+}
+
+#[test]
+fn upsert_retries_as_update_after_a_racing_insert() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("upsert_dup.wasp")).unwrap();
+    let col = engine.create_collection("users".into());
+    col.create_index_with(&IndexDescriptor::new("email", IndexKind::Hash).with_unique(true))
+        .unwrap();
+    col.insert_document(Document::new(doc! {"email": "a@x", "v": 1}, DocumentType::Persistent));
+
+    // The filter misses on `v`, but the seeded insert collides on `email`
+    let f = parse_filter_json(r#"{"$and":[{"field":"email","$eq":"a@x"},{"field":"v","$eq":2}]}"#)
+        .unwrap();
+    let upd = parse_update_json(r#"{"$set": {"seen": true}}"#).unwrap();
+    let err = query::update_one_with(&col, &f, &upd, &UPSERT).unwrap_err();
+    assert!(matches!(err, nexuslite::errors::DbError::DuplicateKey { .. }));
+    assert_eq!(all(&col), vec![doc! {"email": "a@x", "v": 1}]);
+}
+
+#[test]
+fn database_and_cli_upsert() {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("upsert_db.db");
+    let db = Database::new(Some(db_path.to_str().unwrap())).unwrap();
+    let _ = db.create_collection("kv");
+    let f = parse_filter_json(r#"{"field":"k","$eq":"a"}"#).unwrap();
+    let upd = parse_update_json(r#"{"$set": {"v": 1}}"#).unwrap();
+    let r = db.update_one_with("kv", &f, &upd, &UPSERT).unwrap();
+    assert!(r.upserted_id.is_some());
+    let r = db.update_many_with("kv", &f, &upd, &UPSERT).unwrap();
+    assert_eq!((r.matched, r.upserted_id), (1, None));
+    assert!(db.update_one_with("missing", &f, &upd, &UPSERT).is_err());
+
+    let engine = Engine::new(dir.path().join("upsert_cli.wasp")).unwrap();
+    let col = engine.create_collection("kv".into());
+    for upsert in [false, true, true] {
+        run_with_format(
+            &engine,
+            Command::QueryUpdateOne {
+                collection: "kv".into(),
+                filter_json: r#"{"field":"k","$eq":"b"}"#.into(),
+                update_json: r#"{"$inc": {"n": 1}}"#.into(),
+                upsert,
+            },
+            OutputMode::Json,
+        )
+        .unwrap();
+    }
+    assert_eq!(all(&col), vec![doc! {"k": "b", "n": Bson::Int32(2)}]);
+}
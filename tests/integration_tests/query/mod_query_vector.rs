@@ -0,0 +1,268 @@
+use bson::{Bson, doc};
+use nexuslite::collection::Collection;
+use nexuslite::document::{Document, DocumentType};
+use nexuslite::engine::Engine;
+use nexuslite::errors::DbError;
+use nexuslite::index::{IndexDescriptor, IndexImpl, IndexKind};
+use nexuslite::query::{
+    self, HnswOptions, Metric, VectorOptions, VectorQuery, aggregate, parse_filter_json,
+    parse_pipeline_json, parse_update_json, vector_search,
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+const DIMS: usize = 8;
+
+/// Deterministic pseudo-random vectors in `[-1, 1)`.
+fn vectors(n: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut x = seed;
+    let mut next = move || {
+        x = x.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        ((x >> 40) as f32 / (1u64 << 24) as f32).mul_add(2.0, -1.0)
+    };
+    (0..n).map(|_| (0..DIMS).map(|_| next()).collect()).collect()
+}
+
+fn emb(v: &[f32]) -> Bson {
+    Bson::Array(v.iter().map(|f| Bson::Double(f64::from(*f))).collect())
+}
+
+fn seeded(engine: &Engine, name: &str, n: usize) -> Arc<Collection> {
+    let col = engine.create_collection(name.into());
+    for (i, v) in vectors(n, 7).iter().enumerate() {
+        let cat = if i % 4 == 0 { "a" } else { "b" };
+        let d = doc! {"_id": i32::try_from(i).unwrap(), "cat": cat, "emb": emb(v)};
+        col.insert_document(Document::new(d, DocumentType::Persistent));
+    }
+    col
+}
+
+fn ids(hits: &[(Document, f64)]) -> Vec<i32> {
+    hits.iter().map(|(d, _)| d.data.0.get_i32("_id").unwrap()).collect()
+}
+
+/// The `k` nearest ids by brute force, under the L2 metric.
+fn brute_force(col: &Arc<Collection>, q: &[f32], k: usize) -> Vec<i32> {
+    let mut all: Vec<(f32, i32)> = query::find_docs(col, &query::Filter::True, &Default::default())
+        .map(|d| {
+            let v = d.data.0.get_array("emb").unwrap();
+            let dist: f32 =
+                v.iter().zip(q).map(|(a, b)| (a.as_f64().unwrap() as f32 - b).powi(2)).sum();
+            (dist, d.data.0.get_i32("_id").unwrap())
+        })
+        .collect();
+    all.sort_by(|a, b| a.0.total_cmp(&b.0));
+    all.into_iter().take(k).map(|(_, id)| id).collect()
+}
+
+fn hnsw() -> VectorOptions {
+    VectorOptions::new(DIMS).with_metric(Metric::L2).with_hnsw(HnswOptions::default())
+}
+
+#[test]
+fn flat_and_hnsw_indexes_find_nearest_neighbours() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("vector_knn.wasp")).unwrap();
+    let flat = seeded(&engine, "flat", 600);
+    let graph = seeded(&engine, "graph", 600);
+    let flat_opts = VectorOptions::new(DIMS).with_metric(Metric::L2);
+    flat.create_index_with(&IndexDescriptor::new("emb", IndexKind::Vector).with_vector(flat_opts))
+        .unwrap();
+    graph
+        .create_index_with(&IndexDescriptor::new("emb", IndexKind::Vector).with_vector(hnsw()))
+        .unwrap();
+
+    let (mut found, mut wanted) = (0, 0);
+    for q in vectors(20, 99) {
+        let expected = brute_force(&flat, &q, 10);
+        let query = VectorQuery::new("emb", q.clone(), 10);
+        let hits = vector_search(&flat, &query).unwrap();
+        assert_eq!(ids(&hits), expected);
+        assert!(hits.windows(2).all(|w| w[0].1 >= w[1].1));
+        // `exact` bypasses the graph
+        let exact = vector_search(&graph, &query.clone().with_exact(true)).unwrap();
+        assert_eq!(ids(&exact), expected);
+
+        let approx = ids(&vector_search(&graph, &query).unwrap());
+        assert_eq!(approx.len(), 10);
+        wanted += expected.len();
+        found += approx.iter().filter(|id| expected.contains(id)).count();
+    }
+    // Recall of the graph search against brute force
+    assert!(found * 100 >= wanted * 95, "recall {found}/{wanted}");
+
+    let stats = graph.indexes.read().indexes["emb"].stats().clone();
+    assert_eq!((stats.docs, stats.entries), (600, 600));
+    assert!(stats.hits >= 40);
+}
+
+#[test]
+fn metrics_scores_and_validation() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("vector_metrics.wasp")).unwrap();
+    let col = engine.create_collection("points".into());
+    for (id, v) in [
+        ("a", emb(&[1.0, 0.0])),
+        ("b", emb(&[0.0, 2.0])),
+        ("c", emb(&[3.0, 3.0])),
+        ("d", emb(&[-1.0, 0.0])),
+        ("wrong_dims", emb(&[1.0, 0.0, 0.0])),
+        ("not_numbers", Bson::Array(vec!["x".into(), "y".into()])),
+        ("missing", Bson::Null),
+    ] {
+        col.insert_document(Document::new(doc! {"_id": id, "v": v}, DocumentType::Persistent));
+    }
+    let search = |col: &Arc<Collection>, q: &[f32]| {
+        let hits = vector_search(col, &VectorQuery::new("v", q.to_vec(), 4)).unwrap();
+        hits.iter()
+            .map(|(d, s)| (d.data.0.get_str("_id").unwrap().to_string(), *s))
+            .collect::<Vec<_>>()
+    };
+    let index = |metric| {
+        IndexDescriptor::new("v", IndexKind::Vector)
+            .with_vector(VectorOptions::new(2).with_metric(metric))
+    };
+
+    col.create_index_with(&index(Metric::Cosine)).unwrap();
+    // Only the well-formed 2-dimensional vectors are indexed
+    assert_eq!(col.indexes.read().indexes["v"].stats().docs, 4);
+    let got = search(&col, &[1.0, 0.0]);
+    // Cosine ignores magnitude: "c" is at 45 degrees, "b" orthogonal, "d" opposite
+    assert_eq!(got.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), ["a", "c", "b", "d"]);
+    assert!((got[0].1 - 1.0).abs() < 1e-6 && (got[1].1 - 0.5f64.sqrt()).abs() < 1e-6);
+    assert!(got[2].1.abs() < 1e-6 && (got[3].1 + 1.0).abs() < 1e-6);
+
+    col.indexes.write().drop_index("v");
+    col.create_index_with(&index(Metric::Dot)).unwrap();
+    let got = search(&col, &[1.0, 1.0]);
+    assert_eq!(got[0], ("c".to_string(), 6.0));
+    assert_eq!(got[1], ("b".to_string(), 2.0));
+
+    col.indexes.write().drop_index("v");
+    col.create_index_with(&index(Metric::L2)).unwrap();
+    let got = search(&col, &[0.0, 0.0]);
+    assert_eq!(got[0].1, 0.5);
+    assert_eq!(got[3].0, "c");
+    assert!((got[3].1 - 1.0 / (1.0 + 18f64.sqrt())).abs() < 1e-6);
+
+    let bad = |v: VectorOptions| IndexDescriptor::new("w", IndexKind::Vector).with_vector(v);
+    for desc in [
+        bad(VectorOptions::new(0)),
+        bad(VectorOptions::new(5000)),
+        bad(VectorOptions::new(2).with_hnsw(HnswOptions { m: 1, ef_construction: 10 })),
+        bad(VectorOptions::new(2)).with_unique(true),
+        IndexDescriptor::new("w", IndexKind::Vector),
+    ] {
+        assert!(matches!(col.create_index_with(&desc), Err(DbError::QueryError(_))), "{desc:?}");
+    }
+    for q in [
+        VectorQuery::new("v", vec![1.0, 0.0, 0.0], 2),
+        VectorQuery::new("nope", vec![1.0, 0.0], 2),
+        VectorQuery::new("v", vec![1.0, 0.0], 0),
+        VectorQuery::new("v", vec![f32::NAN, 0.0], 2),
+        VectorQuery::new("v", vec![1.0, 0.0], 5).with_num_candidates(2),
+    ] {
+        assert!(matches!(vector_search(&col, &q), Err(DbError::QueryError(_))), "{q:?}");
+    }
+    // The options survive in the descriptor; a vector index never serves equality lookups
+    let descs = col.indexes.read().descriptors();
+    let json = serde_json::to_string(&descs[0]).unwrap();
+    let back: IndexDescriptor = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.vector.unwrap().metric, Metric::L2);
+    let eq = parse_filter_json(r#"{"field": "v", "$eq": 1}"#).unwrap();
+    let report = query::explain(&col, &eq, &Default::default());
+    assert_eq!(report.chosen.path, query::AccessPath::CollectionScan);
+}
+
+#[test]
+fn pre_filter_and_writes() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("vector_filter.wasp")).unwrap();
+    let col = seeded(&engine, "items", 400);
+    col.create_index_with(&IndexDescriptor::new("emb", IndexKind::Vector).with_vector(hnsw()))
+        .unwrap();
+    col.create_index("cat", IndexKind::Hash);
+    let q = vectors(1, 5).remove(0);
+
+    let only_a = parse_filter_json(r#"{"field": "cat", "$eq": "a"}"#).unwrap();
+    let hits =
+        vector_search(&col, &VectorQuery::new("emb", q.clone(), 10).with_filter(only_a)).unwrap();
+    assert_eq!(hits.len(), 10);
+    assert!(hits.iter().all(|(d, _)| d.data.0.get_i32("_id").unwrap() % 4 == 0));
+    let a_ids: Vec<i32> = brute_force(&col, &q, 400).into_iter().filter(|i| i % 4 == 0).collect();
+    // 100 matches do not exceed the 100 default candidates, so they are compared exactly
+    assert_eq!(ids(&hits), a_ids[..10]);
+
+    // A selective filter below the limit returns only what matches
+    let few = parse_filter_json(r#"{"field": "_id", "$in": [3, 5, 7]}"#).unwrap();
+    let hits =
+        vector_search(&col, &VectorQuery::new("emb", q.clone(), 10).with_filter(few)).unwrap();
+    assert_eq!(ids(&hits).into_iter().collect::<HashSet<_>>(), HashSet::from([3, 5, 7]));
+
+    // Writes keep the index in step: move one document onto the query vector
+    let nearest = |col: &Arc<Collection>| {
+        ids(&vector_search(col, &VectorQuery::new("emb", q.clone(), 1)).unwrap())
+    };
+    let upd = parse_update_json(&format!(r#"{{"$set": {{"emb": {:?}}}}}"#, q)).unwrap();
+    let target = parse_filter_json(r#"{"field": "_id", "$eq": 123}"#).unwrap();
+    query::update_one(&col, &target, &upd).unwrap();
+    assert_eq!(nearest(&col), [123]);
+    // Replacing the vector with a malformed one drops the document from the index
+    let upd = parse_update_json(r#"{"$set": {"emb": [1, 2]}}"#).unwrap();
+    query::update_one(&col, &target, &upd).unwrap();
+    assert_ne!(nearest(&col), [123]);
+
+    // Deleting most documents rebuilds the graph from the survivors
+    let gone = parse_filter_json(r#"{"field": "_id", "$gte": 50}"#).unwrap();
+    assert_eq!(query::delete_many(&col, &gone).deleted, 350);
+    let len = |col: &Arc<Collection>| match &col.indexes.read().indexes["emb"] {
+        IndexImpl::Vector(v) => v.len(),
+        _ => unreachable!(),
+    };
+    assert_eq!(len(&col), 50);
+    let hits = vector_search(&col, &VectorQuery::new("emb", q.clone(), 10)).unwrap();
+    assert_eq!(ids(&hits), brute_force(&col, &q, 10));
+}
+
+#[test]
+fn vector_search_pipeline_stage() {
+    let dir = tempdir().unwrap();
+    let engine = Engine::new(dir.path().join("vector_stage.wasp")).unwrap();
+    let col = seeded(&engine, "stage", 200);
+    col.create_index_with(&IndexDescriptor::new("emb", IndexKind::Vector).with_vector(hnsw()))
+        .unwrap();
+    let q = vectors(1, 11).remove(0);
+    let expected = brute_force(&col, &q, 40);
+
+    let json = format!(
+        r#"[{{"$vectorSearch": {{"path": "emb", "queryVector": {q:?}, "limit": 40,
+              "numCandidates": 200, "filter": {{"field": "cat", "$eq": "b"}}}}}},
+            {{"$project": {{"_id": 1, "cat": 1, "score": {{"$meta": "vectorSearchScore"}}}}}},
+            {{"$sort": {{"score": {{"$meta": "vectorSearchScore"}}}}}},
+            {{"$limit": 5}}]"#
+    );
+    let out = aggregate(&col, &parse_pipeline_json(&json).unwrap()).unwrap();
+    assert_eq!(out.len(), 5);
+    let want: Vec<i32> = expected.iter().copied().filter(|i| i % 4 != 0).take(5).collect();
+    assert_eq!(out.iter().map(|d| d.get_i32("_id").unwrap()).collect::<Vec<_>>(), want);
+    assert!(out.iter().all(|d| d.get_str("cat").unwrap() == "b"));
+    assert!(
+        out.windows(2).all(|w| w[0].get_f64("score").unwrap() >= w[1].get_f64("score").unwrap())
+    );
+    assert!(out.iter().all(|d| !d.contains_key("$vectorSearchScore")));
+
+    for bad in [
+        r#"[{"$vectorSearch": {"path": "emb", "queryVector": [1], "limit": 1, "k": 3}}]"#,
+        r#"[{"$vectorSearch": {"path": "emb", "queryVector": ["x"], "limit": 1}}]"#,
+        r#"[{"$vectorSearch": {"queryVector": [1], "limit": 1}}]"#,
+    ] {
+        assert!(parse_pipeline_json(bad).is_err(), "{bad}");
+    }
+    let misplaced = format!(
+        r#"[{{"$match": {{"field": "cat", "$eq": "a"}}}},
+            {{"$vectorSearch": {{"path": "emb", "queryVector": {q:?}, "limit": 3}}}}]"#
+    );
+    let err = aggregate(&col, &parse_pipeline_json(&misplaced).unwrap()).unwrap_err();
+    assert!(matches!(err, DbError::QueryError(m) if m.contains("first stage")));
+}
@@ -1,3 +1,5 @@
+#[path = "prop_compare.rs"]
+mod compare_props;
 #[path = "prop_parse_compare.rs"]
 mod parse_compare_props;
 #[path = "prop_query.rs"]
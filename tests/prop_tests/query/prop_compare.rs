@@ -0,0 +1,100 @@
+use bson::oid::ObjectId;
+use bson::spec::BinarySubtype;
+use bson::{Binary, Bson, DateTime, Decimal128, Timestamp};
+use nexuslite::index::key_from_bson;
+use nexuslite::query::compare_bson;
+use proptest::prelude::*;
+use std::cmp::Ordering;
+
+fn decimal(coef: i64, exp: i32) -> Decimal128 {
+    format!("{coef}E{exp}").parse().unwrap()
+}
+
+fn scalar() -> impl Strategy<Value = Bson> {
+    prop_oneof![
+        Just(Bson::Null),
+        Just(Bson::MinKey),
+        Just(Bson::MaxKey),
+        any::<bool>().prop_map(Bson::Boolean),
+        any::<i32>().prop_map(Bson::Int32),
+        any::<i64>().prop_map(Bson::Int64),
+        (-5i64..5).prop_map(Bson::Int64),
+        any::<f64>().prop_map(Bson::Double),
+        (-5i32..5).prop_map(|i| Bson::Double(f64::from(i) / 2.0)),
+        (-1000i64..1000, -3i32..3).prop_map(|(c, e)| Bson::Decimal128(decimal(c, e))),
+        "[a-c]{0,3}".prop_map(Bson::String),
+        (0u8..3, proptest::collection::vec(any::<u8>(), 0..3)).prop_map(|(s, bytes)| {
+            Bson::Binary(Binary { subtype: BinarySubtype::from(s), bytes })
+        }),
+        any::<[u8; 12]>().prop_map(|b| Bson::ObjectId(ObjectId::from_bytes(b))),
+        (-3i64..3).prop_map(|d| Bson::DateTime(DateTime::from_millis(d * 1000))),
+        (0u32..3, 0u32..3)
+            .prop_map(|(time, increment)| Bson::Timestamp(Timestamp { time, increment })),
+    ]
+}
+
+fn any_bson() -> impl Strategy<Value = Bson> {
+    scalar().prop_recursive(2, 12, 3, |inner| {
+        prop_oneof![
+            proptest::collection::vec(inner.clone(), 0..3).prop_map(Bson::Array),
+            proptest::collection::vec(("[ab]", inner), 0..3)
+                .prop_map(|kv| Bson::Document(kv.into_iter().collect())),
+        ]
+    })
+}
+
+/// Compare `ca * 10^ea` with `cb * 10^eb` exactly.
+fn scaled_cmp(ca: i64, ea: i32, cb: i64, eb: i32) -> Ordering {
+    let e = ea.min(eb);
+    let scale = |c: i64, x: i32| i128::from(c) * 10i128.pow((x - e) as u32);
+    scale(ca, ea).cmp(&scale(cb, eb))
+}
+
+proptest! {
+    #![proptest_config(proptest::test_runner::Config {
+        failure_persistence: Some(Box::new(proptest::test_runner::FileFailurePersistence::WithSource("proptest-regressions"))),
+        cases: 256,
+        .. proptest::test_runner::Config::default()
+    })]
+    #[test]
+    fn prop_compare_is_antisymmetric(a in any_bson(), b in any_bson()) {
+        prop_assert_eq!(compare_bson(&a, &b), compare_bson(&b, &a).reverse());
+        prop_assert_eq!(compare_bson(&a, &a), Ordering::Equal);
+    }
+
+    #[test]
+    fn prop_compare_is_transitive(mut v in proptest::collection::vec(any_bson(), 3)) {
+        // Sorting by a total order leaves every pair, not just neighbours, in order
+        v.sort_by(compare_bson);
+        for i in 0..v.len() {
+            for j in i + 1..v.len() {
+                prop_assert_ne!(compare_bson(&v[i], &v[j]), Ordering::Greater);
+            }
+        }
+    }
+
+    #[test]
+    fn prop_index_keys_follow_compare(a in scalar(), b in scalar()) {
+        if let (Some(ka), Some(kb)) = (key_from_bson(&a), key_from_bson(&b)) {
+            let c = compare_bson(&a, &b);
+            // Equal values of different numeric types are distinct (adjacent) keys
+            if c != Ordering::Equal {
+                prop_assert_eq!(ka.cmp(&kb), c);
+            }
+        }
+    }
+
+    #[test]
+    fn prop_decimals_compare_exactly(
+        ca in -10_000_000_000i64..10_000_000_000, ea in -12i32..12,
+        cb in -10_000_000_000i64..10_000_000_000, eb in -12i32..12,
+    ) {
+        let (a, b) = (Bson::Decimal128(decimal(ca, ea)), Bson::Decimal128(decimal(cb, eb)));
+        prop_assert_eq!(compare_bson(&a, &b), scaled_cmp(ca, ea, cb, eb));
+        // Against integers beyond f64's 53-bit precision
+        let big = (1i64 << 60) + ca;
+        let int = Bson::Int64(big);
+        let dec = Bson::Decimal128(decimal(big + cb.signum(), 0));
+        prop_assert_eq!(compare_bson(&int, &dec), 0.cmp(&cb.signum()));
+    }
+}
@@ -27,7 +27,10 @@ proptest! {
             sort: Some(vec![SortSpec{ field: "a".into(), order: Order::Asc }, SortSpec{ field: "b".into(), order: Order::Asc }]),
             limit: None,
             skip: None,
-            timeout_ms: None
+            timeout_ms: None,
+            sort_memory_limit: None,
+            after: None,
+            collation: None,
         };
     let cur = nexuslite::query::find_docs(&col, &nexuslite::query::Filter::True, &opts);
         let docs = cur.to_vec();